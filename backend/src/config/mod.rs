@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::env;
+use crate::models::GuardrailAction;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AIProvider {
@@ -8,19 +10,100 @@ pub enum AIProvider {
     OpenAI,
     /// Custom RAG API - for production use with custom endpoint
     CustomRAG,
+    /// Ollama - local models through the native `/api/chat` endpoint
+    Ollama,
+    /// Anthropic-style Messages API
+    Anthropic,
+    /// Offline provider with deterministic replies, for development and tests
+    Mock,
 }
 
 impl AIProvider {
+    /// Parse a provider name or one of its aliases
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Some(AIProvider::OpenAI),
+            "lmstudio" | "lm_studio" | "lm-studio" => Some(AIProvider::LMStudio),
+            "custom" | "custom_rag" | "customrag" | "rag" => Some(AIProvider::CustomRAG),
+            "ollama" => Some(AIProvider::Ollama),
+            "anthropic" | "messages" | "messages_api" => Some(AIProvider::Anthropic),
+            "mock" => Some(AIProvider::Mock),
+            _ => None,
+        }
+    }
+
     pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "openai" => AIProvider::OpenAI,
-            "lmstudio" | "lm_studio" | "lm-studio" => AIProvider::LMStudio,
-            "custom" | "custom_rag" | "customrag" | "rag" => AIProvider::CustomRAG,
-            _ => AIProvider::LMStudio, // Default to LMStudio for development
+        Self::parse(s).unwrap_or(AIProvider::LMStudio) // Default to LMStudio for development
+    }
+
+    /// Name the provider is registered under in the provider registry
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AIProvider::LMStudio => "lmstudio",
+            AIProvider::OpenAI => "openai",
+            AIProvider::CustomRAG => "custom_rag",
+            AIProvider::Ollama => "ollama",
+            AIProvider::Anthropic => "anthropic",
+            AIProvider::Mock => "mock",
+        }
+    }
+
+    pub fn all() -> [AIProvider; 6] {
+        [
+            AIProvider::LMStudio,
+            AIProvider::OpenAI,
+            AIProvider::CustomRAG,
+            AIProvider::Ollama,
+            AIProvider::Anthropic,
+            AIProvider::Mock,
+        ]
+    }
+
+    pub fn default_api_url(&self) -> &'static str {
+        match self {
+            AIProvider::OpenAI => "https://api.openai.com",
+            AIProvider::LMStudio => "http://localhost:1234",
+            AIProvider::CustomRAG => "http://localhost:8001",
+            AIProvider::Ollama => "http://localhost:11434",
+            AIProvider::Anthropic => "https://api.anthropic.com",
+            AIProvider::Mock => "mock://local",
+        }
+    }
+
+    /// Note: CustomRAG doesn't use a model name but we keep one for consistency
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            AIProvider::OpenAI => "gpt-4",
+            AIProvider::LMStudio => "GPT-OSS-20B",
+            AIProvider::CustomRAG => "default",
+            AIProvider::Ollama => "llama3.1",
+            AIProvider::Anthropic => "claude-3-5-sonnet-latest",
+            AIProvider::Mock => "mock-1",
+        }
+    }
+
+    /// Prefix of the `<PREFIX>_API_URL` / `<PREFIX>_API_KEY` variables for this provider
+    pub fn env_prefix(&self) -> &'static str {
+        match self {
+            AIProvider::LMStudio => "LM_STUDIO",
+            AIProvider::OpenAI => "OPENAI",
+            AIProvider::CustomRAG => "CUSTOM_RAG",
+            AIProvider::Ollama => "OLLAMA",
+            AIProvider::Anthropic => "ANTHROPIC",
+            AIProvider::Mock => "MOCK",
         }
     }
 }
 
+/// Connection settings for a built-in provider, used when a project
+/// overrides its AI provider
+#[derive(Debug, Clone)]
+pub struct AIEndpoint {
+    pub api_url: String,
+    pub model_name: String,
+    pub api_key: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub server_host: String,
@@ -32,15 +115,81 @@ pub struct Config {
     pub jwt_expiration: i64,
     pub jwt_refresh_expiration: i64,
     // AI Provider Configuration
-    pub ai_provider: AIProvider,
+    /// Provider name as configured, resolved through the provider registry
+    pub ai_provider_name: String,
     pub ai_api_url: String,
     pub ai_model_name: String,
     pub ai_api_key: Option<String>,
+    /// Embeddings model; `None` means chunks use the local hash embedding
+    pub ai_embedding_model: Option<String>,
+    /// Endpoints for the built-in providers, keyed by registry name
+    pub ai_endpoints: HashMap<String, AIEndpoint>,
+    /// Provider used while the configured one's circuit breaker is open
+    pub ai_fallback_provider: Option<String>,
+    pub ai_fallback_model: Option<String>,
+    pub ai_connect_timeout_secs: u64,
+    pub ai_request_timeout_secs: u64,
+    pub ai_stream_timeout_secs: u64,
+    /// Retries for non-streaming calls after a connection error, timeout, 429 or 5xx
+    pub ai_max_retries: u32,
+    pub ai_retry_base_delay_ms: u64,
+    pub ai_retry_max_delay_ms: u64,
+    /// Consecutive failures that open a provider endpoint's circuit breaker
+    pub ai_breaker_failure_threshold: u32,
+    pub ai_breaker_open_secs: u64,
+    /// Serve repeated project questions from the Redis response cache
+    pub ai_cache_enabled: bool,
+    pub ai_cache_ttl_secs: u64,
+    /// Cosine similarity at which a reworded question reuses an answer; unset means exact matches only
+    pub ai_cache_similarity_threshold: Option<f64>,
+    /// Write provider HTTP exchanges to fixture files in this directory
+    pub ai_record_dir: Option<String>,
+    /// Answer provider requests from the fixtures in this directory instead of the network
+    pub ai_replay_dir: Option<String>,
+    /// Let streamed chat replies call data tools
+    pub ai_tools_enabled: bool,
+    /// Model turns per reply that may call tools
+    pub ai_tool_max_rounds: usize,
+    /// Redact personal data from prompts for tenants without their own redaction settings
+    pub pii_redaction_enabled: bool,
+    /// Screen prompts for injection attempts for tenants without their own policy
+    pub guardrails_enabled: bool,
+    pub guardrail_user_input_action: GuardrailAction,
+    /// Action for knowledge base passages and tool results
+    pub guardrail_context_action: GuardrailAction,
+    // Knowledge base
+    pub knowledge_chunk_size: usize,
+    pub knowledge_chunk_overlap: usize,
+    pub knowledge_top_k: usize,
+    pub knowledge_max_upload_bytes: usize,
+    // Datasets
+    pub dataset_max_rows: usize,
+    pub dataset_max_upload_bytes: usize,
+    pub dataset_query_max_rows: usize,
+    /// Generate an insights report for each uploaded dataset
+    pub dataset_insights_enabled: bool,
+    /// Run the table's quality rules on each uploaded dataset
+    pub dataset_quality_on_upload: bool,
+    /// Run scheduled anomaly reports in this server
+    pub scheduled_reports_enabled: bool,
+    /// Seconds between checks for due scheduled reports
+    pub scheduled_reports_tick_secs: u64,
     pub rate_limit_requests: usize,
     pub rate_limit_window_secs: u64,
     pub chat_rate_limit_messages: usize,
     pub chat_rate_limit_window_secs: u64,
     pub chat_context_message_limit: usize,
+    /// Suggest follow-up questions after each chat reply
+    pub chat_follow_ups_enabled: bool,
+    // Chat attachments
+    pub chat_attachment_max_bytes: usize,
+    /// Longest attachment summary, and the most attachment text in one prompt
+    pub chat_attachment_context_chars: usize,
+    /// Uploads never sent with a message are deleted after this many hours
+    pub chat_attachment_unbound_ttl_hours: u64,
+    /// Default monthly token quotas, 0 for unlimited
+    pub quota_tenant_monthly_tokens: i64,
+    pub quota_user_monthly_tokens: i64,
     pub cors_allowed_origins: Vec<String>,
 }
 
@@ -77,37 +226,219 @@ impl Config {
             .parse::<i64>()
             .map_err(|_| "Invalid JWT_REFRESH_EXPIRATION")?;
 
-        // AI Provider configuration - supports LMStudio (dev), OpenAI (prod), CustomRAG (prod)
-        let ai_provider = AIProvider::from_str(
-            &env::var("AI_PROVIDER").unwrap_or_else(|_| "lmstudio".to_string())
-        );
+        // AI Provider configuration - supports LMStudio (dev), OpenAI (prod), CustomRAG (prod),
+        // Ollama and Messages-style APIs. Names outside the built-in list are passed through
+        // so providers registered elsewhere can be selected.
+        let ai_provider_raw = env::var("AI_PROVIDER")
+            .unwrap_or_else(|_| "lmstudio".to_string())
+            .trim()
+            .to_lowercase();
+        let ai_provider = AIProvider::from_str(&ai_provider_raw);
+        let ai_provider_name = AIProvider::parse(&ai_provider_raw)
+            .map(|p| p.as_str().to_string())
+            .unwrap_or(ai_provider_raw);
         
         // Get API URL based on provider or use unified AI_API_URL
         let ai_api_url = env::var("AI_API_URL")
             .or_else(|_| env::var("LM_STUDIO_API_URL"))
             .or_else(|_| env::var("OPENAI_API_URL"))
-            .unwrap_or_else(|_| match ai_provider {
-                AIProvider::OpenAI => "https://api.openai.com".to_string(),
-                AIProvider::LMStudio => "http://localhost:1234".to_string(),
-                AIProvider::CustomRAG => "http://localhost:8001".to_string(),
-            });
+            .unwrap_or_else(|_| ai_provider.default_api_url().to_string());
         
         // Get model name based on provider or use unified AI_MODEL_NAME
-        // Note: CustomRAG doesn't use model_name but we keep it for consistency
         let ai_model_name = env::var("AI_MODEL_NAME")
             .or_else(|_| env::var("LM_STUDIO_MODEL_NAME"))
             .or_else(|_| env::var("OPENAI_MODEL_NAME"))
-            .unwrap_or_else(|_| match ai_provider {
-                AIProvider::OpenAI => "gpt-4".to_string(),
-                AIProvider::LMStudio => "GPT-OSS-20B".to_string(),
-                AIProvider::CustomRAG => "default".to_string(),
-            });
+            .unwrap_or_else(|_| ai_provider.default_model().to_string());
         
-        // API Key (required for OpenAI and CustomRAG, optional for LMStudio)
+        // API Key (required for OpenAI, CustomRAG and Messages APIs, optional for LMStudio and Ollama)
         let ai_api_key = env::var("AI_API_KEY")
             .or_else(|_| env::var("OPENAI_API_KEY"))
+            .or_else(|_| env::var("ANTHROPIC_API_KEY"))
             .ok();
 
+        // Embeddings model for the knowledge base. Custom RAG and Messages APIs have no
+        // embeddings endpoint, so they fall back to local embeddings.
+        let ai_embedding_model = env::var("AI_EMBEDDING_MODEL")
+            .ok()
+            .filter(|m| !m.trim().is_empty())
+            .or_else(|| match ai_provider {
+                AIProvider::OpenAI => Some("text-embedding-3-small".to_string()),
+                AIProvider::LMStudio => Some("text-embedding-nomic-embed-text-v1.5".to_string()),
+                AIProvider::Ollama => Some("nomic-embed-text".to_string()),
+                AIProvider::Mock => Some("mock-embedding".to_string()),
+                AIProvider::CustomRAG | AIProvider::Anthropic => None,
+            });
+
+        // Projects may switch to another built-in provider. The configured provider keeps
+        // its settings above; the others read <PREFIX>_API_URL and <PREFIX>_API_KEY.
+        let ai_endpoints = AIProvider::all()
+            .iter()
+            .map(|provider| {
+                let endpoint = if provider.as_str() == ai_provider_name {
+                    AIEndpoint {
+                        api_url: ai_api_url.clone(),
+                        model_name: ai_model_name.clone(),
+                        api_key: ai_api_key.clone(),
+                    }
+                } else {
+                    let prefix = provider.env_prefix();
+                    AIEndpoint {
+                        api_url: env::var(format!("{}_API_URL", prefix))
+                            .unwrap_or_else(|_| provider.default_api_url().to_string()),
+                        model_name: provider.default_model().to_string(),
+                        api_key: env::var(format!("{}_API_KEY", prefix)).ok(),
+                    }
+                };
+                (provider.as_str().to_string(), endpoint)
+            })
+            .collect();
+
+        // Resilience: a secondary provider for failover, timeouts, retries and circuit breaking
+        let ai_fallback_provider = env::var("AI_FALLBACK_PROVIDER")
+            .ok()
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .map(|p| AIProvider::parse(&p).map(|a| a.as_str().to_string()).unwrap_or(p));
+        let ai_fallback_model = env::var("AI_FALLBACK_MODEL")
+            .ok()
+            .filter(|m| !m.trim().is_empty());
+        let ai_connect_timeout_secs = env::var("AI_CONNECT_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_CONNECT_TIMEOUT_SECS")?;
+        let ai_request_timeout_secs = env::var("AI_REQUEST_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "120".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_REQUEST_TIMEOUT_SECS")?;
+        let ai_stream_timeout_secs = env::var("AI_STREAM_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "600".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_STREAM_TIMEOUT_SECS")?;
+        let ai_max_retries = env::var("AI_MAX_RETRIES")
+            .unwrap_or_else(|_| "2".to_string())
+            .parse::<u32>()
+            .map_err(|_| "Invalid AI_MAX_RETRIES")?;
+        let ai_retry_base_delay_ms = env::var("AI_RETRY_BASE_DELAY_MS")
+            .unwrap_or_else(|_| "250".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_RETRY_BASE_DELAY_MS")?;
+        let ai_retry_max_delay_ms = env::var("AI_RETRY_MAX_DELAY_MS")
+            .unwrap_or_else(|_| "4000".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_RETRY_MAX_DELAY_MS")?;
+        let ai_breaker_failure_threshold = env::var("AI_BREAKER_FAILURE_THRESHOLD")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .map_err(|_| "Invalid AI_BREAKER_FAILURE_THRESHOLD")?
+            .max(1);
+        let ai_breaker_open_secs = env::var("AI_BREAKER_OPEN_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_BREAKER_OPEN_SECS")?;
+        let ai_cache_enabled = env::var("AI_CACHE_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid AI_CACHE_ENABLED")?;
+        let ai_cache_ttl_secs = env::var("AI_CACHE_TTL_SECS")
+            .unwrap_or_else(|_| "86400".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid AI_CACHE_TTL_SECS")?
+            .max(1);
+        let ai_cache_similarity_threshold = match env::var("AI_CACHE_SIMILARITY_THRESHOLD") {
+            Ok(v) if !v.trim().is_empty() => {
+                let threshold = v
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| "Invalid AI_CACHE_SIMILARITY_THRESHOLD")?;
+                if !(0.0..=1.0).contains(&threshold) {
+                    return Err("AI_CACHE_SIMILARITY_THRESHOLD must be between 0 and 1".to_string());
+                }
+                Some(threshold)
+            }
+            _ => None,
+        };
+        let ai_record_dir = env::var("AI_RECORD_DIR").ok().filter(|d| !d.trim().is_empty());
+        let ai_replay_dir = env::var("AI_REPLAY_DIR").ok().filter(|d| !d.trim().is_empty());
+        if ai_record_dir.is_some() && ai_replay_dir.is_some() {
+            return Err("AI_RECORD_DIR and AI_REPLAY_DIR cannot both be set".to_string());
+        }
+        let ai_tools_enabled = env::var("AI_TOOLS_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid AI_TOOLS_ENABLED")?;
+        let ai_tool_max_rounds = env::var("AI_TOOL_MAX_ROUNDS")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid AI_TOOL_MAX_ROUNDS")?
+            .max(1);
+        let pii_redaction_enabled = env::var("PII_REDACTION_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid PII_REDACTION_ENABLED")?;
+        let guardrails_enabled = env::var("GUARDRAILS_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid GUARDRAILS_ENABLED")?;
+        let guardrail_user_input_action = GuardrailAction::parse(
+            &env::var("GUARDRAIL_USER_INPUT_ACTION").unwrap_or_else(|_| "warn".to_string()),
+        )
+        .ok_or("GUARDRAIL_USER_INPUT_ACTION must be warn, strip or block")?;
+        let guardrail_context_action = GuardrailAction::parse(
+            &env::var("GUARDRAIL_CONTEXT_ACTION").unwrap_or_else(|_| "strip".to_string()),
+        )
+        .ok_or("GUARDRAIL_CONTEXT_ACTION must be warn, strip or block")?;
+
+        let knowledge_chunk_size = env::var("KNOWLEDGE_CHUNK_SIZE")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid KNOWLEDGE_CHUNK_SIZE")?;
+        let knowledge_chunk_overlap = env::var("KNOWLEDGE_CHUNK_OVERLAP")
+            .unwrap_or_else(|_| "150".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid KNOWLEDGE_CHUNK_OVERLAP")?;
+        if knowledge_chunk_size == 0 || knowledge_chunk_overlap >= knowledge_chunk_size {
+            return Err("KNOWLEDGE_CHUNK_OVERLAP must be smaller than KNOWLEDGE_CHUNK_SIZE".to_string());
+        }
+        let knowledge_top_k = env::var("KNOWLEDGE_TOP_K")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid KNOWLEDGE_TOP_K")?;
+        let knowledge_max_upload_bytes = env::var("KNOWLEDGE_MAX_UPLOAD_BYTES")
+            .unwrap_or_else(|_| "10485760".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid KNOWLEDGE_MAX_UPLOAD_BYTES")?;
+
+        let dataset_max_rows = env::var("DATASET_MAX_ROWS")
+            .unwrap_or_else(|_| "100000".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid DATASET_MAX_ROWS")?;
+        let dataset_max_upload_bytes = env::var("DATASET_MAX_UPLOAD_BYTES")
+            .unwrap_or_else(|_| "10485760".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid DATASET_MAX_UPLOAD_BYTES")?;
+        let dataset_query_max_rows = env::var("DATASET_QUERY_MAX_ROWS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid DATASET_QUERY_MAX_ROWS")?
+            .max(1);
+        let dataset_insights_enabled = env::var("DATASET_INSIGHTS_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid DATASET_INSIGHTS_ENABLED")?;
+        let dataset_quality_on_upload = env::var("DATASET_QUALITY_ON_UPLOAD")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid DATASET_QUALITY_ON_UPLOAD")?;
+        let scheduled_reports_enabled = env::var("SCHEDULED_REPORTS_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid SCHEDULED_REPORTS_ENABLED")?;
+        let scheduled_reports_tick_secs = env::var("SCHEDULED_REPORTS_TICK_SECS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid SCHEDULED_REPORTS_TICK_SECS")?
+            .max(1);
+
         let rate_limit_requests = env::var("RATE_LIMIT_REQUESTS")
             .unwrap_or_else(|_| "100".to_string())
             .parse::<usize>()
@@ -129,6 +460,34 @@ impl Config {
             .unwrap_or_else(|_| "10".to_string())
             .parse::<usize>()
             .map_err(|_| "Invalid CHAT_CONTEXT_MESSAGE_LIMIT")?;
+        let chat_follow_ups_enabled = env::var("CHAT_FOLLOW_UPS_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|_| "Invalid CHAT_FOLLOW_UPS_ENABLED")?;
+
+        let chat_attachment_max_bytes = env::var("CHAT_ATTACHMENT_MAX_BYTES")
+            .unwrap_or_else(|_| "10485760".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid CHAT_ATTACHMENT_MAX_BYTES")?;
+        let chat_attachment_context_chars = env::var("CHAT_ATTACHMENT_CONTEXT_CHARS")
+            .unwrap_or_else(|_| "12000".to_string())
+            .parse::<usize>()
+            .map_err(|_| "Invalid CHAT_ATTACHMENT_CONTEXT_CHARS")?
+            .max(1);
+        let chat_attachment_unbound_ttl_hours = env::var("CHAT_ATTACHMENT_UNBOUND_TTL_HOURS")
+            .unwrap_or_else(|_| "24".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid CHAT_ATTACHMENT_UNBOUND_TTL_HOURS")?
+            .max(1);
+
+        let quota_tenant_monthly_tokens = env::var("QUOTA_TENANT_MONTHLY_TOKENS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid QUOTA_TENANT_MONTHLY_TOKENS")? as i64;
+        let quota_user_monthly_tokens = env::var("QUOTA_USER_MONTHLY_TOKENS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u64>()
+            .map_err(|_| "Invalid QUOTA_USER_MONTHLY_TOKENS")? as i64;
 
         let cors_allowed_origins = env::var("CORS_ALLOWED_ORIGINS")
             .unwrap_or_else(|_| "http://localhost:4202".to_string())
@@ -145,15 +504,55 @@ impl Config {
             jwt_secret,
             jwt_expiration,
             jwt_refresh_expiration,
-            ai_provider,
+            ai_provider_name,
             ai_api_url,
             ai_model_name,
             ai_api_key,
+            ai_embedding_model,
+            ai_endpoints,
+            ai_fallback_provider,
+            ai_fallback_model,
+            ai_connect_timeout_secs,
+            ai_request_timeout_secs,
+            ai_stream_timeout_secs,
+            ai_max_retries,
+            ai_retry_base_delay_ms,
+            ai_retry_max_delay_ms,
+            ai_breaker_failure_threshold,
+            ai_breaker_open_secs,
+            ai_cache_enabled,
+            ai_cache_ttl_secs,
+            ai_cache_similarity_threshold,
+            ai_record_dir,
+            ai_replay_dir,
+            ai_tools_enabled,
+            ai_tool_max_rounds,
+            pii_redaction_enabled,
+            guardrails_enabled,
+            guardrail_user_input_action,
+            guardrail_context_action,
+            knowledge_chunk_size,
+            knowledge_chunk_overlap,
+            knowledge_top_k,
+            knowledge_max_upload_bytes,
+            dataset_max_rows,
+            dataset_max_upload_bytes,
+            dataset_query_max_rows,
+            dataset_insights_enabled,
+            dataset_quality_on_upload,
+            scheduled_reports_enabled,
+            scheduled_reports_tick_secs,
             rate_limit_requests,
             rate_limit_window_secs,
             chat_rate_limit_messages,
             chat_rate_limit_window_secs,
             chat_context_message_limit,
+            chat_follow_ups_enabled,
+            chat_attachment_max_bytes,
+            chat_attachment_context_chars,
+            chat_attachment_unbound_ttl_hours,
+            quota_tenant_monthly_tokens,
+            quota_user_monthly_tokens,
             cors_allowed_origins,
         })
     }
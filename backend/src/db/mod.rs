@@ -1,7 +1,14 @@
 use mongodb::{Client, Database, Collection};
+use mongodb::gridfs::GridFsBucket;
+use mongodb::options::GridFsBucketOptions;
 use redis::aio::ConnectionManager;
 use std::sync::Arc;
-use crate::models::{User, Project, AnalyticsQuery, Conversation, Role, ProjectMembership};
+use crate::models::{
+    User, Project, AnalyticsQuery, Conversation, ConversationFolder, Role, ProjectMembership,
+    KnowledgeDocument, DocumentChunk, ProjectAiSettings, PromptTemplate, UsageRecord, TokenQuota,
+    Dataset, DatasetRow, RedactionSettings, AuditLogEntry, GuardrailPolicy, GuardrailEvent,
+    ChatAttachment, DatasetInsightReport, QualityRule, QualityRun, ScheduledReport, Notification,
+};
 use crate::config::Config;
 
 #[derive(Clone)]
@@ -56,6 +63,91 @@ impl DatabaseManager {
         self.db.collection("conversations")
     }
 
+    pub fn conversation_folders_collection(&self) -> Collection<ConversationFolder> {
+        self.db.collection("conversation_folders")
+    }
+
+    pub fn knowledge_documents_collection(&self) -> Collection<KnowledgeDocument> {
+        self.db.collection("knowledge_documents")
+    }
+
+    pub fn document_chunks_collection(&self) -> Collection<DocumentChunk> {
+        self.db.collection("document_chunks")
+    }
+
+    pub fn datasets_collection(&self) -> Collection<Dataset> {
+        self.db.collection("datasets")
+    }
+
+    pub fn dataset_rows_collection(&self) -> Collection<DatasetRow> {
+        self.db.collection("dataset_rows")
+    }
+
+    pub fn dataset_insights_collection(&self) -> Collection<DatasetInsightReport> {
+        self.db.collection("dataset_insights")
+    }
+
+    pub fn quality_rules_collection(&self) -> Collection<QualityRule> {
+        self.db.collection("quality_rules")
+    }
+
+    pub fn quality_runs_collection(&self) -> Collection<QualityRun> {
+        self.db.collection("quality_runs")
+    }
+
+    pub fn scheduled_reports_collection(&self) -> Collection<ScheduledReport> {
+        self.db.collection("scheduled_reports")
+    }
+
+    pub fn notifications_collection(&self) -> Collection<Notification> {
+        self.db.collection("notifications")
+    }
+
+    pub fn project_ai_settings_collection(&self) -> Collection<ProjectAiSettings> {
+        self.db.collection("project_ai_settings")
+    }
+
+    pub fn prompt_templates_collection(&self) -> Collection<PromptTemplate> {
+        self.db.collection("prompt_templates")
+    }
+
+    pub fn usage_records_collection(&self) -> Collection<UsageRecord> {
+        self.db.collection("usage_records")
+    }
+
+    pub fn token_quotas_collection(&self) -> Collection<TokenQuota> {
+        self.db.collection("token_quotas")
+    }
+
+    pub fn redaction_settings_collection(&self) -> Collection<RedactionSettings> {
+        self.db.collection("redaction_settings")
+    }
+
+    pub fn audit_logs_collection(&self) -> Collection<AuditLogEntry> {
+        self.db.collection("audit_logs")
+    }
+
+    pub fn guardrail_policies_collection(&self) -> Collection<GuardrailPolicy> {
+        self.db.collection("guardrail_policies")
+    }
+
+    pub fn guardrail_events_collection(&self) -> Collection<GuardrailEvent> {
+        self.db.collection("guardrail_events")
+    }
+
+    pub fn chat_attachments_collection(&self) -> Collection<ChatAttachment> {
+        self.db.collection("chat_attachments")
+    }
+
+    /// GridFS bucket holding chat attachment files
+    pub fn attachments_bucket(&self) -> GridFsBucket {
+        self.db.gridfs_bucket(
+            GridFsBucketOptions::builder()
+                .bucket_name("chat_attachments".to_string())
+                .build(),
+        )
+    }
+
     pub fn roles_collection(&self) -> Collection<Role> {
         self.db.collection("roles")
     }
@@ -108,8 +200,16 @@ impl DatabaseManager {
             .keys(doc! { "user_id": 1 })
             .build();
 
+        let query_text_index = IndexModel::builder()
+            .keys(doc! { "query_text": "text", "response_text": "text" })
+            .options(mongodb::options::IndexOptions::builder()
+                .name("query_text_search".to_string())
+                .weights(doc! { "query_text": 3, "response_text": 1 })
+                .build())
+            .build();
+
         self.queries_collection()
-            .create_indexes(vec![query_project_index, query_user_index])
+            .create_indexes(vec![query_project_index, query_user_index, query_text_index])
             .await
             .map_err(|e| format!("Failed to create query indexes: {}", e))?;
 
@@ -129,11 +229,340 @@ impl DatabaseManager {
                 .build())
             .build();
 
+        let conversation_visibility_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "visibility": 1 })
+            .build();
+
+        let conversation_shared_with_index = IndexModel::builder()
+            .keys(doc! { "shared_with.user_id": 1 })
+            .build();
+
+        let conversation_listing_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "archived": 1, "pinned": -1, "updated_at": -1 })
+            .build();
+
+        let conversation_tags_index = IndexModel::builder()
+            .keys(doc! { "tags": 1 })
+            .build();
+
+        let conversation_folder_index = IndexModel::builder()
+            .keys(doc! { "folder_id": 1 })
+            .build();
+
+        let conversation_text_index = IndexModel::builder()
+            .keys(doc! { "title": "text", "messages.content": "text" })
+            .options(mongodb::options::IndexOptions::builder()
+                .name("conversation_text_search".to_string())
+                .weights(doc! { "title": 5, "messages.content": 1 })
+                .build())
+            .build();
+
         self.conversations_collection()
-            .create_indexes(vec![conversation_project_index, conversation_user_index, conversation_id_index])
+            .create_indexes(vec![
+                conversation_project_index,
+                conversation_user_index,
+                conversation_id_index,
+                conversation_visibility_index,
+                conversation_shared_with_index,
+                conversation_listing_index,
+                conversation_tags_index,
+                conversation_folder_index,
+                conversation_text_index,
+            ])
             .await
             .map_err(|e| format!("Failed to create conversation indexes: {}", e))?;
 
+        // Conversation folder indexes
+        let folder_id_index = IndexModel::builder()
+            .keys(doc! { "folder_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let folder_project_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "name": 1 })
+            .build();
+
+        self.conversation_folders_collection()
+            .create_indexes(vec![folder_id_index, folder_project_index])
+            .await
+            .map_err(|e| format!("Failed to create conversation folder indexes: {}", e))?;
+
+        // Knowledge base indexes
+        let document_id_index = IndexModel::builder()
+            .keys(doc! { "document_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let document_project_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "created_at": -1 })
+            .build();
+
+        self.knowledge_documents_collection()
+            .create_indexes(vec![document_id_index, document_project_index])
+            .await
+            .map_err(|e| format!("Failed to create knowledge document indexes: {}", e))?;
+
+        let chunk_project_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "embedding_model": 1 })
+            .build();
+
+        let chunk_document_index = IndexModel::builder()
+            .keys(doc! { "document_id": 1, "chunk_index": 1 })
+            .build();
+
+        // Retrieval loads the text of its best chunks by id
+        let chunk_id_index = IndexModel::builder()
+            .keys(doc! { "chunk_id": 1 })
+            .build();
+
+        self.document_chunks_collection()
+            .create_indexes(vec![chunk_project_index, chunk_document_index, chunk_id_index])
+            .await
+            .map_err(|e| format!("Failed to create document chunk indexes: {}", e))?;
+
+        // Dataset indexes
+        let dataset_id_index = IndexModel::builder()
+            .keys(doc! { "dataset_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let dataset_name_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "name": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.datasets_collection()
+            .create_indexes(vec![dataset_id_index, dataset_name_index])
+            .await
+            .map_err(|e| format!("Failed to create dataset indexes: {}", e))?;
+
+        let dataset_row_index = IndexModel::builder()
+            .keys(doc! { "dataset_id": 1, "row_index": 1 })
+            .build();
+
+        self.dataset_rows_collection()
+            .create_index(dataset_row_index)
+            .await
+            .map_err(|e| format!("Failed to create dataset row indexes: {}", e))?;
+
+        let dataset_insight_index = IndexModel::builder()
+            .keys(doc! { "dataset_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.dataset_insights_collection()
+            .create_index(dataset_insight_index)
+            .await
+            .map_err(|e| format!("Failed to create dataset insight indexes: {}", e))?;
+
+        // Data quality indexes
+        let quality_rule_id_index = IndexModel::builder()
+            .keys(doc! { "rule_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let quality_rule_table_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "dataset_name": 1 })
+            .build();
+
+        self.quality_rules_collection()
+            .create_indexes(vec![quality_rule_id_index, quality_rule_table_index])
+            .await
+            .map_err(|e| format!("Failed to create quality rule indexes: {}", e))?;
+
+        let quality_run_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "dataset_name": 1, "created_at": -1 })
+            .build();
+
+        self.quality_runs_collection()
+            .create_index(quality_run_index)
+            .await
+            .map_err(|e| format!("Failed to create quality run indexes: {}", e))?;
+
+        // Scheduled report indexes
+        let scheduled_report_id_index = IndexModel::builder()
+            .keys(doc! { "report_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let scheduled_report_project_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1, "created_at": -1 })
+            .build();
+
+        let scheduled_report_due_index = IndexModel::builder()
+            .keys(doc! { "enabled": 1, "next_run_at": 1 })
+            .build();
+
+        self.scheduled_reports_collection()
+            .create_indexes(vec![
+                scheduled_report_id_index,
+                scheduled_report_project_index,
+                scheduled_report_due_index,
+            ])
+            .await
+            .map_err(|e| format!("Failed to create scheduled report indexes: {}", e))?;
+
+        // Notification indexes
+        let notification_id_index = IndexModel::builder()
+            .keys(doc! { "notification_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let notification_user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "read": 1, "created_at": -1 })
+            .build();
+
+        self.notifications_collection()
+            .create_indexes(vec![notification_id_index, notification_user_index])
+            .await
+            .map_err(|e| format!("Failed to create notification indexes: {}", e))?;
+
+        // Project AI settings indexes
+        let ai_settings_project_index = IndexModel::builder()
+            .keys(doc! { "project_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.project_ai_settings_collection()
+            .create_index(ai_settings_project_index)
+            .await
+            .map_err(|e| format!("Failed to create project AI settings indexes: {}", e))?;
+
+        // Prompt template indexes
+        let prompt_version_index = IndexModel::builder()
+            .keys(doc! { "name": 1, "tenant_id": 1, "version": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let prompt_active_index = IndexModel::builder()
+            .keys(doc! { "is_active": 1, "tenant_id": 1 })
+            .build();
+
+        self.prompt_templates_collection()
+            .create_indexes(vec![prompt_version_index, prompt_active_index])
+            .await
+            .map_err(|e| format!("Failed to create prompt template indexes: {}", e))?;
+
+        // Usage record indexes, for monthly totals per tenant and per user
+        let usage_tenant_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "created_at": -1 })
+            .build();
+
+        let usage_user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "created_at": -1 })
+            .build();
+
+        self.usage_records_collection()
+            .create_indexes(vec![usage_tenant_index, usage_user_index])
+            .await
+            .map_err(|e| format!("Failed to create usage record indexes: {}", e))?;
+
+        // Token quota indexes
+        let quota_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "user_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.token_quotas_collection()
+            .create_indexes(vec![quota_index])
+            .await
+            .map_err(|e| format!("Failed to create token quota indexes: {}", e))?;
+
+        // Redaction settings indexes
+        let redaction_tenant_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.redaction_settings_collection()
+            .create_indexes(vec![redaction_tenant_index])
+            .await
+            .map_err(|e| format!("Failed to create redaction settings indexes: {}", e))?;
+
+        // Audit log indexes
+        let audit_tenant_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "created_at": -1 })
+            .build();
+
+        let audit_action_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "action": 1, "created_at": -1 })
+            .build();
+
+        self.audit_logs_collection()
+            .create_indexes(vec![audit_tenant_index, audit_action_index])
+            .await
+            .map_err(|e| format!("Failed to create audit log indexes: {}", e))?;
+
+        // Guardrail indexes
+        let guardrail_policy_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        self.guardrail_policies_collection()
+            .create_indexes(vec![guardrail_policy_index])
+            .await
+            .map_err(|e| format!("Failed to create guardrail policy indexes: {}", e))?;
+
+        let guardrail_event_id_index = IndexModel::builder()
+            .keys(doc! { "event_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let guardrail_event_tenant_index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "reviewed": 1, "created_at": -1 })
+            .build();
+
+        self.guardrail_events_collection()
+            .create_indexes(vec![guardrail_event_id_index, guardrail_event_tenant_index])
+            .await
+            .map_err(|e| format!("Failed to create guardrail event indexes: {}", e))?;
+
+        // Chat attachment indexes
+        let attachment_id_index = IndexModel::builder()
+            .keys(doc! { "attachment_id": 1 })
+            .options(mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build())
+            .build();
+
+        let attachment_conversation_index = IndexModel::builder()
+            .keys(doc! { "conversation_id": 1, "created_at": 1 })
+            .build();
+
+        self.chat_attachments_collection()
+            .create_indexes(vec![attachment_id_index, attachment_conversation_index])
+            .await
+            .map_err(|e| format!("Failed to create chat attachment indexes: {}", e))?;
+
         // Role indexes
         let role_id_index = IndexModel::builder()
             .keys(doc! { "role_id": 1 })
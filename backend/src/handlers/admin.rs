@@ -0,0 +1,556 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use serde::Serialize;
+use validator::Validate;
+use crate::models::{
+    AuditLogQuery, CreatePromptVersionDto, FeedbackReportQuery, GuardrailEventQuery, Permission,
+    PromptScope, PromptScopeQuery, PromptTemplateResponse, RollbackPromptDto, SetTokenQuotaDto,
+    UpdateGuardrailPolicyDto, UpdateRedactionSettingsDto, UsageReportQuery,
+};
+use crate::services::{
+    AIService, AuditService, ChatService, GuardrailService, PromptService, RbacService,
+    RedactionService, UsageService,
+};
+use crate::utils::Claims;
+use crate::middleware::check_permission;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Message feedback aggregated by project, provider and model (admin only)
+pub async fn get_feedback_report(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<FeedbackReportQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Limit the report to projects in the caller's tenant
+    let mut project_ids = match rbac_service
+        .accessible_project_ids(&claims.user_id, &claims.tenant_id, Permission::AdminAccess)
+        .await
+    {
+        Ok(ids) => ids,
+        Err(e) => {
+            log::error!("Failed to resolve tenant projects: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+    if let Some(ref pid) = query.project_id {
+        project_ids.retain(|id| id == pid);
+    }
+
+    match chat_service.feedback_report(&project_ids).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            log::error!("Failed to build feedback report: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Check the caller may manage templates in `scope` and return the tenant the
+/// scope refers to (`None` for global). Tenant templates need `admin:access`,
+/// global ones `system:settings`.
+async fn prompt_scope_tenant(
+    rbac_service: &web::Data<RbacService>,
+    claims: &Claims,
+    scope: Option<PromptScope>,
+) -> Result<Option<String>, HttpResponse> {
+    let (permission, tenant_id) = match scope.unwrap_or(PromptScope::Tenant) {
+        PromptScope::Tenant => (Permission::AdminAccess, Some(claims.tenant_id.clone())),
+        PromptScope::Global => (Permission::SystemSettings, None),
+        PromptScope::Builtin => {
+            return Err(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Built-in templates cannot be changed".to_string(),
+            }));
+        }
+    };
+
+    if let Err(e) = check_permission(rbac_service, &claims.user_id, None, permission).await {
+        return Err(HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() }));
+    }
+
+    Ok(tenant_id)
+}
+
+/// Prompt templates with the version in effect for the caller's tenant
+pub async fn get_prompt_templates(
+    prompt_service: web::Data<PromptService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    let templates: Vec<PromptTemplateResponse> = prompt_service.list_templates(&claims.tenant_id).await;
+    HttpResponse::Ok().json(templates)
+}
+
+/// Version history of a template in the tenant (default) or global scope
+pub async fn get_prompt_versions(
+    prompt_service: web::Data<PromptService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PromptScopeQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let tenant_id = match prompt_scope_tenant(&rbac_service, &claims, query.scope).await {
+        Ok(t) => t,
+        Err(response) => return response,
+    };
+
+    match prompt_service.get_versions(&path.into_inner(), tenant_id.as_deref()).await {
+        Ok(versions) => HttpResponse::Ok().json(versions),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Save a new template version and make it active
+pub async fn create_prompt_version(
+    prompt_service: web::Data<PromptService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<CreatePromptVersionDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let tenant_id = match prompt_scope_tenant(&rbac_service, &claims, dto.scope).await {
+        Ok(t) => t,
+        Err(response) => return response,
+    };
+
+    match prompt_service
+        .create_version(&path.into_inner(), tenant_id.as_deref(), &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(template) => HttpResponse::Created().json(PromptTemplateResponse::from(template)),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Reactivate an earlier version; version 0 drops the override for the scope
+pub async fn rollback_prompt(
+    prompt_service: web::Data<PromptService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<RollbackPromptDto>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let tenant_id = match prompt_scope_tenant(&rbac_service, &claims, dto.scope).await {
+        Ok(t) => t,
+        Err(response) => return response,
+    };
+
+    match prompt_service
+        .rollback(&path.into_inner(), tenant_id.as_deref(), dto.version)
+        .await
+    {
+        Ok(template) => HttpResponse::Ok().json(template),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Token usage in the caller's tenant for a month, by project, user, provider and model
+pub async fn get_usage_report(
+    usage_service: web::Data<UsageService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<UsageReportQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match usage_service.usage_report(&claims.tenant_id, &query).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            log::error!("Failed to build usage report: {}", e);
+            HttpResponse::BadRequest().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Monthly token quotas for the caller's tenant and its users
+pub async fn get_token_quotas(
+    usage_service: web::Data<UsageService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match usage_service.get_quotas(&claims.tenant_id).await {
+        Ok(quotas) => HttpResponse::Ok().json(quotas),
+        Err(e) => {
+            log::error!("Failed to load token quotas: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Set a user's quota (`admin:access`) or the tenant quota (`system:settings`)
+pub async fn set_token_quota(
+    usage_service: web::Data<UsageService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<SetTokenQuotaDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    // Tenant admins may share out their tenant's budget but not raise it
+    let permission = if dto.user_id.is_some() {
+        Permission::AdminAccess
+    } else {
+        Permission::SystemSettings
+    };
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, permission).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match usage_service
+        .set_quota(&claims.tenant_id, &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(quota) => HttpResponse::Ok().json(quota),
+        Err(e) if e == "User not found" => HttpResponse::NotFound().json(ErrorResponse { error: e }),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Circuit breaker state of each AI provider endpoint (requires `system:settings`)
+pub async fn get_ai_breakers(
+    ai_service: web::Data<AIService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::SystemSettings).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    HttpResponse::Ok().json(ai_service.breaker_statuses())
+}
+
+/// The tenant's PII redaction settings (requires `admin:access`)
+pub async fn get_redaction_settings(
+    redaction_service: web::Data<RedactionService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match redaction_service.get_settings(&claims.tenant_id).await {
+        Ok(settings) => HttpResponse::Ok().json(settings),
+        Err(e) => {
+            log::error!("Failed to load redaction settings: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Replace the tenant's PII redaction settings (requires `admin:access`)
+pub async fn update_redaction_settings(
+    redaction_service: web::Data<RedactionService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<UpdateRedactionSettingsDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match redaction_service
+        .update_settings(&claims.tenant_id, &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(settings) => HttpResponse::Ok().json(settings),
+        Err(e) if e.starts_with("Failed to") => {
+            log::error!("Failed to update redaction settings: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// The tenant's audit trail, newest first (requires `admin:access`)
+pub async fn get_audit_log(
+    audit_service: web::Data<AuditService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<AuditLogQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match audit_service.list(&claims.tenant_id, &query).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => {
+            log::error!("Failed to load audit log: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// The tenant's prompt-injection guardrail policy (requires `admin:access`)
+pub async fn get_guardrail_policy(
+    guardrail_service: web::Data<GuardrailService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match guardrail_service.get_policy(&claims.tenant_id).await {
+        Ok(policy) => HttpResponse::Ok().json(policy),
+        Err(e) => {
+            log::error!("Failed to load guardrail policy: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Replace the tenant's guardrail policy (requires `admin:access`)
+pub async fn update_guardrail_policy(
+    guardrail_service: web::Data<GuardrailService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<UpdateGuardrailPolicyDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match guardrail_service
+        .update_policy(&claims.tenant_id, &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(policy) => HttpResponse::Ok().json(policy),
+        Err(e) if e.starts_with("Failed to") => {
+            log::error!("Failed to update guardrail policy: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Guardrail matches in the tenant's projects, newest first (requires `admin:access`)
+pub async fn get_guardrail_events(
+    guardrail_service: web::Data<GuardrailService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<GuardrailEventQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match guardrail_service.list_events(&claims.tenant_id, &query).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            log::error!("Failed to load guardrail events: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Mark a guardrail event as reviewed (requires `admin:access`)
+pub async fn review_guardrail_event(
+    guardrail_service: web::Data<GuardrailService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(&rbac_service, &claims.user_id, None, Permission::AdminAccess).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    let event_id = path.into_inner();
+    match guardrail_service
+        .review_event(&claims.tenant_id, &event_id, &claims.user_id)
+        .await
+    {
+        Ok(event) => HttpResponse::Ok().json(event),
+        Err(e) if e == "Guardrail event not found" => {
+            HttpResponse::NotFound().json(ErrorResponse { error: e })
+        }
+        Err(e) => {
+            log::error!("Failed to review guardrail event: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
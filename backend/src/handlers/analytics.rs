@@ -3,6 +3,9 @@ use serde::Serialize;
 use validator::Validate;
 use crate::models::{CreateQueryDto, Permission};
 use crate::services::{AnalyticsService, RbacService};
+use crate::services::guardrails::{GUARDRAIL_BLOCKED, GUARDRAIL_BLOCKED_MESSAGE};
+use crate::services::llm::resilience::PROVIDER_UNAVAILABLE;
+use crate::services::usage::TOKEN_QUOTA_EXCEEDED;
 use crate::utils::Claims;
 use crate::middleware::check_permission;
 
@@ -11,6 +14,20 @@ struct ErrorResponse {
     error: String,
 }
 
+/// Body of a guardrail block: a readable `error` with the stable `code`
+#[derive(Debug, Serialize)]
+struct CodedErrorResponse {
+    error: &'static str,
+    code: &'static str,
+}
+
+fn guardrail_blocked() -> HttpResponse {
+    HttpResponse::BadRequest().json(CodedErrorResponse {
+        error: GUARDRAIL_BLOCKED_MESSAGE,
+        code: GUARDRAIL_BLOCKED,
+    })
+}
+
 pub async fn create_query(
     analytics_service: web::Data<AnalyticsService>,
     rbac_service: web::Data<RbacService>,
@@ -102,10 +119,18 @@ pub async fn process_query(
     }
 
     match analytics_service.process_query(&query_uuid).await {
-        Ok(response) => HttpResponse::Ok().json(serde_json::json!({
+        Ok((response, cached)) => HttpResponse::Ok().json(serde_json::json!({
             "query_id": query_id.to_string(),
             "response": response,
+            "cached": cached,
         })),
+        Err(e) if e == TOKEN_QUOTA_EXCEEDED => {
+            HttpResponse::TooManyRequests().json(ErrorResponse { error: e })
+        }
+        Err(e) if e == PROVIDER_UNAVAILABLE => {
+            HttpResponse::ServiceUnavailable().json(ErrorResponse { error: e })
+        }
+        Err(e) if e == GUARDRAIL_BLOCKED => guardrail_blocked(),
         Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { error: e }),
     }
 }
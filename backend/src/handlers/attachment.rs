@@ -0,0 +1,318 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use serde::Serialize;
+use validator::Validate;
+use uuid::Uuid;
+use crate::models::{
+    AttachmentFormat, AttachmentResponse, ChatAttachment, DatasetResponse, Permission,
+    PromoteAttachmentDto, UploadAttachmentQuery,
+};
+use crate::services::{AttachmentService, ChatService, RbacService};
+use crate::services::chat::READ_ONLY_CONVERSATION;
+use crate::utils::Claims;
+use crate::middleware::check_permission;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Content-Type an attachment is downloaded with. HTML is sent as plain text
+/// so it is never rendered from the API's origin.
+fn download_content_type(format: AttachmentFormat) -> &'static str {
+    match format {
+        AttachmentFormat::Csv => "text/csv; charset=utf-8",
+        AttachmentFormat::Markdown => "text/markdown; charset=utf-8",
+        AttachmentFormat::Pdf => "application/pdf",
+        AttachmentFormat::Text | AttachmentFormat::Html => "text/plain; charset=utf-8",
+    }
+}
+
+/// Load an attachment the user may see: one in a conversation they can read,
+/// or their own upload that is not attached to a conversation yet.
+async fn readable_attachment(
+    attachment_service: &AttachmentService,
+    chat_service: &ChatService,
+    rbac_service: &web::Data<RbacService>,
+    claims: &Claims,
+    attachment_id: &str,
+) -> Result<ChatAttachment, HttpResponse> {
+    let not_found = || {
+        HttpResponse::NotFound().json(ErrorResponse {
+            error: "Attachment not found".to_string(),
+        })
+    };
+
+    let attachment = match attachment_service.get(attachment_id).await {
+        Ok(Some(a)) => a,
+        Ok(None) => return Err(not_found()),
+        Err(e) => {
+            log::error!("Failed to get attachment: {}", e);
+            return Err(HttpResponse::InternalServerError().json(ErrorResponse { error: e }));
+        }
+    };
+
+    if let Err(e) = check_permission(
+        rbac_service,
+        &claims.user_id,
+        Some(&attachment.project_id),
+        Permission::ChatRead
+    ).await {
+        return Err(HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() }));
+    }
+
+    let conversation_id = match attachment.conversation_id.as_deref() {
+        Some(id) => id,
+        None if attachment.uploaded_by == claims.user_id => return Ok(attachment),
+        None => return Err(not_found()),
+    };
+
+    let (Ok(conversation_id), Ok(user_id)) = (Uuid::parse_str(conversation_id), Uuid::parse_str(&claims.user_id)) else {
+        return Err(not_found());
+    };
+    match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(_)) => Ok(attachment),
+        Ok(None) => Err(not_found()),
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            Err(HttpResponse::InternalServerError().json(ErrorResponse { error: e }))
+        }
+    }
+}
+
+/// Upload a file to attach to chat messages.
+/// The file is the raw request body; its name is passed as `?filename=`.
+pub async fn upload_attachment(
+    attachment_service: web::Data<AttachmentService>,
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<UploadAttachmentQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid user_id".to_string(),
+            });
+        }
+    };
+
+    let query = query.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&query.project_id),
+        Permission::ChatWrite
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Uploading straight into a conversation needs the same access as writing to it
+    if let Some(ref conv_id) = query.conversation_id {
+        let conversation_id = match Uuid::parse_str(conv_id) {
+            Ok(id) => id,
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "Invalid conversation_id format".to_string(),
+                });
+            }
+        };
+        match chat_service.get_conversation(&conversation_id, &user_id).await {
+            Ok(Some(conv)) if conv.project_id.to_string() == query.project_id => {
+                if !chat_service.access_for(&conv, &user_id).await.is_some_and(|a| a.can_write()) {
+                    return HttpResponse::Forbidden().json(ErrorResponse {
+                        error: READ_ONLY_CONVERSATION.to_string(),
+                    });
+                }
+            }
+            Ok(_) => {
+                return HttpResponse::NotFound().json(ErrorResponse {
+                    error: "Conversation not found".to_string(),
+                });
+            }
+            Err(e) => {
+                log::error!("Failed to get conversation: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+            }
+        }
+    }
+
+    // Keep only the base name of whatever path the client sent
+    let filename = query
+        .filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if filename.is_empty() || filename.chars().count() > 255 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "filename must be between 1 and 255 characters".to_string(),
+        });
+    }
+
+    if body.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Attachment body is empty".to_string(),
+        });
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let format = match AttachmentFormat::detect(&filename, content_type) {
+        Some(f) => f,
+        None => {
+            return HttpResponse::UnsupportedMediaType().json(ErrorResponse {
+                error: "Supported formats are CSV, text, Markdown, PDF and HTML".to_string(),
+            });
+        }
+    };
+
+    match attachment_service
+        .upload(
+            &query.project_id,
+            query.conversation_id.as_deref(),
+            &claims.user_id,
+            &filename,
+            format,
+            body.to_vec(),
+        )
+        .await
+    {
+        Ok(attachment) => HttpResponse::Created().json(AttachmentResponse::from(attachment)),
+        Err(e) => {
+            log::error!("Failed to upload attachment: {}", e);
+            HttpResponse::BadRequest().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Get an attachment with its summary
+pub async fn get_attachment(
+    attachment_service: web::Data<AttachmentService>,
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let attachment_id = path.into_inner();
+    match readable_attachment(&attachment_service, &chat_service, &rbac_service, &claims, &attachment_id).await {
+        Ok(attachment) => HttpResponse::Ok().json(AttachmentResponse::from(attachment)),
+        Err(response) => response,
+    }
+}
+
+/// Download the uploaded file
+pub async fn download_attachment(
+    attachment_service: web::Data<AttachmentService>,
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let attachment_id = path.into_inner();
+    let attachment =
+        match readable_attachment(&attachment_service, &chat_service, &rbac_service, &claims, &attachment_id).await {
+            Ok(a) => a,
+            Err(response) => return response,
+        };
+
+    match attachment_service.content(&attachment).await {
+        Ok(data) => HttpResponse::Ok()
+            .content_type(download_content_type(attachment.format))
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(attachment.filename)],
+            })
+            .body(data),
+        Err(e) => {
+            log::error!("Failed to read attachment: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Add a CSV attachment to the project's datasets
+pub async fn promote_attachment(
+    attachment_service: web::Data<AttachmentService>,
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<PromoteAttachmentDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let attachment_id = path.into_inner();
+    let attachment =
+        match readable_attachment(&attachment_service, &chat_service, &rbac_service, &claims, &attachment_id).await {
+            Ok(a) => a,
+            Err(response) => return response,
+        };
+
+    // Same permission as uploading a dataset directly
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&attachment.project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match attachment_service
+        .promote(&attachment, &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(dataset) => HttpResponse::Created().json(DatasetResponse::from(dataset)),
+        Err(e) => {
+            log::error!("Failed to add attachment as dataset: {}", e);
+            HttpResponse::BadRequest().json(ErrorResponse { error: e })
+        }
+    }
+}
@@ -4,8 +4,18 @@ use serde::{Serialize, Deserialize};
 use validator::Validate;
 use uuid::Uuid;
 use futures::StreamExt;
-use crate::models::{SendMessageDto, ChatResponse, ConversationResponse, Permission};
+use crate::models::{
+    SendMessageDto, ChatResponse, ConversationResponse, Permission, UpdateConversationSharingDto,
+    UpdateConversationDto, ConversationSummaryQuery, ConversationFolderDto, ConversationFolderResponse,
+    MessageFeedbackDto, ChatMessageResponse,
+};
 use crate::services::{ChatService, RbacService};
+use crate::services::llm::ChatStreamEvent;
+use crate::services::llm::resilience::PROVIDER_UNAVAILABLE;
+use crate::services::chat::{StreamedReply, OWNER_ONLY_FLAGS, READ_ONLY_CONVERSATION};
+use crate::services::attachments::ATTACHMENT_NOT_FOUND;
+use crate::services::guardrails::{GUARDRAIL_BLOCKED, GUARDRAIL_BLOCKED_MESSAGE};
+use crate::services::usage::TOKEN_QUOTA_EXCEEDED;
 use crate::utils::Claims;
 use crate::middleware::check_permission;
 
@@ -14,6 +24,20 @@ struct ErrorResponse {
     error: String,
 }
 
+/// Body of a guardrail block: a readable `error` with the stable `code`
+#[derive(Debug, Serialize)]
+struct CodedErrorResponse {
+    error: &'static str,
+    code: &'static str,
+}
+
+fn guardrail_blocked() -> HttpResponse {
+    HttpResponse::BadRequest().json(CodedErrorResponse {
+        error: GUARDRAIL_BLOCKED_MESSAGE,
+        code: GUARDRAIL_BLOCKED,
+    })
+}
+
 /// SSE event wrapper for streaming responses
 #[derive(Debug, Serialize)]
 struct StreamEvent {
@@ -28,6 +52,9 @@ pub struct StreamMessageDto {
     pub message: String,
     pub project_id: String,
     pub conversation_id: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 5))]
+    pub attachment_ids: Vec<String>,
 }
 
 /// Request DTO for saving the assistant response after streaming
@@ -136,6 +163,7 @@ pub async fn send_message(
             project_id,
             dto.message.clone(),
             conversation_id,
+            dto.attachment_ids.clone(),
         )
         .await
     {
@@ -143,6 +171,19 @@ pub async fn send_message(
             conversation_id: conv_id,
             message,
         }),
+        Err(e) if e == TOKEN_QUOTA_EXCEEDED => {
+            HttpResponse::TooManyRequests().json(ErrorResponse { error: e })
+        }
+        Err(e) if e == PROVIDER_UNAVAILABLE => {
+            HttpResponse::ServiceUnavailable().json(ErrorResponse { error: e })
+        }
+        Err(e) if e == READ_ONLY_CONVERSATION => {
+            HttpResponse::Forbidden().json(ErrorResponse { error: e })
+        }
+        Err(e) if e == GUARDRAIL_BLOCKED => guardrail_blocked(),
+        Err(e) if e == ATTACHMENT_NOT_FOUND => {
+            HttpResponse::NotFound().json(ErrorResponse { error: e })
+        }
         Err(e) => {
             log::error!("Failed to process chat message: {}", e);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -201,7 +242,9 @@ pub async fn get_conversation(
                 return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
             }
             
-            let response: ConversationResponse = conversation.into();
+            let access = chat_service.access_for(&conversation, &user_id).await.unwrap_or_default();
+            let mut response: ConversationResponse = conversation.into();
+            response.access = access;
             HttpResponse::Ok().json(response)
         }
         Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
@@ -278,6 +321,13 @@ pub async fn delete_conversation(
         return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
     }
 
+    // Shared viewers and collaborators cannot delete someone else's conversation
+    if conversation.user_id != user_id {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Only the conversation owner can delete it".to_string(),
+        });
+    }
+
     // Delete the conversation
     match chat_service.delete_conversation(&conversation_id, &user_id).await {
         Ok(true) => HttpResponse::Ok().json(serde_json::json!({
@@ -295,12 +345,21 @@ pub async fn delete_conversation(
     }
 }
 
-pub async fn get_project_conversations(
+/// Change a conversation's visibility and explicit shares (owner only)
+pub async fn update_conversation_sharing(
     chat_service: web::Data<ChatService>,
     rbac_service: web::Data<RbacService>,
     req: HttpRequest,
     path: web::Path<String>,
+    dto: web::Json<UpdateConversationSharingDto>,
 ) -> HttpResponse {
+    // Validate input
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
     // Get user from JWT claims
     let claims = match req.extensions().get::<Claims>() {
         Some(c) => c.clone(),
@@ -321,50 +380,92 @@ pub async fn get_project_conversations(
         }
     };
 
-    let project_id_str = path.into_inner();
+    // Parse conversation_id
+    let conversation_id = match Uuid::parse_str(&path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid conversation_id format".to_string(),
+            });
+        }
+    };
 
-    // Check permission to read chat in this project
+    let conversation = match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(conv)) => conv,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Conversation not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversation: {}", e),
+            });
+        }
+    };
+
+    let project_id_str = conversation.project_id.to_string();
+
+    // Sharing is a write operation on the project's chat
     if let Err(e) = check_permission(
         &rbac_service,
         &claims.user_id,
         Some(&project_id_str),
-        Permission::ChatRead
+        Permission::ChatWrite
     ).await {
         return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
     }
 
-    // Parse project_id
-    let project_id = match Uuid::parse_str(&project_id_str) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ErrorResponse {
-                error: "Invalid project_id format".to_string(),
-            });
+    if conversation.user_id != user_id {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Only the conversation owner can change sharing".to_string(),
+        });
+    }
+
+    // Every explicit recipient must be able to read chat in this project
+    if let Some(ref shares) = dto.shared_with {
+        for share in shares {
+            let can_read = rbac_service
+                .resolve_permissions(&share.user_id, Some(&project_id_str))
+                .await
+                .map(|p| p.has_permission(Permission::ChatRead))
+                .unwrap_or(false);
+            if !can_read {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: format!("User {} does not have chat access in this project", share.user_id),
+                });
+            }
         }
-    };
+    }
 
-    // Get conversations
     match chat_service
-        .get_project_conversations(&project_id, &user_id)
+        .update_sharing(&conversation_id, &user_id, dto.into_inner())
         .await
     {
-        Ok(conversations) => HttpResponse::Ok().json(conversations),
-        Err(e) => {
-            log::error!("Failed to get project conversations: {}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: format!("Failed to get conversations: {}", e),
-            })
+        Ok(conversation) => {
+            let response: ConversationResponse = conversation.into();
+            HttpResponse::Ok().json(response)
         }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
     }
 }
 
-/// Get lightweight conversation summaries for a project (without message content)
-pub async fn get_project_conversation_summaries(
+/// Rename, pin, archive, tag or file a conversation
+pub async fn update_conversation(
     chat_service: web::Data<ChatService>,
     rbac_service: web::Data<RbacService>,
     req: HttpRequest,
     path: web::Path<String>,
+    dto: web::Json<UpdateConversationDto>,
 ) -> HttpResponse {
+    // Validate input
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
     // Get user from JWT claims
     let claims = match req.extensions().get::<Claims>() {
         Some(c) => c.clone(),
@@ -385,58 +486,72 @@ pub async fn get_project_conversation_summaries(
         }
     };
 
-    let project_id_str = path.into_inner();
+    // Parse conversation_id
+    let conversation_id = match Uuid::parse_str(&path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid conversation_id format".to_string(),
+            });
+        }
+    };
+
+    // Get conversation first to check project_id for permission
+    let conversation = match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(conv)) => conv,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Conversation not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversation: {}", e),
+            });
+        }
+    };
 
-    // Check permission to read chat in this project
     if let Err(e) = check_permission(
         &rbac_service,
         &claims.user_id,
-        Some(&project_id_str),
-        Permission::ChatRead
+        Some(&conversation.project_id.to_string()),
+        Permission::ChatWrite
     ).await {
         return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
     }
 
-    // Parse project_id
-    let project_id = match Uuid::parse_str(&project_id_str) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ErrorResponse {
-                error: "Invalid project_id format".to_string(),
-            });
-        }
-    };
-
-    // Get conversation summaries
     match chat_service
-        .get_project_conversation_summaries(&project_id, &user_id)
+        .update_conversation(&conversation_id, &user_id, dto.into_inner())
         .await
     {
-        Ok(summaries) => HttpResponse::Ok().json(summaries),
-        Err(e) => {
-            log::error!("Failed to get project conversation summaries: {}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: format!("Failed to get conversations: {}", e),
-            })
+        Ok(conversation) => {
+            let access = chat_service.access_for(&conversation, &user_id).await.unwrap_or_default();
+            let mut response: ConversationResponse = conversation.into();
+            response.access = access;
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) if e == READ_ONLY_CONVERSATION || e == OWNER_ONLY_FLAGS => {
+            HttpResponse::Forbidden().json(ErrorResponse { error: e })
         }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
     }
 }
 
-/// Streaming chat endpoint - returns Server-Sent Events (SSE)
-pub async fn stream_message(
+/// Create a conversation folder in a project
+pub async fn create_folder(
     chat_service: web::Data<ChatService>,
     rbac_service: web::Data<RbacService>,
     req: HttpRequest,
-    dto: web::Json<StreamMessageDto>,
+    path: web::Path<String>,
+    dto: web::Json<ConversationFolderDto>,
 ) -> HttpResponse {
-    // Validate input
     if let Err(e) = dto.validate() {
         return HttpResponse::BadRequest().json(ErrorResponse {
             error: format!("Validation error: {}", e),
         });
     }
 
-    // Get user from JWT claims
     let claims = match req.extensions().get::<Claims>() {
         Some(c) => c.clone(),
         None => {
@@ -446,7 +561,6 @@ pub async fn stream_message(
         }
     };
 
-    // Parse user_id from claims
     let user_id = match Uuid::parse_str(&claims.user_id) {
         Ok(id) => id,
         Err(_) => {
@@ -456,18 +570,18 @@ pub async fn stream_message(
         }
     };
 
-    // Check permission
+    let project_id_str = path.into_inner();
+
     if let Err(e) = check_permission(
         &rbac_service,
         &claims.user_id,
-        Some(&dto.project_id),
+        Some(&project_id_str),
         Permission::ChatWrite
     ).await {
         return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
     }
 
-    // Parse project_id
-    let project_id = match Uuid::parse_str(&dto.project_id) {
+    let project_id = match Uuid::parse_str(&project_id_str) {
         Ok(id) => id,
         Err(_) => {
             return HttpResponse::BadRequest().json(ErrorResponse {
@@ -476,104 +590,81 @@ pub async fn stream_message(
         }
     };
 
-    // Check rate limit
-    match chat_service.check_rate_limit(&user_id).await {
-        Ok(true) => {}
-        Ok(false) => {
-            return HttpResponse::TooManyRequests().json(ErrorResponse {
-                error: "Rate limit exceeded. Please wait before sending more messages.".to_string(),
-            });
+    match chat_service.create_folder(&project_id, &user_id, dto.into_inner().name).await {
+        Ok(folder) => {
+            let response: ConversationFolderResponse = folder.into();
+            HttpResponse::Created().json(response)
         }
         Err(e) => {
-            log::error!("Rate limit check failed: {}", e);
-            return HttpResponse::ServiceUnavailable().json(ErrorResponse {
-                error: "Rate limiting service temporarily unavailable.".to_string(),
-            });
+            log::error!("Failed to create folder: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
         }
     }
+}
 
-    // Parse conversation_id if provided
-    let conversation_id = if let Some(ref conv_id_str) = dto.conversation_id {
-        match Uuid::parse_str(conv_id_str) {
-            Ok(id) => Some(id),
-            Err(_) => {
-                return HttpResponse::BadRequest().json(ErrorResponse {
-                    error: "Invalid conversation_id format".to_string(),
-                });
-            }
-        }
-    } else {
-        None
-    };
-
-    // Get streaming response
-    let (conv_id, stream) = match chat_service
-        .stream_message(user_id, project_id, dto.message.clone(), conversation_id)
-        .await
-    {
-        Ok(result) => result,
-        Err(e) => {
-            log::error!("Failed to start streaming: {}", e);
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                error: format!("Failed to start streaming: {}", e),
+/// List the conversation folders of a project
+pub async fn get_project_folders(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
             });
         }
     };
 
-    // Create the SSE response stream
-    let conv_id_clone = conv_id.clone();
-    let response_stream = async_stream::stream! {
-        // Send conversation_id as first event
-        let init_event = format!("event: init\ndata: {}\n\n", serde_json::json!({
-            "conversation_id": conv_id_clone
-        }));
-        yield Ok::<_, actix_web::error::Error>(web::Bytes::from(init_event));
+    let project_id_str = path.into_inner();
 
-        // Stream the AI response chunks
-        let mut pinned_stream = stream;
-        while let Some(chunk_result) = pinned_stream.next().await {
-            match chunk_result {
-                Ok(bytes) => {
-                    // Forward the SSE data directly from the RAG API
-                    yield Ok(web::Bytes::from(bytes.to_vec()));
-                }
-                Err(e) => {
-                    log::error!("Stream error: {}", e);
-                    let error_event = format!("event: error\ndata: {}\n\n", serde_json::json!({
-                        "error": e.to_string()
-                    }));
-                    yield Ok(web::Bytes::from(error_event));
-                    break;
-                }
-            }
-        }
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ChatRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
 
-        // Send done event
-        let done_event = "event: done\ndata: {}\n\n".to_string();
-        yield Ok(web::Bytes::from(done_event));
+    let project_id = match Uuid::parse_str(&project_id_str) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid project_id format".to_string(),
+            });
+        }
     };
 
-    HttpResponse::Ok()
-        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
-        .insert_header((header::CACHE_CONTROL, "no-cache"))
-        .insert_header(("X-Accel-Buffering", "no"))
-        .streaming(response_stream)
+    match chat_service.get_project_folders(&project_id).await {
+        Ok(folders) => {
+            let responses: Vec<ConversationFolderResponse> =
+                folders.into_iter().map(|f| f.into()).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get folders: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
 }
 
-/// Save the assistant response after streaming is complete
-pub async fn save_streamed_response(
+/// Rename a conversation folder
+pub async fn rename_folder(
     chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
     req: HttpRequest,
-    dto: web::Json<SaveStreamedResponseDto>,
+    path: web::Path<String>,
+    dto: web::Json<ConversationFolderDto>,
 ) -> HttpResponse {
-    // Validate input
     if let Err(e) = dto.validate() {
         return HttpResponse::BadRequest().json(ErrorResponse {
             error: format!("Validation error: {}", e),
         });
     }
 
-    // Get user from JWT claims
     let claims = match req.extensions().get::<Claims>() {
         Some(c) => c.clone(),
         None => {
@@ -583,49 +674,229 @@ pub async fn save_streamed_response(
         }
     };
 
-    // Parse user_id from claims
-    let user_id = match Uuid::parse_str(&claims.user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ErrorResponse {
-                error: "Invalid user_id".to_string(),
+    let folder_id = path.into_inner();
+
+    let folder = match chat_service.get_folder(&folder_id).await {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Folder not found".to_string(),
             });
         }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
     };
 
-    // Parse conversation_id
-    let conversation_id = match Uuid::parse_str(&dto.conversation_id) {
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&folder.project_id),
+        Permission::ChatWrite
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match chat_service.rename_folder(&folder_id, dto.into_inner().name).await {
+        Ok(folder) => {
+            let response: ConversationFolderResponse = folder.into();
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) => {
+            log::error!("Failed to rename folder: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Delete a conversation folder; its conversations become unfiled
+pub async fn delete_folder(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let folder_id = path.into_inner();
+
+    let folder = match chat_service.get_folder(&folder_id).await {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Folder not found".to_string(),
+            });
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&folder.project_id),
+        Permission::ChatDelete
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match chat_service.delete_folder(&folder_id).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true
+        })),
+        Err(e) => {
+            log::error!("Failed to delete folder: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+pub async fn get_project_conversations(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    // Get user from JWT claims
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    // Parse user_id from claims
+    let user_id = match Uuid::parse_str(&claims.user_id) {
         Ok(id) => id,
         Err(_) => {
             return HttpResponse::BadRequest().json(ErrorResponse {
-                error: "Invalid conversation_id format".to_string(),
+                error: "Invalid user_id".to_string(),
             });
         }
     };
 
-    // Save the assistant message
+    let project_id_str = path.into_inner();
+
+    // Check permission to read chat in this project
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ChatRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Parse project_id
+    let project_id = match Uuid::parse_str(&project_id_str) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid project_id format".to_string(),
+            });
+        }
+    };
+
+    // Get conversations
     match chat_service
-        .append_assistant_message(&conversation_id, &user_id, dto.content.clone())
+        .get_project_conversations(&project_id, &user_id)
         .await
     {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "success": true
-        })),
+        Ok(conversations) => HttpResponse::Ok().json(conversations),
         Err(e) => {
-            log::error!("Failed to save streamed response: {}", e);
+            log::error!("Failed to get project conversations: {}", e);
             HttpResponse::InternalServerError().json(ErrorResponse {
-                error: format!("Failed to save response: {}", e),
+                error: format!("Failed to get conversations: {}", e),
             })
         }
     }
 }
 
-/// Regenerate a response from a specific message index
-/// This removes messages from the index onwards and regenerates
-pub async fn regenerate_message_stream(
+/// Get lightweight conversation summaries for a project (without message content)
+pub async fn get_project_conversation_summaries(
     chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
     req: HttpRequest,
-    dto: web::Json<RegenerateMessageDto>,
+    path: web::Path<String>,
+    query: web::Query<ConversationSummaryQuery>,
+) -> HttpResponse {
+    // Get user from JWT claims
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    // Parse user_id from claims
+    let user_id = match Uuid::parse_str(&claims.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid user_id".to_string(),
+            });
+        }
+    };
+
+    let project_id_str = path.into_inner();
+
+    // Check permission to read chat in this project
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ChatRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Parse project_id
+    let project_id = match Uuid::parse_str(&project_id_str) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid project_id format".to_string(),
+            });
+        }
+    };
+
+    if let Err(e) = query.sort_spec() {
+        return HttpResponse::BadRequest().json(ErrorResponse { error: e });
+    }
+
+    // Get conversation summaries
+    match chat_service
+        .get_project_conversation_summaries(&project_id, &user_id, &query)
+        .await
+    {
+        Ok(summaries) => HttpResponse::Ok().json(summaries),
+        Err(e) => {
+            log::error!("Failed to get project conversation summaries: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversations: {}", e),
+            })
+        }
+    }
+}
+
+/// Streaming chat endpoint - returns Server-Sent Events (SSE)
+pub async fn stream_message(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<StreamMessageDto>,
 ) -> HttpResponse {
     // Validate input
     if let Err(e) = dto.validate() {
@@ -654,58 +925,180 @@ pub async fn regenerate_message_stream(
         }
     };
 
-    // Parse conversation_id
-    let conversation_id = match Uuid::parse_str(&dto.conversation_id) {
+    // Check permission
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&dto.project_id),
+        Permission::ChatWrite
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Parse project_id
+    let project_id = match Uuid::parse_str(&dto.project_id) {
         Ok(id) => id,
         Err(_) => {
             return HttpResponse::BadRequest().json(ErrorResponse {
-                error: "Invalid conversation_id format".to_string(),
+                error: "Invalid project_id format".to_string(),
             });
         }
     };
 
-    // Get streaming response with regeneration
-    let (conv_id, stream) = match chat_service
-        .regenerate_from_index(user_id, conversation_id, dto.from_index)
+    // Check rate limit
+    match chat_service.check_rate_limit(&user_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::TooManyRequests().json(ErrorResponse {
+                error: "Rate limit exceeded. Please wait before sending more messages.".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Rate limit check failed: {}", e);
+            return HttpResponse::ServiceUnavailable().json(ErrorResponse {
+                error: "Rate limiting service temporarily unavailable.".to_string(),
+            });
+        }
+    }
+
+    // Parse conversation_id if provided
+    let conversation_id = if let Some(ref conv_id_str) = dto.conversation_id {
+        match Uuid::parse_str(conv_id_str) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "Invalid conversation_id format".to_string(),
+                });
+            }
+        }
+    } else {
+        None
+    };
+
+    // Get streaming response
+    let reply = match chat_service
+        .stream_message(user_id, project_id, dto.message.clone(), conversation_id, dto.attachment_ids.clone())
         .await
     {
         Ok(result) => result,
+        Err(e) if e == TOKEN_QUOTA_EXCEEDED => {
+            return HttpResponse::TooManyRequests().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == PROVIDER_UNAVAILABLE => {
+            return HttpResponse::ServiceUnavailable().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == READ_ONLY_CONVERSATION => {
+            return HttpResponse::Forbidden().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == GUARDRAIL_BLOCKED => return guardrail_blocked(),
+        Err(e) if e == ATTACHMENT_NOT_FOUND => {
+            return HttpResponse::NotFound().json(ErrorResponse { error: e });
+        }
         Err(e) => {
-            log::error!("Failed to start regeneration: {}", e);
+            log::error!("Failed to start streaming: {}", e);
             return HttpResponse::InternalServerError().json(ErrorResponse {
-                error: format!("Failed to start regeneration: {}", e),
+                error: format!("Failed to start streaming: {}", e),
             });
         }
     };
 
-    // Create the SSE response stream
-    let conv_id_clone = conv_id.clone();
+    stream_events(chat_service, user_id, reply)
+}
+
+/// Relay a chat event stream to the client as SSE and persist the reply when it ends.
+/// Text arrives as `data: {"content": ...}` and citations as a separate `sources` event.
+/// Tool use is reported as `tool_call` and `tool_result` events.
+fn stream_events(
+    chat_service: web::Data<ChatService>,
+    user_id: Uuid,
+    reply: StreamedReply,
+) -> HttpResponse {
+    let StreamedReply { conversation_id: conv_id, stream, prompt_versions } = reply;
     let response_stream = async_stream::stream! {
         // Send conversation_id as first event
         let init_event = format!("event: init\ndata: {}\n\n", serde_json::json!({
-            "conversation_id": conv_id_clone
+            "conversation_id": conv_id
         }));
         yield Ok::<_, actix_web::error::Error>(web::Bytes::from(init_event));
 
+        let mut content = String::new();
+        let mut citations = Vec::new();
+        let mut tool_calls = Vec::new();
+        let mut failed = false;
+
         // Stream the AI response chunks
         let mut pinned_stream = stream;
-        while let Some(chunk_result) = pinned_stream.next().await {
-            match chunk_result {
-                Ok(bytes) => {
-                    // Forward the SSE data directly from the RAG API
-                    yield Ok(web::Bytes::from(bytes.to_vec()));
+        while let Some(event) = pinned_stream.next().await {
+            match event {
+                Ok(ChatStreamEvent::Content(text)) => {
+                    content.push_str(&text);
+                    let data = format!("data: {}\n\n", serde_json::json!({ "content": text }));
+                    yield Ok(web::Bytes::from(data));
+                }
+                Ok(ChatStreamEvent::Sources(sources)) => {
+                    let data = format!("event: sources\ndata: {}\n\n", serde_json::json!({
+                        "sources": &sources
+                    }));
+                    citations.extend(sources);
+                    yield Ok(web::Bytes::from(data));
+                }
+                Ok(ChatStreamEvent::ToolCall(call)) => {
+                    let data = format!("event: tool_call\ndata: {}\n\n", serde_json::json!({
+                        "id": call.id,
+                        "name": call.name,
+                        "arguments": call.arguments,
+                    }));
+                    yield Ok(web::Bytes::from(data));
+                }
+                Ok(ChatStreamEvent::ToolResult(invocation)) => {
+                    let data = format!("event: tool_result\ndata: {}\n\n", serde_json::json!(&invocation));
+                    tool_calls.push(invocation);
+                    yield Ok(web::Bytes::from(data));
+                }
+                Ok(ChatStreamEvent::Usage(usage)) => {
+                    log::debug!(
+                        "Stream usage - prompt: {}, completion: {}, total: {}",
+                        usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+                    );
                 }
                 Err(e) => {
                     log::error!("Stream error: {}", e);
                     let error_event = format!("event: error\ndata: {}\n\n", serde_json::json!({
-                        "error": e.to_string()
+                        "error": e
                     }));
                     yield Ok(web::Bytes::from(error_event));
+                    failed = true;
                     break;
                 }
             }
         }
 
+        // Store the reply before signalling completion so a follow-up save finds it
+        if !failed && !content.trim().is_empty() {
+            if let Ok(conversation_id) = Uuid::parse_str(&conv_id) {
+                match chat_service
+                    .complete_streamed_message(
+                        &conversation_id,
+                        &user_id,
+                        content.trim().to_string(),
+                        citations,
+                        prompt_versions,
+                        tool_calls,
+                    )
+                    .await
+                {
+                    Ok(suggestions) if !suggestions.is_empty() => {
+                        let data = format!("event: suggestions\ndata: {}\n\n", serde_json::json!({
+                            "suggestions": suggestions
+                        }));
+                        yield Ok(web::Bytes::from(data));
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::error!("Failed to persist streamed response: {}", e),
+                }
+            }
+        }
+
         // Send done event
         let done_event = "event: done\ndata: {}\n\n".to_string();
         yield Ok(web::Bytes::from(done_event));
@@ -717,3 +1110,270 @@ pub async fn regenerate_message_stream(
         .insert_header(("X-Accel-Buffering", "no"))
         .streaming(response_stream)
 }
+
+/// Save the assistant response after streaming is complete
+pub async fn save_streamed_response(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<SaveStreamedResponseDto>,
+) -> HttpResponse {
+    // Validate input
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    // Get user from JWT claims
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    // Parse user_id from claims
+    let user_id = match Uuid::parse_str(&claims.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid user_id".to_string(),
+            });
+        }
+    };
+
+    // Parse conversation_id
+    let conversation_id = match Uuid::parse_str(&dto.conversation_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid conversation_id format".to_string(),
+            });
+        }
+    };
+
+    // Get conversation first to check project_id for permission
+    let conversation = match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(conv)) => conv,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Conversation not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversation: {}", e),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&conversation.project_id.to_string()),
+        Permission::ChatWrite
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Save the assistant message
+    match chat_service
+        .append_assistant_message(&conversation_id, &user_id, dto.content.clone())
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true
+        })),
+        Err(e) if e == READ_ONLY_CONVERSATION => {
+            HttpResponse::Forbidden().json(ErrorResponse { error: e })
+        }
+        Err(e) => {
+            log::error!("Failed to save streamed response: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to save response: {}", e),
+            })
+        }
+    }
+}
+
+/// Regenerate a response from a specific message index
+/// This removes messages from the index onwards and regenerates
+pub async fn regenerate_message_stream(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    dto: web::Json<RegenerateMessageDto>,
+) -> HttpResponse {
+    // Validate input
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    // Get user from JWT claims
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    // Parse user_id from claims
+    let user_id = match Uuid::parse_str(&claims.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid user_id".to_string(),
+            });
+        }
+    };
+
+    // Parse conversation_id
+    let conversation_id = match Uuid::parse_str(&dto.conversation_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid conversation_id format".to_string(),
+            });
+        }
+    };
+
+    // Get conversation first to check project_id for permission
+    let conversation = match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(conv)) => conv,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Conversation not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversation: {}", e),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&conversation.project_id.to_string()),
+        Permission::ChatWrite
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Get streaming response with regeneration
+    let reply = match chat_service
+        .regenerate_from_index(user_id, conversation_id, dto.from_index)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) if e == TOKEN_QUOTA_EXCEEDED => {
+            return HttpResponse::TooManyRequests().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == PROVIDER_UNAVAILABLE => {
+            return HttpResponse::ServiceUnavailable().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == READ_ONLY_CONVERSATION => {
+            return HttpResponse::Forbidden().json(ErrorResponse { error: e });
+        }
+        Err(e) if e == GUARDRAIL_BLOCKED => return guardrail_blocked(),
+        Err(e) => {
+            log::error!("Failed to start regeneration: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to start regeneration: {}", e),
+            });
+        }
+    };
+
+    stream_events(chat_service, user_id, reply)
+}
+
+/// Rate an assistant message with thumbs up/down, an optional reason and comment
+pub async fn submit_message_feedback(
+    chat_service: web::Data<ChatService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    dto: web::Json<MessageFeedbackDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid user_id".to_string(),
+            });
+        }
+    };
+
+    let (conversation_id, message_index) = path.into_inner();
+    let conversation_id = match Uuid::parse_str(&conversation_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid conversation_id format".to_string(),
+            });
+        }
+    };
+
+    let conversation = match chat_service.get_conversation(&conversation_id, &user_id).await {
+        Ok(Some(conv)) => conv,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Conversation not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get conversation: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Failed to get conversation: {}", e),
+            });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&conversation.project_id.to_string()),
+        Permission::ChatRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match chat_service
+        .set_message_feedback(&conversation_id, &user_id, message_index, dto.into_inner())
+        .await
+    {
+        Ok(mut conversation) => {
+            let message: ChatMessageResponse = conversation.messages.swap_remove(message_index).into();
+            HttpResponse::Ok().json(message)
+        }
+        Err(e) if e == "Message not found" => {
+            HttpResponse::NotFound().json(ErrorResponse { error: e })
+        }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
@@ -0,0 +1,764 @@
+use std::sync::Arc;
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use serde::Serialize;
+use validator::Validate;
+use crate::models::{
+    CreateQualityRuleDto, Dataset, DatasetInsightResponse, DatasetPreviewResponse, DatasetProfileResponse,
+    DatasetQueryDto, DatasetResponse, DetectAnomaliesDto, ForecastDto, Permission, QualityRuleResponse,
+    QualityRunQuery, QualityRunResponse, UploadDatasetQuery,
+};
+use crate::services::{AnomalyService, DatasetService, ForecastService, InsightService, QualityService, RbacService};
+use crate::services::anomaly::{AnomalySource, SAVED_QUERY_NOT_FOUND};
+use crate::services::forecast::DATASET_NOT_FOUND;
+use crate::services::profiling;
+use crate::utils::Claims;
+use crate::middleware::check_permission;
+
+/// Rows returned with a single dataset
+const PREVIEW_ROWS: i64 = 20;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Upload a CSV file as a project dataset.
+/// The file is the raw request body; its name is passed as `?filename=`.
+pub async fn upload_dataset(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<UploadDatasetQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Keep only the base name of whatever path the client sent
+    let filename = query
+        .filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if filename.is_empty() || filename.chars().count() > 255 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "filename must be between 1 and 255 characters".to_string(),
+        });
+    }
+
+    if body.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Dataset body is empty".to_string(),
+        });
+    }
+
+    let query = query.into_inner();
+    match dataset_service
+        .upload_csv(
+            &project_id,
+            &claims.user_id,
+            &filename,
+            query.name.as_deref(),
+            query.description,
+            body.to_vec(),
+        )
+        .await
+    {
+        Ok(dataset) => HttpResponse::Created().json(DatasetResponse::from(dataset)),
+        Err(e) => {
+            log::error!("Failed to upload dataset: {}", e);
+            HttpResponse::BadRequest().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// List a project's datasets
+pub async fn get_project_datasets(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match dataset_service.get_project_datasets(&project_id).await {
+        Ok(datasets) => {
+            let responses: Vec<DatasetResponse> = datasets.into_iter().map(|d| d.into()).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get datasets: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Get a dataset with its first rows
+pub async fn get_dataset(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match dataset_service.get_dataset(&dataset_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Dataset not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get dataset: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&dataset.project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match dataset_service.preview(&dataset, PREVIEW_ROWS).await {
+        Ok(preview) => HttpResponse::Ok().json(DatasetPreviewResponse {
+            dataset: dataset.into(),
+            preview,
+        }),
+        Err(e) => {
+            log::error!("Failed to preview dataset: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Delete a dataset and its rows
+pub async fn delete_dataset(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match dataset_service.get_dataset(&dataset_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Dataset not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get dataset: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&dataset.project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match dataset_service.delete_dataset(&dataset).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            log::error!("Failed to delete dataset: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Run a read-only SQL query against the project's datasets
+pub async fn query_datasets(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<DatasetQueryDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match dataset_service
+        .query(&project_id, &dto.sql, dataset_service.query_max_rows())
+        .await
+    {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Forecast a measure over a date column of a dataset or query result
+pub async fn forecast(
+    forecast_service: web::Data<ForecastService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<ForecastDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match forecast_service.forecast(&project_id, &dto).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) if e == DATASET_NOT_FOUND => HttpResponse::NotFound().json(ErrorResponse { error: e }),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Check a numeric column of a dataset or a query result for anomalies
+pub async fn detect_anomalies(
+    anomaly_service: web::Data<AnomalyService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<DetectAnomaliesDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Saved queries are read with the same permission as the analytics endpoints
+    if dto.query_id.is_some() {
+        if let Err(e) = check_permission(
+            &rbac_service,
+            &claims.user_id,
+            Some(&project_id),
+            Permission::ReportRead
+        ).await {
+            return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+        }
+    }
+
+    let source = match (&dto.dataset_id, &dto.sql, &dto.query_id) {
+        (Some(dataset_id), None, None) => AnomalySource::Dataset(dataset_id),
+        (None, Some(sql), None) => AnomalySource::Sql(sql),
+        (None, None, Some(query_id)) => AnomalySource::SavedQuery(query_id),
+        _ => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Give one of dataset_id, sql or query_id".to_string(),
+            });
+        }
+    };
+
+    match anomaly_service.detect(&project_id, source, &dto.options).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) if e == DATASET_NOT_FOUND || e == SAVED_QUERY_NOT_FOUND => {
+            HttpResponse::NotFound().json(ErrorResponse { error: e })
+        }
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Get a dataset's insights report
+pub async fn get_dataset_insights(
+    dataset_service: web::Data<DatasetService>,
+    insight_service: web::Data<InsightService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match dataset_service.get_dataset(&dataset_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Dataset not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get dataset: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&dataset.project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match insight_service.get(&dataset.dataset_id).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(DatasetInsightResponse::from(report)),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "No insights report for this dataset".to_string(),
+        }),
+        Err(e) => {
+            log::error!("Failed to get insights: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Generate a dataset's insights report again. Returns the pending report;
+/// generation continues in the background.
+pub async fn regenerate_dataset_insights(
+    dataset_service: web::Data<DatasetService>,
+    insight_service: web::Data<InsightService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match dataset_service.get_dataset(&dataset_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Dataset not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get dataset: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&dataset.project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    let rows = match dataset_service.rows(&dataset).await {
+        Ok(rows) => Arc::new(rows),
+        Err(e) => {
+            log::error!("Failed to load dataset rows: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    match insight_service.into_inner().start(dataset, &claims.user_id, rows).await {
+        Ok(report) => HttpResponse::Accepted().json(DatasetInsightResponse::from(report)),
+        Err(e) => {
+            log::error!("Failed to start insights: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Load a dataset the user holds `permission` on
+async fn permitted_dataset(
+    dataset_service: &DatasetService,
+    rbac_service: &web::Data<RbacService>,
+    claims: &Claims,
+    dataset_id: &str,
+    permission: Permission,
+) -> Result<Dataset, HttpResponse> {
+    let dataset = match dataset_service.get_dataset(dataset_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(ErrorResponse {
+                error: "Dataset not found".to_string(),
+            }));
+        }
+        Err(e) => {
+            log::error!("Failed to get dataset: {}", e);
+            return Err(HttpResponse::InternalServerError().json(ErrorResponse { error: e }));
+        }
+    };
+
+    if let Err(e) = check_permission(
+        rbac_service,
+        &claims.user_id,
+        Some(&dataset.project_id),
+        permission
+    ).await {
+        return Err(HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() }));
+    }
+    Ok(dataset)
+}
+
+/// Profile every column of a dataset
+pub async fn get_dataset_profile(
+    dataset_service: web::Data<DatasetService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match permitted_dataset(&dataset_service, &rbac_service, &claims, &dataset_id, Permission::ProjectRead).await {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    let rows = match dataset_service.rows(&dataset).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            log::error!("Failed to load dataset rows: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    let columns = dataset.columns.clone();
+    match tokio::task::spawn_blocking(move || profiling::profile_columns(&columns, &rows)).await {
+        Ok(columns) => HttpResponse::Ok().json(DatasetProfileResponse {
+            dataset_id: dataset.dataset_id,
+            row_count: dataset.row_count,
+            columns,
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to profile dataset".to_string(),
+        }),
+    }
+}
+
+/// Add a quality rule on a column of the dataset's table
+pub async fn create_quality_rule(
+    dataset_service: web::Data<DatasetService>,
+    quality_service: web::Data<QualityService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<CreateQualityRuleDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match permitted_dataset(&dataset_service, &rbac_service, &claims, &dataset_id, Permission::ProjectUpdate).await {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    match quality_service.create_rule(&dataset, &claims.user_id, dto.into_inner()).await {
+        Ok(rule) => HttpResponse::Created().json(QualityRuleResponse::from(rule)),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// List the quality rules of the dataset's table
+pub async fn get_quality_rules(
+    dataset_service: web::Data<DatasetService>,
+    quality_service: web::Data<QualityService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match permitted_dataset(&dataset_service, &rbac_service, &claims, &dataset_id, Permission::ProjectRead).await {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    match quality_service.list_rules(&dataset.project_id, &dataset.name).await {
+        Ok(rules) => {
+            let responses: Vec<QualityRuleResponse> = rules.into_iter().map(QualityRuleResponse::from).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get quality rules: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Delete a quality rule
+pub async fn delete_quality_rule(
+    quality_service: web::Data<QualityService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let rule_id = path.into_inner();
+    let rule = match quality_service.get_rule(&rule_id).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Quality rule not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get quality rule: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&rule.project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match quality_service.delete_rule(&rule.rule_id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            log::error!("Failed to delete quality rule: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Profile the dataset and run its table's quality rules now
+pub async fn run_quality_checks(
+    dataset_service: web::Data<DatasetService>,
+    quality_service: web::Data<QualityService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match permitted_dataset(&dataset_service, &rbac_service, &claims, &dataset_id, Permission::ProjectUpdate).await {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    let rows = match dataset_service.rows(&dataset).await {
+        Ok(rows) => Arc::new(rows),
+        Err(e) => {
+            log::error!("Failed to load dataset rows: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    match quality_service.run(&dataset, &claims.user_id, rows).await {
+        Ok(run) => HttpResponse::Created().json(QualityRunResponse::from(run)),
+        Err(e) => {
+            log::error!("Failed to run quality checks: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Quality run history of the dataset's table, newest first
+pub async fn get_quality_runs(
+    dataset_service: web::Data<DatasetService>,
+    quality_service: web::Data<QualityService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<QualityRunQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let dataset_id = path.into_inner();
+    let dataset = match permitted_dataset(&dataset_service, &rbac_service, &claims, &dataset_id, Permission::ProjectRead).await {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    match quality_service
+        .list_runs(&dataset.project_id, &dataset.name, query.limit, query.regressed)
+        .await
+    {
+        Ok(runs) => {
+            let responses: Vec<QualityRunResponse> = runs.into_iter().map(QualityRunResponse::from).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get quality runs: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
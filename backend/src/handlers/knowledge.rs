@@ -0,0 +1,215 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use actix_web::http::header;
+use serde::Serialize;
+use crate::models::{
+    DocumentFormat, KnowledgeDocumentResponse, KnowledgeSearchQuery, Permission, UploadDocumentQuery,
+};
+use crate::services::{KnowledgeService, RbacService};
+use crate::utils::Claims;
+use crate::middleware::check_permission;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Upload a document to a project's knowledge base.
+/// The file is the raw request body; its name is passed as `?filename=`.
+pub async fn upload_document(
+    knowledge_service: web::Data<KnowledgeService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<UploadDocumentQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    // Keep only the base name of whatever path the client sent
+    let filename = query
+        .filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if filename.is_empty() || filename.chars().count() > 255 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "filename must be between 1 and 255 characters".to_string(),
+        });
+    }
+
+    if body.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Document body is empty".to_string(),
+        });
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let format = match DocumentFormat::detect(&filename, content_type) {
+        Some(f) => f,
+        None => {
+            return HttpResponse::UnsupportedMediaType().json(ErrorResponse {
+                error: "Supported formats are text, Markdown, PDF and HTML".to_string(),
+            });
+        }
+    };
+
+    match knowledge_service
+        .upload_document(&project_id, &claims.user_id, &filename, format, body.to_vec())
+        .await
+    {
+        Ok(document) => HttpResponse::Created().json(KnowledgeDocumentResponse::from(document)),
+        Err(e) => {
+            log::error!("Failed to upload document: {}", e);
+            HttpResponse::BadRequest().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// List the documents in a project's knowledge base
+pub async fn get_project_documents(
+    knowledge_service: web::Data<KnowledgeService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match knowledge_service.get_project_documents(&project_id).await {
+        Ok(documents) => {
+            let responses: Vec<KnowledgeDocumentResponse> =
+                documents.into_iter().map(|d| d.into()).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get documents: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Preview which chunks would be retrieved for a question
+pub async fn search_documents(
+    knowledge_service: web::Data<KnowledgeService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<KnowledgeSearchQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    let top_k = query.top_k.map(|k| k.clamp(1, 20));
+    match knowledge_service.retrieve(&project_id, &query.q, top_k).await {
+        Ok(citations) => HttpResponse::Ok().json(citations),
+        Err(e) => {
+            log::error!("Knowledge search failed: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Delete a document and its chunks
+pub async fn delete_document(
+    knowledge_service: web::Data<KnowledgeService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let document_id = path.into_inner();
+    let document = match knowledge_service.get_document(&document_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Document not found".to_string(),
+            });
+        }
+        Err(e) => {
+            log::error!("Failed to get document: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+        }
+    };
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&document.project_id),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match knowledge_service.delete_document(&document).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            log::error!("Failed to delete document: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
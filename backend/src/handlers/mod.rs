@@ -1,6 +1,13 @@
+pub mod admin;
+pub mod attachment;
 pub mod auth;
 pub mod project;
 pub mod analytics;
 pub mod chat;
+pub mod dataset;
+pub mod knowledge;
+pub mod notification;
 pub mod rbac;
+pub mod report;
+pub mod search;
 pub mod user;
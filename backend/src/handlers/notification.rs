@@ -0,0 +1,66 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use serde::Serialize;
+use crate::models::{NotificationQuery, NotificationResponse};
+use crate::services::NotificationService;
+use crate::utils::Claims;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// List the current user's notifications, newest first
+pub async fn get_notifications(
+    notification_service: web::Data<NotificationService>,
+    req: HttpRequest,
+    query: web::Query<NotificationQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    match notification_service.list(&claims.user_id, query.unread, query.limit).await {
+        Ok(notifications) => {
+            let responses: Vec<NotificationResponse> =
+                notifications.into_iter().map(NotificationResponse::from).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get notifications: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Mark one of the current user's notifications as read
+pub async fn mark_notification_read(
+    notification_service: web::Data<NotificationService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let notification_id = path.into_inner();
+    match notification_service.mark_read(&claims.user_id, &notification_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Notification not found".to_string(),
+        }),
+        Err(e) => {
+            log::error!("Failed to mark notification as read: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
 use serde::Serialize;
 use validator::Validate;
-use crate::models::{CreateProjectDto, ProjectResponse, Permission};
-use crate::services::{ProjectService, RbacService};
+use crate::models::{CreateProjectDto, ProjectResponse, Permission, UpdateProjectAiSettingsDto};
+use crate::services::{AIService, AiSettingsService, ProjectService, RbacService};
 use crate::utils::Claims;
 use crate::middleware::check_permission;
 
@@ -257,3 +257,129 @@ pub async fn delete_project(
         Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
     }
 }
+
+pub async fn get_ai_settings(
+    ai_settings_service: web::Data<AiSettingsService>,
+    rbac_service: web::Data<RbacService>,
+    project_id: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>().cloned() {
+        Some(c) => c,
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id_str = project_id.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ProjectRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match ai_settings_service.get_settings(&project_id_str).await {
+        Ok(settings) => HttpResponse::Ok().json(ai_settings_service.to_response(&project_id_str, settings)),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { error: e }),
+    }
+}
+
+pub async fn update_ai_settings(
+    ai_settings_service: web::Data<AiSettingsService>,
+    rbac_service: web::Data<RbacService>,
+    project_id: web::Path<String>,
+    dto: web::Json<UpdateProjectAiSettingsDto>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>().cloned() {
+        Some(c) => c,
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id_str = project_id.into_inner();
+
+    // Changing the model or prompt is a project setting
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    if uuid::Uuid::parse_str(&project_id_str).is_err() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid project ID".to_string(),
+        });
+    }
+
+    match ai_settings_service
+        .update_settings(&project_id_str, &claims.user_id, dto.into_inner())
+        .await
+    {
+        Ok(settings) => HttpResponse::Ok().json(ai_settings_service.to_response(&project_id_str, Some(settings))),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// Drop the project's cached AI answers
+pub async fn clear_ai_cache(
+    ai_service: web::Data<AIService>,
+    rbac_service: web::Data<RbacService>,
+    project_id: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>().cloned() {
+        Some(c) => c,
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id_str = project_id.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id_str),
+        Permission::ProjectUpdate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    if uuid::Uuid::parse_str(&project_id_str).is_err() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid project ID".to_string(),
+        });
+    }
+
+    match ai_service.invalidate_cache(&project_id_str).await {
+        Ok(removed) => HttpResponse::Ok().json(serde_json::json!({
+            "project_id": project_id_str,
+            "removed": removed,
+        })),
+        Err(e) => {
+            log::error!("Failed to clear AI cache: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
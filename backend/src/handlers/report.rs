@@ -0,0 +1,209 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use serde::Serialize;
+use validator::Validate;
+use crate::models::{CreateScheduledReportDto, Permission, ScheduledReport, ScheduledReportResponse};
+use crate::services::{RbacService, ScheduledReportService};
+use crate::utils::Claims;
+use crate::middleware::check_permission;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Load a scheduled report and check the user's permission on its project
+async fn permitted_report(
+    report_service: &ScheduledReportService,
+    rbac_service: &web::Data<RbacService>,
+    claims: &Claims,
+    report_id: &str,
+    permission: Permission,
+) -> Result<ScheduledReport, HttpResponse> {
+    let report = match report_service.get(report_id).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(ErrorResponse {
+                error: "Scheduled report not found".to_string(),
+            }));
+        }
+        Err(e) => {
+            log::error!("Failed to get scheduled report: {}", e);
+            return Err(HttpResponse::InternalServerError().json(ErrorResponse { error: e }));
+        }
+    };
+
+    if let Err(e) = check_permission(
+        rbac_service,
+        &claims.user_id,
+        Some(&report.project_id),
+        permission
+    ).await {
+        return Err(HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() }));
+    }
+    Ok(report)
+}
+
+/// Schedule an anomaly check of a dataset table or query
+pub async fn create_scheduled_report(
+    report_service: web::Data<ScheduledReportService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    dto: web::Json<CreateScheduledReportDto>,
+) -> HttpResponse {
+    if let Err(e) = dto.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: format!("Validation error: {}", e),
+        });
+    }
+
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ReportCreate
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match report_service.create(&project_id, &claims.user_id, dto.into_inner()).await {
+        Ok(report) => HttpResponse::Created().json(ScheduledReportResponse::from(report)),
+        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+    }
+}
+
+/// List a project's scheduled reports
+pub async fn get_scheduled_reports(
+    report_service: web::Data<ScheduledReportService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let project_id = path.into_inner();
+
+    if let Err(e) = check_permission(
+        &rbac_service,
+        &claims.user_id,
+        Some(&project_id),
+        Permission::ReportRead
+    ).await {
+        return HttpResponse::Forbidden().json(ErrorResponse { error: e.to_string() });
+    }
+
+    match report_service.list(&project_id).await {
+        Ok(reports) => {
+            let responses: Vec<ScheduledReportResponse> =
+                reports.into_iter().map(ScheduledReportResponse::from).collect();
+            HttpResponse::Ok().json(responses)
+        }
+        Err(e) => {
+            log::error!("Failed to get scheduled reports: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Get a scheduled report with the anomalies of its last run
+pub async fn get_scheduled_report(
+    report_service: web::Data<ScheduledReportService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let report_id = path.into_inner();
+    match permitted_report(&report_service, &rbac_service, &claims, &report_id, Permission::ReportRead).await {
+        Ok(report) => HttpResponse::Ok().json(ScheduledReportResponse::from(report)),
+        Err(response) => response,
+    }
+}
+
+/// Run a scheduled report now, notifying about new anomalies as a scheduled run would
+pub async fn run_scheduled_report(
+    report_service: web::Data<ScheduledReportService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let report_id = path.into_inner();
+    let report = match permitted_report(&report_service, &rbac_service, &claims, &report_id, Permission::ReportCreate).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    match report_service.run(report).await {
+        Ok(report) => HttpResponse::Ok().json(ScheduledReportResponse::from(report)),
+        Err(e) => {
+            log::error!("Failed to run scheduled report: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
+
+/// Delete a scheduled report
+pub async fn delete_scheduled_report(
+    report_service: web::Data<ScheduledReportService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let report_id = path.into_inner();
+    let report = match permitted_report(&report_service, &rbac_service, &claims, &report_id, Permission::ReportDelete).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    match report_service.delete(&report.report_id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            log::error!("Failed to delete scheduled report: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse { error: e })
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use actix_web::{web, HttpResponse, HttpRequest, HttpMessage};
+use serde::Serialize;
+use crate::models::{Permission, SearchQuery, SearchResponse};
+use crate::services::{RbacService, SearchService};
+use crate::utils::Claims;
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Full-text search across conversations and analytics queries.
+/// Conversations are limited to projects where the caller holds chat:read,
+/// analytics queries to projects where they hold report:read.
+pub async fn search(
+    search_service: web::Data<SearchService>,
+    rbac_service: web::Data<RbacService>,
+    req: HttpRequest,
+    query: web::Query<SearchQuery>,
+) -> HttpResponse {
+    let claims = match req.extensions().get::<Claims>() {
+        Some(c) => c.clone(),
+        None => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Unauthorized".to_string(),
+            });
+        }
+    };
+
+    let q = query.q.trim();
+    if q.is_empty() || q.chars().count() > 200 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Search query must be between 1 and 200 characters".to_string(),
+        });
+    }
+
+    let (include_conversations, include_queries) = match query.types.as_deref() {
+        None => (true, true),
+        Some(types) => {
+            let mut conversations = false;
+            let mut queries = false;
+            for t in types.split(',').map(str::trim) {
+                match t {
+                    "conversations" => conversations = true,
+                    "queries" => queries = true,
+                    other => {
+                        return HttpResponse::BadRequest().json(ErrorResponse {
+                            error: format!("Invalid search type: {}", other),
+                        });
+                    }
+                }
+            }
+            (conversations, queries)
+        }
+    };
+
+    let limit = query.limit.unwrap_or(20).clamp(1, 50);
+
+    // Resolve the projects each result type may come from
+    let scope = |mut ids: Vec<String>| {
+        if let Some(ref pid) = query.project_id {
+            ids.retain(|id| id == pid);
+        }
+        ids
+    };
+
+    let mut hits = Vec::new();
+
+    if include_conversations {
+        let project_ids = match rbac_service
+            .accessible_project_ids(&claims.user_id, &claims.tenant_id, Permission::ChatRead)
+            .await
+        {
+            Ok(ids) => scope(ids),
+            Err(e) => {
+                log::error!("Failed to resolve searchable projects: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+            }
+        };
+
+        match search_service
+            .search_conversations(q, &claims.user_id, &project_ids, limit)
+            .await
+        {
+            Ok(found) => hits.extend(found),
+            Err(e) => {
+                log::error!("Conversation search failed: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+            }
+        }
+    }
+
+    if include_queries {
+        let project_ids = match rbac_service
+            .accessible_project_ids(&claims.user_id, &claims.tenant_id, Permission::ReportRead)
+            .await
+        {
+            Ok(ids) => scope(ids),
+            Err(e) => {
+                log::error!("Failed to resolve searchable projects: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+            }
+        };
+
+        match search_service.search_queries(q, &project_ids, limit).await {
+            Ok(found) => hits.extend(found),
+            Err(e) => {
+                log::error!("Analytics query search failed: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse { error: e });
+            }
+        }
+    }
+
+    // Merge both result sets by relevance
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit as usize);
+
+    HttpResponse::Ok().json(SearchResponse {
+        query: q.to_string(),
+        total: hits.len(),
+        hits,
+    })
+}
@@ -8,7 +8,10 @@ mod handlers;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -41,30 +44,242 @@ async fn main() -> std::io::Result<()> {
         config.jwt_refresh_expiration,
     ));
 
-    // Initialize AI service
-    println!("Initializing AI service with provider: {:?}", config.ai_provider);
+    // Initialize AI service with the provider registered under the configured name
+    println!("Initializing AI service with provider: {}", config.ai_provider_name);
+    let provider_registry = services::llm::ProviderRegistry::with_builtin_providers();
+    let timeouts = services::llm::HttpTimeouts {
+        connect: Duration::from_secs(config.ai_connect_timeout_secs),
+        request: Duration::from_secs(config.ai_request_timeout_secs),
+        stream: Duration::from_secs(config.ai_stream_timeout_secs),
+    };
+    let record_dir = config.ai_record_dir.as_ref().map(|dir| {
+        log::info!("Recording AI provider exchanges to {}", dir);
+        PathBuf::from(dir)
+    });
+    // Every provider talks to the replay server, which answers from recorded fixtures
+    let replay_server = match config.ai_replay_dir {
+        Some(ref dir) => {
+            let fixtures = services::llm::fixtures::Fixture::load_dir(Path::new(dir))
+                .expect("Failed to load AI_REPLAY_DIR fixtures");
+            let server = services::llm::fixtures::ReplayServer::start(fixtures).await?;
+            log::info!("Replaying AI provider fixtures from {} at {}", dir, server.url());
+            Some(server)
+        }
+        None => None,
+    };
+    let api_url = |url: &str| replay_server.as_ref().map(|s| s.url()).unwrap_or_else(|| url.to_string());
+    let provider_endpoints: HashMap<String, services::llm::ProviderConfig> = config
+        .ai_endpoints
+        .iter()
+        .map(|(name, endpoint)| {
+            (name.clone(), services::llm::ProviderConfig {
+                name: name.clone(),
+                api_url: api_url(&endpoint.api_url),
+                model_name: endpoint.model_name.clone(),
+                api_key: endpoint.api_key.clone(),
+                embedding_model: None,
+                timeouts,
+                record_dir: record_dir.clone(),
+            })
+        })
+        .collect();
+    let fallback_provider = config.ai_fallback_provider.as_ref().map(|name| {
+        let mut fallback = provider_endpoints.get(name).cloned().unwrap_or_else(|| {
+            panic!("AI_FALLBACK_PROVIDER '{}' has no endpoint configured", name)
+        });
+        if let Some(ref model) = config.ai_fallback_model {
+            fallback.model_name = model.clone();
+        }
+        log::info!("AI fallback provider: {} ({})", fallback.name, fallback.model_name);
+        fallback
+    });
     let ai_service = services::AIService::new(
-        config.ai_api_url.clone(),
-        config.ai_model_name.clone(),
-        config.ai_provider.clone(),
-        config.ai_api_key.clone(),
-    );
+        provider_registry,
+        services::llm::ProviderConfig {
+            name: config.ai_provider_name.clone(),
+            api_url: api_url(&config.ai_api_url),
+            model_name: config.ai_model_name.clone(),
+            api_key: config.ai_api_key.clone(),
+            embedding_model: config.ai_embedding_model.clone(),
+            timeouts,
+            record_dir,
+        },
+        fallback_provider,
+        provider_endpoints,
+        services::llm::resilience::ResilienceSettings {
+            retry: services::llm::resilience::RetryPolicy {
+                max_retries: config.ai_max_retries,
+                base_delay: Duration::from_millis(config.ai_retry_base_delay_ms),
+                max_delay: Duration::from_millis(config.ai_retry_max_delay_ms),
+            },
+            breaker: services::llm::resilience::BreakerSettings {
+                failure_threshold: config.ai_breaker_failure_threshold,
+                open_duration: Duration::from_secs(config.ai_breaker_open_secs),
+            },
+        },
+    )
+    .expect("Failed to initialize AI provider");
+    let ai_service = if config.ai_cache_enabled {
+        log::info!(
+            "AI response cache enabled (ttl {}s, similarity {})",
+            config.ai_cache_ttl_secs,
+            config
+                .ai_cache_similarity_threshold
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| "off".to_string())
+        );
+        ai_service.with_response_cache(Arc::new(services::ResponseCache::new(
+            db_manager.clone(),
+            services::response_cache::CacheSettings {
+                ttl_secs: config.ai_cache_ttl_secs,
+                similarity_threshold: config.ai_cache_similarity_threshold,
+            },
+        )))
+    } else {
+        ai_service
+    };
 
     // Initialize services
     let user_service = web::Data::new(services::UserService::new(db_manager.clone()));
     let project_service = web::Data::new(services::ProjectService::new(db_manager.clone()));
-    let analytics_service = web::Data::new(services::AnalyticsService::new(
+    let ai_settings_service = Arc::new(services::AiSettingsService::new(
         db_manager.clone(),
         ai_service.clone(),
     ));
-    let chat_service = web::Data::new(services::ChatService::new(
+    let prompt_service = Arc::new(services::PromptService::new(db_manager.clone()));
+    let usage_service = Arc::new(services::UsageService::new(
+        db_manager.clone(),
+        services::usage::QuotaDefaults {
+            tenant_monthly_tokens: config.quota_tenant_monthly_tokens,
+            user_monthly_tokens: config.quota_user_monthly_tokens,
+        },
+    ));
+    let audit_service = Arc::new(services::AuditService::new(db_manager.clone()));
+    let redaction_service = Arc::new(services::RedactionService::new(
+        db_manager.clone(),
+        audit_service.clone(),
+        config.pii_redaction_enabled,
+    ));
+    let guardrail_service = Arc::new(services::GuardrailService::new(
         db_manager.clone(),
-        ai_service,
-        config.chat_rate_limit_messages,
-        config.chat_rate_limit_window_secs,
-        config.chat_context_message_limit,
+        audit_service.clone(),
+        config.guardrails_enabled,
+        config.guardrail_user_input_action,
+        config.guardrail_context_action,
     ));
-    let rbac_service = web::Data::new(services::RbacService::new(db_manager.clone()));
+    let analytics_service = web::Data::new(
+        services::AnalyticsService::new(
+            db_manager.clone(),
+            ai_service.clone(),
+            ai_settings_service.clone(),
+            prompt_service.clone(),
+            usage_service.clone(),
+        )
+        .with_redaction(redaction_service.clone())
+        .with_guardrails(guardrail_service.clone()),
+    );
+    let knowledge_service = Arc::new(services::KnowledgeService::new(
+        db_manager.clone(),
+        ai_service.clone(),
+        config.knowledge_chunk_size,
+        config.knowledge_chunk_overlap,
+        config.knowledge_top_k,
+    ));
+    let insight_service = Arc::new(
+        services::InsightService::new(
+            db_manager.clone(),
+            ai_service.clone(),
+            ai_settings_service.clone(),
+            prompt_service.clone(),
+            usage_service.clone(),
+        )
+        .with_redaction(redaction_service.clone())
+        .with_guardrails(guardrail_service.clone()),
+    );
+    match insight_service.fail_stale().await {
+        Ok(0) => {}
+        Ok(n) => log::info!("Marked {} interrupted insights reports as failed", n),
+        Err(e) => log::warn!("Failed to mark interrupted insights reports: {}", e),
+    }
+    let quality_service = Arc::new(services::QualityService::new(db_manager.clone()));
+    let mut dataset_service = services::DatasetService::new(
+        db_manager.clone(),
+        config.dataset_max_rows,
+        config.dataset_query_max_rows,
+    );
+    if config.dataset_insights_enabled {
+        dataset_service = dataset_service.with_insights(insight_service.clone());
+    }
+    if config.dataset_quality_on_upload {
+        dataset_service = dataset_service.with_quality(quality_service.clone());
+    }
+    let dataset_service = Arc::new(dataset_service);
+    let forecast_service = web::Data::new(services::ForecastService::new(dataset_service.clone()));
+    let anomaly_service = Arc::new(services::AnomalyService::new(db_manager.clone(), dataset_service.clone()));
+    let attachment_service = Arc::new(services::AttachmentService::new(
+        db_manager.clone(),
+        dataset_service.clone(),
+        config.chat_attachment_context_chars,
+    ));
+    attachment_service
+        .clone()
+        .start_cleanup(Duration::from_secs(config.chat_attachment_unbound_ttl_hours * 3600));
+    let rbac_service = Arc::new(services::RbacService::new(db_manager.clone()));
+    let notification_service = Arc::new(services::NotificationService::new(db_manager.clone()));
+    let scheduled_report_service = Arc::new(services::ScheduledReportService::new(
+        db_manager.clone(),
+        anomaly_service.clone(),
+        notification_service.clone(),
+        rbac_service.clone(),
+    ));
+    if config.scheduled_reports_enabled {
+        log::info!("Checking for due scheduled reports every {}s", config.scheduled_reports_tick_secs);
+        scheduled_report_service
+            .clone()
+            .start(Duration::from_secs(config.scheduled_reports_tick_secs));
+    }
+    let mut chat_service = services::ChatService::new(
+        db_manager.clone(),
+        ai_service.clone(),
+        knowledge_service.clone(),
+        ai_settings_service.clone(),
+        prompt_service.clone(),
+        usage_service.clone(),
+        services::chat::ChatLimits {
+            rate_limit_messages: config.chat_rate_limit_messages,
+            rate_limit_window_secs: config.chat_rate_limit_window_secs,
+            context_message_limit: config.chat_context_message_limit,
+        },
+    )
+    .with_rbac(rbac_service.clone())
+    .with_redaction(redaction_service.clone())
+    .with_guardrails(guardrail_service.clone())
+    .with_attachments(attachment_service.clone());
+    if config.chat_follow_ups_enabled {
+        chat_service = chat_service.with_follow_ups(dataset_service.clone());
+    }
+    if config.ai_tools_enabled {
+        let registry = services::ToolRegistry::with_builtin_tools(db_manager.clone(), dataset_service.clone());
+        chat_service = chat_service.with_tools(Arc::new(registry), config.ai_tool_max_rounds);
+    }
+    let chat_service = web::Data::new(chat_service);
+    let rbac_service = web::Data::from(rbac_service);
+    let dataset_service = web::Data::from(dataset_service);
+    let attachment_service = web::Data::from(attachment_service);
+    let insight_service = web::Data::from(insight_service);
+    let quality_service = web::Data::from(quality_service);
+    let anomaly_service = web::Data::from(anomaly_service);
+    let notification_service = web::Data::from(notification_service);
+    let scheduled_report_service = web::Data::from(scheduled_report_service);
+    let audit_service = web::Data::from(audit_service);
+    let redaction_service = web::Data::from(redaction_service);
+    let guardrail_service = web::Data::from(guardrail_service);
+    let search_service = web::Data::new(services::SearchService::new(db_manager.clone()));
+    let knowledge_service = web::Data::from(knowledge_service);
+    let ai_settings_service = web::Data::from(ai_settings_service);
+    let prompt_service = web::Data::from(prompt_service);
+    let usage_service = web::Data::from(usage_service);
+    let ai_service = web::Data::new(ai_service);
 
     // Ensure system roles exist
     rbac_service.ensure_system_roles()
@@ -76,6 +291,9 @@ async fn main() -> std::io::Result<()> {
     let cors_origins = config.cors_allowed_origins.clone();
     let rate_limit_requests = config.rate_limit_requests;
     let rate_limit_window_secs = config.rate_limit_window_secs;
+    let knowledge_max_upload_bytes = config.knowledge_max_upload_bytes;
+    let dataset_max_upload_bytes = config.dataset_max_upload_bytes;
+    let chat_attachment_max_bytes = config.chat_attachment_max_bytes;
 
     log::info!("All services initialized successfully");
 
@@ -105,6 +323,23 @@ async fn main() -> std::io::Result<()> {
             .app_data(analytics_service.clone())
             .app_data(chat_service.clone())
             .app_data(rbac_service.clone())
+            .app_data(search_service.clone())
+            .app_data(knowledge_service.clone())
+            .app_data(dataset_service.clone())
+            .app_data(attachment_service.clone())
+            .app_data(insight_service.clone())
+            .app_data(quality_service.clone())
+            .app_data(forecast_service.clone())
+            .app_data(anomaly_service.clone())
+            .app_data(scheduled_report_service.clone())
+            .app_data(notification_service.clone())
+            .app_data(audit_service.clone())
+            .app_data(redaction_service.clone())
+            .app_data(guardrail_service.clone())
+            .app_data(ai_settings_service.clone())
+            .app_data(prompt_service.clone())
+            .app_data(usage_service.clone())
+            .app_data(ai_service.clone())
             .app_data(jwt_manager_data.clone())
             // Public routes
             .service(
@@ -142,6 +377,41 @@ async fn main() -> std::io::Result<()> {
                             .route("/{project_id}", web::put().to(handlers::project::update_project))
                             .route("/{project_id}", web::delete().to(handlers::project::delete_project))
                             .route("/{project_id}/members", web::get().to(handlers::rbac::get_project_members))
+                            .route("/{project_id}/ai-settings", web::get().to(handlers::project::get_ai_settings))
+                            .route("/{project_id}/ai-settings", web::put().to(handlers::project::update_ai_settings))
+                            .route("/{project_id}/ai-cache", web::delete().to(handlers::project::clear_ai_cache))
+                            .service(
+                                web::resource("/{project_id}/documents")
+                                    .app_data(web::PayloadConfig::new(knowledge_max_upload_bytes))
+                                    .route(web::post().to(handlers::knowledge::upload_document))
+                                    .route(web::get().to(handlers::knowledge::get_project_documents))
+                            )
+                            .route("/{project_id}/documents/search", web::get().to(handlers::knowledge::search_documents))
+                            .route("/documents/{document_id}", web::delete().to(handlers::knowledge::delete_document))
+                            .service(
+                                web::resource("/{project_id}/datasets")
+                                    .app_data(web::PayloadConfig::new(dataset_max_upload_bytes))
+                                    .route(web::post().to(handlers::dataset::upload_dataset))
+                                    .route(web::get().to(handlers::dataset::get_project_datasets))
+                            )
+                            .route("/{project_id}/datasets/query", web::post().to(handlers::dataset::query_datasets))
+                            .route("/{project_id}/forecast", web::post().to(handlers::dataset::forecast))
+                            .route("/{project_id}/anomalies", web::post().to(handlers::dataset::detect_anomalies))
+                            .route("/{project_id}/scheduled-reports", web::post().to(handlers::report::create_scheduled_report))
+                            .route("/{project_id}/scheduled-reports", web::get().to(handlers::report::get_scheduled_reports))
+                            .route("/datasets/{dataset_id}", web::get().to(handlers::dataset::get_dataset))
+                            .route("/datasets/{dataset_id}", web::delete().to(handlers::dataset::delete_dataset))
+                            .route("/datasets/{dataset_id}/insights", web::get().to(handlers::dataset::get_dataset_insights))
+                            .route("/datasets/{dataset_id}/insights", web::post().to(handlers::dataset::regenerate_dataset_insights))
+                            .route("/datasets/{dataset_id}/profile", web::get().to(handlers::dataset::get_dataset_profile))
+                            .route("/datasets/{dataset_id}/quality/rules", web::post().to(handlers::dataset::create_quality_rule))
+                            .route("/datasets/{dataset_id}/quality/rules", web::get().to(handlers::dataset::get_quality_rules))
+                            .route("/datasets/{dataset_id}/quality/runs", web::post().to(handlers::dataset::run_quality_checks))
+                            .route("/datasets/{dataset_id}/quality/runs", web::get().to(handlers::dataset::get_quality_runs))
+                            .route("/quality/rules/{rule_id}", web::delete().to(handlers::dataset::delete_quality_rule))
+                            .route("/scheduled-reports/{report_id}", web::get().to(handlers::report::get_scheduled_report))
+                            .route("/scheduled-reports/{report_id}", web::delete().to(handlers::report::delete_scheduled_report))
+                            .route("/scheduled-reports/{report_id}/run", web::post().to(handlers::report::run_scheduled_report))
                     )
                     .service(
                         web::scope("/analytics")
@@ -156,10 +426,53 @@ async fn main() -> std::io::Result<()> {
                             .route("/message/stream", web::post().to(handlers::chat::stream_message))
                             .route("/message/stream/save", web::post().to(handlers::chat::save_streamed_response))
                             .route("/message/regenerate", web::post().to(handlers::chat::regenerate_message_stream))
+                            .service(
+                                web::resource("/attachments")
+                                    .app_data(web::PayloadConfig::new(chat_attachment_max_bytes))
+                                    .route(web::post().to(handlers::attachment::upload_attachment))
+                            )
+                            .route("/attachments/{attachment_id}", web::get().to(handlers::attachment::get_attachment))
+                            .route("/attachments/{attachment_id}/content", web::get().to(handlers::attachment::download_attachment))
+                            .route("/attachments/{attachment_id}/dataset", web::post().to(handlers::attachment::promote_attachment))
                             .route("/conversations/{conversation_id}", web::get().to(handlers::chat::get_conversation))
+                            .route("/conversations/{conversation_id}", web::put().to(handlers::chat::update_conversation))
                             .route("/conversations/{conversation_id}", web::delete().to(handlers::chat::delete_conversation))
+                            .route("/conversations/{conversation_id}/sharing", web::put().to(handlers::chat::update_conversation_sharing))
+                            .route("/conversations/{conversation_id}/messages/{message_index}/feedback", web::post().to(handlers::chat::submit_message_feedback))
                             .route("/projects/{project_id}/conversations", web::get().to(handlers::chat::get_project_conversations))
                             .route("/projects/{project_id}/conversations/summaries", web::get().to(handlers::chat::get_project_conversation_summaries))
+                            .route("/projects/{project_id}/folders", web::post().to(handlers::chat::create_folder))
+                            .route("/projects/{project_id}/folders", web::get().to(handlers::chat::get_project_folders))
+                            .route("/folders/{folder_id}", web::put().to(handlers::chat::rename_folder))
+                            .route("/folders/{folder_id}", web::delete().to(handlers::chat::delete_folder))
+                    )
+                    .service(
+                        web::scope("/notifications")
+                            .route("", web::get().to(handlers::notification::get_notifications))
+                            .route("/{notification_id}/read", web::post().to(handlers::notification::mark_notification_read))
+                    )
+                    .route("/search", web::get().to(handlers::search::search))
+                    .service(
+                        web::scope("/admin")
+                            .route("/feedback", web::get().to(handlers::admin::get_feedback_report))
+                            .route("/prompts", web::get().to(handlers::admin::get_prompt_templates))
+                            .route("/prompts/{name}/versions", web::get().to(handlers::admin::get_prompt_versions))
+                            .route("/prompts/{name}/versions", web::post().to(handlers::admin::create_prompt_version))
+                            .route("/prompts/{name}/rollback", web::post().to(handlers::admin::rollback_prompt))
+                            .route("/usage", web::get().to(handlers::admin::get_usage_report))
+                            .route("/quotas", web::get().to(handlers::admin::get_token_quotas))
+                            .route("/quotas", web::put().to(handlers::admin::set_token_quota))
+                            .route("/ai/breakers", web::get().to(handlers::admin::get_ai_breakers))
+                            .route("/redaction", web::get().to(handlers::admin::get_redaction_settings))
+                            .route("/redaction", web::put().to(handlers::admin::update_redaction_settings))
+                            .route("/audit", web::get().to(handlers::admin::get_audit_log))
+                            .route("/guardrails", web::get().to(handlers::admin::get_guardrail_policy))
+                            .route("/guardrails", web::put().to(handlers::admin::update_guardrail_policy))
+                            .route("/guardrails/events", web::get().to(handlers::admin::get_guardrail_events))
+                            .route(
+                                "/guardrails/events/{event_id}/review",
+                                web::post().to(handlers::admin::review_guardrail_event),
+                            )
                     )
                     .service(
                         web::scope("/rbac")
@@ -429,6 +429,9 @@ pub struct AnalyticsQuery {
     pub status: QueryStatus,
     pub created_at: DateTime,
     pub completed_at: Option<DateTime>,
+    /// Answer served from the response cache
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -507,6 +510,209 @@ pub struct ChatMessage {
     pub role: String, // "user" or "assistant"
     pub content: String,
     pub timestamp: DateTime,
+    /// Provider and model that generated an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Ratings left on an assistant message, at most one per user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feedback: Vec<MessageFeedback>,
+    /// Retrieved passages the assistant answer was grounded on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<MessageCitation>,
+    /// Prompt template versions used to generate an assistant message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompt_versions: Vec<PromptVersionRef>,
+    /// Assistant answer served from the response cache
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Server-side tools the assistant called while answering
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolInvocation>,
+    /// Follow-up questions offered after an assistant reply
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+    /// Files the user attached to a message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<MessageAttachment>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: String) -> Self {
+        ChatMessage {
+            role: role.to_string(),
+            content,
+            timestamp: DateTime::now(),
+            provider: None,
+            model: None,
+            feedback: vec![],
+            citations: vec![],
+            prompt_versions: vec![],
+            cached: false,
+            tool_calls: vec![],
+            suggestions: vec![],
+            attachments: vec![],
+        }
+    }
+}
+
+/// A tool call made during an assistant reply, with its output or error
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolInvocation {
+    pub call_id: String,
+    pub name: String,
+    /// Arguments as sent by the model
+    pub arguments: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A source passage returned by the retrieval backend
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageCitation {
+    pub content: String,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub document_id: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Document metadata as returned by the retrieval backend
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackRating {
+    Up,
+    Down,
+}
+
+/// Reason category for a rating, mostly useful for thumbs-down
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackReason {
+    Inaccurate,
+    Incomplete,
+    NotHelpful,
+    WrongData,
+    Formatting,
+    Harmful,
+    Other,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageFeedback {
+    pub user_id: String,
+    pub rating: FeedbackRating,
+    #[serde(default)]
+    pub reason: Option<FeedbackReason>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MessageFeedbackDto {
+    pub rating: FeedbackRating,
+    pub reason: Option<FeedbackReason>,
+    #[validate(length(max = 2000))]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MessageFeedbackResponse {
+    pub user_id: String,
+    pub rating: FeedbackRating,
+    pub reason: Option<FeedbackReason>,
+    pub comment: Option<String>,
+    pub updated_at: String,
+}
+
+impl From<MessageFeedback> for MessageFeedbackResponse {
+    fn from(feedback: MessageFeedback) -> Self {
+        MessageFeedbackResponse {
+            user_id: feedback.user_id,
+            rating: feedback.rating,
+            reason: feedback.reason,
+            comment: feedback.comment,
+            updated_at: feedback.updated_at.to_string(),
+        }
+    }
+}
+
+/// Query parameters for the admin feedback report
+#[derive(Debug, Deserialize)]
+pub struct FeedbackReportQuery {
+    pub project_id: Option<String>,
+}
+
+/// Feedback totals for one project / provider / model combination
+#[derive(Debug, Serialize, Clone)]
+pub struct FeedbackAggregate {
+    pub project_id: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub thumbs_up: u64,
+    pub thumbs_down: u64,
+    pub total: u64,
+    /// Share of ratings that are thumbs-up, between 0 and 1
+    pub satisfaction_rate: f64,
+    pub reasons: std::collections::BTreeMap<String, u64>,
+}
+
+/// Who besides the creator can see a conversation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationVisibility {
+    /// Only the creator
+    #[default]
+    Private,
+    /// Every project member holding chat:read
+    Project,
+    /// Only the users listed in `shared_with`
+    Users,
+}
+
+impl ConversationVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConversationVisibility::Private => "private",
+            ConversationVisibility::Project => "project",
+            ConversationVisibility::Users => "users",
+        }
+    }
+}
+
+/// Access level a user has on a conversation, ordered from least to most
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationAccess {
+    /// Read-only: can open the thread but not continue it
+    #[default]
+    View,
+    /// Can send messages and regenerate responses
+    Collaborate,
+    /// Creator of the conversation
+    Owner,
+}
+
+impl ConversationAccess {
+    pub fn can_write(&self) -> bool {
+        *self >= ConversationAccess::Collaborate
+    }
+}
+
+/// Explicit share of a conversation with a single user
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConversationShare {
+    pub user_id: String,
+    pub access: ConversationAccess,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -520,17 +726,163 @@ pub struct Conversation {
     #[serde(with = "uuid_as_string")]
     pub user_id: uuid::Uuid,
     pub title: String,
+    /// Set once a user renames the conversation so generated titles never overwrite it
+    #[serde(default)]
+    pub title_is_custom: bool,
     pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub visibility: ConversationVisibility,
+    /// Access granted to project members when visibility is `project`
+    #[serde(default)]
+    pub project_access: ConversationAccess,
+    #[serde(default)]
+    pub shared_with: Vec<ConversationShare>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Conversation {
+    /// Resolve the caller's access to this conversation, or `None` if it is not visible to them.
+    /// `project_member` says whether the caller may read chats in the conversation's project;
+    /// project-wide access is only granted to members.
+    pub fn access_for(&self, user_id: &uuid::Uuid, project_member: bool) -> Option<ConversationAccess> {
+        if self.user_id == *user_id {
+            return Some(ConversationAccess::Owner);
+        }
+
+        let user_id_str = user_id.to_string();
+        let explicit = self
+            .shared_with
+            .iter()
+            .find(|s| s.user_id == user_id_str)
+            .map(|s| s.access);
+
+        match self.visibility {
+            ConversationVisibility::Private => None,
+            ConversationVisibility::Users => explicit,
+            ConversationVisibility::Project if project_member => {
+                Some(explicit.map_or(self.project_access, |a| a.max(self.project_access)))
+            }
+            ConversationVisibility::Project => explicit,
+        }
+    }
+}
+
+/// DTO for renaming, pinning, archiving, tagging and filing a conversation.
+/// An empty `folder_id` removes the conversation from its folder.
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateConversationDto {
+    #[validate(length(min = 1, max = 200))]
+    pub title: Option<String>,
+    pub pinned: Option<bool>,
+    pub archived: Option<bool>,
+    #[validate(length(max = 20))]
+    pub tags: Option<Vec<String>>,
+    pub folder_id: Option<String>,
+}
+
+/// Query parameters for filtering and sorting conversation summaries
+#[derive(Debug, Deserialize)]
+pub struct ConversationSummaryQuery {
+    pub tag: Option<String>,
+    /// Defaults to `false`, hiding archived conversations
+    pub archived: Option<bool>,
+    pub pinned: Option<bool>,
+    pub folder_id: Option<String>,
+    /// `updated_at` (default), `created_at` or `title`
+    pub sort: Option<String>,
+    /// `desc` (default) or `asc`
+    pub order: Option<String>,
+}
+
+impl ConversationSummaryQuery {
+    /// Resolve the requested sort into a field name and MongoDB direction
+    pub fn sort_spec(&self) -> Result<(&'static str, i32), String> {
+        let field = match self.sort.as_deref() {
+            None | Some("updated_at") => "updated_at",
+            Some("created_at") => "created_at",
+            Some("title") => "title",
+            Some(other) => return Err(format!("Invalid sort field: {}", other)),
+        };
+        let direction = match self.order.as_deref() {
+            None | Some("desc") => -1,
+            Some("asc") => 1,
+            Some(other) => return Err(format!("Invalid sort order: {}", other)),
+        };
+        Ok((field, direction))
+    }
+}
+
+/// Project-level folder used to organise conversations
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversationFolder {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub folder_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub created_by: String,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
 
+/// DTO for creating or renaming a conversation folder
+#[derive(Debug, Deserialize, Validate)]
+pub struct ConversationFolderDto {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationFolderResponse {
+    pub folder_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<ConversationFolder> for ConversationFolderResponse {
+    fn from(folder: ConversationFolder) -> Self {
+        ConversationFolderResponse {
+            folder_id: folder.folder_id,
+            project_id: folder.project_id,
+            name: folder.name,
+            created_by: folder.created_by,
+            created_at: folder.created_at.to_string(),
+            updated_at: folder.updated_at.to_string(),
+        }
+    }
+}
+
+/// DTO for changing who a conversation is shared with
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateConversationSharingDto {
+    pub visibility: ConversationVisibility,
+    pub project_access: Option<ConversationAccess>,
+    #[validate(length(max = 100))]
+    pub shared_with: Option<Vec<ConversationShare>>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct SendMessageDto {
     #[validate(length(min = 1))]
     pub message: String,
     pub project_id: String,
     pub conversation_id: Option<String>,
+    /// Uploaded attachments to add to the conversation with this message
+    #[serde(default)]
+    #[validate(length(max = 5))]
+    pub attachment_ids: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -544,6 +896,15 @@ pub struct ChatMessageResponse {
     pub role: String,
     pub content: String,
     pub timestamp: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub feedback: Vec<MessageFeedbackResponse>,
+    pub citations: Vec<MessageCitation>,
+    pub prompt_versions: Vec<PromptVersionRef>,
+    pub cached: bool,
+    pub tool_calls: Vec<ToolInvocation>,
+    pub suggestions: Vec<String>,
+    pub attachments: Vec<MessageAttachment>,
 }
 
 #[derive(Debug, Serialize)]
@@ -553,6 +914,15 @@ pub struct ConversationResponse {
     pub user_id: String,
     pub title: String,
     pub messages: Vec<ChatMessageResponse>,
+    pub visibility: ConversationVisibility,
+    pub project_access: ConversationAccess,
+    pub shared_with: Vec<ConversationShare>,
+    /// Caller's access level; defaults to owner and is narrowed by the handler
+    pub access: ConversationAccess,
+    pub pinned: bool,
+    pub archived: bool,
+    pub tags: Vec<String>,
+    pub folder_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -562,8 +932,14 @@ pub struct ConversationResponse {
 pub struct ConversationSummary {
     pub conversation_id: String,
     pub project_id: String,
+    pub user_id: String,
     pub title: String,
     pub message_count: usize,
+    pub visibility: ConversationVisibility,
+    pub pinned: bool,
+    pub archived: bool,
+    pub tags: Vec<String>,
+    pub folder_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -573,8 +949,14 @@ impl From<Conversation> for ConversationSummary {
         ConversationSummary {
             conversation_id: conv.conversation_id.to_string(),
             project_id: conv.project_id.to_string(),
+            user_id: conv.user_id.to_string(),
             title: conv.title,
             message_count: conv.messages.len(),
+            visibility: conv.visibility,
+            pinned: conv.pinned,
+            archived: conv.archived,
+            tags: conv.tags,
+            folder_id: conv.folder_id,
             created_at: conv.created_at.to_string(),
             updated_at: conv.updated_at.to_string(),
         }
@@ -587,6 +969,15 @@ impl From<ChatMessage> for ChatMessageResponse {
             role: msg.role,
             content: msg.content,
             timestamp: msg.timestamp.to_string(),
+            provider: msg.provider,
+            model: msg.model,
+            feedback: msg.feedback.into_iter().map(|f| f.into()).collect(),
+            citations: msg.citations,
+            prompt_versions: msg.prompt_versions,
+            cached: msg.cached,
+            tool_calls: msg.tool_calls,
+            suggestions: msg.suggestions,
+            attachments: msg.attachments,
         }
     }
 }
@@ -599,30 +990,1687 @@ impl From<Conversation> for ConversationResponse {
             user_id: conv.user_id.to_string(),
             title: conv.title,
             messages: conv.messages.into_iter().map(|m| m.into()).collect(),
+            visibility: conv.visibility,
+            project_access: conv.project_access,
+            shared_with: conv.shared_with,
+            access: ConversationAccess::Owner,
+            pinned: conv.pinned,
+            archived: conv.archived,
+            tags: conv.tags,
+            folder_id: conv.folder_id,
             created_at: conv.created_at.to_string(),
             updated_at: conv.updated_at.to_string(),
         }
     }
 }
 
-// Rendering Module Models
+// Knowledge Base Models
+// ============================================================================
+
+/// Source format of an uploaded knowledge document
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentFormat {
+    Text,
+    Markdown,
+    Pdf,
+    Html,
+}
+
+impl DocumentFormat {
+    /// Resolve the format from the file extension, falling back to the Content-Type header
+    pub fn detect(filename: &str, content_type: Option<&str>) -> Option<Self> {
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("txt") | Some("text") | Some("csv") | Some("log") => return Some(DocumentFormat::Text),
+            Some("md") | Some("markdown") => return Some(DocumentFormat::Markdown),
+            Some("pdf") => return Some(DocumentFormat::Pdf),
+            Some("html") | Some("htm") => return Some(DocumentFormat::Html),
+            _ => {}
+        }
+
+        let mime = content_type?.split(';').next()?.trim().to_lowercase();
+        match mime.as_str() {
+            "text/plain" => Some(DocumentFormat::Text),
+            "text/markdown" => Some(DocumentFormat::Markdown),
+            "application/pdf" => Some(DocumentFormat::Pdf),
+            "text/html" => Some(DocumentFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// A document uploaded to a project's knowledge base
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KnowledgeDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub document_id: String,
+    pub project_id: String,
+    pub filename: String,
+    pub format: DocumentFormat,
+    pub size_bytes: i64,
+    pub chunk_count: i64,
+    /// Embedding model used for this document's chunks
+    pub embedding_model: String,
+    pub uploaded_by: String,
+    pub created_at: DateTime,
+}
 
+/// A chunk of document text with its embedding vector
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DocumentChunk {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub chunk_id: String,
+    pub document_id: String,
+    pub project_id: String,
+    pub filename: String,
+    pub chunk_index: i32,
+    pub content: String,
+    /// Left out when chunks are loaded for their text only
+    #[serde(default)]
+    pub embedding: Vec<f32>,
+    pub embedding_model: String,
+    pub created_at: DateTime,
+}
+
+/// Query parameters for a document upload; the file itself is the request body
+#[derive(Debug, Deserialize)]
+pub struct UploadDocumentQuery {
+    pub filename: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KnowledgeDocumentResponse {
+    pub document_id: String,
+    pub project_id: String,
+    pub filename: String,
+    pub format: DocumentFormat,
+    pub size_bytes: i64,
+    pub chunk_count: i64,
+    pub embedding_model: String,
+    pub uploaded_by: String,
+    pub created_at: String,
+}
+
+impl From<KnowledgeDocument> for KnowledgeDocumentResponse {
+    fn from(document: KnowledgeDocument) -> Self {
+        KnowledgeDocumentResponse {
+            document_id: document.document_id,
+            project_id: document.project_id,
+            filename: document.filename,
+            format: document.format,
+            size_bytes: document.size_bytes,
+            chunk_count: document.chunk_count,
+            embedding_model: document.embedding_model,
+            uploaded_by: document.uploaded_by,
+            created_at: document.created_at.to_string(),
+        }
+    }
+}
+
+/// Query parameters for testing retrieval against a project's knowledge base
+#[derive(Debug, Deserialize)]
+pub struct KnowledgeSearchQuery {
+    pub q: String,
+    pub top_k: Option<usize>,
+}
+
+// ============================================================================
+// Dataset Models
+// ============================================================================
+
+/// A tabular dataset uploaded to a project, queryable with read-only SQL.
+/// Rows are stored separately in `dataset_rows`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Dataset {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub dataset_id: String,
+    pub project_id: String,
+    /// Table name used in SQL, unique within the project
+    pub name: String,
+    pub description: Option<String>,
+    pub filename: String,
+    /// Column names with inferred types: `integer`, `number`, `boolean` or `string`
+    pub columns: Vec<ColumnInfo>,
+    pub row_count: i64,
+    pub uploaded_by: String,
+    pub created_at: DateTime,
+}
+
+/// One row of a dataset, values in column order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatasetRow {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub dataset_id: String,
+    pub row_index: i64,
+    pub values: Vec<serde_json::Value>,
+}
+
+/// Query parameters for a CSV upload; the file itself is the request body
+#[derive(Debug, Deserialize)]
+pub struct UploadDatasetQuery {
+    pub filename: String,
+    /// Table name; derived from the filename when omitted
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatasetResponse {
+    pub dataset_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub filename: String,
+    pub columns: Vec<ColumnInfo>,
+    pub row_count: i64,
+    pub uploaded_by: String,
+    pub created_at: String,
+}
+
+impl From<Dataset> for DatasetResponse {
+    fn from(dataset: Dataset) -> Self {
+        DatasetResponse {
+            dataset_id: dataset.dataset_id,
+            project_id: dataset.project_id,
+            name: dataset.name,
+            description: dataset.description,
+            filename: dataset.filename,
+            columns: dataset.columns,
+            row_count: dataset.row_count,
+            uploaded_by: dataset.uploaded_by,
+            created_at: dataset.created_at.to_string(),
+        }
+    }
+}
+
+/// A dataset with its first rows
+#[derive(Debug, Serialize)]
+pub struct DatasetPreviewResponse {
+    #[serde(flatten)]
+    pub dataset: DatasetResponse,
+    pub preview: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DatasetQueryDto {
+    #[validate(length(min = 1, max = 10000))]
+    pub sql: String,
+}
+
+/// Result of a read-only SQL query over a project's datasets
+#[derive(Debug, Serialize, Clone)]
+pub struct SqlQueryResult {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// More rows matched than the row limit allowed
+    pub truncated: bool,
+}
+
+// ============================================================================
+// Dataset Insights
+// ============================================================================
+
+/// Bucket of a numeric column's histogram, `[start, end)` except the last which includes `end`
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-#[serde(tag = "type", rename_all = "lowercase")]
-pub enum RenderContent {
-    Text { content: String },
-    Chart { data: ChartData },
-    Equation { latex: String, display: Option<bool> },
-    Table { data: TableData },
-    Dataset { data: DatasetData },
+pub struct HistogramBin {
+    pub start: f64,
+    pub end: f64,
+    pub count: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct ChartData {
-    pub chart_type: ChartType,
-    pub title: Option<String>,
+pub struct NumericColumnProfile {
+    pub column: String,
+    pub count: i64,
+    pub nulls: i64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: Option<f64>,
+    pub median: f64,
+    pub histogram: Vec<HistogramBin>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CategoricalColumnProfile {
+    pub column: String,
+    pub distinct: i64,
+    pub nulls: i64,
+    /// Most frequent values, most common first
+    pub top_values: Vec<ValueCount>,
+}
+
+/// Pearson correlation between two numeric columns
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ColumnCorrelation {
+    pub a: String,
+    pub b: String,
+    pub coefficient: f64,
+}
+
+/// Values outside the 1.5 × IQR fences of a numeric column
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OutlierSummary {
+    pub column: String,
+    pub count: i64,
+    pub lower_fence: f64,
+    pub upper_fence: f64,
+    /// The most extreme outlying values
+    pub examples: Vec<f64>,
+}
+
+/// A measure summed per day or month of a date column
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimeTrend {
+    pub date_column: String,
+    pub measure: String,
+    /// `day` or `month`
+    pub period: String,
     pub labels: Vec<String>,
-    pub datasets: Vec<ChartDataset>,
+    pub values: Vec<f64>,
+    /// Least-squares change per period
+    pub slope: f64,
+    /// Change from the first to the last period, when the first is not zero
+    pub change_pct: Option<f64>,
+}
+
+/// Statistical profile of a dataset that insights are generated from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct DataProfile {
+    pub row_count: i64,
+    pub numeric: Vec<NumericColumnProfile>,
+    pub categorical: Vec<CategoricalColumnProfile>,
+    pub correlations: Vec<ColumnCorrelation>,
+    pub outliers: Vec<OutlierSummary>,
+    pub trends: Vec<TimeTrend>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InsightStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// The insights report of a dataset, generated when it is ingested
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatasetInsightReport {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub dataset_id: String,
+    pub project_id: String,
+    pub status: InsightStatus,
+    pub profile: Option<DataProfile>,
+    /// Narrative insights with recommended charts and a column overview
+    pub report: Option<StructuredResponse>,
+    pub error: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub requested_by: String,
+    pub created_at: DateTime,
+    pub completed_at: Option<DateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatasetInsightResponse {
+    pub dataset_id: String,
+    pub project_id: String,
+    pub status: InsightStatus,
+    pub profile: Option<DataProfile>,
+    pub report: Option<StructuredResponse>,
+    pub error: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub requested_by: String,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+impl From<DatasetInsightReport> for DatasetInsightResponse {
+    fn from(report: DatasetInsightReport) -> Self {
+        DatasetInsightResponse {
+            dataset_id: report.dataset_id,
+            project_id: report.project_id,
+            status: report.status,
+            profile: report.profile,
+            report: report.report,
+            error: report.error,
+            provider: report.provider,
+            model: report.model,
+            requested_by: report.requested_by,
+            created_at: report.created_at.to_string(),
+            completed_at: report.completed_at.map(|d| d.to_string()),
+        }
+    }
+}
+
+// ============================================================================
+// Data Quality
+// ============================================================================
+
+/// What a column's values represent, inferred from its name and values
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SemanticType {
+    Id,
+    Date,
+    Currency,
+    Category,
+    Boolean,
+    Numeric,
+    Text,
+}
+
+impl SemanticType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SemanticType::Id => "id",
+            SemanticType::Date => "date",
+            SemanticType::Currency => "currency",
+            SemanticType::Category => "category",
+            SemanticType::Boolean => "boolean",
+            SemanticType::Numeric => "numeric",
+            SemanticType::Text => "text",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Quantiles {
+    pub p5: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
+
+/// Profile of one dataset column
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub column: String,
+    pub data_type: String,
+    pub semantic_type: SemanticType,
+    pub nulls: i64,
+    /// Share of rows that are empty, 0 to 1
+    pub null_rate: f64,
+    pub distinct: i64,
+    /// Numeric statistics, also for currency values stored as text
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub quantiles: Option<Quantiles>,
+    /// First and last dates of a date column
+    pub earliest: Option<String>,
+    pub latest: Option<String>,
+    pub top_values: Vec<ValueCount>,
+}
+
+/// A dataset's column profiles
+#[derive(Debug, Serialize)]
+pub struct DatasetProfileResponse {
+    pub dataset_id: String,
+    pub row_count: i64,
+    pub columns: Vec<ColumnProfile>,
+}
+
+/// Condition every value of a column is expected to meet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QualityCheck {
+    NotNull,
+    Unique,
+    /// Numeric values within the inclusive bounds; either bound may be open
+    Range { min: Option<f64>, max: Option<f64> },
+    /// Non-empty values must match the whole pattern
+    Regex { pattern: String },
+}
+
+/// A user-defined check on a dataset column. Rules belong to the table name, so
+/// they keep applying when a dataset is deleted and uploaded again.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QualityRule {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub rule_id: String,
+    pub project_id: String,
+    pub dataset_name: String,
+    pub column: String,
+    pub check: QualityCheck,
+    /// Share of checked values allowed to fail, 0 to 1
+    pub max_failure_rate: f64,
+    pub created_by: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateQualityRuleDto {
+    #[validate(length(min = 1, max = 255))]
+    pub column: String,
+    pub check: QualityCheck,
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub max_failure_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QualityRuleResponse {
+    pub rule_id: String,
+    pub project_id: String,
+    pub dataset_name: String,
+    pub column: String,
+    pub check: QualityCheck,
+    pub max_failure_rate: f64,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+impl From<QualityRule> for QualityRuleResponse {
+    fn from(rule: QualityRule) -> Self {
+        QualityRuleResponse {
+            rule_id: rule.rule_id,
+            project_id: rule.project_id,
+            dataset_name: rule.dataset_name,
+            column: rule.column,
+            check: rule.check,
+            max_failure_rate: rule.max_failure_rate,
+            created_by: rule.created_by,
+            created_at: rule.created_at.to_string(),
+        }
+    }
+}
+
+/// Outcome of one rule in a quality run
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QualityRuleResult {
+    pub rule_id: String,
+    pub column: String,
+    pub check: QualityCheck,
+    pub checked: i64,
+    pub failed: i64,
+    pub failure_rate: f64,
+    pub passed: bool,
+    /// A few failing values
+    pub examples: Vec<String>,
+    /// Worse than in the previous run of the same table
+    pub regressed: bool,
+}
+
+/// Something that got worse since the previous run of the same table
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QualityRegression {
+    pub column: String,
+    pub rule_id: Option<String>,
+    pub message: String,
+}
+
+/// Profiles and rule results of a dataset at one point in time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QualityRun {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub run_id: String,
+    pub project_id: String,
+    pub dataset_id: String,
+    pub dataset_name: String,
+    pub row_count: i64,
+    pub profiles: Vec<ColumnProfile>,
+    pub results: Vec<QualityRuleResult>,
+    pub regressions: Vec<QualityRegression>,
+    /// Every rule passed
+    pub passed: bool,
+    pub triggered_by: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QualityRunResponse {
+    pub run_id: String,
+    pub project_id: String,
+    pub dataset_id: String,
+    pub dataset_name: String,
+    pub row_count: i64,
+    pub profiles: Vec<ColumnProfile>,
+    pub results: Vec<QualityRuleResult>,
+    pub regressions: Vec<QualityRegression>,
+    pub passed: bool,
+    pub triggered_by: String,
+    pub created_at: String,
+}
+
+impl From<QualityRun> for QualityRunResponse {
+    fn from(run: QualityRun) -> Self {
+        QualityRunResponse {
+            run_id: run.run_id,
+            project_id: run.project_id,
+            dataset_id: run.dataset_id,
+            dataset_name: run.dataset_name,
+            row_count: run.row_count,
+            profiles: run.profiles,
+            results: run.results,
+            regressions: run.regressions,
+            passed: run.passed,
+            triggered_by: run.triggered_by,
+            created_at: run.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QualityRunQuery {
+    pub limit: Option<i64>,
+    /// Only runs that flagged regressions
+    #[serde(default)]
+    pub regressed: bool,
+}
+
+// ============================================================================
+// Forecasting
+// ============================================================================
+
+/// Period a series is summed or averaged over before forecasting
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastInterval {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// How the measure is combined within a period
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastAggregate {
+    #[default]
+    Sum,
+    Avg,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastMethod {
+    /// Additive Holt-Winters: level, trend and a repeating season
+    HoltWinters,
+    /// Holt's linear trend, used when the history is shorter than two seasons
+    HoltLinear,
+}
+
+/// Forecast a measure over a date column of a dataset or a query result
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct ForecastDto {
+    /// Dataset to read; give this or `sql`
+    pub dataset_id: Option<String>,
+    /// Read-only query over the project's datasets
+    #[validate(length(min = 1, max = 10000))]
+    pub sql: Option<String>,
+    #[validate(length(min = 1, max = 255))]
+    pub date_column: String,
+    #[validate(length(min = 1, max = 255))]
+    pub measure: String,
+    /// Chosen from the date range when omitted
+    pub interval: Option<ForecastInterval>,
+    #[serde(default)]
+    pub aggregate: ForecastAggregate,
+    /// Periods to forecast
+    #[validate(range(min = 1, max = 365))]
+    pub horizon: Option<usize>,
+    /// Coverage of the prediction interval, default 0.95
+    #[validate(range(min = 0.5, max = 0.99))]
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ForecastObservation {
+    pub period: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ForecastPoint {
+    pub period: String,
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForecastResponse {
+    pub date_column: String,
+    pub measure: String,
+    pub interval: ForecastInterval,
+    pub aggregate: ForecastAggregate,
+    pub method: ForecastMethod,
+    /// Periods per season for Holt-Winters
+    pub season_length: Option<usize>,
+    /// Smoothing parameters of the level, trend and season
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: Option<f64>,
+    /// Root mean squared one-step-ahead error over the history
+    pub rmse: f64,
+    pub confidence: f64,
+    /// Rows without a readable date or measure
+    pub skipped_rows: usize,
+    pub history: Vec<ForecastObservation>,
+    pub forecast: Vec<ForecastPoint>,
+    /// History, fitted values and forecast with its interval as a line chart
+    pub chart: ChartData,
+}
+
+// ============================================================================
+// Anomaly Detection
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMethod {
+    /// Distance from the mean in standard deviations
+    #[default]
+    ZScore,
+    /// Distance beyond the quartiles in interquartile ranges
+    Iqr,
+    /// Robust z-score of what remains after removing trend and season
+    Seasonal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalySeverity {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyDirection {
+    Above,
+    Below,
+}
+
+/// What to check for anomalies and how
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct AnomalyOptions {
+    /// Numeric column to check
+    #[validate(length(min = 1, max = 255))]
+    pub column: String,
+    /// Orders the values and sums or averages them per `interval`; without
+    /// it every row is a point, in row order
+    #[validate(length(min = 1, max = 255))]
+    pub date_column: Option<String>,
+    pub interval: Option<ForecastInterval>,
+    #[serde(default)]
+    pub aggregate: ForecastAggregate,
+    #[serde(default)]
+    pub method: AnomalyMethod,
+    /// Score above which a point is flagged: 3 for z-scores, 1.5 for IQR
+    #[validate(range(min = 0.5, max = 10.0))]
+    pub threshold: Option<f64>,
+    /// Points per season for the seasonal method; taken from the interval when omitted
+    #[validate(range(min = 2, max = 366))]
+    pub season_length: Option<usize>,
+}
+
+/// Check a column of a dataset or a query result for anomalies
+#[derive(Debug, Deserialize, Validate)]
+pub struct DetectAnomaliesDto {
+    /// Dataset to read; give this, `sql` or `query_id`
+    pub dataset_id: Option<String>,
+    /// Read-only query over the project's datasets
+    #[validate(length(min = 1, max = 10000))]
+    pub sql: Option<String>,
+    /// Saved analytics query whose result table is read
+    pub query_id: Option<String>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub options: AnomalyOptions,
+}
+
+/// A flagged point
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnomalyPoint {
+    /// Period, or `row N` without a date column
+    pub label: String,
+    pub index: usize,
+    pub value: f64,
+    /// Mean, median or trend plus season, by method
+    pub expected: f64,
+    pub score: f64,
+    pub direction: AnomalyDirection,
+    pub severity: AnomalySeverity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyResponse {
+    pub column: String,
+    pub method: AnomalyMethod,
+    pub threshold: f64,
+    pub season_length: Option<usize>,
+    pub points_checked: usize,
+    /// Rows without a readable number (or date)
+    pub skipped_rows: usize,
+    pub anomalies: Vec<AnomalyPoint>,
+    /// Values and expected values with the anomalies annotated
+    pub chart: ChartData,
+}
+
+// ============================================================================
+// Scheduled Reports
+// ============================================================================
+
+/// Anomaly check that runs on a schedule and notifies the project about new
+/// anomalies. The source is a dataset table by name, so it keeps working when
+/// the dataset is uploaded again, or a query.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduledReport {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub report_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub dataset_name: Option<String>,
+    pub sql: Option<String>,
+    /// Saved analytics query whose result table is checked
+    #[serde(default)]
+    pub query_id: Option<String>,
+    pub detection: AnomalyOptions,
+    pub every_minutes: i64,
+    pub enabled: bool,
+    pub next_run_at: DateTime,
+    pub last_run_at: Option<DateTime>,
+    pub last_error: Option<String>,
+    pub last_anomalies: Vec<AnomalyPoint>,
+    /// Anomalies already notified, so each is reported once
+    #[serde(default)]
+    pub notified: Vec<String>,
+    pub created_by: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateScheduledReportDto {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    /// Dataset table to check; give this, `sql` or `query_id`
+    #[validate(length(min = 1, max = 255))]
+    pub dataset_name: Option<String>,
+    #[validate(length(min = 1, max = 10000))]
+    pub sql: Option<String>,
+    /// Saved analytics query whose result table is checked
+    pub query_id: Option<String>,
+    #[validate(nested)]
+    pub detection: AnomalyOptions,
+    /// Minutes between runs, 5 minutes to 30 days
+    #[validate(range(min = 5, max = 43200))]
+    pub every_minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledReportResponse {
+    pub report_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub dataset_name: Option<String>,
+    pub sql: Option<String>,
+    pub query_id: Option<String>,
+    pub detection: AnomalyOptions,
+    pub every_minutes: i64,
+    pub enabled: bool,
+    pub next_run_at: String,
+    pub last_run_at: Option<String>,
+    pub last_error: Option<String>,
+    pub last_anomalies: Vec<AnomalyPoint>,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+impl From<ScheduledReport> for ScheduledReportResponse {
+    fn from(report: ScheduledReport) -> Self {
+        ScheduledReportResponse {
+            report_id: report.report_id,
+            project_id: report.project_id,
+            name: report.name,
+            dataset_name: report.dataset_name,
+            sql: report.sql,
+            query_id: report.query_id,
+            detection: report.detection,
+            every_minutes: report.every_minutes,
+            enabled: report.enabled,
+            next_run_at: report.next_run_at.to_string(),
+            last_run_at: report.last_run_at.map(|d| d.to_string()),
+            last_error: report.last_error,
+            last_anomalies: report.last_anomalies,
+            created_by: report.created_by,
+            created_at: report.created_at.to_string(),
+        }
+    }
+}
+
+// ============================================================================
+// Notifications
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Anomaly,
+}
+
+/// Message to one user
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub notification_id: String,
+    pub user_id: String,
+    pub project_id: String,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub message: String,
+    /// Scheduled report that raised it
+    pub report_id: Option<String>,
+    pub read: bool,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationResponse {
+    pub notification_id: String,
+    pub project_id: String,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub message: String,
+    pub report_id: Option<String>,
+    pub read: bool,
+    pub created_at: String,
+}
+
+impl From<Notification> for NotificationResponse {
+    fn from(notification: Notification) -> Self {
+        NotificationResponse {
+            notification_id: notification.notification_id,
+            project_id: notification.project_id,
+            kind: notification.kind,
+            title: notification.title,
+            message: notification.message,
+            report_id: notification.report_id,
+            read: notification.read,
+            created_at: notification.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    /// Only unread notifications
+    #[serde(default)]
+    pub unread: bool,
+    pub limit: Option<i64>,
+}
+
+// ============================================================================
+// Chat Attachments
+// ============================================================================
+
+/// Kind of file attached to a chat
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentFormat {
+    Csv,
+    Text,
+    Markdown,
+    Pdf,
+    Html,
+}
+
+impl AttachmentFormat {
+    /// Resolve the format from the file extension, falling back to the Content-Type header
+    pub fn detect(filename: &str, content_type: Option<&str>) -> Option<Self> {
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+        let mime = content_type
+            .and_then(|c| c.split(';').next())
+            .map(|m| m.trim().to_lowercase());
+        if matches!(extension.as_deref(), Some("csv") | Some("tsv"))
+            || matches!(mime.as_deref(), Some("text/csv") | Some("text/tab-separated-values"))
+        {
+            return Some(AttachmentFormat::Csv);
+        }
+
+        DocumentFormat::detect(filename, content_type).map(|format| match format {
+            DocumentFormat::Text => AttachmentFormat::Text,
+            DocumentFormat::Markdown => AttachmentFormat::Markdown,
+            DocumentFormat::Pdf => AttachmentFormat::Pdf,
+            DocumentFormat::Html => AttachmentFormat::Html,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentFormat::Csv => "csv",
+            AttachmentFormat::Text => "text",
+            AttachmentFormat::Markdown => "markdown",
+            AttachmentFormat::Pdf => "pdf",
+            AttachmentFormat::Html => "html",
+        }
+    }
+
+    /// Document format used to extract text, or `None` for tabular files
+    pub fn document_format(&self) -> Option<DocumentFormat> {
+        match self {
+            AttachmentFormat::Csv => None,
+            AttachmentFormat::Text => Some(DocumentFormat::Text),
+            AttachmentFormat::Markdown => Some(DocumentFormat::Markdown),
+            AttachmentFormat::Pdf => Some(DocumentFormat::Pdf),
+            AttachmentFormat::Html => Some(DocumentFormat::Html),
+        }
+    }
+}
+
+/// A file uploaded into a chat. The file itself is stored in GridFS.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatAttachment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub attachment_id: String,
+    pub project_id: String,
+    /// Set once the attachment is sent with a message or uploaded into a conversation
+    pub conversation_id: Option<String>,
+    pub filename: String,
+    pub format: AttachmentFormat,
+    pub size_bytes: i64,
+    /// GridFS file holding the upload
+    pub file_id: ObjectId,
+    /// Extracted text, or schema, statistics and sample rows for tabular files,
+    /// shortened for prompts
+    pub summary: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<ColumnInfo>,
+    pub row_count: Option<i64>,
+    /// Dataset created from this attachment
+    pub dataset_id: Option<String>,
+    pub uploaded_by: String,
+    pub created_at: DateTime,
+}
+
+/// An attachment as listed on the message it was sent with
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageAttachment {
+    pub attachment_id: String,
+    pub filename: String,
+    pub format: AttachmentFormat,
+    pub size_bytes: i64,
+}
+
+impl From<&ChatAttachment> for MessageAttachment {
+    fn from(attachment: &ChatAttachment) -> Self {
+        MessageAttachment {
+            attachment_id: attachment.attachment_id.clone(),
+            filename: attachment.filename.clone(),
+            format: attachment.format,
+            size_bytes: attachment.size_bytes,
+        }
+    }
+}
+
+/// Query parameters for an attachment upload; the file itself is the request body
+#[derive(Debug, Deserialize)]
+pub struct UploadAttachmentQuery {
+    pub project_id: String,
+    pub filename: String,
+    /// Attach straight to an existing conversation
+    pub conversation_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentResponse {
+    pub attachment_id: String,
+    pub project_id: String,
+    pub conversation_id: Option<String>,
+    pub filename: String,
+    pub format: AttachmentFormat,
+    pub size_bytes: i64,
+    pub summary: String,
+    pub columns: Vec<ColumnInfo>,
+    pub row_count: Option<i64>,
+    pub dataset_id: Option<String>,
+    pub uploaded_by: String,
+    pub created_at: String,
+}
+
+impl From<ChatAttachment> for AttachmentResponse {
+    fn from(attachment: ChatAttachment) -> Self {
+        AttachmentResponse {
+            attachment_id: attachment.attachment_id,
+            project_id: attachment.project_id,
+            conversation_id: attachment.conversation_id,
+            filename: attachment.filename,
+            format: attachment.format,
+            size_bytes: attachment.size_bytes,
+            summary: attachment.summary,
+            columns: attachment.columns,
+            row_count: attachment.row_count,
+            dataset_id: attachment.dataset_id,
+            uploaded_by: attachment.uploaded_by,
+            created_at: attachment.created_at.to_string(),
+        }
+    }
+}
+
+/// Turn a tabular attachment into a project dataset
+#[derive(Debug, Deserialize, Validate)]
+pub struct PromoteAttachmentDto {
+    /// Table name; derived from the filename when omitted
+    #[validate(length(min = 1, max = 64))]
+    pub name: Option<String>,
+    #[validate(length(max = 1000))]
+    pub description: Option<String>,
+}
+
+// ============================================================================
+// Project AI Settings
+// ============================================================================
+
+/// Per-project overrides for chat and analytics requests. Unset fields fall
+/// back to the server configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProjectAiSettings {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub project_id: String,
+    /// Provider registry name, e.g. `openai`
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    /// Replaces the default assistant system prompt
+    pub system_prompt: Option<String>,
+    /// Knowledge base chunks retrieved per message
+    pub top_k: Option<i32>,
+    pub updated_by: String,
+    pub updated_at: DateTime,
+}
+
+/// Replaces a project's AI settings; omitted or null fields reset to the default
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProjectAiSettingsDto {
+    #[validate(length(min = 1, max = 64))]
+    pub provider: Option<String>,
+    #[validate(length(min = 1, max = 200))]
+    pub model: Option<String>,
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+    #[validate(range(min = 1, max = 32768))]
+    pub max_tokens: Option<i32>,
+    #[validate(length(min = 1, max = 20000))]
+    pub system_prompt: Option<String>,
+    #[validate(range(min = 1, max = 50))]
+    pub top_k: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectAiSettingsResponse {
+    pub project_id: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub system_prompt: Option<String>,
+    pub top_k: Option<i32>,
+    /// Provider and model used when no override is set
+    pub default_provider: String,
+    pub default_model: Option<String>,
+    pub available_providers: Vec<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+// ============================================================================
+// Prompt Templates
+// ============================================================================
+
+/// Where a prompt template version comes from. Tenant versions override global
+/// ones, which override the built-in defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptScope {
+    Builtin,
+    Global,
+    Tenant,
+}
+
+/// One stored version of a named prompt template
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptTemplate {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub template_id: String,
+    pub name: String,
+    /// `None` for global versions
+    pub tenant_id: Option<String>,
+    pub version: i32,
+    pub content: String,
+    /// Variables referenced as `{{name}}` in the content
+    pub variables: Vec<String>,
+    pub description: Option<String>,
+    /// At most one version per name and tenant is active
+    pub is_active: bool,
+    pub created_by: String,
+    pub created_at: DateTime,
+}
+
+/// Template name and version recorded on generated messages
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PromptVersionRef {
+    pub name: String,
+    pub scope: PromptScope,
+    /// 0 for built-in templates
+    pub version: i32,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePromptVersionDto {
+    #[validate(length(min = 1, max = 50000))]
+    pub content: String,
+    #[validate(length(max = 500))]
+    pub description: Option<String>,
+    /// `tenant` (default) or `global`
+    #[serde(default)]
+    pub scope: Option<PromptScope>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RollbackPromptDto {
+    pub version: i32,
+    #[serde(default)]
+    pub scope: Option<PromptScope>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptScopeQuery {
+    pub scope: Option<PromptScope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptTemplateResponse {
+    pub name: String,
+    pub scope: PromptScope,
+    pub version: i32,
+    pub content: String,
+    pub variables: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl From<PromptTemplate> for PromptTemplateResponse {
+    fn from(template: PromptTemplate) -> Self {
+        PromptTemplateResponse {
+            name: template.name,
+            scope: if template.tenant_id.is_some() { PromptScope::Tenant } else { PromptScope::Global },
+            version: template.version,
+            content: template.content,
+            variables: template.variables,
+            description: template.description,
+            is_active: template.is_active,
+            created_by: Some(template.created_by),
+            created_at: Some(template.created_at.to_string()),
+        }
+    }
+}
+
+// ============================================================================
+// Token Usage and Quotas
+// ============================================================================
+
+/// Tokens consumed by one AI request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub usage_id: String,
+    pub tenant_id: String,
+    pub project_id: String,
+    pub user_id: String,
+    /// What the request was for, e.g. `chat` or `analytics`
+    pub operation: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+    /// Counted locally because the provider reported no usage
+    #[serde(default)]
+    pub estimated: bool,
+    pub created_at: DateTime,
+}
+
+/// Monthly token limit for a tenant, or for one user when `user_id` is set
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenQuota {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub tenant_id: String,
+    pub user_id: Option<String>,
+    /// 0 means unlimited
+    pub monthly_tokens: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SetTokenQuotaDto {
+    /// Omit to set the tenant-wide quota
+    pub user_id: Option<String>,
+    /// `null` removes the override so the configured default applies; 0 means unlimited
+    #[validate(range(min = 0))]
+    pub monthly_tokens: Option<i64>,
+}
+
+/// Query parameters for `GET /api/admin/usage`
+#[derive(Debug, Deserialize)]
+pub struct UsageReportQuery {
+    /// `YYYY-MM`, defaults to the current month
+    pub month: Option<String>,
+    pub project_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+/// Usage totals for one project, user, provider and model
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageAggregate {
+    pub project_id: String,
+    pub user_id: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReportResponse {
+    pub tenant_id: String,
+    pub month: String,
+    pub total_tokens: i64,
+    /// Tenant quota in effect, 0 when unlimited
+    pub tenant_quota: i64,
+    pub breakdown: Vec<UsageAggregate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenQuotaResponse {
+    pub user_id: Option<String>,
+    pub monthly_tokens: i64,
+    pub used_tokens: i64,
+    /// `override` when set for the tenant or user, `default` when from configuration
+    pub source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenQuotasResponse {
+    pub tenant_id: String,
+    pub month: String,
+    pub tenant: TokenQuotaResponse,
+    /// Applies to users without an override, 0 when unlimited
+    pub default_user_monthly_tokens: i64,
+    pub users: Vec<TokenQuotaResponse>,
+}
+
+// ============================================================================
+// PII Redaction
+// ============================================================================
+
+/// Built-in kinds of personal data removed from prompts
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiCategory {
+    Email,
+    Phone,
+    CardNumber,
+    NationalId,
+}
+
+impl PiiCategory {
+    pub fn all() -> Vec<PiiCategory> {
+        vec![PiiCategory::Email, PiiCategory::Phone, PiiCategory::CardNumber, PiiCategory::NationalId]
+    }
+
+    /// Placeholder label, as in `[EMAIL_1]`
+    pub fn label(&self) -> &'static str {
+        match self {
+            PiiCategory::Email => "EMAIL",
+            PiiCategory::Phone => "PHONE",
+            PiiCategory::CardNumber => "CARD",
+            PiiCategory::NationalId => "NATIONAL_ID",
+        }
+    }
+}
+
+/// Tenant-defined regex; matches are replaced with `[<NAME>_<n>]`
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct CustomRedactionPattern {
+    #[validate(length(min = 1, max = 32))]
+    pub name: String,
+    #[validate(length(min = 1, max = 500))]
+    pub pattern: String,
+}
+
+/// A tenant's redaction settings, overriding the configured default
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedactionSettings {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub tenant_id: String,
+    pub enabled: bool,
+    pub categories: Vec<PiiCategory>,
+    #[serde(default)]
+    pub custom_patterns: Vec<CustomRedactionPattern>,
+    pub updated_by: String,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateRedactionSettingsDto {
+    pub enabled: bool,
+    /// Defaults to every built-in category
+    pub categories: Option<Vec<PiiCategory>>,
+    #[serde(default)]
+    #[validate(length(max = 20), nested)]
+    pub custom_patterns: Vec<CustomRedactionPattern>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedactionSettingsResponse {
+    pub tenant_id: String,
+    pub enabled: bool,
+    pub categories: Vec<PiiCategory>,
+    pub custom_patterns: Vec<CustomRedactionPattern>,
+    /// `override` when set for the tenant, `default` when from configuration
+    pub source: String,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+// ============================================================================
+// Guardrails
+// ============================================================================
+
+/// What happens when a prompt input looks like an injection attempt
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    /// Let it through and record the match
+    Warn,
+    /// Remove the sentences that matched
+    Strip,
+    /// Refuse the message, or leave the passage out of the prompt
+    Block,
+}
+
+impl GuardrailAction {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "warn" => Some(GuardrailAction::Warn),
+            "strip" => Some(GuardrailAction::Strip),
+            "block" => Some(GuardrailAction::Block),
+            _ => None,
+        }
+    }
+}
+
+/// Where screened text came from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailSource {
+    UserInput,
+    Knowledge,
+    ToolResult,
+}
+
+/// A tenant's guardrail policy, overriding the configured default
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GuardrailPolicy {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub tenant_id: String,
+    pub enabled: bool,
+    pub user_input_action: GuardrailAction,
+    /// Applies to knowledge base passages and tool results
+    pub context_action: GuardrailAction,
+    pub updated_by: String,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateGuardrailPolicyDto {
+    pub enabled: bool,
+    pub user_input_action: GuardrailAction,
+    pub context_action: GuardrailAction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuardrailPolicyResponse {
+    pub tenant_id: String,
+    pub enabled: bool,
+    pub user_input_action: GuardrailAction,
+    pub context_action: GuardrailAction,
+    /// `override` when set for the tenant, `default` when from configuration
+    pub source: String,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// A guardrail match, kept for admins to review
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GuardrailEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub event_id: String,
+    pub tenant_id: String,
+    pub project_id: String,
+    pub user_id: String,
+    /// Request type, e.g. `chat` or `analytics`
+    pub operation: String,
+    pub source: GuardrailSource,
+    /// Ids of the checks that matched
+    pub rules: Vec<String>,
+    pub action: GuardrailAction,
+    /// The first matching sentence, shortened
+    pub excerpt: String,
+    #[serde(default)]
+    pub reviewed: bool,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+/// Query parameters for `GET /api/admin/guardrails/events`
+#[derive(Debug, Deserialize)]
+pub struct GuardrailEventQuery {
+    pub reviewed: Option<bool>,
+    pub project_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuardrailEventResponse {
+    pub event_id: String,
+    pub project_id: String,
+    pub user_id: String,
+    pub operation: String,
+    pub source: GuardrailSource,
+    pub rules: Vec<String>,
+    pub action: GuardrailAction,
+    pub excerpt: String,
+    pub reviewed: bool,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
+    pub created_at: String,
+}
+
+impl From<GuardrailEvent> for GuardrailEventResponse {
+    fn from(event: GuardrailEvent) -> Self {
+        GuardrailEventResponse {
+            event_id: event.event_id,
+            project_id: event.project_id,
+            user_id: event.user_id,
+            operation: event.operation,
+            source: event.source,
+            rules: event.rules,
+            action: event.action,
+            excerpt: event.excerpt,
+            reviewed: event.reviewed,
+            reviewed_by: event.reviewed_by,
+            reviewed_at: event.reviewed_at.map(|t| t.to_string()),
+            created_at: event.created_at.to_string(),
+        }
+    }
+}
+
+// ============================================================================
+// Audit Log
+// ============================================================================
+
+/// A security-relevant event in a tenant
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditLogEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub audit_id: String,
+    pub tenant_id: String,
+    pub project_id: Option<String>,
+    pub user_id: String,
+    /// What happened, e.g. `pii.redacted`
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime,
+}
+
+/// Query parameters for `GET /api/admin/audit`
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub action: Option<String>,
+    pub project_id: Option<String>,
+    pub user_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogEntryResponse {
+    pub audit_id: String,
+    pub project_id: Option<String>,
+    pub user_id: String,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: String,
+}
+
+impl From<AuditLogEntry> for AuditLogEntryResponse {
+    fn from(entry: AuditLogEntry) -> Self {
+        AuditLogEntryResponse {
+            audit_id: entry.audit_id,
+            project_id: entry.project_id,
+            user_id: entry.user_id,
+            action: entry.action,
+            details: entry.details,
+            created_at: entry.created_at.to_string(),
+        }
+    }
+}
+
+// ============================================================================
+// Search Models
+
+/// Query parameters for `GET /api/search`
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Restrict results to one project
+    pub project_id: Option<String>,
+    /// Comma-separated list of `conversations` and `queries` (default both)
+    pub types: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Conversation,
+    AnalyticsQuery,
+}
+
+/// Character offsets of a matched term inside a snippet (end is exclusive)
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    /// conversation_id or query_id
+    pub id: String,
+    pub project_id: String,
+    pub title: String,
+    pub snippet: String,
+    pub highlights: Vec<HighlightRange>,
+    /// Index of the matching message for conversation hits
+    pub message_index: Option<usize>,
+    pub score: f64,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+// Rendering Module Models
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RenderContent {
+    Text { content: String },
+    Chart { data: ChartData },
+    Equation { latex: String, display: Option<bool> },
+    Table { data: TableData },
+    Dataset { data: DatasetData },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChartData {
+    pub chart_type: ChartType,
+    pub title: Option<String>,
+    pub labels: Vec<String>,
+    pub datasets: Vec<ChartDataset>,
+    /// Notes on individual points, such as detected anomalies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<ChartAnnotation>,
+}
+
+/// Note on the point at `label`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChartAnnotation {
+    pub label: String,
+    pub value: f64,
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -1,349 +1,239 @@
-use reqwest::Client;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use std::pin::Pin;
-use futures::Stream;
-use bytes::Bytes;
-use crate::config::AIProvider;
-use crate::models::StructuredResponse;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use crate::models::{ProjectAiSettings, StructuredResponse};
+use crate::services::prompts::{
+    PromptSet, ANALYTICS_SYSTEM, CHART_INSTRUCTION, CHAT_SYSTEM, CONVERSATION_TITLE,
+    ATTACHMENTS_CONTEXT, FOLLOW_UP_SUGGESTIONS, KNOWLEDGE_CONTEXT, STRUCTURED_SYSTEM, TOOLS_INSTRUCTION,
+};
+use crate::services::llm::{
+    ChatEventStream, ChatStreamEvent, CompletionRequest, LlmMessage, LlmProvider, LlmResponse,
+    ProviderConfig, ProviderRegistry, TokenUsage, ToolDefinition,
+};
+use crate::services::llm::resilience::{
+    BreakerRegistry, BreakerStatus, ResilienceSettings, ResilientProvider, RetryPolicy,
+};
+use crate::services::response_cache::{CacheKey, QuestionEmbedding, ResponseCache};
+use futures::StreamExt;
+
+/// Providers built for project overrides, keyed by provider and model
+type ProviderCache = RwLock<HashMap<(String, String), Arc<ResilientProvider>>>;
+
+/// Retrieval depth passed to providers that do their own retrieval
+const DEFAULT_PROVIDER_TOP_K: i32 = 5;
 
 // ============================================================================
-// OpenAI / LM Studio Compatible Request/Response Structures
-// ============================================================================
-
-#[derive(Debug, Serialize)]
-struct ChatCompletionRequest {
-    model: String,
-    messages: Vec<Message>,
-    temperature: f32,
-    max_tokens: i32,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Message {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatCompletionResponse {
-    choices: Vec<Choice>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: Message,
-}
-
-// ============================================================================
-// Custom RAG API Request/Response Structures
+// AI Service Implementation
 // ============================================================================
 
-#[derive(Debug, Serialize)]
-struct CustomRAGRequest {
-    message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    session_id: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    top_k: Option<i32>,
-    temperature: f32,
-    max_tokens: i32,
-}
-
-#[derive(Debug, Deserialize)]
-struct CustomRAGResponse {
-    #[serde(default)]
-    session_id: Option<String>,
-    message: RAGMessage,
-    #[serde(default)]
-    sources: Vec<RAGSource>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-struct RAGMessage {
-    #[serde(default)]
-    id: Option<String>,
-    #[serde(default)]
-    role: String,
-    content: String,
-    #[serde(default)]
-    sources: Vec<RAGSource>,
-    #[serde(default)]
-    created_at: Option<String>,
+/// Project material a chat reply is grounded in, already formatted for the prompt
+#[derive(Debug, Clone, Default)]
+pub struct Grounding {
+    /// Numbered knowledge base excerpts
+    pub knowledge: Option<String>,
+    /// Summaries of files attached to the conversation
+    pub attachments: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct RAGSource {
-    #[serde(default)]
-    content: String,
-    #[serde(default)]
-    metadata: Option<serde_json::Value>,
+impl Grounding {
+    fn push_messages(&self, messages: &mut Vec<LlmMessage>, prompts: &mut PromptSet) {
+        if let Some(docs) = &self.knowledge {
+            messages.push(LlmMessage::system(prompts.render(KNOWLEDGE_CONTEXT, &[("documents", docs)])));
+        }
+        if let Some(files) = &self.attachments {
+            messages.push(LlmMessage::system(prompts.render(ATTACHMENTS_CONTEXT, &[("files", files)])));
+        }
+    }
 }
 
-// ============================================================================
-// AI Service Implementation
-// ============================================================================
-
+/// Prompt building on top of the configured [`LlmProvider`]
 #[derive(Clone)]
 pub struct AIService {
-    client: Client,
-    api_url: String,
-    model_name: String,
-    provider: AIProvider,
-    api_key: Option<String>,
+    provider: Arc<ResilientProvider>,
+    /// Used while the selected provider's circuit breaker is open
+    fallback: Option<Arc<ResilientProvider>>,
+    registry: Arc<ProviderRegistry>,
+    breakers: Arc<BreakerRegistry>,
+    retry: RetryPolicy,
+    /// Connection settings per provider name, used for project overrides
+    endpoints: Arc<HashMap<String, ProviderConfig>>,
+    overrides: Arc<ProviderCache>,
+    /// Answers reused for repeated project questions, when enabled
+    cache: Option<Arc<ResponseCache>>,
 }
 
 impl AIService {
-    pub fn new(api_url: String, model_name: String, provider: AIProvider, api_key: Option<String>) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .expect("Failed to create HTTP client");
-
-        AIService {
-            client,
-            api_url,
-            model_name,
+    /// Build the default provider from `config` and the optional `fallback`.
+    /// `endpoints` holds the settings used when a project selects a different provider.
+    pub fn new(
+        registry: ProviderRegistry,
+        config: ProviderConfig,
+        fallback: Option<ProviderConfig>,
+        endpoints: HashMap<String, ProviderConfig>,
+        resilience: ResilienceSettings,
+    ) -> Result<Self, String> {
+        let breakers = Arc::new(BreakerRegistry::new(resilience.breaker));
+        let build = |config: &ProviderConfig| -> Result<Arc<ResilientProvider>, String> {
+            Ok(Arc::new(ResilientProvider::new(
+                registry.build(config)?,
+                breakers.breaker(&config.name, &config.api_url),
+                resilience.retry,
+            )))
+        };
+
+        let provider = build(&config)?;
+        let fallback = match fallback {
+            Some(ref f) if f.name == config.name && f.api_url == config.api_url => {
+                return Err("AI_FALLBACK_PROVIDER must use a different endpoint than AI_PROVIDER".to_string());
+            }
+            Some(ref f) => Some(build(f)?),
+            None => None,
+        };
+        let mut endpoints = endpoints;
+        endpoints.insert(config.name.clone(), config);
+
+        Ok(AIService {
             provider,
-            api_key,
-        }
+            fallback,
+            registry: Arc::new(registry),
+            breakers,
+            retry: resilience.retry,
+            endpoints: Arc::new(endpoints),
+            overrides: Arc::new(RwLock::new(HashMap::new())),
+            cache: None,
+        })
     }
 
-    /// Get the provider name for logging purposes
-    fn provider_name(&self) -> &str {
-        match self.provider {
-            AIProvider::OpenAI => "OpenAI",
-            AIProvider::LMStudio => "LM Studio",
-            AIProvider::CustomRAG => "Custom RAG API",
-        }
+    /// Serve repeated chat and analytics questions from `cache`
+    pub fn with_response_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
-    // ========================================================================
-    // OpenAI / LM Studio Methods
-    // ========================================================================
-
-    /// Build request for OpenAI-compatible APIs (OpenAI, LM Studio)
-    fn build_openai_request(&self, request: &ChatCompletionRequest) -> Result<reqwest::RequestBuilder, String> {
-        let url = format!("{}/v1/chat/completions", self.api_url);
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        
-        // Add authorization header for OpenAI or if API key is provided
-        if let Some(ref api_key) = self.api_key {
-            let auth_value = format!("Bearer {}", api_key);
-            headers.insert(
-                AUTHORIZATION,
-                HeaderValue::from_str(&auth_value)
-                    .map_err(|e| format!("Invalid API key format: {}", e))?
-            );
+    /// Drop a project's cached answers, e.g. after its knowledge base changed.
+    /// Returns how many cache keys were removed.
+    pub async fn invalidate_cache(&self, project_id: &str) -> Result<u64, String> {
+        match self.cache {
+            Some(ref cache) => cache.invalidate_project(project_id).await,
+            None => Ok(0),
         }
-        
-        Ok(self.client
-            .post(&url)
-            .headers(headers)
-            .json(request))
     }
 
-    /// Send request to OpenAI-compatible APIs
-    async fn send_openai_request(&self, request: ChatCompletionRequest) -> Result<String, String> {
-        let req = self.build_openai_request(&request)?;
-        
-        let response = req
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request to {}: {}", self.provider_name(), e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("{} API error ({}): {}", self.provider_name(), status, error_text));
-        }
+    /// Circuit breaker state of every provider endpoint used so far
+    pub fn breaker_statuses(&self) -> Vec<BreakerStatus> {
+        self.breakers.statuses()
+    }
 
-        let ai_response: ChatCompletionResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse {} response: {}", self.provider_name(), e))?;
+    /// Identifier of the configured provider
+    pub fn provider_id(&self) -> &str {
+        self.provider.name()
+    }
 
-        ai_response
-            .choices
-            .first()
-            .map(|choice| choice.message.content.clone())
-            .ok_or_else(|| "No response from AI model".to_string())
+    /// Model name of the configured provider
+    pub fn model_label(&self) -> Option<String> {
+        self.provider.model().map(str::to_string)
     }
 
-    // ========================================================================
-    // Custom RAG API Methods
-    // ========================================================================
+    /// Provider names a project may select
+    pub fn provider_names(&self) -> Vec<String> {
+        self.registry.names()
+    }
 
-    /// Build request for Custom RAG API
-    fn build_rag_request(&self, request: &CustomRAGRequest) -> Result<reqwest::RequestBuilder, String> {
-        let url = format!("{}/api/v1/chat", self.api_url);
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        
-        // Add X-API-Key header for Custom RAG
-        if let Some(ref api_key) = self.api_key {
-            headers.insert(
-                HeaderValue::from_static("X-API-Key").to_str()
-                    .map(|_| "X-API-Key")
-                    .map(|name| reqwest::header::HeaderName::from_static(name))
-                    .unwrap_or(reqwest::header::HeaderName::from_static("x-api-key")),
-                HeaderValue::from_str(api_key)
-                    .map_err(|e| format!("Invalid API key format: {}", e))?
-            );
+    /// Provider for a project: the configured one unless the project overrides the
+    /// provider or model, or the fallback while that provider's circuit is open
+    pub fn provider_for(&self, settings: Option<&ProjectAiSettings>) -> Arc<dyn LlmProvider> {
+        let selected = self.selected_provider(settings);
+        match self.fallback {
+            Some(ref fallback) if !selected.is_available() && fallback.is_available() => {
+                log::warn!(
+                    "{} circuit is open, failing over to {}",
+                    selected.display_name(),
+                    fallback.display_name()
+                );
+                fallback.clone()
+            }
+            _ => selected,
         }
-        
-        Ok(self.client
-            .post(&url)
-            .headers(headers)
-            .json(request))
     }
 
-    /// Send request to Custom RAG API
-    async fn send_rag_request(&self, query: &str, session_id: Option<String>, temperature: f32, max_tokens: i32) -> Result<String, String> {
-        let request = CustomRAGRequest {
-            message: query.to_string(),
-            session_id,
-            top_k: Some(5),
-            temperature,
-            max_tokens,
+    /// Overrides that cannot be built fall back to the default provider
+    fn selected_provider(&self, settings: Option<&ProjectAiSettings>) -> Arc<ResilientProvider> {
+        let settings = match settings {
+            Some(s) if s.provider.is_some() || s.model.is_some() => s,
+            _ => return self.provider.clone(),
         };
 
-        let url = format!("{}/api/v1/chat", self.api_url);
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        
-        // Add X-API-Key header for Custom RAG
-        if let Some(ref api_key) = self.api_key {
-            headers.insert(
-                reqwest::header::HeaderName::from_static("x-api-key"),
-                HeaderValue::from_str(api_key)
-                    .map_err(|e| format!("Invalid API key format: {}", e))?
-            );
+        let name = settings.provider.as_deref().unwrap_or(self.provider.name());
+        let mut config = match self.endpoints.get(name) {
+            Some(config) => config.clone(),
+            None => {
+                log::warn!("No endpoint configured for AI provider '{}', using default", name);
+                return self.provider.clone();
+            }
+        };
+        if let Some(ref model) = settings.model {
+            config.model_name = model.clone();
         }
+        // The knowledge base always embeds with the default provider
+        config.embedding_model = None;
 
-        let response = self.client
-            .post(&url)
-            .headers(headers)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request to {}: {}", self.provider_name(), e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("{} API error ({}): {}", self.provider_name(), status, error_text));
+        let key = (config.name.clone(), config.model_name.clone());
+        if let Some(provider) = self.overrides.read().ok().and_then(|cache| cache.get(&key).cloned()) {
+            return provider;
         }
 
-        let rag_response: CustomRAGResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse {} response: {}", self.provider_name(), e))?;
-
-        Ok(rag_response.message.content)
+        match self.registry.build(&config) {
+            Ok(provider) => {
+                let breaker = self.breakers.breaker(&config.name, &config.api_url);
+                let provider = Arc::new(ResilientProvider::new(provider, breaker, self.retry));
+                if let Ok(mut cache) = self.overrides.write() {
+                    cache.insert(key, provider.clone());
+                }
+                provider
+            }
+            Err(e) => {
+                log::warn!("Failed to build AI provider override: {}", e);
+                self.provider.clone()
+            }
+        }
     }
 
-    /// Send streaming request to Custom RAG API
-    /// Returns a stream of bytes from the SSE response
-    pub async fn send_rag_stream_request(
-        &self,
-        query: &str,
-        session_id: Option<String>,
-        top_k: Option<i32>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>, String> {
-        let request = CustomRAGRequest {
-            message: query.to_string(),
-            session_id,
-            top_k: top_k.or(Some(3)),
-            temperature: 0.7,
-            max_tokens: 2048,
-        };
+    /// Sampling parameters after project overrides
+    fn sampling(settings: Option<&ProjectAiSettings>, temperature: f32, max_tokens: i32) -> (f32, i32) {
+        (
+            settings.and_then(|s| s.temperature).unwrap_or(temperature),
+            settings.and_then(|s| s.max_tokens).unwrap_or(max_tokens),
+        )
+    }
 
-        let url = format!("{}/api/v1/chat/stream", self.api_url);
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-
-        // Add X-API-Key header for Custom RAG
-        if let Some(ref api_key) = self.api_key {
-            headers.insert(
-                reqwest::header::HeaderName::from_static("x-api-key"),
-                HeaderValue::from_str(api_key)
-                    .map_err(|e| format!("Invalid API key format: {}", e))?
-            );
+    /// Project system prompt, or the `template` prompt when none is set
+    fn system_prompt(settings: Option<&ProjectAiSettings>, prompts: &mut PromptSet, template: &str) -> String {
+        match settings.and_then(|s| s.system_prompt.as_deref()) {
+            Some(prompt) => prompt.to_string(),
+            None => prompts.render(template, &[]),
         }
+    }
 
-        println!("Sending streaming request to: {}", url);
-
-        let response = self.client
-            .post(&url)
-            .headers(headers)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send streaming request to {}: {}", self.provider_name(), e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("{} API error ({}): {}", self.provider_name(), status, error_text));
-        }
+    /// Embeddings model used for the knowledge base, if the provider offers one
+    pub fn embedding_model(&self) -> Option<&str> {
+        self.provider.embedding_model()
+    }
 
-        Ok(Box::pin(response.bytes_stream()))
+    /// Embed texts with the provider. Vectors are returned in input order.
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        Ok(self.provider.embed(inputs).await?)
     }
 
-    /// Stream chat message for Custom RAG API
-    /// Combines messages into a query and returns a streaming response
-    pub async fn stream_chat_message(
+    /// Messages for a streamed chat reply. `tools` adds the instruction for
+    /// replies that may call server-side tools.
+    pub fn chat_messages(
         &self,
         message: &str,
         context: Option<&str>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>, String> {
-        // Chart instruction for rendering visual charts
-        let chart_instruction = r#"
-IMPORTANT: When the user asks for a chart or visualization, you MUST output the data in a JSON code block.
-
-Supported chart types: pie, bar, line, doughnut
-
-Use this EXACT format inside a ```json code block:
-{
-  "type": "pie",
-  "title": "Chart Title",
-  "labels": ["Category A", "Category B", "Category C"],
-  "data": [100, 200, 300]
-}
-
-Examples:
-
-For a PIE CHART showing tax vs take-home:
-```json
-{"type": "pie", "title": "Income Distribution", "labels": ["Tax Payable", "Take-Home Salary"], "data": [351000, 1649000]}
-```
-
-For a BAR CHART showing tax by slab:
-```json
-{"type": "bar", "title": "Tax by Income Slab", "labels": ["0-2.5L", "2.5L-5L", "5L-10L", "10L-20L"], "data": [0, 12500, 100000, 300000]}
-```
-
-For a LINE CHART showing trends:
-```json
-{"type": "line", "title": "Monthly Trend", "labels": ["Jan", "Feb", "Mar"], "data": [100, 150, 200]}
-```
-
-For a DOUGHNUT CHART:
-```json
-{"type": "doughnut", "title": "Expense Breakdown", "labels": ["Rent", "Food", "Transport"], "data": [500, 200, 100]}
-```
-
-ALWAYS use the correct "type" field based on what the user asks for:
-- "bar chart" or "bar graph" → type: "bar"
-- "pie chart" or "pie graph" → type: "pie"
-- "line chart" or "line graph" or "trend" → type: "line"
-- "doughnut" or "donut" → type: "doughnut"
-- Default to "pie" if no specific type is mentioned
-"#;
-
+        grounding: &Grounding,
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+        tools: bool,
+    ) -> Vec<LlmMessage> {
         // Check if user is asking for a chart
         let message_lower = message.to_lowercase();
         let wants_chart = message_lower.contains("chart") 
@@ -355,208 +245,384 @@ ALWAYS use the correct "type" field based on what the user asks for:
             || message_lower.contains("doughnut")
             || message_lower.contains("donut");
 
-        // Build the full query with context and chart instructions
-        let full_query = match (context, wants_chart) {
-            (Some(ctx), true) => format!("{}\n\nContext:\n{}\n\nQuery: {}", chart_instruction, ctx, message),
-            (Some(ctx), false) => format!("Context:\n{}\n\nQuery: {}", ctx, message),
-            (None, true) => format!("{}\n\nQuery: {}", chart_instruction, message),
-            (None, false) => message.to_string(),
+        let mut messages = Vec::new();
+        if let Some(prompt) = settings.and_then(|s| s.system_prompt.as_deref()) {
+            messages.push(LlmMessage::system(prompt));
+        }
+        if tools {
+            messages.push(LlmMessage::system(prompts.render(TOOLS_INSTRUCTION, &[])));
+        }
+        if wants_chart {
+            messages.push(LlmMessage::system(prompts.render(CHART_INSTRUCTION, &[])));
+        }
+        grounding.push_messages(&mut messages, prompts);
+        if let Some(ctx) = context {
+            messages.push(LlmMessage::system(format!("Previous conversation:\n{}", ctx)));
+        }
+        messages.push(LlmMessage::user(message));
+        messages
+    }
+
+    /// Whether the project's provider can be offered tools
+    pub fn supports_tools(&self, settings: Option<&ProjectAiSettings>) -> bool {
+        self.provider_for(settings).supports_tools()
+    }
+
+    /// Stream a reply to `messages` from the project's provider. When the provider
+    /// has no tool support, `tools` are dropped and earlier tool turns become text.
+    pub async fn stream_messages(
+        &self,
+        messages: Vec<LlmMessage>,
+        tools: Vec<ToolDefinition>,
+        settings: Option<&ProjectAiSettings>,
+    ) -> Result<ChatEventStream, String> {
+        let provider = self.provider_for(settings);
+        let (messages, tools) = if provider.supports_tools() {
+            (messages, tools)
+        } else {
+            (messages.iter().map(LlmMessage::without_tools).collect(), vec![])
         };
 
-        self.send_rag_stream_request(&full_query, None, Some(5)).await
+        let (temperature, max_tokens) = Self::sampling(settings, 0.7, 2048);
+        let prompt_text = Self::prompt_text(&messages);
+        let stream = provider
+            .stream(CompletionRequest {
+                messages,
+                temperature,
+                max_tokens,
+                top_k: Some(settings.and_then(|s| s.top_k).unwrap_or(DEFAULT_PROVIDER_TOP_K)),
+                tools,
+            })
+            .await?;
+
+        Ok(Self::with_usage(stream, prompt_text))
+    }
+
+    /// Text of all messages, for estimating prompt tokens
+    fn prompt_text(messages: &[LlmMessage]) -> String {
+        messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// End the stream with an estimated usage event when the provider sent none
+    fn with_usage(stream: ChatEventStream, prompt_text: String) -> ChatEventStream {
+        let mut stream = stream;
+        Box::pin(async_stream::stream! {
+            let mut completion = String::new();
+            let mut reported = false;
+
+            while let Some(event) = stream.next().await {
+                match &event {
+                    Ok(ChatStreamEvent::Content(text)) => completion.push_str(text),
+                    Ok(ChatStreamEvent::Usage(_)) => reported = true,
+                    _ => {}
+                }
+                yield event;
+            }
+
+            if !reported {
+                yield Ok(ChatStreamEvent::Usage(TokenUsage::estimate(&prompt_text, &completion)));
+            }
+        })
     }
 
     // ========================================================================
     // Unified Chat Request Method
     // ========================================================================
 
-    /// Send a chat request to the configured AI provider
-    async fn send_chat_request(&self, messages: Vec<Message>, temperature: f32, max_tokens: i32) -> Result<String, String> {
-        println!("##############################################start");
-        println!("Sending chat request to {} with model {}", self.provider_name(), self.model_name);
-        // Log request details for debugging
-        println!("Request URL: {}", match self.provider {
-            AIProvider::OpenAI | AIProvider::LMStudio => format!("{}/v1/chat/completions", self.api_url),
-            AIProvider::CustomRAG => format!("{}/api/v1/chat", self.api_url),
-        });
-        println!("Headers: Content-Type: application/json");
-        if let Some(ref api_key) = self.api_key {
-            match self.provider {
-            AIProvider::OpenAI | AIProvider::LMStudio => println!("Headers: Authorization: Bearer {}...", &api_key[..api_key.len().min(10)]),
-            AIProvider::CustomRAG => println!("Headers: X-API-Key: {}...", &api_key[..api_key.len().min(10)]),
-            }
+    /// Send a chat request to the project's AI provider
+    async fn send_chat_request(
+        &self,
+        messages: Vec<LlmMessage>,
+        temperature: f32,
+        max_tokens: i32,
+        settings: Option<&ProjectAiSettings>,
+    ) -> Result<LlmResponse, String> {
+        let provider = self.provider_for(settings);
+        let (temperature, max_tokens) = Self::sampling(settings, temperature, max_tokens);
+        log::debug!(
+            "Sending chat request to {} with model {:?}",
+            provider.display_name(),
+            provider.model()
+        );
+
+        let prompt_text = Self::prompt_text(&messages);
+        let mut response = provider
+            .complete(CompletionRequest {
+                messages,
+                temperature,
+                max_tokens,
+                top_k: settings.and_then(|s| s.top_k),
+                tools: vec![],
+            })
+            .await?;
+
+        let usage = *response
+            .usage
+            .get_or_insert_with(|| TokenUsage::estimate(&prompt_text, &response.content));
+        log::debug!(
+            "{} usage: {} prompt + {} completion tokens{}",
+            provider.display_name(),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            if usage.estimated { " (estimated)" } else { "" }
+        );
+
+        Ok(response)
+    }
+
+    /// [`Self::send_chat_request`] behind the response cache. Requests without a
+    /// project are never cached so invalidation always reaches every entry.
+    async fn send_cacheable(
+        &self,
+        project_id: Option<&str>,
+        messages: Vec<LlmMessage>,
+        temperature: f32,
+        max_tokens: i32,
+        settings: Option<&ProjectAiSettings>,
+    ) -> Result<LlmResponse, String> {
+        let (cache, project_id) = match (&self.cache, project_id) {
+            (Some(cache), Some(project_id)) => (cache, project_id),
+            _ => return self.send_chat_request(messages, temperature, max_tokens, settings).await,
+        };
+
+        let provider = self.provider_for(settings);
+        let (resolved_temperature, resolved_max_tokens) = Self::sampling(settings, temperature, max_tokens);
+        let key = CacheKey::new(
+            project_id,
+            provider.name(),
+            provider.model(),
+            resolved_temperature,
+            resolved_max_tokens,
+            settings.and_then(|s| s.top_k),
+            &messages,
+        );
+
+        let embedding = if cache.similarity_enabled() {
+            Some(self.question_embedding(key.question()).await)
+        } else {
+            None
+        };
+
+        if let Some(response) = cache.lookup(&key, embedding.as_ref()).await {
+            log::debug!("Serving cached answer for project {}", project_id);
+            return Ok(response);
         }
-        println!("Payload: {:?}", serde_json::json!({
-            "model": &self.model_name,
-            "messages": &messages,
-            "temperature": temperature,
-            "max_tokens": max_tokens
-        }));
-        println!("##############################################end");
-        match self.provider {
-            AIProvider::OpenAI | AIProvider::LMStudio => {
-                let request = ChatCompletionRequest {
-                    model: self.model_name.clone(),
-                    messages,
-                    temperature,
-                    max_tokens,
-                };
-                self.send_openai_request(request).await
-            }
-            AIProvider::CustomRAG => {
-                // For RAG, combine messages into a single query
-                // Use the last user message as the primary query
-                let query = messages
-                    .iter()
-                    .filter(|m| m.role == "user")
-                    .last()
-                    .map(|m| m.content.clone())
-                    .unwrap_or_default();
-                
-                // Include context from system messages if present
-                let context: Vec<String> = messages
-                    .iter()
-                    .filter(|m| m.role == "system" || m.role == "assistant")
-                    .map(|m| m.content.clone())
-                    .collect();
-                
-                let full_query = if context.is_empty() {
-                    query
-                } else {
-                    format!("Context:\n{}\n\nQuery: {}", context.join("\n"), query)
-                };
-                
-                self.send_rag_request(&full_query, None, temperature, max_tokens).await
+
+        let response = self.send_chat_request(messages, temperature, max_tokens, settings).await?;
+        cache.store(&key, &response, embedding.as_ref()).await;
+        Ok(response)
+    }
+
+    /// Provider embedding of a question, or the local one when the provider has none
+    async fn question_embedding(&self, question: &str) -> QuestionEmbedding {
+        if let Some(model) = self.embedding_model() {
+            match self.embed(&[question.to_string()]).await {
+                Ok(mut vectors) if vectors.len() == 1 => {
+                    return QuestionEmbedding {
+                        model: model.to_string(),
+                        vector: vectors.remove(0),
+                    };
+                }
+                Ok(_) => log::warn!("Embeddings model returned no vector for cache lookup"),
+                Err(e) => log::warn!("Failed to embed question for cache lookup: {}", e),
             }
         }
+        QuestionEmbedding::local(question)
     }
 
+    /// Analytics answer; repeated questions are served from the cache when a project is given
     pub async fn process_analytics_query(
         &self,
+        project_id: Option<&str>,
         query: &str,
         context: Option<&str>,
-    ) -> Result<String, String> {
-        let system_message = "You are DencapsBI, an advanced AI analytics assistant. \
-            You help users analyze data, generate insights, and create visualizations. \
-            Provide clear, actionable, and data-driven responses. \
-            When appropriate, suggest SQL queries, statistical analyses, or visualization recommendations.";
-
-        let mut messages = vec![Message {
-            role: "system".to_string(),
-            content: system_message.to_string(),
-        }];
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<LlmResponse, String> {
+        let mut messages = vec![LlmMessage::system(Self::system_prompt(settings, prompts, ANALYTICS_SYSTEM))];
 
         if let Some(ctx) = context {
-            messages.push(Message {
-                role: "assistant".to_string(),
-                content: format!("Context: {}", ctx),
-            });
+            messages.push(LlmMessage::assistant(format!("Context: {}", ctx)));
         }
 
-        messages.push(Message {
-            role: "user".to_string(),
-            content: query.to_string(),
-        });
+        messages.push(LlmMessage::user(query));
 
-        self.send_chat_request(messages, 0.7, 2000).await
+        self.send_cacheable(project_id, messages, 0.7, 2000, settings).await
     }
 
+    /// Insights, trends and recommendations from a statistical summary of a dataset
     pub async fn generate_data_insights(
         &self,
+        project_id: Option<&str>,
         data_summary: &str,
-    ) -> Result<String, String> {
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<LlmResponse, String> {
         let query = format!(
             "Analyze the following data summary and provide key insights, trends, and recommendations:\n\n{}",
             data_summary
         );
 
-        self.process_analytics_query(&query, None).await
+        self.process_analytics_query(project_id, &query, None, settings, prompts).await
     }
 
+    /// Which visualizations suit the described data, and why
     pub async fn suggest_visualization(
         &self,
+        project_id: Option<&str>,
         data_description: &str,
-    ) -> Result<String, String> {
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<LlmResponse, String> {
         let query = format!(
             "Based on the following data description, suggest the most appropriate visualization types and explain why:\n\n{}",
             data_description
         );
 
-        self.process_analytics_query(&query, None).await
+        self.process_analytics_query(project_id, &query, None, settings, prompts).await
     }
 
+    /// Chat answer; repeated questions are served from the cache when a project is given
     pub async fn process_chat_message(
         &self,
+        project_id: Option<&str>,
         message: &str,
         context: Option<&str>,
-    ) -> Result<String, String> {
-        let system_message = "You are DencapsBI Chat Assistant, an advanced AI analytics assistant. \
-            You help users with data analysis, business intelligence questions, and provide insights. \
-            You can discuss data visualization, analytics strategies, SQL queries, and statistical methods. \
-            Provide clear, structured, and actionable responses. When providing structured data like lists, \
-            tables, or code snippets, use markdown formatting.";
-
-        let mut messages = vec![Message {
-            role: "system".to_string(),
-            content: system_message.to_string(),
-        }];
+        grounding: &Grounding,
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<LlmResponse, String> {
+        let mut messages = vec![LlmMessage::system(Self::system_prompt(settings, prompts, CHAT_SYSTEM))];
 
         if let Some(ctx) = context {
-            messages.push(Message {
-                role: "system".to_string(),
-                content: format!("Previous conversation:\n{}", ctx),
-            });
+            messages.push(LlmMessage::system(format!("Previous conversation:\n{}", ctx)));
         }
 
-        messages.push(Message {
-            role: "user".to_string(),
-            content: message.to_string(),
-        });
+        grounding.push_messages(&mut messages, prompts);
 
-        println!("Sending request to {} API: {}", self.provider_name(), self.api_url);
-        self.send_chat_request(messages, 0.7, 2000).await
+        messages.push(LlmMessage::user(message));
+
+        self.send_cacheable(project_id, messages, 0.7, 2000, settings).await
+    }
+
+    /// Generate a short conversation title from the first exchange, with the tokens used
+    pub async fn generate_conversation_title(
+        &self,
+        user_message: &str,
+        assistant_reply: &str,
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<(String, Option<TokenUsage>), String> {
+        let system_message = prompts.render(CONVERSATION_TITLE, &[]);
+
+        // Keep the request small; the opening of each message is enough to name the topic
+        let excerpt = |text: &str| -> String { text.chars().take(1000).collect() };
+
+        let messages = vec![
+            LlmMessage::system(system_message),
+            LlmMessage::user(format!(
+                "User: {}\n\nAssistant: {}\n\nTitle:",
+                excerpt(user_message),
+                excerpt(assistant_reply)
+            )),
+        ];
+
+        let response = self.send_chat_request(messages, 0.3, 20, settings).await?;
+        let raw = response.content;
+
+        let title: String = raw
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .trim_start_matches("Title:")
+            .trim()
+            .trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '#')
+            .trim_end_matches(['.', '!', '?', ':'])
+            .chars()
+            .take(80)
+            .collect();
+
+        if title.trim().is_empty() {
+            return Err("AI returned an empty title".to_string());
+        }
+
+        Ok((title.trim().to_string(), response.usage))
+    }
+
+    /// Suggest two to four follow-up questions to a reply, with the tokens used.
+    /// `datasets` describes the project's tables so suggestions can point at them.
+    pub async fn suggest_follow_ups(
+        &self,
+        user_message: &str,
+        assistant_reply: &str,
+        datasets: Option<&str>,
+        settings: Option<&ProjectAiSettings>,
+        prompts: &mut PromptSet,
+    ) -> Result<(Vec<String>, Option<TokenUsage>), String> {
+        let system_message = prompts.render(FOLLOW_UP_SUGGESTIONS, &[]);
+
+        let excerpt = |text: &str| -> String { text.chars().take(2000).collect() };
+        let mut request = format!(
+            "User: {}\n\nAssistant: {}",
+            excerpt(user_message),
+            excerpt(assistant_reply)
+        );
+        if let Some(datasets) = datasets {
+            request.push_str(&format!("\n\nDatasets:\n{}", datasets));
+        }
+        request.push_str("\n\nFollow-up questions:");
+
+        let messages = vec![LlmMessage::system(system_message), LlmMessage::user(request)];
+        let response = self.send_chat_request(messages, 0.5, 200, settings).await?;
+
+        let mut suggestions: Vec<String> = Vec::new();
+        for line in response.content.lines() {
+            let question = line
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '•' | '.' | ')'))
+                .trim()
+                .trim_matches(|c: char| c == '"' || c == '\'' || c == '*')
+                .trim();
+            if question.is_empty() || question.ends_with(':') || question.chars().count() > 200 {
+                continue;
+            }
+            if !suggestions.iter().any(|s| s.eq_ignore_ascii_case(question)) {
+                suggestions.push(question.to_string());
+            }
+            if suggestions.len() == 4 {
+                break;
+            }
+        }
+
+        if suggestions.is_empty() {
+            return Err("AI returned no follow-up questions".to_string());
+        }
+
+        Ok((suggestions, response.usage))
     }
 
     pub async fn process_chat_message_structured(
         &self,
         message: &str,
         context: Option<&str>,
+        prompts: &mut PromptSet,
     ) -> Result<StructuredResponse, String> {
-        let system_message = "You are DencapsBI Chat Assistant, an advanced AI analytics assistant. \
-            You respond with structured JSON that can include text, charts, equations, tables, and datasets. \
-            \
-            Response Format (JSON):\n\
-            {\n\
-              \"items\": [\n\
-                {\"type\": \"text\", \"content\": \"Your explanation here\"},\n\
-                {\"type\": \"chart\", \"data\": {\"chart_type\": \"bar|line|pie\", \"title\": \"Chart Title\", \"labels\": [\"A\", \"B\"], \"datasets\": [{\"label\": \"Series\", \"data\": [1, 2]}]}},\n\
-                {\"type\": \"equation\", \"latex\": \"E = mc^2\", \"display\": true},\n\
-                {\"type\": \"table\", \"data\": {\"headers\": [\"Col1\", \"Col2\"], \"rows\": [[\"val1\", \"val2\"]]}},\n\
-                {\"type\": \"dataset\", \"data\": {\"name\": \"Dataset Name\", \"description\": \"Optional\", \"columns\": [{\"name\": \"col\", \"data_type\": \"string\"}], \"rows\": [[\"value\"]]}}\n\
-              ]\n\
-            }\n\
-            \
-            Always respond with valid JSON. Use text type for explanations, chart for visualizations, \
-            equation for math (LaTeX), table for tabular data, and dataset for structured data with schema.";
-
-        let mut messages = vec![Message {
-            role: "system".to_string(),
-            content: system_message.to_string(),
-        }];
+        let system_message = prompts.render(STRUCTURED_SYSTEM, &[]);
+
+        let mut messages = vec![LlmMessage::system(system_message)];
 
         if let Some(ctx) = context {
-            messages.push(Message {
-                role: "system".to_string(),
-                content: format!("Previous conversation:\n{}", ctx),
-            });
+            messages.push(LlmMessage::system(format!("Previous conversation:\n{}", ctx)));
         }
 
-        messages.push(Message {
-            role: "user".to_string(),
-            content: message.to_string(),
-        });
+        messages.push(LlmMessage::user(message));
 
-        let content = self.send_chat_request(messages, 0.7, 3000).await?;
+        let content = self.send_chat_request(messages, 0.7, 3000, None).await?.content;
 
         // Parse the structured response
         self.parse_and_validate_structured_response(&content)
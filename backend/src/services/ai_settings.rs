@@ -0,0 +1,130 @@
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::ReplaceOptions;
+use crate::config::AIProvider;
+use crate::db::DatabaseManager;
+use crate::models::{ProjectAiSettings, ProjectAiSettingsResponse, UpdateProjectAiSettingsDto};
+use crate::services::AIService;
+
+/// Stores per-project AI overrides
+pub struct AiSettingsService {
+    db: DatabaseManager,
+    ai_service: AIService,
+}
+
+impl AiSettingsService {
+    pub fn new(db: DatabaseManager, ai_service: AIService) -> Self {
+        AiSettingsService { db, ai_service }
+    }
+
+    /// Overrides for a project, if any were saved
+    pub async fn get_settings(&self, project_id: &str) -> Result<Option<ProjectAiSettings>, String> {
+        self.db
+            .project_ai_settings_collection()
+            .find_one(doc! { "project_id": project_id })
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Overrides for a project. Lookup failures are logged and the defaults are used,
+    /// so a settings problem never blocks a chat request.
+    pub async fn settings_or_default(&self, project_id: &str) -> Option<ProjectAiSettings> {
+        match self.get_settings(project_id).await {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Failed to load AI settings for project {}: {}", project_id, e);
+                None
+            }
+        }
+    }
+
+    /// Replace a project's overrides
+    pub async fn update_settings(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        dto: UpdateProjectAiSettingsDto,
+    ) -> Result<ProjectAiSettings, String> {
+        let provider = match dto.provider {
+            Some(name) => Some(self.resolve_provider_name(&name)?),
+            None => None,
+        };
+
+        let settings = ProjectAiSettings {
+            id: None,
+            project_id: project_id.to_string(),
+            provider,
+            model: dto.model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()),
+            temperature: dto.temperature,
+            max_tokens: dto.max_tokens,
+            system_prompt: dto.system_prompt.filter(|p| !p.trim().is_empty()),
+            top_k: dto.top_k,
+            updated_by: user_id.to_string(),
+            updated_at: DateTime::now(),
+        };
+
+        self.db
+            .project_ai_settings_collection()
+            .replace_one(doc! { "project_id": project_id }, &settings)
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await
+            .map_err(|e| format!("Failed to save AI settings: {}", e))?;
+
+        Ok(settings)
+    }
+
+    /// Canonical registry name for a provider, accepting the same aliases as `AI_PROVIDER`
+    fn resolve_provider_name(&self, name: &str) -> Result<String, String> {
+        let name = AIProvider::parse(name)
+            .map(|p| p.as_str().to_string())
+            .unwrap_or_else(|| name.trim().to_lowercase());
+
+        let available = self.ai_service.provider_names();
+        if available.contains(&name) {
+            Ok(name)
+        } else {
+            Err(format!(
+                "Unknown AI provider '{}'. Available providers: {}",
+                name,
+                available.join(", ")
+            ))
+        }
+    }
+
+    /// Response with the project's overrides alongside the server defaults
+    pub fn to_response(&self, project_id: &str, settings: Option<ProjectAiSettings>) -> ProjectAiSettingsResponse {
+        let default_provider = self.ai_service.provider_id().to_string();
+        let default_model = self.ai_service.model_label();
+        let available_providers = self.ai_service.provider_names();
+
+        match settings {
+            Some(s) => ProjectAiSettingsResponse {
+                project_id: s.project_id,
+                provider: s.provider,
+                model: s.model,
+                temperature: s.temperature,
+                max_tokens: s.max_tokens,
+                system_prompt: s.system_prompt,
+                top_k: s.top_k,
+                default_provider,
+                default_model,
+                available_providers,
+                updated_by: Some(s.updated_by),
+                updated_at: Some(s.updated_at.to_string()),
+            },
+            None => ProjectAiSettingsResponse {
+                project_id: project_id.to_string(),
+                provider: None,
+                model: None,
+                temperature: None,
+                max_tokens: None,
+                system_prompt: None,
+                top_k: None,
+                default_provider,
+                default_model,
+                available_providers,
+                updated_by: None,
+                updated_at: None,
+            },
+        }
+    }
+}
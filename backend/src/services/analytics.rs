@@ -1,17 +1,54 @@
 use mongodb::bson::{doc, DateTime};
 use uuid::Uuid;
 use crate::db::DatabaseManager;
-use crate::models::{AnalyticsQuery, CreateQueryDto, QueryStatus};
-use crate::services::AIService;
+use crate::models::{AnalyticsQuery, CreateQueryDto, GuardrailSource, QueryStatus};
+use crate::services::{
+    AIService, AiSettingsService, GuardrailService, PromptService, RedactionService, UsageService,
+};
+use crate::services::guardrails::{GUARDRAIL_BLOCKED, GUARDRAIL_BLOCKED_MESSAGE};
+use crate::services::redaction::Redactor;
+use crate::services::usage::UsageEvent;
+use std::sync::Arc;
 
 pub struct AnalyticsService {
     db: DatabaseManager,
     ai_service: AIService,
+    ai_settings_service: Arc<AiSettingsService>,
+    prompt_service: Arc<PromptService>,
+    usage_service: Arc<UsageService>,
+    redaction_service: Option<Arc<RedactionService>>,
+    guardrail_service: Option<Arc<GuardrailService>>,
 }
 
 impl AnalyticsService {
-    pub fn new(db: DatabaseManager, ai_service: AIService) -> Self {
-        AnalyticsService { db, ai_service }
+    pub fn new(
+        db: DatabaseManager,
+        ai_service: AIService,
+        ai_settings_service: Arc<AiSettingsService>,
+        prompt_service: Arc<PromptService>,
+        usage_service: Arc<UsageService>,
+    ) -> Self {
+        AnalyticsService {
+            db,
+            ai_service,
+            ai_settings_service,
+            prompt_service,
+            usage_service,
+            redaction_service: None,
+            guardrail_service: None,
+        }
+    }
+
+    /// Replace personal data in questions before they leave the server and restore it in answers
+    pub fn with_redaction(mut self, redaction_service: Arc<RedactionService>) -> Self {
+        self.redaction_service = Some(redaction_service);
+        self
+    }
+
+    /// Screen questions for prompt injection before they reach the model
+    pub fn with_guardrails(mut self, guardrail_service: Arc<GuardrailService>) -> Self {
+        self.guardrail_service = Some(guardrail_service);
+        self
     }
 
     pub async fn create_query(
@@ -35,6 +72,7 @@ impl AnalyticsService {
             status: QueryStatus::Pending,
             created_at: now,
             completed_at: None,
+            cached: false,
         };
 
         self.db
@@ -46,7 +84,8 @@ impl AnalyticsService {
         Ok(query)
     }
 
-    pub async fn process_query(&self, query_id: &Uuid) -> Result<String, String> {
+    /// Answer a query, returning the response and whether it came from the cache
+    pub async fn process_query(&self, query_id: &Uuid) -> Result<(String, bool), String> {
         let uuid_str = query_id.to_string();
         
         let query = self.db
@@ -56,6 +95,22 @@ impl AnalyticsService {
             .map_err(|e| format!("Database error: {}", e))?
             .ok_or_else(|| "Query not found".to_string())?;
 
+        self.usage_service.check_quota(&query.project_id, &query.user_id).await?;
+
+        let screened = match self.guardrail_service {
+            Some(ref service) => {
+                let guard = service.guard(&query.project_id, &query.user_id, "analytics").await;
+                let screened = guard.screen(GuardrailSource::UserInput, &query.query_text);
+                service.record(&guard).await;
+                screened
+            }
+            None => Some(query.query_text.clone()),
+        };
+        let Some(screened) = screened else {
+            self.mark_failed(&uuid_str, GUARDRAIL_BLOCKED_MESSAGE).await?;
+            return Err(GUARDRAIL_BLOCKED.to_string());
+        };
+
         // Update status to processing
         self.db
             .queries_collection()
@@ -66,24 +121,45 @@ impl AnalyticsService {
             .await
             .map_err(|e| format!("Failed to update query status: {}", e))?;
 
-        // Process with AI
-        let response = match self.ai_service.process_analytics_query(&query.query_text, None).await {
-            Ok(resp) => resp,
+        // Process with AI, using the project's overrides
+        let settings = self.ai_settings_service.settings_or_default(&query.project_id).await;
+        let mut prompts = self.prompt_service.resolve_for_project(&query.project_id).await;
+        let redactor = match self.redaction_service {
+            Some(ref service) => service.redactor(&query.project_id, &query.user_id, "analytics").await,
+            None => Redactor::disabled(),
+        };
+        let query_text = redactor.redact(&screened);
+        if let Some(ref service) = self.redaction_service {
+            service.audit(&redactor).await;
+        }
+        let response = match self
+            .ai_service
+            .process_analytics_query(
+                Some(&query.project_id),
+                &query_text,
+                None,
+                settings.as_ref(),
+                &mut prompts,
+            )
+            .await
+        {
+            Ok(mut resp) => {
+                resp.content = redactor.restore(&resp.content);
+                if let Some(usage) = resp.usage {
+                    let provider = self.ai_service.provider_for(settings.as_ref());
+                    let event = UsageEvent {
+                        project_id: query.project_id.clone(),
+                        user_id: query.user_id.clone(),
+                        operation: "analytics",
+                        provider: provider.name().to_string(),
+                        model: provider.model().map(str::to_string),
+                    };
+                    self.usage_service.record(event, usage).await;
+                }
+                resp
+            }
             Err(e) => {
-                // Update status to failed
-                self.db
-                    .queries_collection()
-                    .update_one(
-                        doc! { "query_id": &uuid_str },
-                        doc! { "$set": { 
-                            "status": "Failed",
-                            "response_text": format!("Error: {}", e),
-                            "completed_at": DateTime::now()
-                        } }
-                    )
-                    .await
-                    .map_err(|e| format!("Failed to update query: {}", e))?;
-                
+                self.mark_failed(&uuid_str, &e).await?;
                 return Err(e);
             }
         };
@@ -95,14 +171,32 @@ impl AnalyticsService {
                 doc! { "query_id": &uuid_str },
                 doc! { "$set": { 
                     "status": "Completed",
-                    "response_text": &response,
-                    "completed_at": DateTime::now()
+                    "response_text": &response.content,
+                    "completed_at": DateTime::now(),
+                    "cached": response.cached
                 } }
             )
             .await
             .map_err(|e| format!("Failed to update query: {}", e))?;
 
-        Ok(response)
+        Ok((response.content, response.cached))
+    }
+
+    /// Record that a query could not be answered
+    async fn mark_failed(&self, query_id: &str, error: &str) -> Result<(), String> {
+        self.db
+            .queries_collection()
+            .update_one(
+                doc! { "query_id": query_id },
+                doc! { "$set": { 
+                    "status": "Failed",
+                    "response_text": format!("Error: {}", error),
+                    "completed_at": DateTime::now()
+                } }
+            )
+            .await
+            .map_err(|e| format!("Failed to update query: {}", e))?;
+        Ok(())
     }
 
     pub async fn get_query_by_id(&self, query_id: &Uuid) -> Result<AnalyticsQuery, String> {
@@ -0,0 +1,456 @@
+//! Statistical anomaly detection over a numeric column.
+//!
+//! Points are scored by z-score, by distance beyond the interquartile range,
+//! or by the robust z-score of the residual left after removing a moving
+//! median trend and the typical season. Points scoring above the threshold
+//! are flagged, with a severity by how far above it they are.
+//!
+//! The values come from a dataset, a read-only query, or the result table of a
+//! saved analytics query.
+
+use std::sync::Arc;
+use mongodb::bson::doc;
+use serde_json::Value;
+use crate::db::DatabaseManager;
+use crate::models::{
+    AnomalyDirection, AnomalyMethod, AnomalyOptions, AnomalyPoint, AnomalyResponse, AnomalySeverity,
+    ChartAnnotation, ChartData, ChartDataset, ChartType, ColumnInfo, RenderContent, StructuredResponse,
+};
+use crate::services::forecast::{self, DATASET_NOT_FOUND};
+use crate::services::{profiling, stats, DatasetService};
+
+/// Error returned when a saved query is not in the project
+pub const SAVED_QUERY_NOT_FOUND: &str = "Saved query not found";
+
+/// Fewest points a column is checked with
+const MIN_POINTS: usize = 5;
+const DEFAULT_Z_THRESHOLD: f64 = 3.0;
+const DEFAULT_IQR_THRESHOLD: f64 = 1.5;
+/// Scale of the median absolute deviation to a standard deviation for normal data
+const MAD_SCALE: f64 = 1.4826;
+/// Scores this many times the threshold are medium and high severity
+const MEDIUM_SEVERITY: f64 = 1.5;
+const HIGH_SEVERITY: f64 = 2.0;
+
+/// Where the checked values come from
+pub enum AnomalySource<'a> {
+    /// A dataset by id
+    Dataset(&'a str),
+    /// A dataset by table name
+    Table(&'a str),
+    /// A read-only query over the project's datasets
+    Sql(&'a str),
+    /// The table in the answer of a saved analytics query, by query id
+    SavedQuery(&'a str),
+}
+
+/// Checks numeric columns of the project's datasets and query results for anomalies
+pub struct AnomalyService {
+    db: DatabaseManager,
+    datasets: Arc<DatasetService>,
+}
+
+impl AnomalyService {
+    pub fn new(db: DatabaseManager, datasets: Arc<DatasetService>) -> Self {
+        AnomalyService { db, datasets }
+    }
+
+    pub async fn detect(
+        &self,
+        project_id: &str,
+        source: AnomalySource<'_>,
+        options: &AnomalyOptions,
+    ) -> Result<AnomalyResponse, String> {
+        let dataset = match source {
+            AnomalySource::Dataset(id) => self.datasets.get_dataset(id).await?,
+            AnomalySource::Table(name) => self.datasets.find_by_name(project_id, name).await?,
+            AnomalySource::Sql(sql) => {
+                // A query never returns more rows than its table holds
+                let result = self.datasets.query(project_id, sql, usize::MAX).await?;
+                return Self::detect_in(result.columns, result.rows, options).await;
+            }
+            AnomalySource::SavedQuery(query_id) => {
+                let query = self
+                    .db
+                    .queries_collection()
+                    .find_one(doc! { "query_id": query_id, "project_id": project_id })
+                    .await
+                    .map_err(|e| format!("Failed to get query: {}", e))?
+                    .ok_or_else(|| SAVED_QUERY_NOT_FOUND.to_string())?;
+                let (columns, rows) = query
+                    .response_text
+                    .as_deref()
+                    .and_then(result_table)
+                    .ok_or_else(|| "Saved query has no result table".to_string())?;
+                return Self::detect_in(columns, rows, options).await;
+            }
+        }
+        .filter(|d| d.project_id == project_id)
+        .ok_or_else(|| DATASET_NOT_FOUND.to_string())?;
+        let rows = self.datasets.rows(&dataset).await?;
+        Self::detect_in(dataset.columns, rows, options).await
+    }
+
+    async fn detect_in(
+        columns: Vec<ColumnInfo>,
+        rows: Vec<Vec<Value>>,
+        options: &AnomalyOptions,
+    ) -> Result<AnomalyResponse, String> {
+        let options = options.clone();
+        tokio::task::spawn_blocking(move || detect_rows(&columns, &rows, &options))
+            .await
+            .map_err(|_| "Anomaly detection failed".to_string())?
+    }
+}
+
+/// The table in a saved query's answer: the first table or dataset of a
+/// structured answer, or else the first Markdown table in the text
+pub fn result_table(answer: &str) -> Option<(Vec<ColumnInfo>, Vec<Vec<Value>>)> {
+    let (headers, records) = match serde_json::from_str::<StructuredResponse>(answer.trim()) {
+        Ok(structured) => structured.items.into_iter().find_map(|item| match item {
+            RenderContent::Table { data } => Some((data.headers, data.rows)),
+            RenderContent::Dataset { data } => {
+                let headers = data.columns.iter().map(|c| c.name.clone()).collect();
+                let records = data.rows.iter().map(|r| r.iter().map(profiling::cell_text).collect()).collect();
+                Some((headers, records))
+            }
+            _ => None,
+        })?,
+        Err(_) => markdown_table(answer)?,
+    };
+    if headers.is_empty() || records.is_empty() {
+        return None;
+    }
+
+    let width = headers.len();
+    let records = records
+        .into_iter()
+        .map(|mut record| {
+            record.resize(width, String::new());
+            record
+        })
+        .collect();
+    Some(DatasetService::typed_rows(DatasetService::headers(headers), records))
+}
+
+/// Header and rows of the first `| a | b |` table, skipping its `|---|` separator
+fn markdown_table(text: &str) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let cells = |line: &str| -> Vec<String> {
+        line.trim().trim_matches('|').split('|').map(|c| c.trim().to_string()).collect()
+    };
+    let is_separator = |line: &str| {
+        line.contains('-') && line.trim().chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+    };
+
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines
+        .windows(2)
+        .position(|pair| pair[0].trim_start().starts_with('|') && is_separator(pair[1]))?;
+    let rows = lines[start + 2..]
+        .iter()
+        .take_while(|line| line.trim_start().starts_with('|'))
+        .map(|line| cells(line))
+        .collect();
+    Some((cells(lines[start]), rows))
+}
+
+/// Expected value of every point and the flagged ones with their scores
+#[derive(Debug)]
+pub struct Detection {
+    pub expected: Vec<f64>,
+    pub flagged: Vec<(usize, f64)>,
+}
+
+fn median(values: &[f64]) -> f64 {
+    stats::quantile(&stats::sorted(values), 0.5).unwrap_or_default()
+}
+
+/// Points further than `threshold` standard deviations from the mean
+fn z_scores(values: &[f64], threshold: f64) -> Detection {
+    let mean = stats::mean(values).unwrap_or_default();
+    let flagged = match stats::std_dev(values) {
+        Some(std_dev) if std_dev > 0.0 => values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, (v - mean).abs() / std_dev))
+            .filter(|(_, score)| *score > threshold)
+            .collect(),
+        _ => vec![],
+    };
+    Detection { expected: vec![mean; values.len()], flagged }
+}
+
+/// Points more than `threshold` interquartile ranges below the first or above the third quartile
+fn iqr(values: &[f64], threshold: f64) -> Detection {
+    let sorted = stats::sorted(values);
+    let quartile = |q| stats::quantile(&sorted, q).unwrap_or_default();
+    let (q1, median, q3) = (quartile(0.25), quartile(0.5), quartile(0.75));
+    let range = q3 - q1;
+    let flagged = if range > 0.0 {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, (q1 - v).max(v - q3).max(0.0) / range))
+            .filter(|(_, score)| *score > threshold)
+            .collect()
+    } else {
+        vec![]
+    };
+    Detection { expected: vec![median; values.len()], flagged }
+}
+
+/// Centred moving median over one season, held flat at the ends. Unlike a
+/// moving average it isn't pulled towards the anomalies being looked for.
+fn moving_median(values: &[f64], season: usize) -> Vec<f64> {
+    let n = values.len();
+    let half = season / 2;
+    let mut trend: Vec<f64> = (half..n - half).map(|t| median(&values[t - half..=t + half])).collect();
+    let (first, last) = (trend[0], trend[trend.len() - 1]);
+    trend.splice(0..0, std::iter::repeat_n(first, half));
+    trend.extend(std::iter::repeat_n(last, half));
+    trend
+}
+
+/// Points whose residual after trend and season is more than `threshold`
+/// robust standard deviations from the typical residual
+fn seasonal(values: &[f64], season: usize, threshold: f64) -> Detection {
+    let trend = moving_median(values, season);
+    let detrended: Vec<f64> = values.iter().zip(&trend).map(|(v, t)| v - t).collect();
+
+    let mut pattern: Vec<f64> = (0..season)
+        .map(|p| median(&detrended.iter().skip(p).step_by(season).copied().collect::<Vec<_>>()))
+        .collect();
+    let offset = stats::mean(&pattern).unwrap_or_default();
+    pattern.iter_mut().for_each(|s| *s -= offset);
+
+    let expected: Vec<f64> = trend.iter().enumerate().map(|(t, level)| level + pattern[t % season]).collect();
+    let residuals: Vec<f64> = values.iter().zip(&expected).map(|(v, e)| v - e).collect();
+    let center = median(&residuals);
+    let deviations: Vec<f64> = residuals.iter().map(|r| (r - center).abs()).collect();
+    let spread = match median(&deviations) * MAD_SCALE {
+        mad if mad > 0.0 => mad,
+        _ => stats::std_dev(&residuals).unwrap_or_default(),
+    };
+
+    let flagged = if spread > 0.0 {
+        deviations
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (i, d / spread))
+            .filter(|(_, score)| *score > threshold)
+            .collect()
+    } else {
+        vec![]
+    };
+    Detection { expected, flagged }
+}
+
+/// Score `values` with `method` and flag those above `threshold`
+pub fn detect(values: &[f64], method: AnomalyMethod, threshold: f64, season: Option<usize>) -> Result<Detection, String> {
+    if values.len() < MIN_POINTS {
+        return Err(format!("Anomaly detection needs at least {} points; this has {}", MIN_POINTS, values.len()));
+    }
+    match method {
+        AnomalyMethod::ZScore => Ok(z_scores(values, threshold)),
+        AnomalyMethod::Iqr => Ok(iqr(values, threshold)),
+        AnomalyMethod::Seasonal => {
+            let season = season.ok_or_else(|| {
+                "The seasonal method needs a season_length or a date column with a seasonal interval".to_string()
+            })?;
+            if values.len() < 2 * season {
+                return Err(format!(
+                    "The seasonal method needs two seasons of {} points; this has {}",
+                    season,
+                    values.len()
+                ));
+            }
+            Ok(seasonal(values, season, threshold))
+        }
+    }
+}
+
+fn severity(score: f64, threshold: f64) -> AnomalySeverity {
+    if score >= threshold * HIGH_SEVERITY {
+        AnomalySeverity::High
+    } else if score >= threshold * MEDIUM_SEVERITY {
+        AnomalySeverity::Medium
+    } else {
+        AnomalySeverity::Low
+    }
+}
+
+/// Check rows already loaded
+pub fn detect_rows(columns: &[ColumnInfo], rows: &[Vec<Value>], options: &AnomalyOptions) -> Result<AnomalyResponse, String> {
+    let (labels, values, skipped_rows, interval) = match options.date_column {
+        Some(ref date_column) => {
+            let series = forecast::series(columns, rows, date_column, &options.column, options.interval, options.aggregate)?;
+            let labels = (0..series.values.len())
+                .map(|i| forecast::period_label(series.interval, series.start + i as i64))
+                .collect();
+            (labels, series.values, series.skipped_rows, Some(series.interval))
+        }
+        None => {
+            let index = columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(&options.column))
+                .ok_or_else(|| format!("No column '{}'", options.column))?;
+            let (labels, values): (Vec<String>, Vec<f64>) = rows
+                .iter()
+                .enumerate()
+                .filter_map(|(i, row)| Some((format!("row {}", i + 1), profiling::numeric_value(&row[index])?)))
+                .unzip();
+            let skipped_rows = rows.len() - values.len();
+            (labels, values, skipped_rows, None)
+        }
+    };
+
+    let threshold = options.threshold.unwrap_or(match options.method {
+        AnomalyMethod::Iqr => DEFAULT_IQR_THRESHOLD,
+        AnomalyMethod::ZScore | AnomalyMethod::Seasonal => DEFAULT_Z_THRESHOLD,
+    });
+    let season_length = match options.method {
+        AnomalyMethod::Seasonal => options.season_length.or_else(|| interval.and_then(forecast::season_length)),
+        _ => None,
+    };
+    let detection = detect(&values, options.method, threshold, season_length)?;
+
+    let anomalies: Vec<AnomalyPoint> = detection
+        .flagged
+        .iter()
+        .map(|&(index, score)| {
+            let (value, expected) = (values[index], detection.expected[index]);
+            AnomalyPoint {
+                label: labels[index].clone(),
+                index,
+                value: stats::round(value, 4),
+                expected: stats::round(expected, 4),
+                score: stats::round(score, 2),
+                direction: if value >= expected { AnomalyDirection::Above } else { AnomalyDirection::Below },
+                severity: severity(score, threshold),
+            }
+        })
+        .collect();
+
+    let annotations = anomalies
+        .iter()
+        .map(|a| ChartAnnotation {
+            label: a.label.clone(),
+            value: a.value,
+            text: format!(
+                "{:?} anomaly: {} is {} the expected {} (score {})",
+                a.severity,
+                a.value,
+                if a.direction == AnomalyDirection::Above { "above" } else { "below" },
+                a.expected,
+                a.score
+            ),
+        })
+        .collect();
+    let series_of = |label: &str, data: Vec<f64>| ChartDataset {
+        label: label.to_string(),
+        data,
+        background_color: None,
+        border_color: None,
+    };
+    let chart = ChartData {
+        chart_type: ChartType::Line,
+        title: Some(format!("{} anomalies", options.column)),
+        labels,
+        datasets: vec![
+            series_of("Value", values.iter().map(|v| stats::round(*v, 4)).collect()),
+            series_of("Expected", detection.expected.iter().map(|v| stats::round(*v, 4)).collect()),
+        ],
+        annotations,
+    };
+
+    Ok(AnomalyResponse {
+        column: options.column.clone(),
+        method: options.method,
+        threshold,
+        season_length,
+        points_checked: values.len(),
+        skipped_rows,
+        anomalies,
+        chart,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ForecastAggregate, ForecastInterval};
+
+    fn options(method: AnomalyMethod, date_column: Option<&str>) -> AnomalyOptions {
+        AnomalyOptions {
+            column: "orders".to_string(),
+            date_column: date_column.map(str::to_string),
+            interval: date_column.map(|_| ForecastInterval::Day),
+            aggregate: ForecastAggregate::Sum,
+            method,
+            threshold: None,
+            season_length: None,
+        }
+    }
+
+    #[test]
+    fn reads_the_table_of_a_saved_query_answer() {
+        let answer = "Weekly orders:\n\n| Week | Orders |\n|---|---:|\n| 1 | 10 |\n| 2 | 12 |\n| 3 | 95 |\n\nWeek 3 stands out.";
+        let (columns, rows) = result_table(answer).unwrap();
+        assert_eq!(columns[1].name, "Orders");
+        assert_eq!(columns[1].data_type, "integer");
+        assert_eq!(rows[2][1], Value::from(95));
+
+        let structured = r#"{"items": [{"type": "text", "content": "Orders"}, {"type": "table", "data": {"headers": ["orders"], "rows": [["10"], ["12"]]}}]}"#;
+        let (columns, rows) = result_table(structured).unwrap();
+        assert_eq!((columns[0].name.as_str(), rows.len()), ("orders", 2));
+
+        assert!(result_table("No table here.").is_none());
+    }
+
+    #[test]
+    fn flags_outliers_by_z_score_and_iqr() {
+        let csv = "orders\n10\n12\n11\n9\n10\n13\n11\n10\n12\n95\n11\nn/a\n";
+        let (columns, rows) = crate::services::DatasetService::parse_table("orders.csv", csv.as_bytes()).unwrap();
+
+        let z = detect_rows(&columns, &rows, &options(AnomalyMethod::ZScore, None)).unwrap();
+        assert_eq!((z.points_checked, z.skipped_rows), (11, 1));
+        assert_eq!(z.anomalies.len(), 1);
+        assert_eq!(z.anomalies[0].label, "row 10");
+        assert_eq!(z.anomalies[0].direction, AnomalyDirection::Above);
+        assert_eq!(z.chart.annotations[0].label, "row 10");
+
+        let iqr = detect_rows(&columns, &rows, &options(AnomalyMethod::Iqr, None)).unwrap();
+        assert_eq!(iqr.anomalies.len(), 1);
+        assert_eq!(iqr.anomalies[0].severity, AnomalySeverity::High);
+        assert_eq!(iqr.anomalies[0].expected, 11.0);
+    }
+
+    #[test]
+    fn seasonal_method_ignores_the_regular_cycle() {
+        // Weekday orders with quiet weekends, and one Wednesday that is as quiet as a weekend
+        let mut csv = "day,orders\n".to_string();
+        for day in 0..28 {
+            let weekday = day % 7;
+            let orders = match (day, weekday) {
+                (16, _) => 20,
+                (_, 5) | (_, 6) => 20 + day % 3,
+                _ => 100 + day % 4,
+            };
+            csv.push_str(&format!("2024-01-{:02},{}\n", day + 1, orders));
+        }
+        let (columns, rows) = crate::services::DatasetService::parse_table("orders.csv", csv.as_bytes()).unwrap();
+
+        // Weekends are far from the mean, so z-scores see nothing unusual about the quiet weekday
+        let z = detect_rows(&columns, &rows, &options(AnomalyMethod::ZScore, Some("day"))).unwrap();
+        assert!(z.anomalies.iter().all(|a| a.label != "2024-01-17"));
+
+        let seasonal = detect_rows(&columns, &rows, &options(AnomalyMethod::Seasonal, Some("day"))).unwrap();
+        assert_eq!(seasonal.season_length, Some(7));
+        let labels: Vec<&str> = seasonal.anomalies.iter().map(|a| a.label.as_str()).collect();
+        assert_eq!(labels, vec!["2024-01-17"]);
+        assert_eq!(seasonal.anomalies[0].direction, AnomalyDirection::Below);
+
+        let short = detect(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], AnomalyMethod::Seasonal, 3.0, Some(7));
+        assert!(short.is_err());
+    }
+}
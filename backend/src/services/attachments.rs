@@ -0,0 +1,524 @@
+//! Files users attach to a chat.
+//!
+//! The upload is kept in GridFS. On upload its text is extracted once, or for
+//! CSV files its schema, column statistics and first rows, and shortened into a
+//! summary that goes into the prompt of every later message in the conversation.
+//! A CSV attachment can be turned into a project dataset.
+
+use std::sync::Arc;
+use std::time::Duration;
+use futures::{AsyncReadExt, AsyncWriteExt, TryStreamExt};
+use mongodb::bson::{doc, Bson, DateTime};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{
+    AttachmentFormat, ChatAttachment, ColumnInfo, Dataset, MessageAttachment, PromoteAttachmentDto,
+};
+use crate::services::{DatasetService, KnowledgeService};
+
+/// Error returned when a message refers to an attachment the caller can't use
+pub const ATTACHMENT_NOT_FOUND: &str = "Attachment not found";
+
+/// `dataset_id` of an attachment while it is being added as a dataset
+pub const DATASET_PENDING: &str = "pending";
+
+/// How often uploads never sent with a message are looked for
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Rows shown in the summary of a tabular attachment
+const SAMPLE_ROWS: usize = 10;
+
+/// Longest cell value shown in sample rows
+const SAMPLE_CELL_CHARS: usize = 60;
+
+pub struct AttachmentService {
+    db: DatabaseManager,
+    dataset_service: Arc<DatasetService>,
+    /// Longest summary stored per attachment, and the most attachment text one prompt carries
+    context_chars: usize,
+}
+
+impl AttachmentService {
+    pub fn new(db: DatabaseManager, dataset_service: Arc<DatasetService>, context_chars: usize) -> Self {
+        AttachmentService { db, dataset_service, context_chars }
+    }
+
+    /// Summaries for the prompt, newest first until the budget is spent and then
+    /// listed in upload order. Takes `(attachment, summary)` pairs so callers can
+    /// pass screened summaries.
+    pub fn format_context(&self, attachments: &[(&ChatAttachment, String)]) -> Option<String> {
+        let mut remaining = self.context_chars;
+        let mut included: Vec<String> = Vec::new();
+        let mut omitted: Vec<&str> = Vec::new();
+        for (attachment, summary) in attachments.iter().rev() {
+            let section = format!("### {} ({})\n{}", attachment.filename, attachment.format.as_str(), summary);
+            let len = section.chars().count();
+            if len <= remaining {
+                remaining -= len;
+                included.push(section);
+            } else {
+                omitted.push(&attachment.filename);
+            }
+        }
+        if included.is_empty() {
+            return None;
+        }
+
+        included.reverse();
+        let mut context = included.join("\n\n");
+        if !omitted.is_empty() {
+            omitted.reverse();
+            context.push_str(&format!("\n\n(Not included for length: {})", omitted.join(", ")));
+        }
+        Some(context)
+    }
+
+    /// Summarize and store an uploaded file. With `conversation_id` it is attached
+    /// to that conversation straight away; otherwise once a message refers to it.
+    pub async fn upload(
+        &self,
+        project_id: &str,
+        conversation_id: Option<&str>,
+        user_id: &str,
+        filename: &str,
+        format: AttachmentFormat,
+        data: Vec<u8>,
+    ) -> Result<ChatAttachment, String> {
+        let size_bytes = data.len() as i64;
+
+        // PDF parsing is CPU bound and can panic on malformed files, so keep it off the executor
+        let summary_chars = self.context_chars;
+        let (data, extracted) = {
+            let filename = filename.to_string();
+            tokio::task::spawn_blocking(move || {
+                let extracted = Self::extract(&filename, format, &data, summary_chars);
+                (data, extracted)
+            })
+            .await
+            .map_err(|_| "Failed to read attachment".to_string())?
+        };
+        let (summary, columns, row_count) = extracted?;
+        if summary.trim().is_empty() {
+            return Err("Attachment contains no text".to_string());
+        }
+
+        let bucket = self.db.attachments_bucket();
+        let mut upload = bucket
+            .open_upload_stream(filename)
+            .await
+            .map_err(|e| format!("Failed to store attachment: {}", e))?;
+        upload
+            .write_all(&data)
+            .await
+            .map_err(|e| format!("Failed to store attachment: {}", e))?;
+        upload
+            .close()
+            .await
+            .map_err(|e| format!("Failed to store attachment: {}", e))?;
+        let file_id = upload
+            .id()
+            .as_object_id()
+            .ok_or_else(|| "Failed to store attachment: unexpected file id".to_string())?;
+
+        let attachment = ChatAttachment {
+            id: None,
+            attachment_id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            conversation_id: conversation_id.map(str::to_string),
+            filename: filename.to_string(),
+            format,
+            size_bytes,
+            file_id,
+            summary,
+            columns,
+            row_count,
+            dataset_id: None,
+            uploaded_by: user_id.to_string(),
+            created_at: DateTime::now(),
+        };
+
+        if let Err(e) = self.db.chat_attachments_collection().insert_one(&attachment).await {
+            // Don't leave an orphaned file behind
+            bucket.delete(Bson::ObjectId(file_id)).await.ok();
+            return Err(format!("Failed to store attachment: {}", e));
+        }
+
+        Ok(attachment)
+    }
+
+    pub async fn get(&self, attachment_id: &str) -> Result<Option<ChatAttachment>, String> {
+        self.db
+            .chat_attachments_collection()
+            .find_one(doc! { "attachment_id": attachment_id })
+            .await
+            .map_err(|e| format!("Failed to get attachment: {}", e))
+    }
+
+    /// The uploaded file as stored
+    pub async fn content(&self, attachment: &ChatAttachment) -> Result<Vec<u8>, String> {
+        let mut download = self
+            .db
+            .attachments_bucket()
+            .open_download_stream(Bson::ObjectId(attachment.file_id))
+            .await
+            .map_err(|e| format!("Failed to read attachment: {}", e))?;
+        let mut data = Vec::with_capacity(attachment.size_bytes.max(0) as usize);
+        download
+            .read_to_end(&mut data)
+            .await
+            .map_err(|e| format!("Failed to read attachment: {}", e))?;
+        Ok(data)
+    }
+
+    /// Check that uploads may be sent with a message in a conversation. Each must
+    /// have been uploaded by `user_id` to `project_id` and not belong to another
+    /// conversation. Nothing is bound until `bind` is called.
+    pub async fn resolve(
+        &self,
+        project_id: &str,
+        conversation_id: &str,
+        user_id: &str,
+        attachment_ids: &[String],
+    ) -> Result<Vec<MessageAttachment>, String> {
+        let mut attached = Vec::with_capacity(attachment_ids.len());
+        for attachment_id in attachment_ids {
+            let attachment = match self.get(attachment_id).await? {
+                Some(a)
+                    if a.project_id == project_id
+                        && a.uploaded_by == user_id
+                        && a.conversation_id.as_deref().is_none_or(|c| c == conversation_id) =>
+                {
+                    a
+                }
+                _ => return Err(ATTACHMENT_NOT_FOUND.to_string()),
+            };
+            if !attached.iter().any(|a: &MessageAttachment| a.attachment_id == attachment.attachment_id) {
+                attached.push(MessageAttachment::from(&attachment));
+            }
+        }
+        Ok(attached)
+    }
+
+    /// Bind resolved uploads to the conversation their message was saved in.
+    /// An upload another conversation claimed in the meantime is left alone.
+    pub async fn bind(&self, conversation_id: &str, attachments: &[MessageAttachment]) -> Result<(), String> {
+        if attachments.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<&str> = attachments.iter().map(|a| a.attachment_id.as_str()).collect();
+        let result = self
+            .db
+            .chat_attachments_collection()
+            .update_many(
+                doc! {
+                    "attachment_id": { "$in": &ids },
+                    "$or": [{ "conversation_id": Bson::Null }, { "conversation_id": conversation_id }],
+                },
+                doc! { "$set": { "conversation_id": conversation_id } },
+            )
+            .await
+            .map_err(|e| format!("Failed to attach files: {}", e))?;
+        if (result.matched_count as usize) < ids.len() {
+            log::warn!("Some attachments of conversation {} were claimed by another conversation", conversation_id);
+        }
+        Ok(())
+    }
+
+    /// A conversation's attachments, oldest first
+    pub async fn for_conversation(&self, conversation_id: &str) -> Result<Vec<ChatAttachment>, String> {
+        self.db
+            .chat_attachments_collection()
+            .find(doc! { "conversation_id": conversation_id })
+            .sort(doc! { "created_at": 1 })
+            .await
+            .map_err(|e| format!("Failed to get attachments: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect attachments: {}", e))
+    }
+
+    /// Store a CSV attachment as a project dataset
+    pub async fn promote(
+        &self,
+        attachment: &ChatAttachment,
+        user_id: &str,
+        dto: PromoteAttachmentDto,
+    ) -> Result<Dataset, String> {
+        if attachment.format != AttachmentFormat::Csv {
+            return Err("Only CSV attachments can be added as datasets".to_string());
+        }
+
+        // Claim the attachment so concurrent requests can't add it twice
+        let collection = self.db.chat_attachments_collection();
+        let claimed = collection
+            .update_one(
+                doc! { "attachment_id": &attachment.attachment_id, "dataset_id": Bson::Null },
+                doc! { "$set": { "dataset_id": DATASET_PENDING } },
+            )
+            .await
+            .map_err(|e| format!("Failed to update attachment: {}", e))?;
+        if claimed.modified_count != 1 {
+            return Err("Attachment was already added as a dataset".to_string());
+        }
+
+        let created = match self.content(attachment).await {
+            Ok(data) => {
+                self.dataset_service
+                    .upload_csv(
+                        &attachment.project_id,
+                        user_id,
+                        &attachment.filename,
+                        dto.name.as_deref(),
+                        dto.description,
+                        data,
+                    )
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let dataset_id = match created {
+            Ok(ref dataset) => Bson::String(dataset.dataset_id.clone()),
+            Err(_) => Bson::Null,
+        };
+        collection
+            .update_one(
+                doc! { "attachment_id": &attachment.attachment_id, "dataset_id": DATASET_PENDING },
+                doc! { "$set": { "dataset_id": dataset_id } },
+            )
+            .await
+            .map_err(|e| format!("Failed to update attachment: {}", e))?;
+
+        created
+    }
+
+    /// Remove a conversation's attachments and their files
+    pub async fn delete_for_conversation(&self, conversation_id: &str) -> Result<(), String> {
+        let attachments = self.for_conversation(conversation_id).await?;
+        self.delete_all(&attachments).await
+    }
+
+    /// Remove attachments, such as those of messages dropped when a reply is regenerated
+    pub async fn delete(&self, attachment_ids: &[String]) -> Result<(), String> {
+        if attachment_ids.is_empty() {
+            return Ok(());
+        }
+        let attachments: Vec<ChatAttachment> = self
+            .db
+            .chat_attachments_collection()
+            .find(doc! { "attachment_id": { "$in": attachment_ids } })
+            .await
+            .map_err(|e| format!("Failed to get attachments: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect attachments: {}", e))?;
+        self.delete_all(&attachments).await
+    }
+
+    /// Remove uploads older than `ttl` that were never sent with a message
+    pub async fn delete_unbound(&self, ttl: Duration) -> Result<usize, String> {
+        let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - ttl.as_millis() as i64);
+        let attachments: Vec<ChatAttachment> = self
+            .db
+            .chat_attachments_collection()
+            .find(doc! { "conversation_id": Bson::Null, "created_at": { "$lt": cutoff } })
+            .await
+            .map_err(|e| format!("Failed to get unbound attachments: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect unbound attachments: {}", e))?;
+        self.delete_all(&attachments).await?;
+        Ok(attachments.len())
+    }
+
+    /// Delete uploads never sent with a message once they are older than `ttl`,
+    /// checking every hour in the background
+    pub fn start_cleanup(self: Arc<Self>, ttl: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                match self.delete_unbound(ttl).await {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("Deleted {} unused chat attachments", n),
+                    Err(e) => log::warn!("Failed to delete unused chat attachments: {}", e),
+                }
+            }
+        });
+    }
+
+    async fn delete_all(&self, attachments: &[ChatAttachment]) -> Result<(), String> {
+        if attachments.is_empty() {
+            return Ok(());
+        }
+        let bucket = self.db.attachments_bucket();
+        for attachment in attachments {
+            if let Err(e) = bucket.delete(Bson::ObjectId(attachment.file_id)).await {
+                log::warn!("Failed to delete attachment file {}: {}", attachment.attachment_id, e);
+            }
+        }
+
+        let ids: Vec<&str> = attachments.iter().map(|a| a.attachment_id.as_str()).collect();
+        self.db
+            .chat_attachments_collection()
+            .delete_many(doc! { "attachment_id": { "$in": ids } })
+            .await
+            .map_err(|e| format!("Failed to delete attachments: {}", e))?;
+        Ok(())
+    }
+
+    /// Summary, columns and row count of a file
+    #[allow(clippy::type_complexity)]
+    fn extract(
+        filename: &str,
+        format: AttachmentFormat,
+        data: &[u8],
+        max_chars: usize,
+    ) -> Result<(String, Vec<ColumnInfo>, Option<i64>), String> {
+        match format.document_format() {
+            Some(document_format) => {
+                let text = KnowledgeService::extract_text(document_format, data)?;
+                Ok((shorten(&normalize_whitespace(&text), max_chars), vec![], None))
+            }
+            None => {
+                let (columns, rows) = DatasetService::parse_table(filename, data)?;
+                let summary = table_summary(&columns, &rows);
+                Ok((shorten(&summary, max_chars), columns, Some(rows.len() as i64)))
+            }
+        }
+    }
+}
+
+/// Collapse runs of blank lines and trailing spaces left by text extraction
+fn normalize_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
+
+/// `text` cut to `max_chars`, noting how much was left out
+fn shorten(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+    format!(
+        "{}\n[... truncated, {} of {} characters shown]",
+        text.chars().take(max_chars).collect::<String>().trim_end(),
+        max_chars,
+        total
+    )
+}
+
+fn cell_text(value: &Value) -> String {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    if text.chars().count() > SAMPLE_CELL_CHARS {
+        format!("{}…", text.chars().take(SAMPLE_CELL_CHARS).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Row count, per-column statistics and the first rows of a table
+fn table_summary(columns: &[ColumnInfo], rows: &[Vec<Value>]) -> String {
+    let mut out = format!("{} rows, {} columns\n\nColumns:\n", rows.len(), columns.len());
+
+    for (i, column) in columns.iter().enumerate() {
+        let cells: Vec<&Value> = rows.iter().map(|r| &r[i]).filter(|v| !v.is_null()).collect();
+        let empty = rows.len() - cells.len();
+        let stats = match column.data_type.as_str() {
+            "integer" | "number" => {
+                let numbers: Vec<f64> = cells.iter().filter_map(|v| v.as_f64()).collect();
+                if numbers.is_empty() {
+                    String::new()
+                } else {
+                    let min = numbers.iter().cloned().fold(f64::INFINITY, f64::min);
+                    let max = numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                    let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+                    format!("min {}, max {}, mean {:.2}", min, max, mean)
+                }
+            }
+            _ => {
+                let mut distinct: Vec<String> = cells.iter().map(|v| cell_text(v)).collect();
+                distinct.sort();
+                distinct.dedup();
+                format!("{} distinct", distinct.len())
+            }
+        };
+        let mut details: Vec<String> = vec![column.data_type.clone()];
+        if !stats.is_empty() {
+            details.push(stats);
+        }
+        if empty > 0 {
+            details.push(format!("{} empty", empty));
+        }
+        out.push_str(&format!("- {} ({})\n", column.name, details.join("; ")));
+    }
+
+    if !rows.is_empty() {
+        out.push_str(&format!("\nFirst {} rows:\n", rows.len().min(SAMPLE_ROWS)));
+        out.push_str(&columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(" | "));
+        out.push('\n');
+        for row in rows.iter().take(SAMPLE_ROWS) {
+            out.push_str(&row.iter().map(cell_text).collect::<Vec<_>>().join(" | "));
+            out.push('\n');
+        }
+    }
+
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_tables_with_column_statistics() {
+        let (columns, rows) =
+            DatasetService::parse_table("sales.csv", b"region,revenue,units\nNorth,100.5,3\nSouth,,5\nNorth,300,\n")
+                .unwrap();
+
+        assert_eq!(
+            table_summary(&columns, &rows),
+            "3 rows, 3 columns\n\n\
+             Columns:\n\
+             - region (string; 2 distinct)\n\
+             - revenue (number; min 100.5, max 300, mean 200.25; 1 empty)\n\
+             - units (integer; min 3, max 5, mean 4.00; 1 empty)\n\n\
+             First 3 rows:\n\
+             region | revenue | units\n\
+             North | 100.5 | 3\n\
+             South |  | 5\n\
+             North | 300.0 |"
+        );
+    }
+
+    #[test]
+    fn shortens_long_text_and_detects_formats() {
+        assert_eq!(shorten("abcdef", 10), "abcdef");
+        assert_eq!(shorten("abcdef", 3), "abc\n[... truncated, 3 of 6 characters shown]");
+        assert_eq!(normalize_whitespace("a  \n\n\n\nb\n"), "a\n\nb");
+
+        assert_eq!(AttachmentFormat::detect("q3.CSV", None), Some(AttachmentFormat::Csv));
+        assert_eq!(AttachmentFormat::detect("data", Some("text/csv; charset=utf-8")), Some(AttachmentFormat::Csv));
+        assert_eq!(AttachmentFormat::detect("report.pdf", None), Some(AttachmentFormat::Pdf));
+        assert_eq!(AttachmentFormat::detect("image.png", Some("image/png")), None);
+    }
+}
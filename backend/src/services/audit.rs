@@ -0,0 +1,79 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{AuditLogEntry, AuditLogEntryResponse, AuditLogQuery};
+
+/// Entries returned per request when no limit is given
+const DEFAULT_LIMIT: i64 = 100;
+
+/// Something to record in a tenant's audit trail
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub tenant_id: String,
+    pub project_id: Option<String>,
+    pub user_id: String,
+    pub action: &'static str,
+    pub details: Value,
+}
+
+/// Append-only trail of security-relevant events per tenant
+pub struct AuditService {
+    db: DatabaseManager,
+}
+
+impl AuditService {
+    pub fn new(db: DatabaseManager) -> Self {
+        AuditService { db }
+    }
+
+    /// Store an event. Failures are logged, not returned.
+    pub async fn record(&self, event: AuditEvent) {
+        let entry = AuditLogEntry {
+            id: None,
+            audit_id: Uuid::new_v4().to_string(),
+            tenant_id: event.tenant_id,
+            project_id: event.project_id,
+            user_id: event.user_id,
+            action: event.action.to_string(),
+            details: event.details,
+            created_at: DateTime::now(),
+        };
+
+        if let Err(e) = self.db.audit_logs_collection().insert_one(&entry).await {
+            log::warn!("Failed to record audit event {}: {}", entry.action, e);
+        }
+    }
+
+    /// A tenant's most recent events, newest first
+    pub async fn list(
+        &self,
+        tenant_id: &str,
+        query: &AuditLogQuery,
+    ) -> Result<Vec<AuditLogEntryResponse>, String> {
+        let mut filter = doc! { "tenant_id": tenant_id };
+        if let Some(ref action) = query.action {
+            filter.insert("action", action);
+        }
+        if let Some(ref project_id) = query.project_id {
+            filter.insert("project_id", project_id);
+        }
+        if let Some(ref user_id) = query.user_id {
+            filter.insert("user_id", user_id);
+        }
+
+        let entries: Vec<AuditLogEntry> = self.db
+            .audit_logs_collection()
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 1000))
+            .await
+            .map_err(|e| format!("Failed to load audit log: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect audit log: {}", e))?;
+
+        Ok(entries.into_iter().map(|e| e.into()).collect())
+    }
+}
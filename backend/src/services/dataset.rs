@@ -0,0 +1,372 @@
+use std::sync::Arc;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime as BsonDateTime};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{ColumnInfo, Dataset, DatasetRow, SqlQueryResult};
+use crate::services::{sql, InsightService, QualityService};
+
+/// Rows written per insert
+const INSERT_BATCH_SIZE: usize = 1000;
+
+/// Project-scoped tables uploaded as CSV and queried with read-only SQL
+pub struct DatasetService {
+    db: DatabaseManager,
+    /// Largest number of data rows accepted per upload
+    max_rows: usize,
+    /// Largest number of rows a query returns to API callers
+    query_max_rows: usize,
+    /// Generates an insights report for each upload, when enabled
+    insights: Option<Arc<InsightService>>,
+    /// Runs the table's quality rules on each upload, when enabled
+    quality: Option<Arc<QualityService>>,
+}
+
+impl DatasetService {
+    pub fn new(db: DatabaseManager, max_rows: usize, query_max_rows: usize) -> Self {
+        DatasetService { db, max_rows, query_max_rows, insights: None, quality: None }
+    }
+
+    /// Profile each uploaded dataset and generate an insights report for it
+    pub fn with_insights(mut self, insight_service: Arc<InsightService>) -> Self {
+        self.insights = Some(insight_service);
+        self
+    }
+
+    /// Profile each uploaded dataset and run its table's quality rules
+    pub fn with_quality(mut self, quality_service: Arc<QualityService>) -> Self {
+        self.quality = Some(quality_service);
+        self
+    }
+
+    pub fn query_max_rows(&self) -> usize {
+        self.query_max_rows
+    }
+
+    /// Parse a CSV (or TSV) upload, infer column types and store it as table `name`
+    pub async fn upload_csv(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        filename: &str,
+        name: Option<&str>,
+        description: Option<String>,
+        data: Vec<u8>,
+    ) -> Result<Dataset, String> {
+        let name = Self::table_name(name.unwrap_or_else(|| {
+            filename.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(filename)
+        }))?;
+        if self.find_by_name(project_id, &name).await?.is_some() {
+            return Err(format!("A dataset named '{}' already exists in this project", name));
+        }
+
+        let (columns, rows) = Self::parse_table(filename, &data)?;
+        if rows.len() > self.max_rows {
+            return Err(format!("Dataset has {} rows; the limit is {}", rows.len(), self.max_rows));
+        }
+
+        let dataset_id = Uuid::new_v4().to_string();
+        let dataset = Dataset {
+            id: None,
+            dataset_id: dataset_id.clone(),
+            project_id: project_id.to_string(),
+            name,
+            description,
+            filename: filename.to_string(),
+            columns,
+            row_count: rows.len() as i64,
+            uploaded_by: user_id.to_string(),
+            created_at: BsonDateTime::now(),
+        };
+
+        let rows: Vec<DatasetRow> = rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, values)| DatasetRow {
+                id: None,
+                dataset_id: dataset_id.clone(),
+                row_index: index as i64,
+                values,
+            })
+            .collect();
+        for batch in rows.chunks(INSERT_BATCH_SIZE) {
+            if let Err(e) = self.db.dataset_rows_collection().insert_many(batch).await {
+                self.delete_rows(&dataset_id).await.ok();
+                return Err(format!("Failed to store dataset rows: {}", e));
+            }
+        }
+
+        if let Err(e) = self.db.datasets_collection().insert_one(&dataset).await {
+            // Don't leave orphaned rows behind
+            self.delete_rows(&dataset_id).await.ok();
+            return Err(format!("Failed to store dataset: {}", e));
+        }
+
+        if self.insights.is_none() && self.quality.is_none() {
+            return Ok(dataset);
+        }
+        let values: Arc<Vec<Vec<Value>>> = Arc::new(rows.into_iter().map(|r| r.values).collect());
+        if let Some(ref quality) = self.quality {
+            quality.spawn_run(dataset.clone(), user_id, Arc::clone(&values));
+        }
+        if let Some(ref insights) = self.insights {
+            if let Err(e) = insights.start(dataset.clone(), user_id, values).await {
+                log::warn!("Failed to start insights for dataset {}: {}", dataset.dataset_id, e);
+            }
+        }
+
+        Ok(dataset)
+    }
+
+    /// Parse a CSV (or, by extension, TSV) file into typed columns and rows
+    pub fn parse_table(filename: &str, data: &[u8]) -> Result<(Vec<ColumnInfo>, Vec<Vec<Value>>), String> {
+        let text = std::str::from_utf8(data).map_err(|_| "Dataset must be UTF-8 text".to_string())?;
+        let delimiter = if filename.to_lowercase().ends_with(".tsv") { '\t' } else { ',' };
+        let (headers, records) = Self::parse_csv(text, delimiter)?;
+        Ok(Self::typed_rows(headers, records))
+    }
+
+    pub async fn get_dataset(&self, dataset_id: &str) -> Result<Option<Dataset>, String> {
+        self.db
+            .datasets_collection()
+            .find_one(doc! { "dataset_id": dataset_id })
+            .await
+            .map_err(|e| format!("Failed to get dataset: {}", e))
+    }
+
+    /// Dataset by its table name, ignoring case
+    pub async fn find_by_name(&self, project_id: &str, name: &str) -> Result<Option<Dataset>, String> {
+        self.db
+            .datasets_collection()
+            .find_one(doc! { "project_id": project_id, "name": name.to_lowercase() })
+            .await
+            .map_err(|e| format!("Failed to get dataset: {}", e))
+    }
+
+    pub async fn get_project_datasets(&self, project_id: &str) -> Result<Vec<Dataset>, String> {
+        self.db
+            .datasets_collection()
+            .find(doc! { "project_id": project_id })
+            .sort(doc! { "name": 1 })
+            .await
+            .map_err(|e| format!("Failed to get datasets: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect datasets: {}", e))
+    }
+
+    /// First `limit` rows in upload order
+    pub async fn preview(&self, dataset: &Dataset, limit: i64) -> Result<Vec<Vec<Value>>, String> {
+        self.load_rows(&dataset.dataset_id, Some(limit)).await
+    }
+
+    /// Every row in upload order
+    pub async fn rows(&self, dataset: &Dataset) -> Result<Vec<Vec<Value>>, String> {
+        self.load_rows(&dataset.dataset_id, None).await
+    }
+
+    pub async fn delete_dataset(&self, dataset: &Dataset) -> Result<(), String> {
+        if let Some(ref insights) = self.insights {
+            insights.delete(&dataset.dataset_id).await?;
+        }
+        self.delete_rows(&dataset.dataset_id).await?;
+        self.db
+            .datasets_collection()
+            .delete_one(doc! { "dataset_id": &dataset.dataset_id })
+            .await
+            .map_err(|e| format!("Failed to delete dataset: {}", e))?;
+        Ok(())
+    }
+
+    /// Run a read-only query against one of the project's datasets, returning at most `max_rows` rows
+    pub async fn query(&self, project_id: &str, sql: &str, max_rows: usize) -> Result<SqlQueryResult, String> {
+        let query = sql::parse(sql)?;
+        let dataset = self
+            .find_by_name(project_id, query.table())
+            .await?
+            .ok_or_else(|| format!("Unknown table '{}'", query.table()))?;
+        let rows = self.load_rows(&dataset.dataset_id, None).await?;
+
+        tokio::task::spawn_blocking(move || sql::execute(&query, &dataset.columns, rows, max_rows))
+            .await
+            .map_err(|_| "Query failed".to_string())?
+    }
+
+    async fn load_rows(&self, dataset_id: &str, limit: Option<i64>) -> Result<Vec<Vec<Value>>, String> {
+        let collection = self.db.dataset_rows_collection();
+        let mut find = collection
+            .find(doc! { "dataset_id": dataset_id })
+            .sort(doc! { "row_index": 1 });
+        if let Some(limit) = limit {
+            find = find.limit(limit);
+        }
+        let rows: Vec<DatasetRow> = find
+            .await
+            .map_err(|e| format!("Failed to load dataset rows: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect dataset rows: {}", e))?;
+        Ok(rows.into_iter().map(|r| r.values).collect())
+    }
+
+    async fn delete_rows(&self, dataset_id: &str) -> Result<(), String> {
+        self.db
+            .dataset_rows_collection()
+            .delete_many(doc! { "dataset_id": dataset_id })
+            .await
+            .map_err(|e| format!("Failed to delete dataset rows: {}", e))?;
+        Ok(())
+    }
+
+    /// Lowercase SQL identifier for a table: letters, digits and underscores
+    fn table_name(raw: &str) -> Result<String, String> {
+        let mut name: String = raw
+            .trim()
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        name = name.trim_matches('_').to_string();
+        if name.is_empty() {
+            return Err("Dataset name must contain letters or digits".to_string());
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert_str(0, "t_");
+        }
+        Ok(name.chars().take(64).collect())
+    }
+
+    /// Split CSV text into a header row and records. Fields may be quoted, with
+    /// doubled quotes inside, and quoted fields may span lines.
+    fn parse_csv(text: &str, delimiter: char) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut records: Vec<Vec<String>> = Vec::new();
+        let mut record: Vec<String> = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if quoted {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    '"' => quoted = false,
+                    _ => field.push(c),
+                }
+            } else if c == '"' && field.is_empty() {
+                quoted = true;
+            } else if c == delimiter {
+                record.push(std::mem::take(&mut field));
+            } else if c == '\n' || c == '\r' {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            } else {
+                field.push(c);
+            }
+        }
+        if quoted {
+            return Err("CSV has an unterminated quoted field".to_string());
+        }
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push(record);
+        }
+
+        // Blank lines carry no data
+        records.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+        if records.is_empty() {
+            return Err("CSV is empty".to_string());
+        }
+
+        let headers = Self::headers(records.remove(0));
+        for (i, record) in records.iter().enumerate() {
+            if record.len() != headers.len() {
+                return Err(format!(
+                    "Row {} has {} fields; the header has {}",
+                    i + 2,
+                    record.len(),
+                    headers.len()
+                ));
+            }
+        }
+        Ok((headers, records))
+    }
+
+    /// Trimmed, unique column names; blank ones become `column_<n>`
+    pub(crate) fn headers(raw: Vec<String>) -> Vec<String> {
+        let mut headers: Vec<String> = Vec::with_capacity(raw.len());
+        for (i, header) in raw.into_iter().enumerate() {
+            let base = match header.trim() {
+                "" => format!("column_{}", i + 1),
+                name => name.to_string(),
+            };
+            let mut name = base.clone();
+            let mut n = 2;
+            while headers.iter().any(|h| h.eq_ignore_ascii_case(&name)) {
+                name = format!("{}_{}", base, n);
+                n += 1;
+            }
+            headers.push(name);
+        }
+        headers
+    }
+
+    /// Infer each column's type from its non-empty cells and convert the cells.
+    /// Empty cells become null.
+    pub(crate) fn typed_rows(headers: Vec<String>, records: Vec<Vec<String>>) -> (Vec<ColumnInfo>, Vec<Vec<Value>>) {
+        let types: Vec<&'static str> = (0..headers.len())
+            .map(|i| {
+                let mut cells = records.iter().map(|r| r[i].trim()).filter(|c| !c.is_empty()).peekable();
+                if cells.peek().is_none() {
+                    return "string";
+                }
+                let cells: Vec<&str> = cells.collect();
+                if cells.iter().all(|c| c.parse::<i64>().is_ok()) {
+                    "integer"
+                } else if cells.iter().all(|c| c.parse::<f64>().is_ok_and(f64::is_finite)) {
+                    "number"
+                } else if cells.iter().all(|c| c.eq_ignore_ascii_case("true") || c.eq_ignore_ascii_case("false")) {
+                    "boolean"
+                } else {
+                    "string"
+                }
+            })
+            .collect();
+
+        let rows = records
+            .into_iter()
+            .map(|record| {
+                record
+                    .into_iter()
+                    .zip(&types)
+                    .map(|(cell, data_type)| {
+                        let trimmed = cell.trim();
+                        if trimmed.is_empty() {
+                            return Value::Null;
+                        }
+                        match *data_type {
+                            "integer" => trimmed.parse::<i64>().map(Value::from).unwrap_or(Value::Null),
+                            "number" => trimmed.parse::<f64>().map(Value::from).unwrap_or(Value::Null),
+                            "boolean" => Value::Bool(trimmed.eq_ignore_ascii_case("true")),
+                            _ => Value::String(cell),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let columns = headers
+            .into_iter()
+            .zip(types)
+            .map(|(name, data_type)| ColumnInfo { name, data_type: data_type.to_string() })
+            .collect();
+        (columns, rows)
+    }
+}
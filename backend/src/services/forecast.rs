@@ -0,0 +1,473 @@
+//! Time-series forecasts of a measure over a date column.
+//!
+//! Values are summed or averaged per period and fitted with additive
+//! Holt-Winters exponential smoothing. The smoothing parameters are the ones
+//! with the smallest one-step-ahead error over the history, and prediction
+//! intervals widen with the horizon.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use chrono::{Datelike, NaiveDate};
+use serde_json::Value;
+use crate::models::{
+    ChartData, ChartDataset, ChartType, ColumnInfo, ForecastAggregate, ForecastDto, ForecastInterval,
+    ForecastMethod, ForecastObservation, ForecastPoint, ForecastResponse,
+};
+use crate::services::{profiling, stats, DatasetService};
+
+pub const DATASET_NOT_FOUND: &str = "Dataset not found";
+
+/// Fewest periods a forecast is fitted on
+const MIN_PERIODS: usize = 4;
+/// Most periods between the first and last date
+const MAX_PERIODS: usize = 5000;
+const DEFAULT_CONFIDENCE: f64 = 0.95;
+/// Date ranges up to this many days are forecast per day, up to
+/// `WEEKLY_MAX_DAYS` per week and longer ones per month
+const DAILY_MAX_DAYS: i64 = 120;
+const WEEKLY_MAX_DAYS: i64 = 730;
+/// Smoothing parameters tried for the level, trend and season
+const SMOOTHING_GRID: [f64; 10] = [0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Forecasts measures of the project's datasets and query results
+pub struct ForecastService {
+    datasets: Arc<DatasetService>,
+}
+
+impl ForecastService {
+    pub fn new(datasets: Arc<DatasetService>) -> Self {
+        ForecastService { datasets }
+    }
+
+    /// Forecast `dto.measure` over `dto.date_column` of a dataset or query in `project_id`
+    pub async fn forecast(&self, project_id: &str, dto: &ForecastDto) -> Result<ForecastResponse, String> {
+        let (columns, rows) = match (&dto.dataset_id, &dto.sql) {
+            (Some(dataset_id), None) => {
+                let dataset = self
+                    .datasets
+                    .get_dataset(dataset_id)
+                    .await?
+                    .filter(|d| d.project_id == project_id)
+                    .ok_or_else(|| DATASET_NOT_FOUND.to_string())?;
+                let rows = self.datasets.rows(&dataset).await?;
+                (dataset.columns, rows)
+            }
+            (None, Some(sql)) => {
+                // A query never returns more rows than its table holds
+                let result = self.datasets.query(project_id, sql, usize::MAX).await?;
+                (result.columns, result.rows)
+            }
+            _ => return Err("Give either dataset_id or sql".to_string()),
+        };
+
+        let dto = dto.clone();
+        tokio::task::spawn_blocking(move || forecast_rows(&columns, &rows, &dto))
+            .await
+            .map_err(|_| "Forecast failed".to_string())?
+    }
+}
+
+/// Periods in one season, `None` when there is no natural cycle
+pub fn season_length(interval: ForecastInterval) -> Option<usize> {
+    match interval {
+        ForecastInterval::Day => Some(7),
+        ForecastInterval::Week => Some(52),
+        ForecastInterval::Month => Some(12),
+        ForecastInterval::Quarter => Some(4),
+        ForecastInterval::Year => None,
+    }
+}
+
+fn default_horizon(interval: ForecastInterval) -> usize {
+    match interval {
+        ForecastInterval::Day => 14,
+        ForecastInterval::Week => 8,
+        ForecastInterval::Month => 6,
+        ForecastInterval::Quarter => 4,
+        ForecastInterval::Year => 3,
+    }
+}
+
+/// Consecutive number of the period containing `date`
+fn period_index(interval: ForecastInterval, date: NaiveDate) -> i64 {
+    match interval {
+        ForecastInterval::Day => date.num_days_from_ce() as i64,
+        // Weeks start on Monday; day 1 of the common era is a Monday
+        ForecastInterval::Week => {
+            (date.num_days_from_ce() as i64 - date.weekday().num_days_from_monday() as i64) / 7
+        }
+        ForecastInterval::Month => date.year() as i64 * 12 + date.month0() as i64,
+        ForecastInterval::Quarter => date.year() as i64 * 4 + (date.month0() / 3) as i64,
+        ForecastInterval::Year => date.year() as i64,
+    }
+}
+
+/// Label of a period: its date, week's Monday, `2024-03`, `2024-Q1` or `2024`
+pub fn period_label(interval: ForecastInterval, index: i64) -> String {
+    let day = |days: i64| {
+        NaiveDate::from_num_days_from_ce_opt(days as i32)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+    match interval {
+        ForecastInterval::Day => day(index),
+        ForecastInterval::Week => day(index * 7 + 1),
+        ForecastInterval::Month => format!("{:04}-{:02}", index.div_euclid(12), index.rem_euclid(12) + 1),
+        ForecastInterval::Quarter => format!("{}-Q{}", index.div_euclid(4), index.rem_euclid(4) + 1),
+        ForecastInterval::Year => index.to_string(),
+    }
+}
+
+fn column_index(columns: &[ColumnInfo], name: &str) -> Result<usize, String> {
+    columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("No column '{}'", name))
+}
+
+/// A measure per period, without gaps
+#[derive(Debug, PartialEq)]
+pub struct Series {
+    pub interval: ForecastInterval,
+    /// Period number of the first value
+    pub start: i64,
+    pub values: Vec<f64>,
+    /// Rows without a readable date or measure
+    pub skipped_rows: usize,
+}
+
+/// Sum or average `measure` per period of `date_column`. Periods without rows
+/// count as 0 when summing and are interpolated when averaging.
+pub fn series(
+    columns: &[ColumnInfo],
+    rows: &[Vec<Value>],
+    date_column: &str,
+    measure: &str,
+    interval: Option<ForecastInterval>,
+    aggregate: ForecastAggregate,
+) -> Result<Series, String> {
+    let date_index = column_index(columns, date_column)?;
+    let measure_index = column_index(columns, measure)?;
+
+    let points: Vec<(NaiveDate, f64)> = rows
+        .iter()
+        .filter_map(|row| Some((stats::parse_date(&row[date_index])?, profiling::numeric_value(&row[measure_index])?)))
+        .collect();
+    let skipped_rows = rows.len() - points.len();
+    let (Some(first), Some(last)) = (points.iter().map(|p| p.0).min(), points.iter().map(|p| p.0).max()) else {
+        return Err(format!("No rows have both a date in '{}' and a number in '{}'", date_column, measure));
+    };
+
+    let interval = interval.unwrap_or(match (last - first).num_days() {
+        days if days <= DAILY_MAX_DAYS => ForecastInterval::Day,
+        days if days <= WEEKLY_MAX_DAYS => ForecastInterval::Week,
+        _ => ForecastInterval::Month,
+    });
+    let start = period_index(interval, first);
+    let periods = (period_index(interval, last) - start + 1) as usize;
+    if periods > MAX_PERIODS {
+        return Err(format!(
+            "The dates span {} periods; the limit is {}. Choose a longer interval.",
+            periods, MAX_PERIODS
+        ));
+    }
+
+    let mut buckets: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (date, value) in points {
+        let bucket = buckets.entry(period_index(interval, date) - start).or_default();
+        bucket.0 += value;
+        bucket.1 += 1;
+    }
+
+    let mut values = vec![0.0; periods];
+    let mut known: Vec<(usize, f64)> = Vec::with_capacity(buckets.len());
+    for (offset, (sum, count)) in buckets {
+        let value = match aggregate {
+            ForecastAggregate::Sum => sum,
+            ForecastAggregate::Avg => sum / count as f64,
+        };
+        values[offset as usize] = value;
+        known.push((offset as usize, value));
+    }
+    if aggregate == ForecastAggregate::Avg {
+        for pair in known.windows(2) {
+            let ((a, va), (b, vb)) = (pair[0], pair[1]);
+            for (i, value) in values.iter_mut().enumerate().take(b).skip(a + 1) {
+                *value = va + (vb - va) * (i - a) as f64 / (b - a) as f64;
+            }
+        }
+    }
+
+    Ok(Series { interval, start, values, skipped_rows })
+}
+
+/// Smoothing run over a history with fixed parameters
+struct Smoothed {
+    sse: f64,
+    /// One-step-ahead errors counted in `sse`
+    errors: usize,
+    fitted: Vec<f64>,
+    level: f64,
+    trend: f64,
+    /// Seasonal component per position in the season
+    seasonals: Vec<f64>,
+}
+
+fn smooth(values: &[f64], season: Option<usize>, alpha: f64, beta: f64, gamma: f64) -> Smoothed {
+    let (mut level, mut trend, mut seasonals, start) = match season {
+        Some(m) => {
+            let first = stats::mean(&values[..m]).unwrap_or_default();
+            let second = stats::mean(&values[m..2 * m]).unwrap_or_default();
+            let trend = (second - first) / m as f64;
+            let middle = (m - 1) as f64 / 2.0;
+            // Seasonal offsets from the trend line through the first season
+            let seasonals: Vec<f64> = values[..m]
+                .iter()
+                .enumerate()
+                .map(|(i, y)| y - (first + (i as f64 - middle) * trend))
+                .collect();
+            (first + middle * trend, trend, seasonals, m)
+        }
+        None => (values[0], values[1] - values[0], vec![0.0], 1),
+    };
+
+    let m = seasonals.len();
+    let mut fitted = values[..start].to_vec();
+    let mut sse = 0.0;
+    for (t, &y) in values.iter().enumerate().skip(start) {
+        let s = seasonals[t % m];
+        let forecast = level + trend + s;
+        fitted.push(forecast);
+        sse += (y - forecast).powi(2);
+
+        let previous = level;
+        level = alpha * (y - s) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        if season.is_some() {
+            seasonals[t % m] = gamma * (y - level) + (1.0 - gamma) * s;
+        }
+    }
+
+    Smoothed { sse, errors: values.len() - start, fitted, level, trend, seasonals }
+}
+
+/// Fitted model with point forecasts and their standard errors
+#[derive(Debug)]
+pub struct Fit {
+    pub method: ForecastMethod,
+    pub season_length: Option<usize>,
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: Option<f64>,
+    pub rmse: f64,
+    /// One-step-ahead fit of each history value
+    pub fitted: Vec<f64>,
+    pub forecast: Vec<f64>,
+    /// Standard error of each forecast
+    pub std_errors: Vec<f64>,
+}
+
+/// Fit Holt-Winters with `season` periods per season, or Holt's linear trend
+/// when the history is shorter than two seasons, and forecast `horizon` periods
+pub fn fit(values: &[f64], season: Option<usize>, horizon: usize) -> Result<Fit, String> {
+    if values.len() < MIN_PERIODS {
+        return Err(format!(
+            "A forecast needs at least {} periods of history; this has {}",
+            MIN_PERIODS,
+            values.len()
+        ));
+    }
+    let season = season.filter(|&m| m >= 2 && values.len() >= 2 * m);
+    let gammas: &[f64] = if season.is_some() { &SMOOTHING_GRID } else { &[0.0] };
+
+    let mut best: Option<(f64, f64, f64, Smoothed)> = None;
+    for &alpha in &SMOOTHING_GRID {
+        for &beta in &SMOOTHING_GRID {
+            for &gamma in gammas {
+                let run = smooth(values, season, alpha, beta, gamma);
+                if best.as_ref().is_none_or(|b| run.sse < b.3.sse) {
+                    best = Some((alpha, beta, gamma, run));
+                }
+            }
+        }
+    }
+    let (alpha, beta, gamma, run) = best.ok_or_else(|| "Forecast failed".to_string())?;
+
+    let rmse = (run.sse / run.errors.max(1) as f64).sqrt();
+    let n = values.len();
+    let m = run.seasonals.len();
+    let forecast = (1..=horizon)
+        .map(|h| run.level + h as f64 * run.trend + run.seasonals[(n - 1 + h) % m])
+        .collect();
+    // Error variance h steps ahead grows with the weight of earlier errors
+    let mut weights: f64 = 0.0;
+    let std_errors = (1..=horizon)
+        .map(|h| {
+            let spread = rmse * (1.0 + weights).sqrt();
+            let seasonal = if season.is_some_and(|m| h % m == 0) { gamma } else { 0.0 };
+            weights += (alpha * (1.0 + h as f64 * beta) + seasonal).powi(2);
+            spread
+        })
+        .collect();
+
+    Ok(Fit {
+        method: if season.is_some() { ForecastMethod::HoltWinters } else { ForecastMethod::HoltLinear },
+        season_length: season,
+        alpha,
+        beta,
+        gamma: season.map(|_| gamma),
+        rmse,
+        fitted: run.fitted,
+        forecast,
+        std_errors,
+    })
+}
+
+/// Forecast from rows already loaded
+pub fn forecast_rows(columns: &[ColumnInfo], rows: &[Vec<Value>], dto: &ForecastDto) -> Result<ForecastResponse, String> {
+    let series = series(columns, rows, &dto.date_column, &dto.measure, dto.interval, dto.aggregate)?;
+    let interval = series.interval;
+    let horizon = dto.horizon.unwrap_or_else(|| default_horizon(interval));
+    let fit = fit(&series.values, season_length(interval), horizon)?;
+
+    let confidence = dto.confidence.unwrap_or(DEFAULT_CONFIDENCE);
+    let z = stats::normal_quantile((1.0 + confidence) / 2.0);
+    // A measure that was never negative isn't forecast below zero
+    let floor = if series.values.iter().all(|v| *v >= 0.0) { 0.0 } else { f64::NEG_INFINITY };
+    let label = |offset: usize| period_label(interval, series.start + offset as i64);
+
+    let history: Vec<ForecastObservation> = series
+        .values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| ForecastObservation { period: label(i), value: stats::round(*v, 4) })
+        .collect();
+    let n = history.len();
+    let forecast: Vec<ForecastPoint> = fit
+        .forecast
+        .iter()
+        .zip(&fit.std_errors)
+        .enumerate()
+        .map(|(i, (value, std_error))| ForecastPoint {
+            period: label(n + i),
+            value: stats::round(value.max(floor), 4),
+            lower: stats::round((value - z * std_error).max(floor), 4),
+            upper: stats::round((value + z * std_error).max(floor), 4),
+        })
+        .collect();
+
+    // Bands start at the fitted line and open up over the forecast
+    let fitted: Vec<f64> = fit.fitted.iter().map(|v| stats::round(*v, 4)).collect();
+    let percent = (confidence * 100.0).round();
+    let series_of = |label: String, data: Vec<f64>| ChartDataset {
+        label,
+        data,
+        background_color: None,
+        border_color: None,
+    };
+    let chart = ChartData {
+        chart_type: ChartType::Line,
+        title: Some(format!("{} forecast", dto.measure)),
+        labels: history.iter().map(|h| h.period.clone()).chain(forecast.iter().map(|f| f.period.clone())).collect(),
+        datasets: vec![
+            series_of("Actual".to_string(), history.iter().map(|h| h.value).collect()),
+            series_of("Forecast".to_string(), fitted.iter().copied().chain(forecast.iter().map(|f| f.value)).collect()),
+            series_of(format!("Lower {}%", percent), fitted.iter().copied().chain(forecast.iter().map(|f| f.lower)).collect()),
+            series_of(format!("Upper {}%", percent), fitted.iter().copied().chain(forecast.iter().map(|f| f.upper)).collect()),
+        ],
+        annotations: vec![],
+    };
+
+    Ok(ForecastResponse {
+        date_column: dto.date_column.clone(),
+        measure: dto.measure.clone(),
+        interval,
+        aggregate: dto.aggregate,
+        method: fit.method,
+        season_length: fit.season_length,
+        alpha: fit.alpha,
+        beta: fit.beta,
+        gamma: fit.gamma,
+        rmse: stats::round(fit.rmse, 4),
+        confidence,
+        skipped_rows: series.skipped_rows,
+        history,
+        forecast,
+        chart,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(interval: Option<ForecastInterval>, aggregate: ForecastAggregate) -> ForecastDto {
+        ForecastDto {
+            dataset_id: None,
+            sql: None,
+            date_column: "day".to_string(),
+            measure: "sales".to_string(),
+            interval,
+            aggregate,
+            horizon: Some(4),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn buckets_rows_into_periods() {
+        let csv = "day,sales\n2024-01-01,10\n2024-01-01,4\n2024-01-03,6\nlater,5\n2024-01-08,20\n";
+        let (columns, rows) = DatasetService::parse_table("sales.csv", csv.as_bytes()).unwrap();
+
+        let daily = series(&columns, &rows, "day", "Sales", None, ForecastAggregate::Sum).unwrap();
+        assert_eq!(daily.interval, ForecastInterval::Day);
+        assert_eq!(daily.values, vec![14.0, 0.0, 6.0, 0.0, 0.0, 0.0, 0.0, 20.0]);
+        assert_eq!(daily.skipped_rows, 1);
+
+        let averaged = series(&columns, &rows, "day", "sales", None, ForecastAggregate::Avg).unwrap();
+        assert_eq!(&averaged.values[..3], &[7.0, 6.5, 6.0]);
+        assert_eq!(stats::round(averaged.values[3], 4), 8.8);
+
+        let weekly = series(&columns, &rows, "day", "sales", Some(ForecastInterval::Week), ForecastAggregate::Sum).unwrap();
+        assert_eq!(weekly.values, vec![20.0, 20.0]);
+        assert_eq!(period_label(ForecastInterval::Week, weekly.start + 1), "2024-01-08");
+        assert_eq!(period_label(ForecastInterval::Quarter, period_index(ForecastInterval::Quarter, NaiveDate::from_ymd_opt(2024, 8, 1).unwrap())), "2024-Q3");
+
+        assert!(series(&columns, &rows, "day", "missing", None, ForecastAggregate::Sum).is_err());
+    }
+
+    #[test]
+    fn forecasts_trend_and_season() {
+        // Quarterly sales growing by 1 a quarter with a repeating +5, -5, 0, 0 season
+        let season = [5.0, -5.0, 0.0, 0.0];
+        let mut csv = "day,sales\n".to_string();
+        for t in 0..12 {
+            csv.push_str(&format!("{}-{:02}-15,{}\n", 2021 + t / 4, (t % 4) * 3 + 1, 10.0 + t as f64 + season[t % 4]));
+        }
+        let (columns, rows) = DatasetService::parse_table("sales.csv", csv.as_bytes()).unwrap();
+
+        let response = forecast_rows(&columns, &rows, &request(Some(ForecastInterval::Quarter), ForecastAggregate::Sum)).unwrap();
+        assert_eq!(response.method, ForecastMethod::HoltWinters);
+        assert_eq!(response.season_length, Some(4));
+        assert_eq!(response.rmse, 0.0);
+        let values: Vec<f64> = response.forecast.iter().map(|f| f.value).collect();
+        assert_eq!(values, vec![27.0, 18.0, 24.0, 25.0]);
+        assert_eq!(response.forecast[0].period, "2024-Q1");
+        assert_eq!((response.forecast[0].lower, response.forecast[0].upper), (27.0, 27.0));
+
+        assert_eq!(response.chart.labels.len(), 16);
+        assert_eq!(response.chart.datasets[0].data.len(), 12);
+        assert_eq!(response.chart.datasets[3].label, "Upper 95%");
+    }
+
+    #[test]
+    fn intervals_widen_with_the_horizon() {
+        let values = [12.0, 15.0, 13.0, 17.0, 16.0, 19.0, 18.0, 22.0];
+        let fit = fit(&values, Some(7), 5).unwrap();
+        // Too short for two seasons of 7
+        assert_eq!(fit.method, ForecastMethod::HoltLinear);
+        assert!(fit.rmse > 0.0);
+        assert!(fit.std_errors.windows(2).all(|w| w[1] > w[0]));
+        assert!(super::fit(&values[..3], None, 2).is_err());
+    }
+}
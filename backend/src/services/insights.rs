@@ -0,0 +1,814 @@
+//! Insights reports generated when a dataset is ingested.
+//!
+//! A statistical profile (distributions, correlations, outliers and time
+//! trends) is computed from the rows, described to the model for narrative
+//! insights and chart advice, and stored with charts built from the profile.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use chrono::NaiveDate;
+use mongodb::bson::{self, doc, DateTime};
+use mongodb::options::ReplaceOptions;
+use serde_json::Value;
+use crate::db::DatabaseManager;
+use crate::models::{
+    CategoricalColumnProfile, ChartData, ChartDataset, ChartType, ColumnCorrelation, ColumnInfo,
+    DataProfile, Dataset, DatasetInsightReport, GuardrailSource, HistogramBin, InsightStatus,
+    NumericColumnProfile, OutlierSummary, ProjectAiSettings, RenderContent, StructuredResponse,
+    TableData, TimeTrend, ValueCount,
+};
+use crate::services::{
+    AIService, AiSettingsService, GuardrailService, PromptService, RedactionService, UsageService,
+};
+use crate::services::guardrails::GUARDRAIL_BLOCKED_MESSAGE;
+use crate::services::llm::TokenUsage;
+use crate::services::redaction::Redactor;
+use crate::services::stats;
+use crate::services::usage::UsageEvent;
+
+const HISTOGRAM_BINS: usize = 10;
+const TOP_VALUES: usize = 5;
+/// Numeric columns compared pairwise for correlations
+const MAX_CORRELATED_COLUMNS: usize = 20;
+const MIN_CORRELATION: f64 = 0.5;
+const MAX_CORRELATIONS: usize = 10;
+const OUTLIER_EXAMPLES: usize = 5;
+const MAX_TREND_MEASURES: usize = 5;
+/// Longer date ranges are summed per month, shorter ones per day
+const DAILY_TREND_MAX_DAYS: i64 = 90;
+const MAX_CHARTS: usize = 4;
+/// Longest category value shown to the model
+const VALUE_CHARS: usize = 40;
+/// Pending reports older than this were left behind by a server that stopped
+const STALE_PENDING_MINUTES: i64 = 30;
+/// Error stored on a report whose generation never finished
+const INTERRUPTED: &str = "Report generation was interrupted; generate it again";
+
+/// Profiles ingested datasets and stores an AI-written insights report for each
+pub struct InsightService {
+    db: DatabaseManager,
+    ai_service: AIService,
+    ai_settings_service: Arc<AiSettingsService>,
+    prompt_service: Arc<PromptService>,
+    usage_service: Arc<UsageService>,
+    redaction_service: Option<Arc<RedactionService>>,
+    guardrail_service: Option<Arc<GuardrailService>>,
+}
+
+impl InsightService {
+    pub fn new(
+        db: DatabaseManager,
+        ai_service: AIService,
+        ai_settings_service: Arc<AiSettingsService>,
+        prompt_service: Arc<PromptService>,
+        usage_service: Arc<UsageService>,
+    ) -> Self {
+        InsightService {
+            db,
+            ai_service,
+            ai_settings_service,
+            prompt_service,
+            usage_service,
+            redaction_service: None,
+            guardrail_service: None,
+        }
+    }
+
+    /// Replace personal data in dataset summaries before they reach the model
+    pub fn with_redaction(mut self, redaction_service: Arc<RedactionService>) -> Self {
+        self.redaction_service = Some(redaction_service);
+        self
+    }
+
+    /// Screen dataset summaries for prompt injection
+    pub fn with_guardrails(mut self, guardrail_service: Arc<GuardrailService>) -> Self {
+        self.guardrail_service = Some(guardrail_service);
+        self
+    }
+
+    /// Store a pending report for `dataset` and generate it in the background
+    pub async fn start(
+        self: &Arc<Self>,
+        dataset: Dataset,
+        user_id: &str,
+        rows: Arc<Vec<Vec<Value>>>,
+    ) -> Result<DatasetInsightReport, String> {
+        let report = DatasetInsightReport {
+            id: None,
+            dataset_id: dataset.dataset_id.clone(),
+            project_id: dataset.project_id.clone(),
+            status: InsightStatus::Pending,
+            profile: None,
+            report: None,
+            error: None,
+            provider: None,
+            model: None,
+            requested_by: user_id.to_string(),
+            created_at: DateTime::now(),
+            completed_at: None,
+        };
+        self.db
+            .dataset_insights_collection()
+            .replace_one(doc! { "dataset_id": &report.dataset_id }, &report)
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await
+            .map_err(|e| format!("Failed to store insights: {}", e))?;
+
+        let service = Arc::clone(self);
+        let pending = report.clone();
+        tokio::spawn(async move {
+            let dataset_id = dataset.dataset_id.clone();
+            if let Err(e) = service.generate(report, &dataset, rows).await {
+                log::warn!("Failed to generate insights for dataset {}: {}", dataset_id, e);
+            }
+        });
+        Ok(pending)
+    }
+
+    /// The dataset's report. A report left pending by a server that stopped is
+    /// marked failed first.
+    pub async fn get(&self, dataset_id: &str) -> Result<Option<DatasetInsightReport>, String> {
+        let report = self
+            .db
+            .dataset_insights_collection()
+            .find_one(doc! { "dataset_id": dataset_id })
+            .await
+            .map_err(|e| format!("Failed to get insights: {}", e))?;
+
+        match report {
+            Some(mut report) if report.status == InsightStatus::Pending && report.created_at < stale_cutoff() => {
+                let completed_at = DateTime::now();
+                self.finish(
+                    &report,
+                    doc! { "status": status(InsightStatus::Failed)?, "error": INTERRUPTED, "completed_at": completed_at },
+                )
+                .await?;
+                report.status = InsightStatus::Failed;
+                report.error = Some(INTERRUPTED.to_string());
+                report.completed_at = Some(completed_at);
+                Ok(Some(report))
+            }
+            report => Ok(report),
+        }
+    }
+
+    /// Mark reports left pending by a server that stopped as failed
+    pub async fn fail_stale(&self) -> Result<u64, String> {
+        let result = self
+            .db
+            .dataset_insights_collection()
+            .update_many(
+                doc! { "status": status(InsightStatus::Pending)?, "created_at": { "$lt": stale_cutoff() } },
+                doc! { "$set": {
+                    "status": status(InsightStatus::Failed)?,
+                    "error": INTERRUPTED,
+                    "completed_at": DateTime::now(),
+                } },
+            )
+            .await
+            .map_err(|e| format!("Failed to update stale insights: {}", e))?;
+        Ok(result.modified_count)
+    }
+
+    pub async fn delete(&self, dataset_id: &str) -> Result<(), String> {
+        self.db
+            .dataset_insights_collection()
+            .delete_one(doc! { "dataset_id": dataset_id })
+            .await
+            .map_err(|e| format!("Failed to delete insights: {}", e))?;
+        Ok(())
+    }
+
+    /// Profile the rows, ask the model about the profile and store the result.
+    /// Failures are stored on the report as well as returned.
+    async fn generate(
+        &self,
+        mut report: DatasetInsightReport,
+        dataset: &Dataset,
+        rows: Arc<Vec<Vec<Value>>>,
+    ) -> Result<(), String> {
+        let columns = dataset.columns.clone();
+        let profile = match tokio::task::spawn_blocking(move || profile_dataset(&columns, &rows)).await {
+            Ok(profile) => profile,
+            Err(_) => return self.fail(report, "Failed to profile dataset".to_string()).await,
+        };
+        report.profile = Some(profile.clone());
+
+        if let Err(e) = self.usage_service.check_quota(&report.project_id, &report.requested_by).await {
+            return self.fail(report, e).await;
+        }
+
+        let summary = describe(dataset, &profile);
+        let screened = match self.guardrail_service {
+            Some(ref service) => {
+                let guard = service.guard(&report.project_id, &report.requested_by, "insights").await;
+                // Cell values reach the prompt the same way tool results over the data do
+                let screened = guard.screen(GuardrailSource::ToolResult, &summary);
+                service.record(&guard).await;
+                screened
+            }
+            None => Some(summary),
+        };
+        let Some(screened) = screened else {
+            return self.fail(report, GUARDRAIL_BLOCKED_MESSAGE.to_string()).await;
+        };
+
+        let redactor = match self.redaction_service {
+            Some(ref service) => service.redactor(&report.project_id, &report.requested_by, "insights").await,
+            None => Redactor::disabled(),
+        };
+        let summary = redactor.redact(&screened);
+        if let Some(ref service) = self.redaction_service {
+            service.audit(&redactor).await;
+        }
+
+        let settings = self.ai_settings_service.settings_or_default(&report.project_id).await;
+        let mut prompts = self.prompt_service.resolve_for_project(&report.project_id).await;
+        let project_id = report.project_id.clone();
+
+        let insights = match self
+            .ai_service
+            .generate_data_insights(Some(&project_id), &summary, settings.as_ref(), &mut prompts)
+            .await
+        {
+            Ok(response) => {
+                self.record_usage(&report, settings.as_ref(), response.usage).await;
+                redactor.restore(&response.content)
+            }
+            Err(e) => return self.fail(report, e).await,
+        };
+
+        // Chart advice is a nice-to-have; the report stands without it
+        let advice = match self
+            .ai_service
+            .suggest_visualization(Some(&project_id), &summary, settings.as_ref(), &mut prompts)
+            .await
+        {
+            Ok(response) => {
+                self.record_usage(&report, settings.as_ref(), response.usage).await;
+                Some(redactor.restore(&response.content))
+            }
+            Err(e) => {
+                log::warn!("Failed to suggest visualizations for dataset {}: {}", report.dataset_id, e);
+                None
+            }
+        };
+
+        let structured = build_report(&profile, &insights, advice.as_deref());
+        if let Err(e) = structured.validate_content() {
+            return self.fail(report, e).await;
+        }
+
+        let provider = self.ai_service.provider_for(settings.as_ref());
+        let profile = bson::to_bson(&profile).map_err(|e| format!("Failed to encode profile: {}", e))?;
+        let structured = bson::to_bson(&structured).map_err(|e| format!("Failed to encode insights: {}", e))?;
+        self.finish(
+            &report,
+            doc! {
+                "status": status(InsightStatus::Completed)?,
+                "profile": profile,
+                "report": structured,
+                "provider": provider.name(),
+                "model": provider.model(),
+                "completed_at": DateTime::now(),
+            },
+        )
+        .await
+    }
+
+    async fn record_usage(
+        &self,
+        report: &DatasetInsightReport,
+        settings: Option<&ProjectAiSettings>,
+        usage: Option<TokenUsage>,
+    ) {
+        let Some(usage) = usage else {
+            return;
+        };
+        let provider = self.ai_service.provider_for(settings);
+        let event = UsageEvent {
+            project_id: report.project_id.clone(),
+            user_id: report.requested_by.clone(),
+            operation: "insights",
+            provider: provider.name().to_string(),
+            model: provider.model().map(str::to_string),
+        };
+        self.usage_service.record(event, usage).await;
+    }
+
+    /// Record that the report could not be generated and return the error
+    async fn fail(&self, report: DatasetInsightReport, error: String) -> Result<(), String> {
+        let profile = bson::to_bson(&report.profile).map_err(|e| format!("Failed to encode profile: {}", e))?;
+        self.finish(
+            &report,
+            doc! {
+                "status": status(InsightStatus::Failed)?,
+                "profile": profile,
+                "error": &error,
+                "completed_at": DateTime::now(),
+            },
+        )
+        .await?;
+        Err(error)
+    }
+
+    /// Set the outcome of a pending report. Nothing is written when the dataset
+    /// was deleted or its report was started again in the meantime.
+    async fn finish(&self, report: &DatasetInsightReport, outcome: bson::Document) -> Result<(), String> {
+        self.db
+            .dataset_insights_collection()
+            .update_one(
+                doc! {
+                    "dataset_id": &report.dataset_id,
+                    "status": status(InsightStatus::Pending)?,
+                    "created_at": report.created_at,
+                },
+                doc! { "$set": outcome },
+            )
+            .await
+            .map_err(|e| format!("Failed to store insights: {}", e))?;
+        Ok(())
+    }
+}
+
+fn status(status: InsightStatus) -> Result<bson::Bson, String> {
+    bson::to_bson(&status).map_err(|e| format!("Failed to encode status: {}", e))
+}
+
+fn stale_cutoff() -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - STALE_PENDING_MINUTES * 60_000)
+}
+
+/// Text form of a cell for counting and display
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() > VALUE_CHARS {
+        format!("{}…", text.chars().take(VALUE_CHARS).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Distributions, correlations, outliers and time trends of a dataset
+pub fn profile_dataset(columns: &[ColumnInfo], rows: &[Vec<Value>]) -> DataProfile {
+    let mut profile = DataProfile {
+        row_count: rows.len() as i64,
+        ..DataProfile::default()
+    };
+
+    let mut numeric_columns: Vec<usize> = Vec::new();
+    let mut date_columns: Vec<usize> = Vec::new();
+    for (i, column) in columns.iter().enumerate() {
+        let cells: Vec<&Value> = rows.iter().map(|r| &r[i]).filter(|v| !v.is_null()).collect();
+        let nulls = (rows.len() - cells.len()) as i64;
+        match column.data_type.as_str() {
+            "integer" | "number" => {
+                let values: Vec<f64> = cells.iter().filter_map(|v| stats::number(v)).collect();
+                if let Some(numeric) = numeric_profile(&column.name, &values, nulls) {
+                    profile.outliers.extend(outliers(&column.name, &values));
+                    profile.numeric.push(numeric);
+                    numeric_columns.push(i);
+                }
+            }
+            _ => {
+                if stats::is_date_column(&cells) {
+                    date_columns.push(i);
+                    continue;
+                }
+                profile.categorical.push(categorical_profile(&column.name, &cells, nulls));
+            }
+        }
+    }
+
+    profile.correlations = correlations(columns, rows, &numeric_columns);
+    for &date_column in &date_columns {
+        profile.trends.extend(trends(columns, rows, date_column, &numeric_columns));
+    }
+    profile
+}
+
+fn numeric_profile(column: &str, values: &[f64], nulls: i64) -> Option<NumericColumnProfile> {
+    let sorted = stats::sorted(values);
+    let (min, max) = (*sorted.first()?, *sorted.last()?);
+
+    let bins = if min == max { 1 } else { HISTOGRAM_BINS };
+    let width = (max - min) / bins as f64;
+    let mut histogram: Vec<HistogramBin> = (0..bins)
+        .map(|b| HistogramBin {
+            start: stats::round(min + width * b as f64, 4),
+            end: stats::round(if b + 1 == bins { max } else { min + width * (b + 1) as f64 }, 4),
+            count: 0,
+        })
+        .collect();
+    for value in values {
+        let bin = if width == 0.0 { 0 } else { (((value - min) / width) as usize).min(bins - 1) };
+        histogram[bin].count += 1;
+    }
+
+    Some(NumericColumnProfile {
+        column: column.to_string(),
+        count: values.len() as i64,
+        nulls,
+        min,
+        max,
+        mean: stats::round(stats::mean(values)?, 4),
+        std_dev: stats::std_dev(values).map(|s| stats::round(s, 4)),
+        median: stats::quantile(&sorted, 0.5)?,
+        histogram,
+    })
+}
+
+fn categorical_profile(column: &str, cells: &[&Value], nulls: i64) -> CategoricalColumnProfile {
+    let mut counts: HashMap<String, i64> = HashMap::new();
+    for cell in cells {
+        *counts.entry(cell_text(cell)).or_default() += 1;
+    }
+    let distinct = counts.len() as i64;
+    let mut top: Vec<ValueCount> = counts
+        .into_iter()
+        .map(|(value, count)| ValueCount { value, count })
+        .collect();
+    top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    top.truncate(TOP_VALUES);
+
+    CategoricalColumnProfile {
+        column: column.to_string(),
+        distinct,
+        nulls,
+        top_values: top,
+    }
+}
+
+/// Values outside the 1.5 × IQR fences, or `None` if there are none
+fn outliers(column: &str, values: &[f64]) -> Option<OutlierSummary> {
+    if values.len() < 4 {
+        return None;
+    }
+    let sorted = stats::sorted(values);
+    let (q1, q3) = (stats::quantile(&sorted, 0.25)?, stats::quantile(&sorted, 0.75)?);
+    let iqr = q3 - q1;
+    let (lower_fence, upper_fence) = (q1 - 1.5 * iqr, q3 + 1.5 * iqr);
+
+    let median = stats::quantile(&sorted, 0.5)?;
+    let mut outlying: Vec<f64> = values
+        .iter()
+        .copied()
+        .filter(|v| *v < lower_fence || *v > upper_fence)
+        .collect();
+    if outlying.is_empty() {
+        return None;
+    }
+    let count = outlying.len() as i64;
+    outlying.sort_by(|a, b| (b - median).abs().total_cmp(&(a - median).abs()));
+    outlying.truncate(OUTLIER_EXAMPLES);
+
+    Some(OutlierSummary {
+        column: column.to_string(),
+        count,
+        lower_fence: stats::round(lower_fence, 4),
+        upper_fence: stats::round(upper_fence, 4),
+        examples: outlying,
+    })
+}
+
+/// Strongly correlated pairs of numeric columns, strongest first
+fn correlations(columns: &[ColumnInfo], rows: &[Vec<Value>], numeric: &[usize]) -> Vec<ColumnCorrelation> {
+    let numeric = &numeric[..numeric.len().min(MAX_CORRELATED_COLUMNS)];
+    let mut correlations = Vec::new();
+    for (n, &a) in numeric.iter().enumerate() {
+        for &b in &numeric[n + 1..] {
+            let (xs, ys): (Vec<f64>, Vec<f64>) = rows
+                .iter()
+                .filter_map(|r| Some((stats::number(&r[a])?, stats::number(&r[b])?)))
+                .unzip();
+            if let Some(r) = stats::pearson(&xs, &ys).filter(|r| r.abs() >= MIN_CORRELATION) {
+                correlations.push(ColumnCorrelation {
+                    a: columns[a].name.clone(),
+                    b: columns[b].name.clone(),
+                    coefficient: stats::round(r, 3),
+                });
+            }
+        }
+    }
+    correlations.sort_by(|x, y| y.coefficient.abs().total_cmp(&x.coefficient.abs()));
+    correlations.truncate(MAX_CORRELATIONS);
+    correlations
+}
+
+/// Numeric measures summed per period of a date column. Without numeric
+/// columns the number of rows per period is used.
+fn trends(columns: &[ColumnInfo], rows: &[Vec<Value>], date_column: usize, numeric: &[usize]) -> Vec<TimeTrend> {
+    let dated: Vec<(NaiveDate, &Vec<Value>)> = rows
+        .iter()
+        .filter_map(|r| Some((stats::parse_date(&r[date_column])?, r)))
+        .collect();
+    let (Some(first), Some(last)) = (dated.iter().map(|(d, _)| *d).min(), dated.iter().map(|(d, _)| *d).max()) else {
+        return vec![];
+    };
+    let (period, format) = if (last - first).num_days() > DAILY_TREND_MAX_DAYS {
+        ("month", "%Y-%m")
+    } else {
+        ("day", "%Y-%m-%d")
+    };
+
+    let measures: Vec<Option<usize>> = if numeric.is_empty() {
+        vec![None]
+    } else {
+        numeric.iter().take(MAX_TREND_MEASURES).map(|&m| Some(m)).collect()
+    };
+
+    measures
+        .into_iter()
+        .filter_map(|measure| {
+            let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+            for (date, row) in &dated {
+                let value = match measure {
+                    Some(m) => stats::number(&row[m]).unwrap_or(0.0),
+                    None => 1.0,
+                };
+                *totals.entry(date.format(format).to_string()).or_default() += value;
+            }
+            if totals.len() < 3 {
+                return None;
+            }
+            let (labels, values): (Vec<String>, Vec<f64>) =
+                totals.into_iter().map(|(label, total)| (label, stats::round(total, 4))).unzip();
+            let (slope, _) = stats::linear_fit(&values)?;
+            let (first, last) = (values[0], values[values.len() - 1]);
+            Some(TimeTrend {
+                date_column: columns[date_column].name.clone(),
+                measure: measure.map(|m| columns[m].name.clone()).unwrap_or_else(|| "rows".to_string()),
+                period: period.to_string(),
+                labels,
+                values,
+                slope: stats::round(slope, 4),
+                change_pct: (first != 0.0).then(|| stats::round((last - first) / first.abs() * 100.0, 2)),
+            })
+        })
+        .collect()
+}
+
+/// The profile as text for the model
+fn describe(dataset: &Dataset, profile: &DataProfile) -> String {
+    let mut out = format!("Dataset \"{}\" ({}), {} rows.\n", dataset.name, dataset.filename, profile.row_count);
+    if let Some(ref description) = dataset.description {
+        out.push_str(&format!("Description: {}\n", description));
+    }
+
+    if !profile.numeric.is_empty() {
+        out.push_str("\nNumeric columns:\n");
+        for n in &profile.numeric {
+            out.push_str(&format!(
+                "- {}: min {}, median {}, mean {}, max {}{}{}\n",
+                n.column,
+                n.min,
+                n.median,
+                n.mean,
+                n.max,
+                n.std_dev.map(|s| format!(", std dev {}", s)).unwrap_or_default(),
+                if n.nulls > 0 { format!(", {} empty", n.nulls) } else { String::new() },
+            ));
+        }
+    }
+    if !profile.categorical.is_empty() {
+        out.push_str("\nCategorical columns:\n");
+        for c in &profile.categorical {
+            let top: Vec<String> = c
+                .top_values
+                .iter()
+                .map(|v| format!("{} ({})", shorten(&v.value), v.count))
+                .collect();
+            out.push_str(&format!(
+                "- {}: {} distinct{}; most common: {}\n",
+                c.column,
+                c.distinct,
+                if c.nulls > 0 { format!(", {} empty", c.nulls) } else { String::new() },
+                top.join(", "),
+            ));
+        }
+    }
+    if !profile.correlations.is_empty() {
+        out.push_str("\nCorrelations:\n");
+        for c in &profile.correlations {
+            out.push_str(&format!("- {} and {}: {}\n", c.a, c.b, c.coefficient));
+        }
+    }
+    if !profile.outliers.is_empty() {
+        out.push_str("\nOutliers (outside 1.5 × IQR):\n");
+        for o in &profile.outliers {
+            let examples: Vec<String> = o.examples.iter().map(|e| e.to_string()).collect();
+            out.push_str(&format!(
+                "- {}: {} values outside [{}, {}], e.g. {}\n",
+                o.column,
+                o.count,
+                o.lower_fence,
+                o.upper_fence,
+                examples.join(", "),
+            ));
+        }
+    }
+    if !profile.trends.is_empty() {
+        out.push_str("\nTime trends:\n");
+        for t in &profile.trends {
+            out.push_str(&format!(
+                "- {} per {} of {}: {} periods from {} to {}, slope {} per {}{}\n",
+                t.measure,
+                t.period,
+                t.date_column,
+                t.labels.len(),
+                t.labels[0],
+                t.labels[t.labels.len() - 1],
+                t.slope,
+                t.period,
+                t.change_pct.map(|c| format!(", {}% first to last", c)).unwrap_or_default(),
+            ));
+        }
+    }
+    out
+}
+
+fn chart(chart_type: ChartType, title: String, label: &str, labels: Vec<String>, data: Vec<f64>) -> RenderContent {
+    RenderContent::Chart {
+        data: ChartData {
+            chart_type,
+            title: Some(title),
+            labels,
+            datasets: vec![ChartDataset {
+                label: label.to_string(),
+                data,
+                background_color: None,
+                border_color: None,
+            }],
+            annotations: vec![],
+        },
+    }
+}
+
+/// Charts worth showing for a profile: trends first, then the main
+/// categorical breakdown and the first numeric distribution
+fn recommended_charts(profile: &DataProfile) -> Vec<RenderContent> {
+    let mut charts: Vec<RenderContent> = profile
+        .trends
+        .iter()
+        .take(2)
+        .map(|t| {
+            chart(
+                ChartType::Line,
+                format!("{} by {}", t.measure, t.period),
+                &t.measure,
+                t.labels.clone(),
+                t.values.clone(),
+            )
+        })
+        .collect();
+
+    if let Some(c) = profile.categorical.iter().find(|c| (2..=50).contains(&c.distinct)) {
+        charts.push(chart(
+            ChartType::Bar,
+            format!("Most common {}", c.column),
+            "rows",
+            c.top_values.iter().map(|v| shorten(&v.value)).collect(),
+            c.top_values.iter().map(|v| v.count as f64).collect(),
+        ));
+    }
+
+    if let Some(n) = profile.numeric.iter().find(|n| n.histogram.len() > 1) {
+        charts.push(chart(
+            ChartType::Bar,
+            format!("Distribution of {}", n.column),
+            "rows",
+            n.histogram.iter().map(|b| format!("{}–{}", b.start, b.end)).collect(),
+            n.histogram.iter().map(|b| b.count as f64).collect(),
+        ));
+    }
+
+    charts.truncate(MAX_CHARTS);
+    charts
+}
+
+/// One row per column for the report's overview table
+fn column_overview(profile: &DataProfile) -> TableData {
+    let mut rows: Vec<Vec<String>> = profile
+        .numeric
+        .iter()
+        .map(|n| {
+            vec![
+                n.column.clone(),
+                "numeric".to_string(),
+                n.nulls.to_string(),
+                format!("{} to {}, mean {}", n.min, n.max, n.mean),
+            ]
+        })
+        .collect();
+    rows.extend(profile.categorical.iter().map(|c| {
+        vec![
+            c.column.clone(),
+            "categorical".to_string(),
+            c.nulls.to_string(),
+            match c.top_values.first() {
+                Some(top) => format!("{} distinct, most common {}", c.distinct, shorten(&top.value)),
+                None => "no values".to_string(),
+            },
+        ]
+    }));
+    let mut date_columns: Vec<&str> = profile.trends.iter().map(|t| t.date_column.as_str()).collect();
+    date_columns.dedup();
+    rows.extend(date_columns.into_iter().filter_map(|column| {
+        let trend = profile.trends.iter().find(|t| t.date_column == column)?;
+        Some(vec![
+            column.to_string(),
+            "date".to_string(),
+            String::new(),
+            format!("{} to {}", trend.labels[0], trend.labels[trend.labels.len() - 1]),
+        ])
+    }));
+
+    TableData {
+        headers: vec!["Column".to_string(), "Kind".to_string(), "Empty".to_string(), "Summary".to_string()],
+        rows,
+    }
+}
+
+fn build_report(profile: &DataProfile, insights: &str, advice: Option<&str>) -> StructuredResponse {
+    let mut items = vec![RenderContent::Text { content: insights.trim().to_string() }];
+    items.extend(recommended_charts(profile));
+    if let Some(advice) = advice.map(str::trim).filter(|a| !a.is_empty()) {
+        items.push(RenderContent::Text { content: advice.to_string() });
+    }
+    let overview = column_overview(profile);
+    if !overview.rows.is_empty() {
+        items.push(RenderContent::Table { data: overview });
+    }
+    StructuredResponse { items }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::DatasetService;
+
+    fn sample() -> (Vec<ColumnInfo>, Vec<Vec<Value>>) {
+        let mut csv = String::from("date,region,revenue,units\n");
+        for day in 1..=12 {
+            let region = if day % 3 == 0 { "South" } else { "North" };
+            let revenue = if day == 12 { 5000 } else { 100 + day * 10 };
+            csv.push_str(&format!("2024-01-{:02},{},{},{}\n", day, region, revenue, day * 2));
+        }
+        DatasetService::parse_table("sales.csv", csv.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn profiles_distributions_correlations_outliers_and_trends() {
+        let (columns, rows) = sample();
+        let profile = profile_dataset(&columns, &rows);
+
+        assert_eq!(profile.row_count, 12);
+        assert_eq!(profile.numeric.len(), 2);
+        let revenue = &profile.numeric[0];
+        assert_eq!((revenue.min, revenue.max, revenue.count), (110.0, 5000.0, 12));
+        assert_eq!(revenue.histogram.iter().map(|b| b.count).sum::<i64>(), 12);
+
+        assert_eq!(profile.categorical.len(), 1);
+        assert_eq!(profile.categorical[0].top_values[0], ValueCount { value: "North".to_string(), count: 8 });
+
+        assert_eq!(profile.outliers.len(), 1);
+        assert_eq!(profile.outliers[0].column, "revenue");
+        assert_eq!(profile.outliers[0].examples, vec![5000.0]);
+
+        assert_eq!(profile.correlations[0].a, "revenue");
+        assert_eq!(profile.correlations[0].b, "units");
+
+        let units = profile.trends.iter().find(|t| t.measure == "units").unwrap();
+        assert_eq!((units.date_column.as_str(), units.period.as_str()), ("date", "day"));
+        assert_eq!(units.labels.len(), 12);
+        assert_eq!(units.slope, 2.0);
+        assert_eq!(units.change_pct, Some(1100.0));
+    }
+
+    #[test]
+    fn builds_a_valid_report_with_recommended_charts() {
+        let (columns, rows) = sample();
+        let profile = profile_dataset(&columns, &rows);
+        let report = build_report(&profile, "Revenue spiked on the 12th.", Some("Use a line chart."));
+
+        assert!(report.validate_content().is_ok());
+        let charts: Vec<&ChartData> = report
+            .items
+            .iter()
+            .filter_map(|i| match i {
+                RenderContent::Chart { data } => Some(data),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(charts.len(), MAX_CHARTS);
+        assert_eq!(charts[0].chart_type, ChartType::Line);
+        assert_eq!(charts[2].title.as_deref(), Some("Most common region"));
+        assert!(matches!(report.items.last(), Some(RenderContent::Table { .. })));
+    }
+}
@@ -0,0 +1,544 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime as BsonDateTime};
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{DocumentChunk, DocumentFormat, KnowledgeDocument, MessageCitation};
+use crate::services::AIService;
+
+/// Model name recorded for chunks embedded without the provider
+pub const LOCAL_EMBEDDING_MODEL: &str = "local-hash-v1";
+
+/// Dimensions of the local hashed embedding
+const LOCAL_EMBEDDING_DIMS: usize = 384;
+
+/// Texts sent per embeddings request
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+/// The fields of a stored chunk needed to score it
+#[derive(Deserialize)]
+struct ChunkVector {
+    chunk_id: String,
+    embedding: Vec<f32>,
+    embedding_model: String,
+}
+
+/// Project-scoped document store with chunking, embeddings and similarity search
+pub struct KnowledgeService {
+    db: DatabaseManager,
+    ai_service: AIService,
+    chunk_size: usize,
+    chunk_overlap: usize,
+    top_k: usize,
+}
+
+impl KnowledgeService {
+    pub fn new(
+        db: DatabaseManager,
+        ai_service: AIService,
+        chunk_size: usize,
+        chunk_overlap: usize,
+        top_k: usize,
+    ) -> Self {
+        KnowledgeService {
+            db,
+            ai_service,
+            chunk_size,
+            chunk_overlap,
+            top_k,
+        }
+    }
+
+    /// Extract, chunk, embed and store an uploaded document
+    pub async fn upload_document(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        filename: &str,
+        format: DocumentFormat,
+        data: Vec<u8>,
+    ) -> Result<KnowledgeDocument, String> {
+        let size_bytes = data.len() as i64;
+
+        // PDF parsing is CPU bound and can panic on malformed files, so keep it off the executor
+        let text = tokio::task::spawn_blocking(move || Self::extract_text(format, &data))
+            .await
+            .map_err(|_| "Failed to read document".to_string())??;
+
+        let chunks = Self::chunk_text(&text, self.chunk_size, self.chunk_overlap);
+        if chunks.is_empty() {
+            return Err("Document contains no text".to_string());
+        }
+
+        let (embedding_model, embeddings) = self.embed_documents(&chunks).await;
+
+        let document_id = Uuid::new_v4().to_string();
+        let now = BsonDateTime::now();
+        let chunk_docs: Vec<DocumentChunk> = chunks
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(index, (content, embedding))| DocumentChunk {
+                id: None,
+                chunk_id: Uuid::new_v4().to_string(),
+                document_id: document_id.clone(),
+                project_id: project_id.to_string(),
+                filename: filename.to_string(),
+                chunk_index: index as i32,
+                content,
+                embedding,
+                embedding_model: embedding_model.clone(),
+                created_at: now,
+            })
+            .collect();
+
+        let document = KnowledgeDocument {
+            id: None,
+            document_id: document_id.clone(),
+            project_id: project_id.to_string(),
+            filename: filename.to_string(),
+            format,
+            size_bytes,
+            chunk_count: chunk_docs.len() as i64,
+            embedding_model,
+            uploaded_by: user_id.to_string(),
+            created_at: now,
+        };
+
+        self.db
+            .document_chunks_collection()
+            .insert_many(&chunk_docs)
+            .await
+            .map_err(|e| format!("Failed to store document chunks: {}", e))?;
+
+        if let Err(e) = self.db.knowledge_documents_collection().insert_one(&document).await {
+            // Don't leave orphaned chunks behind
+            self.db
+                .document_chunks_collection()
+                .delete_many(doc! { "document_id": &document_id })
+                .await
+                .ok();
+            return Err(format!("Failed to store document: {}", e));
+        }
+
+        self.invalidate_answers(project_id).await;
+        Ok(document)
+    }
+
+    pub async fn get_document(&self, document_id: &str) -> Result<Option<KnowledgeDocument>, String> {
+        self.db
+            .knowledge_documents_collection()
+            .find_one(doc! { "document_id": document_id })
+            .await
+            .map_err(|e| format!("Failed to get document: {}", e))
+    }
+
+    pub async fn get_project_documents(&self, project_id: &str) -> Result<Vec<KnowledgeDocument>, String> {
+        self.db
+            .knowledge_documents_collection()
+            .find(doc! { "project_id": project_id })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(|e| format!("Failed to get documents: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect documents: {}", e))
+    }
+
+    pub async fn delete_document(&self, document: &KnowledgeDocument) -> Result<(), String> {
+        let document_id = document.document_id.as_str();
+        self.db
+            .document_chunks_collection()
+            .delete_many(doc! { "document_id": document_id })
+            .await
+            .map_err(|e| format!("Failed to delete document chunks: {}", e))?;
+
+        self.db
+            .knowledge_documents_collection()
+            .delete_one(doc! { "document_id": document_id })
+            .await
+            .map_err(|e| format!("Failed to delete document: {}", e))?;
+
+        self.invalidate_answers(&document.project_id).await;
+        Ok(())
+    }
+
+    /// Cached answers may quote or miss the documents that just changed
+    async fn invalidate_answers(&self, project_id: &str) {
+        if let Err(e) = self.ai_service.invalidate_cache(project_id).await {
+            log::warn!("Failed to invalidate response cache for project {}: {}", project_id, e);
+        }
+    }
+
+    /// Return the project's chunks most similar to `query`, best first.
+    /// Similarity is computed in process over the project's vectors; each chunk is only
+    /// compared with a query embedding from the same model it was stored with. Only ids
+    /// and vectors are streamed for scoring, and text is loaded for the best `top_k`.
+    pub async fn retrieve(
+        &self,
+        project_id: &str,
+        query: &str,
+        top_k: Option<usize>,
+    ) -> Result<Vec<MessageCitation>, String> {
+        let top_k = top_k.unwrap_or(self.top_k);
+        if top_k == 0 || query.trim().is_empty() {
+            return Ok(vec![]);
+        }
+
+        let collection = self.db.document_chunks_collection();
+        let models = collection
+            .distinct("embedding_model", doc! { "project_id": project_id })
+            .await
+            .map_err(|e| format!("Failed to load embedding models: {}", e))?;
+        if models.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Embed the query once per model present in the project
+        let mut query_vectors: HashMap<String, Vec<f32>> = HashMap::new();
+        for model in models.iter().filter_map(|m| m.as_str()) {
+            if let Some(vector) = self.embed_query(model, query).await {
+                query_vectors.insert(model.to_string(), vector);
+            }
+        }
+
+        let mut vectors = collection
+            .clone_with_type::<ChunkVector>()
+            .find(doc! { "project_id": project_id })
+            .projection(doc! { "_id": 0, "chunk_id": 1, "embedding": 1, "embedding_model": 1 })
+            .await
+            .map_err(|e| format!("Failed to load document chunks: {}", e))?;
+
+        let mut best: Vec<(f64, String)> = Vec::with_capacity(top_k + 1);
+        while let Some(chunk) = vectors
+            .try_next()
+            .await
+            .map_err(|e| format!("Failed to read document chunks: {}", e))?
+        {
+            let Some(query_vector) = query_vectors.get(&chunk.embedding_model) else {
+                continue;
+            };
+            let score = Self::cosine_similarity(query_vector, &chunk.embedding);
+            Self::keep_best(&mut best, top_k, score, chunk.chunk_id);
+        }
+        if best.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ids: Vec<&str> = best.iter().map(|(_, id)| id.as_str()).collect();
+        let mut chunks: HashMap<String, DocumentChunk> = collection
+            .find(doc! { "chunk_id": { "$in": ids } })
+            .projection(doc! { "embedding": 0 })
+            .await
+            .map_err(|e| format!("Failed to load document chunks: {}", e))?
+            .try_collect::<Vec<DocumentChunk>>()
+            .await
+            .map_err(|e| format!("Failed to collect document chunks: {}", e))?
+            .into_iter()
+            .map(|chunk| (chunk.chunk_id.clone(), chunk))
+            .collect();
+
+        Ok(best
+            .into_iter()
+            .filter_map(|(score, chunk_id)| {
+                let chunk = chunks.remove(&chunk_id)?;
+                Some(MessageCitation {
+                    metadata: Some(serde_json::json!({
+                        "source": "knowledge_base",
+                        "document_id": &chunk.document_id,
+                        "filename": &chunk.filename,
+                        "chunk_index": chunk.chunk_index,
+                    })),
+                    content: chunk.content,
+                    score: Some(score),
+                    document_id: Some(chunk.document_id),
+                    title: Some(chunk.filename),
+                })
+            })
+            .collect())
+    }
+
+    /// Render retrieved chunks as a numbered block for the prompt
+    pub fn format_context(citations: &[MessageCitation]) -> Option<String> {
+        if citations.is_empty() {
+            return None;
+        }
+
+        Some(
+            citations
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    format!(
+                        "[{}] {}\n{}",
+                        i + 1,
+                        c.title.as_deref().unwrap_or("document"),
+                        c.content
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        )
+    }
+
+    /// Embed chunks with the provider, falling back to local embeddings if it is
+    /// unavailable. All chunks of a document share one model.
+    async fn embed_documents(&self, chunks: &[String]) -> (String, Vec<Vec<f32>>) {
+        if let Some(model) = self.ai_service.embedding_model() {
+            let mut vectors = Vec::with_capacity(chunks.len());
+            let mut failed = false;
+            for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+                match self.ai_service.embed(batch).await {
+                    Ok(v) => vectors.extend(v),
+                    Err(e) => {
+                        log::warn!("Embeddings unavailable, using local embeddings: {}", e);
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            if !failed {
+                return (model.to_string(), vectors);
+            }
+        }
+
+        (
+            LOCAL_EMBEDDING_MODEL.to_string(),
+            chunks.iter().map(|c| Self::local_embedding(c)).collect(),
+        )
+    }
+
+    async fn embed_query(&self, model: &str, query: &str) -> Option<Vec<f32>> {
+        if model == LOCAL_EMBEDDING_MODEL {
+            return Some(Self::local_embedding(query));
+        }
+        if self.ai_service.embedding_model() != Some(model) {
+            log::warn!("Skipping chunks embedded with unconfigured model {}", model);
+            return None;
+        }
+        match self.ai_service.embed(&[query.to_string()]).await {
+            Ok(mut vectors) => vectors.pop(),
+            Err(e) => {
+                log::warn!("Failed to embed query with {}: {}", model, e);
+                None
+            }
+        }
+    }
+
+    /// Plain text of a document. PDF parsing is CPU bound; call it off the executor.
+    pub fn extract_text(format: DocumentFormat, data: &[u8]) -> Result<String, String> {
+        match format {
+            DocumentFormat::Text | DocumentFormat::Markdown => String::from_utf8(data.to_vec())
+                .map_err(|_| "Document is not valid UTF-8 text".to_string()),
+            DocumentFormat::Html => Ok(html2text::from_read(data, 120)),
+            DocumentFormat::Pdf => pdf_extract::extract_text_from_mem(data)
+                .map_err(|e| format!("Failed to extract PDF text: {}", e)),
+        }
+    }
+
+    /// Add a scored chunk to `best`, which holds at most `top_k` positive scores, highest
+    /// first; ties keep the chunk seen first
+    fn keep_best(best: &mut Vec<(f64, String)>, top_k: usize, score: f64, chunk_id: String) {
+        if score <= 0.0 || (best.len() == top_k && score <= best[top_k - 1].0) {
+            return;
+        }
+        let at = best.partition_point(|(s, _)| *s >= score);
+        best.insert(at, (score, chunk_id));
+        best.truncate(top_k);
+    }
+
+    /// Split text into windows of about `size` characters that overlap by `overlap`.
+    /// Windows end at a paragraph, line, sentence or word break where one is available
+    /// in the second half of the window.
+    fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+
+        while start < chars.len() {
+            let mut end = (start + size).min(chars.len());
+
+            if end < chars.len() {
+                let window = &chars[start + size / 2..end];
+                let break_at = |pred: &dyn Fn(usize) -> bool| {
+                    (0..window.len()).rev().find(|&i| pred(i)).map(|i| start + size / 2 + i + 1)
+                };
+                end = break_at(&|i| window[i] == '\n' && i > 0 && window[i - 1] == '\n')
+                    .or_else(|| break_at(&|i| window[i] == '\n'))
+                    .or_else(|| break_at(&|i| matches!(window[i], '.' | '!' | '?') && i + 1 < window.len() && window[i + 1].is_whitespace()))
+                    .or_else(|| break_at(&|i| window[i].is_whitespace()))
+                    .unwrap_or(end);
+            }
+
+            let chunk: String = chars[start..end].iter().collect();
+            let chunk = chunk.trim();
+            if !chunk.is_empty() {
+                chunks.push(chunk.to_string());
+            }
+
+            if end == chars.len() {
+                break;
+            }
+            start = end.saturating_sub(overlap).max(start + 1);
+        }
+
+        chunks
+    }
+
+    /// Deterministic embedding for offline use: words and character trigrams are
+    /// hashed into a fixed number of signed buckets, then L2-normalised.
+    pub(crate) fn local_embedding(text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; LOCAL_EMBEDDING_DIMS];
+        let mut add = |feature: &str, weight: f32| {
+            let hash = Self::fnv1a(feature.as_bytes());
+            let bucket = (hash % LOCAL_EMBEDDING_DIMS as u64) as usize;
+            let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+            vector[bucket] += sign * weight;
+        };
+
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+        {
+            add(&word, 1.0);
+            let padded: Vec<char> = format!(" {} ", word).chars().collect();
+            for trigram in padded.windows(3) {
+                add(&trigram.iter().collect::<String>(), 0.5);
+            }
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+        if a.len() != b.len() || a.is_empty() {
+            return 0.0;
+        }
+        let (mut dot, mut norm_a, mut norm_b) = (0f64, 0f64, 0f64);
+        for (x, y) in a.iter().zip(b) {
+            dot += *x as f64 * *y as f64;
+            norm_a += *x as f64 * *x as f64;
+            norm_b += *y as f64 * *y as f64;
+        }
+        if norm_a == 0.0 || norm_b == 0.0 {
+            0.0
+        } else {
+            dot / (norm_a.sqrt() * norm_b.sqrt())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn short_text_is_one_trimmed_chunk() {
+        assert_eq!(KnowledgeService::chunk_text("  Revenue grew.\n", 100, 20), vec!["Revenue grew."]);
+        assert!(KnowledgeService::chunk_text(" \n\n ", 100, 20).is_empty());
+    }
+
+    #[test]
+    fn chunks_break_at_paragraphs_then_sentences() {
+        let text = "Sales rose in March across all regions.\n\nCosts fell slightly. Margins improved overall.";
+        let chunks = KnowledgeService::chunk_text(text, 60, 0);
+        assert_eq!(chunks[0], "Sales rose in March across all regions.");
+
+        let text = "Sales rose in March. Costs fell slightly in April and May.";
+        let chunks = KnowledgeService::chunk_text(text, 30, 0);
+        assert_eq!(chunks[0], "Sales rose in March.");
+        assert!(chunks[1].starts_with("Costs fell"), "{:?}", chunks[1]);
+    }
+
+    #[test]
+    fn consecutive_chunks_overlap() {
+        let text: String = (0..60).map(|i| format!("word{} ", i)).collect();
+        let chunks = KnowledgeService::chunk_text(&text, 50, 15);
+
+        assert!(chunks.len() > 2);
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 50, "{:?}", chunk);
+        }
+        // The overlap is counted in characters, so a chunk may start mid-word
+        for pair in chunks.windows(2) {
+            let shared: String = pair[1].chars().take(10).collect();
+            assert!(pair[0].contains(&shared), "{:?} does not overlap {:?}", pair[1], pair[0]);
+        }
+        // Every word survives chunking
+        let seen: std::collections::HashSet<&str> = chunks.iter().flat_map(|c| words(c)).collect();
+        assert!(words(&text).iter().all(|w| seen.contains(w)));
+    }
+
+    #[test]
+    fn chunking_counts_characters_not_bytes() {
+        let text = "Überschuss für März: 東京の売上は増えました。 ".repeat(20);
+        let chunks = KnowledgeService::chunk_text(&text, 40, 10);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 40, "{:?}", chunk);
+            assert!(text.contains(chunk.as_str()));
+        }
+        for pair in chunks.windows(2) {
+            let shared: String = pair[1].chars().take(5).collect();
+            assert!(pair[0].contains(&shared), "{:?} does not overlap {:?}", pair[1], pair[0]);
+        }
+        assert!(chunks[0].starts_with("Überschuss"));
+        assert!(text.trim_end().ends_with(chunks.last().unwrap().as_str()));
+    }
+
+    #[test]
+    fn local_embedding_is_deterministic_and_normalised() {
+        let a = KnowledgeService::local_embedding("Quarterly revenue by region");
+        let b = KnowledgeService::local_embedding("Quarterly revenue by region");
+        assert_eq!(a.len(), LOCAL_EMBEDDING_DIMS);
+        assert_eq!(a, b);
+        let norm: f32 = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+
+        // Case and punctuation do not change the embedding
+        assert_eq!(a, KnowledgeService::local_embedding("quarterly REVENUE, by region!"));
+
+        let related = KnowledgeService::local_embedding("revenue by region");
+        let unrelated = KnowledgeService::local_embedding("holiday schedule for staff");
+        assert!(
+            KnowledgeService::cosine_similarity(&a, &related) > KnowledgeService::cosine_similarity(&a, &unrelated)
+        );
+
+        let empty = KnowledgeService::local_embedding("");
+        assert_eq!(empty.len(), LOCAL_EMBEDDING_DIMS);
+        assert!(empty.iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn keep_best_holds_the_top_k_highest_first() {
+        let mut best = Vec::new();
+        for (score, id) in [(0.2, "a"), (0.9, "b"), (-0.5, "c"), (0.0, "d"), (0.5, "e"), (0.7, "f"), (0.5, "g")] {
+            KnowledgeService::keep_best(&mut best, 3, score, id.to_string());
+        }
+        let ids: Vec<&str> = best.iter().map(|(_, id)| id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "f", "e"]);
+
+        let mut best = Vec::new();
+        KnowledgeService::keep_best(&mut best, 2, 0.4, "x".to_string());
+        KnowledgeService::keep_best(&mut best, 2, 0.4, "y".to_string());
+        KnowledgeService::keep_best(&mut best, 2, 0.4, "z".to_string());
+        assert_eq!(best, vec![(0.4, "x".to_string()), (0.4, "y".to_string())]);
+    }
+}
@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use super::{
+    check_status, sse_data_lines, ChatEventStream, ChatStreamEvent, HttpClient, HttpTimeouts,
+    CompletionRequest, LlmMessage, LlmProvider, LlmResponse, ProviderConfig, ProviderError, TokenUsage,
+};
+
+const DISPLAY_NAME: &str = "Messages API";
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<LlmMessage>,
+    max_tokens: i32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+struct MessagesUsage {
+    #[serde(default)]
+    input_tokens: Option<u32>,
+    #[serde(default)]
+    output_tokens: Option<u32>,
+}
+
+impl From<MessagesUsage> for TokenUsage {
+    fn from(usage: MessagesUsage) -> Self {
+        let prompt_tokens = usage.input_tokens.unwrap_or(0);
+        let completion_tokens = usage.output_tokens.unwrap_or(0);
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated: false,
+        }
+    }
+}
+
+/// One `data:` payload of the event stream; which fields are set depends on `type`
+#[derive(Debug, Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    message: Option<StreamMessage>,
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+    #[serde(default)]
+    usage: Option<MessagesUsage>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    #[serde(default)]
+    message: String,
+}
+
+/// Backends speaking a Messages-style API: the system prompt is a top-level field
+/// and the stream is a sequence of typed events
+pub struct MessagesApiProvider {
+    client: HttpClient,
+    name: String,
+    api_url: String,
+    model_name: String,
+    api_key: Option<String>,
+    timeouts: HttpTimeouts,
+}
+
+impl MessagesApiProvider {
+    pub fn new(config: &ProviderConfig) -> Self {
+        MessagesApiProvider {
+            client: HttpClient::new(config),
+            name: config.name.clone(),
+            api_url: config.api_url.clone(),
+            model_name: config.model_name.clone(),
+            api_key: config.api_key.clone(),
+            timeouts: config.timeouts,
+        }
+    }
+
+    fn headers(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            HeaderName::from_static("anthropic-version"),
+            HeaderValue::from_static(API_VERSION),
+        );
+
+        if let Some(ref api_key) = self.api_key {
+            headers.insert(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_str(api_key)
+                    .map_err(|e| format!("Invalid API key format: {}", e))?
+            );
+        }
+
+        Ok(headers)
+    }
+
+    /// Move system messages into the `system` field. The API wants turns to
+    /// alternate starting with the user, so consecutive same-role turns are merged.
+    fn build_request(&self, request: &CompletionRequest, stream: bool) -> MessagesRequest<'_> {
+        let system: Vec<&str> = request
+            .messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.content.as_str())
+            .collect();
+
+        let mut messages: Vec<LlmMessage> = Vec::new();
+        for message in request.messages.iter().filter(|m| m.role != "system") {
+            match messages.last_mut() {
+                Some(last) if last.role == message.role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&message.content);
+                }
+                None if message.role != "user" => {
+                    messages.push(LlmMessage::user("(continued)"));
+                    messages.push(message.clone());
+                }
+                _ => messages.push(message.clone()),
+            }
+        }
+
+        MessagesRequest {
+            model: &self.model_name,
+            system: if system.is_empty() { None } else { Some(system.join("\n\n")) },
+            messages,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream,
+        }
+    }
+
+    async fn post_messages(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}/v1/messages", self.api_url);
+
+        let request = self.client
+            .post(&url)
+            .headers(self.headers()?)
+            .timeout(self.timeouts.for_call(stream))
+            .json(&self.build_request(request, stream));
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send request to {}", DISPLAY_NAME), e))?;
+
+        check_status(response, DISPLAY_NAME).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MessagesApiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn display_name(&self) -> &str {
+        DISPLAY_NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        let response = self.post_messages(&request, false).await?;
+
+        let parsed: MessagesResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} response", DISPLAY_NAME), e))?;
+
+        let text: Vec<String> = parsed
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        if text.is_empty() {
+            return Err("No response from AI model".to_string().into());
+        }
+
+        Ok(LlmResponse {
+            content: text.concat(),
+            citations: vec![],
+            usage: parsed.usage.map(TokenUsage::from),
+            cached: false,
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        let response = self.post_messages(&request, true).await?;
+        let mut lines = sse_data_lines(Box::pin(response.bytes_stream()));
+
+        Ok(Box::pin(async_stream::stream! {
+            // Input tokens arrive in `message_start`, output tokens in `message_delta`
+            let mut usage = MessagesUsage::default();
+
+            while let Some(line) = lines.next().await {
+                let (_, data) = match line {
+                    Ok(l) => l,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                let event: StreamEvent = match serde_json::from_str(&data) {
+                    Ok(e) => e,
+                    Err(_) => continue,
+                };
+
+                match event.kind.as_str() {
+                    "message_start" => {
+                        if let Some(u) = event.message.and_then(|m| m.usage) {
+                            usage.input_tokens = u.input_tokens;
+                        }
+                    }
+                    "content_block_delta" => {
+                        if let Some(text) = event.delta.and_then(|d| d.text).filter(|t| !t.is_empty()) {
+                            yield Ok(ChatStreamEvent::Content(text));
+                        }
+                    }
+                    "message_delta" => {
+                        if let Some(u) = event.usage {
+                            usage.output_tokens = u.output_tokens;
+                            if u.input_tokens.is_some() {
+                                usage.input_tokens = u.input_tokens;
+                            }
+                            yield Ok(ChatStreamEvent::Usage(usage.into()));
+                        }
+                    }
+                    "error" => {
+                        let message = event
+                            .error
+                            .map(|e| e.message)
+                            .unwrap_or_else(|| "Unknown error".to_string());
+                        yield Err(format!("{} error: {}", DISPLAY_NAME, message));
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }))
+    }
+}
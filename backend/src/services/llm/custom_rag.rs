@@ -0,0 +1,247 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use crate::models::MessageCitation;
+use super::{
+    check_status, sse_data_lines, ChatEventStream, ChatStreamEvent, HttpClient, HttpTimeouts,
+    CompletionRequest, LlmProvider, LlmResponse, ProviderConfig, ProviderError,
+};
+
+/// Retrieval depth used when the request doesn't set one
+const DEFAULT_TOP_K: i32 = 5;
+
+#[derive(Debug, Serialize)]
+struct CustomRAGRequest {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<i32>,
+    temperature: f32,
+    max_tokens: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CustomRAGResponse {
+    message: RAGMessage,
+    #[serde(default)]
+    sources: Vec<RAGSource>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct RAGMessage {
+    content: String,
+    #[serde(default)]
+    sources: Vec<RAGSource>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct RAGSource {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    score: Option<f64>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+}
+
+impl From<RAGSource> for MessageCitation {
+    fn from(source: RAGSource) -> Self {
+        let meta_str = |keys: &[&str]| -> Option<String> {
+            let meta = source.metadata.as_ref()?;
+            keys.iter().find_map(|k| match meta.get(*k)? {
+                serde_json::Value::String(s) => Some(s.clone()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+        };
+
+        MessageCitation {
+            document_id: meta_str(&["document_id", "doc_id", "id"]),
+            title: meta_str(&["title", "filename", "source"]),
+            content: source.content,
+            score: source.score,
+            metadata: source.metadata,
+        }
+    }
+}
+
+/// Custom RAG API: takes a single query string and does its own retrieval
+pub struct CustomRagProvider {
+    client: HttpClient,
+    name: String,
+    api_url: String,
+    api_key: Option<String>,
+    timeouts: HttpTimeouts,
+}
+
+impl CustomRagProvider {
+    const DISPLAY_NAME: &'static str = "Custom RAG API";
+
+    pub fn new(config: &ProviderConfig) -> Self {
+        CustomRagProvider {
+            client: HttpClient::new(config),
+            name: config.name.clone(),
+            api_url: config.api_url.clone(),
+            api_key: config.api_key.clone(),
+            timeouts: config.timeouts,
+        }
+    }
+
+    /// The RAG API takes one query string: the last user message is the query and
+    /// system and assistant messages are folded in as context
+    fn build_request(request: &CompletionRequest) -> CustomRAGRequest {
+        let query = request
+            .messages
+            .iter()
+            .rfind(|m| m.role == "user")
+            .map(|m| m.content.clone())
+            .unwrap_or_default();
+
+        let context: Vec<&str> = request
+            .messages
+            .iter()
+            .filter(|m| m.role == "system" || m.role == "assistant")
+            .map(|m| m.content.as_str())
+            .collect();
+
+        let message = if context.is_empty() {
+            query
+        } else {
+            format!("Context:\n{}\n\nQuery: {}", context.join("\n"), query)
+        };
+
+        CustomRAGRequest {
+            message,
+            session_id: None,
+            top_k: Some(request.top_k.unwrap_or(DEFAULT_TOP_K)),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+        }
+    }
+
+    async fn post(&self, path: &str, request: &CustomRAGRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}{}", self.api_url, path);
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        // Add X-API-Key header for Custom RAG
+        if let Some(ref api_key) = self.api_key {
+            headers.insert(
+                reqwest::header::HeaderName::from_static("x-api-key"),
+                HeaderValue::from_str(api_key)
+                    .map_err(|e| format!("Invalid API key format: {}", e))?
+            );
+        }
+
+        let request = self.client
+            .post(&url)
+            .headers(headers)
+            .timeout(self.timeouts.for_call(stream))
+            .json(request);
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send request to {}", Self::DISPLAY_NAME), e))?;
+
+        check_status(response, Self::DISPLAY_NAME).await
+    }
+
+    /// Interpret one SSE `data:` payload. JSON payloads may carry `content` or `token`
+    /// text and/or a `sources` array; anything that is not JSON is treated as raw text.
+    fn parse_stream_data(event_name: &str, data: &str) -> Vec<ChatStreamEvent> {
+        if data.trim() == "[DONE]" || data.trim() == "{}" {
+            return vec![];
+        }
+
+        let value: serde_json::Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(_) if event_name == "sources" => return vec![],
+            Err(_) => return vec![ChatStreamEvent::Content(data.to_string())],
+        };
+
+        let mut events = Vec::new();
+        let sources = if event_name == "sources" && value.is_array() {
+            Some(value.clone())
+        } else {
+            value.get("sources").filter(|v| v.is_array()).cloned()
+        };
+        if let Some(sources) = sources {
+            let sources: Vec<RAGSource> = serde_json::from_value(sources).unwrap_or_default();
+            if !sources.is_empty() {
+                events.push(ChatStreamEvent::Sources(
+                    sources.into_iter().map(MessageCitation::from).collect(),
+                ));
+            }
+        }
+
+        let text = match &value {
+            serde_json::Value::String(s) => Some(s.as_str()),
+            v => v
+                .get("content")
+                .or_else(|| v.get("token"))
+                .and_then(|c| c.as_str()),
+        };
+        if let Some(text) = text.filter(|t| !t.is_empty()) {
+            events.push(ChatStreamEvent::Content(text.to_string()));
+        }
+
+        events
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CustomRagProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn display_name(&self) -> &str {
+        Self::DISPLAY_NAME
+    }
+
+    /// The RAG API picks its own model
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        let response = self.post("/api/v1/chat", &Self::build_request(&request), false).await?;
+
+        let rag_response: CustomRAGResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} response", Self::DISPLAY_NAME), e))?;
+
+        // Sources may be attached to the message or to the response envelope
+        let sources = if rag_response.message.sources.is_empty() {
+            rag_response.sources
+        } else {
+            rag_response.message.sources
+        };
+
+        Ok(LlmResponse {
+            content: rag_response.message.content,
+            citations: sources.into_iter().map(MessageCitation::from).collect(),
+            usage: None,
+            cached: false,
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        let response = self.post("/api/v1/chat/stream", &Self::build_request(&request), true).await?;
+        let lines = sse_data_lines(Box::pin(response.bytes_stream()));
+
+        Ok(Box::pin(lines.flat_map(|line| {
+            let events: Vec<Result<ChatStreamEvent, String>> = match line {
+                Ok((event_name, data)) => Self::parse_stream_data(&event_name, &data)
+                    .into_iter()
+                    .map(Ok)
+                    .collect(),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(events)
+        })))
+    }
+}
@@ -0,0 +1,360 @@
+//! Recorded provider HTTP exchanges.
+//!
+//! With `AI_RECORD_DIR` set, every provider request goes through a
+//! [`FixtureRecorder`] that writes the request, the response status and each
+//! body chunk with its delay to a JSON file. A [`ReplayServer`] serves those
+//! files back from a local port, so providers can be exercised without a live
+//! model: the test suite uses it, and so does `AI_REPLAY_DIR`.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Response headers worth keeping; the rest vary per call or identify the account
+const RECORDED_HEADERS: &[&str] = &["content-type"];
+
+/// Makes fixture names unique within a process
+static FIXTURE_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// One request and the response it got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub provider: String,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and query; the host differs between recording and replay
+    pub path: String,
+    /// JSON body, or the raw text if it was not JSON
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub chunks: Vec<RecordedChunk>,
+}
+
+/// A piece of the response body as it arrived
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedChunk {
+    /// Time since the previous chunk, or since the response headers for the first
+    #[serde(default)]
+    pub delay_ms: u64,
+    pub data: String,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read fixture {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Invalid fixture {}: {}", path.display(), e))
+    }
+
+    /// Every `*.json` fixture in `dir`, in file name order
+    pub fn load_dir(dir: &Path) -> Result<Vec<Self>, String> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read fixture directory {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        paths.iter().map(|path| Self::load(path)).collect()
+    }
+
+    fn body_value(bytes: &[u8]) -> serde_json::Value {
+        serde_json::from_slice(bytes)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+/// Writes each exchange of one provider to `dir`
+#[derive(Debug)]
+pub struct FixtureRecorder {
+    dir: PathBuf,
+    provider: String,
+}
+
+impl FixtureRecorder {
+    pub fn new(dir: &Path, provider: &str) -> Self {
+        FixtureRecorder {
+            dir: dir.to_path_buf(),
+            provider: provider.to_string(),
+        }
+    }
+
+    /// Send `request` and pass the response through unchanged while its body is
+    /// copied to a fixture. The file is written once the body has been read to the end.
+    pub async fn send(&self, client: &Client, request: RequestBuilder) -> Result<reqwest::Response, reqwest::Error> {
+        let request = request.build()?;
+        let recorded = RecordedRequest {
+            method: request.method().to_string(),
+            path: match request.url().query() {
+                Some(query) => format!("{}?{}", request.url().path(), query),
+                None => request.url().path().to_string(),
+            },
+            body: request.body().and_then(|b| b.as_bytes()).map(Fixture::body_value),
+        };
+
+        let response = client.execute(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+
+        let mut fixture = Fixture {
+            provider: self.provider.clone(),
+            request: recorded,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: RECORDED_HEADERS
+                    .iter()
+                    .filter_map(|name| {
+                        let value = headers.get(*name)?.to_str().ok()?;
+                        Some((name.to_string(), value.to_string()))
+                    })
+                    .collect(),
+                chunks: Vec::new(),
+            },
+        };
+        let path = self.fixture_path(&fixture.request.path);
+
+        // Forward the body from a task so the caller sees chunks as they arrive
+        let (sender, receiver) = futures::channel::mpsc::unbounded::<Result<bytes::Bytes, reqwest::Error>>();
+        let mut upstream = response.bytes_stream();
+        tokio::spawn(async move {
+            let mut last = Instant::now();
+            while let Some(chunk) = upstream.next().await {
+                if let Ok(ref bytes) = chunk {
+                    fixture.response.chunks.push(RecordedChunk {
+                        delay_ms: last.elapsed().as_millis() as u64,
+                        data: String::from_utf8_lossy(bytes).into_owned(),
+                    });
+                    last = Instant::now();
+                }
+                let failed = chunk.is_err();
+                if sender.unbounded_send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+            Self::write(&path, &fixture);
+        });
+
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in headers.iter() {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .body(reqwest::Body::wrap_stream(receiver))
+            .expect("status and headers come from a valid response");
+        Ok(reqwest::Response::from(response))
+    }
+
+    fn fixture_path(&self, request_path: &str) -> PathBuf {
+        let slug: String = request_path
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        self.dir.join(format!(
+            "{}-{}-{:04}-{}.json",
+            self.provider,
+            chrono::Utc::now().format("%Y%m%d%H%M%S"),
+            FIXTURE_SEQUENCE.fetch_add(1, Ordering::Relaxed),
+            slug
+        ))
+    }
+
+    fn write(path: &Path, fixture: &Fixture) {
+        let result = std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(fixture).map_err(|e| e.to_string()))
+            .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(()) => log::info!("Recorded AI fixture {}", path.display()),
+            Err(e) => log::warn!("Failed to record AI fixture {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Fixtures left to serve, and every request received
+#[derive(Default)]
+struct ReplayState {
+    fixtures: Vec<(Fixture, bool)>,
+    received: Vec<RecordedRequest>,
+}
+
+impl ReplayState {
+    /// The first unserved fixture for the method and path, else the last one served.
+    /// Fixtures whose recorded body equals the request body are preferred.
+    fn take(&mut self, request: &RecordedRequest) -> Option<Fixture> {
+        let matches: Vec<usize> = (0..self.fixtures.len())
+            .filter(|&i| {
+                let recorded = &self.fixtures[i].0.request;
+                recorded.method == request.method && recorded.path == request.path
+            })
+            .collect();
+
+        let index = matches
+            .iter()
+            .find(|&&i| !self.fixtures[i].1 && self.fixtures[i].0.request.body == request.body)
+            .or_else(|| matches.iter().find(|&&i| !self.fixtures[i].1))
+            .or_else(|| matches.last())
+            .copied()?;
+
+        self.fixtures[index].1 = true;
+        Some(self.fixtures[index].0.clone())
+    }
+}
+
+/// Local HTTP server answering provider requests from fixtures, keeping the
+/// recorded chunk timing. Requests without a fixture get a 404. Stops when dropped.
+pub struct ReplayServer {
+    addr: SocketAddr,
+    #[cfg(test)]
+    state: Arc<Mutex<ReplayState>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ReplayServer {
+    pub async fn start(fixtures: Vec<Fixture>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ReplayState {
+            fixtures: fixtures.into_iter().map(|f| (f, false)).collect(),
+            received: Vec::new(),
+        }));
+
+        let server_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = Self::serve(stream, state).await {
+                        log::warn!("Replay server connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(ReplayServer {
+            addr,
+            #[cfg(test)]
+            state,
+            task,
+        })
+    }
+
+    /// Base URL to use as a provider's API URL
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Requests received so far, oldest first
+    #[cfg(test)]
+    pub fn received(&self) -> Vec<RecordedRequest> {
+        self.state
+            .lock()
+            .map(|s| s.received.clone())
+            .unwrap_or_default()
+    }
+
+    async fn serve(stream: TcpStream, state: Arc<Mutex<ReplayState>>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut content_length = 0usize;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).await?;
+        let request = RecordedRequest {
+            method,
+            path,
+            body: (!body.is_empty()).then(|| Fixture::body_value(&body)),
+        };
+
+        let fixture = {
+            let mut state = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.received.push(request.clone());
+            state.take(&request)
+        };
+        let response = fixture.map(|f| f.response).unwrap_or_else(|| RecordedResponse {
+            status: 404,
+            headers: BTreeMap::from([("content-type".to_string(), "application/json".to_string())]),
+            chunks: vec![RecordedChunk {
+                delay_ms: 0,
+                data: format!(
+                    r#"{{"error":"No fixture for {} {}"}}"#,
+                    request.method, request.path
+                ),
+            }],
+        });
+
+        let mut stream = reader.into_inner();
+        let reason = reqwest::StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Unknown");
+        let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("transfer-encoding: chunked\r\nconnection: close\r\n\r\n");
+        stream.write_all(head.as_bytes()).await?;
+        stream.flush().await?;
+
+        for chunk in &response.chunks {
+            if chunk.delay_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(chunk.delay_ms)).await;
+            }
+            if chunk.data.is_empty() {
+                continue;
+            }
+            stream
+                .write_all(format!("{:x}\r\n{}\r\n", chunk.data.len(), chunk.data).as_bytes())
+                .await?;
+            stream.flush().await?;
+        }
+        stream.write_all(b"0\r\n\r\n").await?;
+        stream.shutdown().await
+    }
+}
+
+impl Drop for ReplayServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
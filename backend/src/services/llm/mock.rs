@@ -0,0 +1,488 @@
+//! Offline provider with deterministic replies, for development and tests.
+//!
+//! Replies come from a script file when a rule matches, otherwise from built-in
+//! rules that mimic what the prompts ask for: conversation titles, chart JSON
+//! blocks and `StructuredResponse` payloads. Failures can be injected by putting
+//! a directive in the user message:
+//!
+//! - `[mock:error=503]` fails the call with that HTTP status
+//! - `[mock:error=timeout]` fails the call like a connection timeout
+//! - `[mock:stream_error]` cuts a streamed reply off halfway
+//!
+//! When tools are offered, `[mock:tool=list_datasets]` calls that tool with no
+//! arguments and `[mock:sql=SELECT ...]` calls `run_sql`. The reply after the
+//! tool results lists them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::Deserialize;
+use crate::models::{
+    ChartData, ChartDataset, ChartType, RenderContent, StructuredResponse, TableData,
+};
+use crate::services::KnowledgeService;
+use super::{
+    ChatEventStream, ChatStreamEvent, CompletionRequest, LlmMessage, LlmProvider, LlmResponse,
+    ProviderConfig, ProviderError, TokenUsage, ToolCall,
+};
+
+/// One scripted reply. The first rule whose `contains` text appears in the user
+/// message (ignoring case) answers it, with `reply` or a failure with status `error`.
+#[derive(Debug, Clone, Deserialize)]
+struct ScriptRule {
+    contains: String,
+    #[serde(default)]
+    reply: Option<String>,
+    #[serde(default)]
+    error: Option<u16>,
+}
+
+/// Behaviour read from `MOCK_AI_*` variables
+#[derive(Debug, Clone, Default)]
+struct MockSettings {
+    script: Vec<ScriptRule>,
+    /// Wait before answering or starting a stream
+    latency: Duration,
+    /// Wait between streamed chunks
+    chunk_delay: Duration,
+    /// Fail every nth call with a 503; 0 never does
+    fail_every: u64,
+}
+
+impl MockSettings {
+    fn from_env() -> Self {
+        let millis = |name: &str, default: u64| {
+            Duration::from_millis(Self::number(name).unwrap_or(default))
+        };
+
+        MockSettings {
+            script: std::env::var("MOCK_AI_SCRIPT")
+                .ok()
+                .filter(|p| !p.trim().is_empty())
+                .map(|path| Self::load_script(&path))
+                .unwrap_or_default(),
+            latency: millis("MOCK_AI_LATENCY_MS", 0),
+            chunk_delay: millis("MOCK_AI_CHUNK_DELAY_MS", 25),
+            fail_every: Self::number("MOCK_AI_FAIL_EVERY").unwrap_or(0),
+        }
+    }
+
+    fn number(name: &str) -> Option<u64> {
+        let value = std::env::var(name).ok()?;
+        match value.trim().parse::<u64>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                log::warn!("Ignoring invalid {}: {}", name, value);
+                None
+            }
+        }
+    }
+
+    fn load_script(path: &str) -> Vec<ScriptRule> {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| serde_json::from_str::<Vec<ScriptRule>>(&text).map_err(|e| e.to_string()));
+
+        match parsed {
+            Ok(rules) => {
+                log::info!("Loaded {} mock AI rules from {}", rules.len(), path);
+                rules
+            }
+            Err(e) => {
+                log::error!("Failed to load MOCK_AI_SCRIPT {}: {}", path, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// What the mock decided to do with a request
+enum Outcome {
+    Reply(String),
+    Fail(ProviderError),
+}
+
+/// Deterministic stand-in for a real model; makes no network calls
+pub struct MockProvider {
+    name: String,
+    model_name: String,
+    embedding_model: Option<String>,
+    settings: MockSettings,
+    calls: AtomicU64,
+}
+
+impl MockProvider {
+    pub fn new(config: &ProviderConfig) -> Self {
+        MockProvider {
+            name: config.name.clone(),
+            model_name: config.model_name.clone(),
+            embedding_model: config.embedding_model.clone(),
+            settings: MockSettings::from_env(),
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    /// Answer from the script file at `path` instead of `MOCK_AI_SCRIPT`
+    #[cfg(test)]
+    pub fn with_script(mut self, path: &std::path::Path) -> Self {
+        self.settings.script = MockSettings::load_script(&path.to_string_lossy());
+        self
+    }
+
+    fn respond(&self, messages: &[LlmMessage]) -> Outcome {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.settings.fail_every > 0 && call.is_multiple_of(self.settings.fail_every) {
+            return Outcome::Fail(Self::status_error(503));
+        }
+
+        let question = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.as_str())
+            .unwrap_or_default();
+
+        if let Some(code) = Self::directive(question, "error") {
+            return Outcome::Fail(match code.parse::<u16>() {
+                Ok(status) => Self::status_error(status),
+                Err(_) => ProviderError::Transport("Failed to send request to Mock: operation timed out".to_string()),
+            });
+        }
+
+        // Answer a finished tool round with what the tools returned
+        let results: Vec<&str> = messages
+            .iter()
+            .rev()
+            .take_while(|m| m.role != "user")
+            .filter(|m| m.role == "tool")
+            .map(|m| m.content.as_str())
+            .collect();
+        if !results.is_empty() {
+            let lines: Vec<String> = results.iter().rev().map(|r| format!("- {}", r)).collect();
+            return Outcome::Reply(format!("Tool results:\n{}", lines.join("\n")));
+        }
+
+        let lower = question.to_lowercase();
+        if let Some(rule) = self
+            .settings
+            .script
+            .iter()
+            .find(|r| lower.contains(&r.contains.to_lowercase()))
+        {
+            return match (rule.error, &rule.reply) {
+                (Some(status), _) => Outcome::Fail(Self::status_error(status)),
+                (None, Some(reply)) => Outcome::Reply(reply.clone()),
+                (None, None) => Outcome::Reply(String::new()),
+            };
+        }
+
+        let system = messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let question = Self::strip_directives(question);
+
+        Outcome::Reply(if question.trim_end().ends_with("Title:") {
+            Self::title(&question)
+        } else if system.contains("\"items\"") {
+            Self::structured(&question)
+        } else if Self::wants_chart(&lower) {
+            Self::chart_reply(&question)
+        } else {
+            Self::text_reply(&question, system.contains("Documents:"))
+        })
+    }
+
+    fn status_error(status: u16) -> ProviderError {
+        let reason = reqwest::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Unknown");
+        ProviderError::Status {
+            status,
+            message: format!("Mock API error ({} {}): injected failure", status, reason),
+        }
+    }
+
+    /// Value of a `[mock:name=value]` directive, or `""` for a bare `[mock:name]`
+    fn directive<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+        text.match_indices("[mock:").find_map(|(start, _)| {
+            let body = &text[start + 6..];
+            let body = &body[..body.find(']')?];
+            match body.split_once('=') {
+                Some((key, value)) if key == name => Some(value),
+                None if body == name => Some(""),
+                _ => None,
+            }
+        })
+    }
+
+    fn strip_directives(text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("[mock:") {
+            out.push_str(&rest[..start]);
+            match rest[start..].find(']') {
+                Some(end) => rest = &rest[start + end + 1..],
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            }
+        }
+        out.push_str(rest);
+        out.trim().to_string()
+    }
+
+    /// Same keywords the chart instruction is added for
+    fn wants_chart(lower: &str) -> bool {
+        ["chart", "pie", "bar", "line graph", "visualiz", "graph", "doughnut", "donut"]
+            .iter()
+            .any(|k| lower.contains(k))
+    }
+
+    /// Chart type picked the way the chart instruction asks for, defaulting to pie
+    fn chart_type(lower: &str) -> &'static str {
+        if lower.contains("doughnut") || lower.contains("donut") {
+            "doughnut"
+        } else if lower.contains("line") || lower.contains("trend") {
+            "line"
+        } else if lower.contains("bar") {
+            "bar"
+        } else {
+            "pie"
+        }
+    }
+
+    /// Four values derived from the question so the same question gets the same chart
+    fn series(text: &str) -> Vec<f64> {
+        let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        (0..4).map(|i| ((hash >> (i * 16)) % 900 + 100) as f64).collect()
+    }
+
+    fn topic(question: &str) -> String {
+        let topic: Vec<&str> = question
+            .split_whitespace()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|w| !w.is_empty())
+            .take(6)
+            .collect();
+        if topic.is_empty() {
+            "your question".to_string()
+        } else {
+            topic.join(" ")
+        }
+    }
+
+    fn title(request: &str) -> String {
+        let user = request
+            .lines()
+            .find_map(|l| l.strip_prefix("User:"))
+            .unwrap_or(request);
+        Self::topic(user)
+            .split(' ')
+            .map(|w| {
+                let mut chars = w.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn text_reply(question: &str, grounded: bool) -> String {
+        let mut reply = format!(
+            "This is a mock answer about {}.\n\n- The figures are placeholders\n- No model was called",
+            Self::topic(question)
+        );
+        if grounded {
+            reply.push_str("\n\nSee the project documents [1] for details.");
+        }
+        reply
+    }
+
+    fn chart_reply(question: &str) -> String {
+        let chart = serde_json::json!({
+            "type": Self::chart_type(&question.to_lowercase()),
+            "title": format!("Mock data for {}", Self::topic(question)),
+            "labels": ["Q1", "Q2", "Q3", "Q4"],
+            "data": Self::series(question),
+        });
+        format!("Here is a mock chart.\n\n```json\n{}\n```", chart)
+    }
+
+    fn structured(question: &str) -> String {
+        let lower = question.to_lowercase();
+        let chart_type = match Self::chart_type(&lower) {
+            "line" => ChartType::Line,
+            "bar" => ChartType::Bar,
+            _ => ChartType::Pie,
+        };
+        let labels: Vec<String> = ["Q1", "Q2", "Q3", "Q4"].iter().map(|l| l.to_string()).collect();
+        let data = Self::series(question);
+
+        let response = StructuredResponse {
+            items: vec![
+                RenderContent::Text {
+                    content: format!("This is a mock answer about {}.", Self::topic(question)),
+                },
+                RenderContent::Chart {
+                    data: ChartData {
+                        chart_type,
+                        title: Some("Mock data".to_string()),
+                        labels: labels.clone(),
+                        datasets: vec![ChartDataset {
+                            label: "Value".to_string(),
+                            data: data.clone(),
+                            background_color: None,
+                            border_color: None,
+                        }],
+                        annotations: vec![],
+                    },
+                },
+                RenderContent::Table {
+                    data: TableData {
+                        headers: vec!["Quarter".to_string(), "Value".to_string()],
+                        rows: labels
+                            .into_iter()
+                            .zip(data)
+                            .map(|(label, value)| vec![label, value.to_string()])
+                            .collect(),
+                    },
+                },
+            ],
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    /// Tool call asked for by a directive in the last message, when tools are offered
+    fn tool_call(request: &CompletionRequest) -> Option<ToolCall> {
+        let last = request.messages.last().filter(|m| m.role == "user")?;
+        if request.tools.is_empty() {
+            return None;
+        }
+        let (name, arguments) = match Self::directive(&last.content, "tool") {
+            Some(name) => (name.to_string(), "{}".to_string()),
+            None => {
+                let sql = Self::directive(&last.content, "sql")?;
+                ("run_sql".to_string(), serde_json::json!({ "sql": sql }).to_string())
+            }
+        };
+        request.tools.iter().find(|t| t.name == name)?;
+        Some(ToolCall { id: "call_mock_1".to_string(), name, arguments })
+    }
+
+    /// Whitespace-separated word counts stand in for tokens
+    fn usage(messages: &[LlmMessage], reply: &str) -> TokenUsage {
+        let words = |text: &str| text.split_whitespace().count() as u32;
+        let prompt_tokens = messages.iter().map(|m| words(&m.content)).sum();
+        let completion_tokens = words(reply);
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated: false,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn display_name(&self) -> &str {
+        "Mock"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.embedding_model.as_deref()
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        tokio::time::sleep(self.settings.latency).await;
+
+        match self.respond(&request.messages) {
+            Outcome::Fail(e) => Err(e),
+            Outcome::Reply(content) => Ok(LlmResponse {
+                usage: Some(Self::usage(&request.messages, &content)),
+                content,
+                citations: vec![],
+                cached: false,
+            }),
+        }
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        tokio::time::sleep(self.settings.latency).await;
+
+        if let Some(call) = Self::tool_call(&request) {
+            let usage = Self::usage(&request.messages, &call.arguments);
+            return Ok(Box::pin(futures::stream::iter(vec![
+                Ok(ChatStreamEvent::ToolCall(call)),
+                Ok(ChatStreamEvent::Usage(usage)),
+            ])));
+        }
+
+        let content = match self.respond(&request.messages) {
+            Outcome::Fail(e) => return Err(e),
+            Outcome::Reply(content) => content,
+        };
+        let usage = Self::usage(&request.messages, &content);
+        let interrupt = request
+            .messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .is_some_and(|m| Self::directive(&m.content, "stream_error").is_some());
+        let delay = self.settings.chunk_delay;
+
+        Ok(Box::pin(async_stream::stream! {
+            let chunks: Vec<String> = content
+                .split_inclusive(char::is_whitespace)
+                .map(str::to_string)
+                .collect();
+            let cut_at = if interrupt { chunks.len() / 2 } else { chunks.len() };
+
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                if i == cut_at {
+                    yield Err("Mock stream interrupted: injected failure".to_string());
+                    return;
+                }
+                if i > 0 && !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                yield Ok(ChatStreamEvent::Content(chunk));
+            }
+            if interrupt {
+                yield Err("Mock stream interrupted: injected failure".to_string());
+                return;
+            }
+            yield Ok(ChatStreamEvent::Usage(usage));
+        }))
+    }
+
+    /// Local hashed embeddings under the configured model name
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        if self.embedding_model.is_none() {
+            return Err("Mock has no embeddings model configured".to_string().into());
+        }
+        Ok(inputs.iter().map(|text| KnowledgeService::local_embedding(text)).collect())
+    }
+}
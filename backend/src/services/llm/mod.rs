@@ -0,0 +1,405 @@
+//! Pluggable LLM backends.
+//!
+//! Each backend implements [`LlmProvider`] and is registered by name in the
+//! [`ProviderRegistry`]. `AIService` only talks to the trait, so adding a backend
+//! means adding an implementation and a registry entry.
+
+pub mod anthropic;
+pub mod custom_rag;
+pub mod fixtures;
+pub mod mock;
+pub mod ollama;
+pub mod openai;
+pub mod registry;
+pub mod resilience;
+#[cfg(test)]
+mod tests;
+
+use async_trait::async_trait;
+use futures::Stream;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::models::{MessageCitation, ToolInvocation};
+use fixtures::FixtureRecorder;
+
+pub use registry::{ProviderConfig, ProviderRegistry};
+
+/// One message in a provider-agnostic chat request
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LlmMessage {
+    pub role: String,
+    pub content: String,
+    /// Tools the assistant asked to call. Only providers with tool support send these.
+    #[serde(skip)]
+    pub tool_calls: Vec<ToolCall>,
+    /// Call a `tool` message answers
+    #[serde(skip)]
+    pub tool_call_id: Option<String>,
+}
+
+impl LlmMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        LlmMessage { role: "system".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        LlmMessage { role: "user".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        LlmMessage { role: "assistant".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    /// Assistant turn that requested `calls`, with any text it produced alongside
+    pub fn tool_request(content: impl Into<String>, calls: Vec<ToolCall>) -> Self {
+        LlmMessage { tool_calls: calls, ..Self::assistant(content) }
+    }
+
+    /// Output of the tool call `call_id`
+    pub fn tool_result(call_id: &str, content: impl Into<String>) -> Self {
+        LlmMessage {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_calls: vec![],
+            tool_call_id: Some(call_id.to_string()),
+        }
+    }
+
+    /// Same message for a provider without tool support: requests and results become plain text
+    pub fn without_tools(&self) -> Self {
+        if self.role == "tool" {
+            return Self::system(format!("Tool result: {}", self.content));
+        }
+        if self.tool_calls.is_empty() {
+            return self.clone();
+        }
+        let calls: Vec<String> = self
+            .tool_calls
+            .iter()
+            .map(|c| format!("{}({})", c.name, c.arguments))
+            .collect();
+        let mut content = self.content.clone();
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(&format!("Called tools: {}", calls.join(", ")));
+        Self::assistant(content)
+    }
+}
+
+/// A server-side function the model may call
+#[derive(Debug, Serialize, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+/// A function call requested by the model
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// Arguments as the JSON text the model produced
+    pub arguments: String,
+}
+
+/// A chat completion request
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub messages: Vec<LlmMessage>,
+    pub temperature: f32,
+    pub max_tokens: i32,
+    /// Retrieval depth for providers that do their own retrieval
+    pub top_k: Option<i32>,
+    /// Tools offered to the model; left out for providers without tool support
+    pub tools: Vec<ToolDefinition>,
+}
+
+/// Token counts reported by the provider
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+    /// Counted locally because the provider did not report usage
+    #[serde(default)]
+    pub estimated: bool,
+}
+
+impl TokenUsage {
+    /// Rough count for providers that report no usage, at about four characters per token
+    pub fn estimate(prompt: &str, completion: &str) -> Self {
+        let tokens = |text: &str| text.chars().count().div_ceil(4) as u32;
+        let prompt_tokens = tokens(prompt);
+        let completion_tokens = tokens(completion);
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated: true,
+        }
+    }
+}
+
+/// Completion text plus any sources the provider retrieved
+#[derive(Debug, Clone, Default)]
+pub struct LlmResponse {
+    pub content: String,
+    pub citations: Vec<MessageCitation>,
+    pub usage: Option<TokenUsage>,
+    /// Served from the response cache rather than the provider
+    pub cached: bool,
+}
+
+/// A normalized event from a streaming chat response
+#[derive(Debug, Clone)]
+pub enum ChatStreamEvent {
+    Content(String),
+    Sources(Vec<MessageCitation>),
+    /// Token counts, usually sent once near the end of the stream
+    Usage(TokenUsage),
+    /// The model asked for a tool call; sent once the call's arguments are complete
+    ToolCall(ToolCall),
+    /// A tool call finished on the server
+    ToolResult(ToolInvocation),
+}
+
+pub type ChatEventStream = Pin<Box<dyn Stream<Item = Result<ChatStreamEvent, String>> + Send>>;
+
+/// Why a provider call failed. Displays as the message shown to users; the
+/// kind decides whether the call is retried and counts against the breaker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderError {
+    /// The request could not be sent or the response not read: connection
+    /// failures and resets, and timeouts
+    Transport(String),
+    /// The provider answered with a non-success status
+    Status { status: u16, message: String },
+    /// The provider's circuit breaker is open
+    Unavailable,
+    /// Anything else, such as a malformed response or missing configuration
+    Other(String),
+}
+
+impl ProviderError {
+    /// Classify a failed HTTP call by the kind of reqwest error
+    pub(crate) fn http(context: impl fmt::Display, error: reqwest::Error) -> Self {
+        let message = format!("{}: {}", context, error);
+        if error.is_timeout() || error.is_connect() || error.is_request() || error.is_body() {
+            ProviderError::Transport(message)
+        } else if let Some(status) = error.status() {
+            ProviderError::Status { status: status.as_u16(), message }
+        } else {
+            ProviderError::Other(message)
+        }
+    }
+
+    /// Upstream hiccups worth retrying: transport failures, 429 and 5xx
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ProviderError::Transport(_) => true,
+            ProviderError::Status { status, .. } => *status == 429 || *status >= 500,
+            ProviderError::Unavailable | ProviderError::Other(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Transport(message)
+            | ProviderError::Status { message, .. }
+            | ProviderError::Other(message) => f.write_str(message),
+            ProviderError::Unavailable => f.write_str(resilience::PROVIDER_UNAVAILABLE),
+        }
+    }
+}
+
+impl From<String> for ProviderError {
+    fn from(message: String) -> Self {
+        ProviderError::Other(message)
+    }
+}
+
+impl From<ProviderError> for String {
+    fn from(error: ProviderError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Raw response body of a streaming HTTP request
+pub(crate) type ByteStream = Pin<Box<dyn Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>;
+
+/// Text lines of a streaming body
+pub(crate) type LineStream = Pin<Box<dyn Stream<Item = Result<String, String>> + Send>>;
+
+/// `(event name, data)` pairs parsed from an SSE body
+pub(crate) type SseLineStream = Pin<Box<dyn Stream<Item = Result<(String, String), String>> + Send>>;
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Registry name recorded on generated messages, e.g. `openai`
+    fn name(&self) -> &str;
+
+    /// Human readable name for logs and error messages
+    fn display_name(&self) -> &str;
+
+    /// Chat model, if the backend lets the caller choose one
+    fn model(&self) -> Option<&str>;
+
+    /// Embeddings model, if the backend offers embeddings
+    fn embedding_model(&self) -> Option<&str> {
+        None
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError>;
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError>;
+
+    /// Whether the backend accepts `CompletionRequest::tools` and streams tool calls
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Embed texts, returning vectors in input order
+    async fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        Err(format!("{} does not support embeddings", self.display_name()).into())
+    }
+}
+
+/// Timeouts for provider HTTP calls
+#[derive(Debug, Clone, Copy)]
+pub struct HttpTimeouts {
+    pub connect: Duration,
+    /// Whole request, for non-streaming calls
+    pub request: Duration,
+    /// Whole request, for streamed replies
+    pub stream: Duration,
+}
+
+impl HttpTimeouts {
+    pub(crate) fn for_call(&self, stream: bool) -> Duration {
+        if stream { self.stream } else { self.request }
+    }
+}
+
+/// HTTP client used by the built-in providers. Streaming calls override the
+/// request timeout per call. Exchanges are recorded as fixtures when the
+/// provider config has a record directory.
+pub(crate) struct HttpClient {
+    client: Client,
+    recorder: Option<Arc<FixtureRecorder>>,
+}
+
+impl HttpClient {
+    pub(crate) fn new(config: &ProviderConfig) -> Self {
+        HttpClient {
+            client: Client::builder()
+                .connect_timeout(config.timeouts.connect)
+                .timeout(config.timeouts.request)
+                .build()
+                .expect("Failed to create HTTP client"),
+            recorder: config
+                .record_dir
+                .as_deref()
+                .map(|dir| Arc::new(FixtureRecorder::new(dir, &config.name))),
+        }
+    }
+
+    pub(crate) fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, reqwest::Error> {
+        match self.recorder {
+            Some(ref recorder) => recorder.send(&self.client, request).await,
+            None => request.send().await,
+        }
+    }
+}
+
+/// Turn a non-success HTTP response into a status error with the response body
+pub(crate) async fn check_status(
+    response: reqwest::Response,
+    provider: &str,
+) -> Result<reqwest::Response, ProviderError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    Err(ProviderError::Status {
+        status: status.as_u16(),
+        message: format!("{} API error ({}): {}", provider, status, error_text),
+    })
+}
+
+/// Split a byte stream into lines without trailing `\r\n`.
+/// Lines are buffered as bytes so multi-byte characters split across chunks survive.
+pub(crate) fn byte_lines(mut raw: ByteStream) -> LineStream {
+    use futures::StreamExt;
+
+    Box::pin(async_stream::stream! {
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = raw.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    yield Err(e.to_string());
+                    return;
+                }
+            };
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                yield Ok(line.trim_end_matches(['\r', '\n']).to_string());
+            }
+        }
+
+        // Flush a final line that was not newline-terminated
+        if !buffer.is_empty() {
+            yield Ok(String::from_utf8_lossy(&buffer).trim_end().to_string());
+        }
+    })
+}
+
+/// Split an SSE byte stream into `(event name, data)` pairs
+pub(crate) fn sse_data_lines(raw: ByteStream) -> SseLineStream {
+    use futures::StreamExt;
+
+    let mut lines = byte_lines(raw);
+    Box::pin(async_stream::stream! {
+        let mut event_name = String::new();
+
+        while let Some(line) = lines.next().await {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+
+            if line.is_empty() {
+                event_name.clear();
+            } else if let Some(name) = line.strip_prefix("event:") {
+                event_name = name.trim().to_string();
+            } else if let Some(data) = line.strip_prefix("data:") {
+                let data = data.strip_prefix(' ').unwrap_or(data);
+                yield Ok((event_name.clone(), data.to_string()));
+            }
+        }
+    })
+}
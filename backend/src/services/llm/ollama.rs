@@ -0,0 +1,249 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use super::{
+    byte_lines, check_status, ChatEventStream, ChatStreamEvent, CompletionRequest,
+    HttpClient, HttpTimeouts, LlmMessage, LlmProvider, LlmResponse, ProviderConfig, ProviderError, TokenUsage,
+};
+
+const DISPLAY_NAME: &str = "Ollama";
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [LlmMessage],
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+    num_predict: i32,
+}
+
+/// A full response, or one NDJSON line of a streamed response
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+}
+
+impl OllamaChatResponse {
+    /// Ollama reports counts on the final (`done`) message only
+    fn usage(&self) -> Option<TokenUsage> {
+        if !self.done || (self.prompt_eval_count.is_none() && self.eval_count.is_none()) {
+            return None;
+        }
+        let prompt_tokens = self.prompt_eval_count.unwrap_or(0);
+        let completion_tokens = self.eval_count.unwrap_or(0);
+        Some(TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated: false,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Ollama's native chat API. Streams newline-delimited JSON rather than SSE.
+pub struct OllamaProvider {
+    client: HttpClient,
+    name: String,
+    api_url: String,
+    model_name: String,
+    api_key: Option<String>,
+    embedding_model: Option<String>,
+    timeouts: HttpTimeouts,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &ProviderConfig) -> Self {
+        OllamaProvider {
+            client: HttpClient::new(config),
+            name: config.name.clone(),
+            api_url: config.api_url.clone(),
+            model_name: config.model_name.clone(),
+            api_key: config.api_key.clone(),
+            embedding_model: config.embedding_model.clone(),
+            timeouts: config.timeouts,
+        }
+    }
+
+    /// Ollama itself has no auth; a key is sent as a bearer token for proxied deployments
+    fn headers(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(ref api_key) = self.api_key {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", api_key))
+                    .map_err(|e| format!("Invalid API key format: {}", e))?
+            );
+        }
+        Ok(headers)
+    }
+
+    async fn post_chat(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}/api/chat", self.api_url);
+        let body = OllamaChatRequest {
+            model: &self.model_name,
+            messages: &request.messages,
+            stream,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                num_predict: request.max_tokens,
+            },
+        };
+
+        let request = self.client
+            .post(&url)
+            .headers(self.headers()?)
+            .timeout(self.timeouts.for_call(stream))
+            .json(&body);
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send request to {}", DISPLAY_NAME), e))?;
+
+        check_status(response, DISPLAY_NAME).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn display_name(&self) -> &str {
+        DISPLAY_NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.embedding_model.as_deref()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        let response = self.post_chat(&request, false).await?;
+
+        let parsed: OllamaChatResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} response", DISPLAY_NAME), e))?;
+
+        if let Some(error) = parsed.error {
+            return Err(format!("{} error: {}", DISPLAY_NAME, error).into());
+        }
+
+        let usage = parsed.usage();
+        let content = parsed
+            .message
+            .map(|m| m.content)
+            .ok_or_else(|| "No response from AI model".to_string())?;
+
+        Ok(LlmResponse {
+            content,
+            citations: vec![],
+            usage,
+            cached: false,
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        let response = self.post_chat(&request, true).await?;
+        let lines = byte_lines(Box::pin(response.bytes_stream()));
+
+        Ok(Box::pin(lines.flat_map(|line| {
+            let events: Vec<Result<ChatStreamEvent, String>> = match line {
+                Err(e) => vec![Err(e)],
+                Ok(line) if line.trim().is_empty() => vec![],
+                Ok(line) => match serde_json::from_str::<OllamaChatResponse>(&line) {
+                    Err(e) => {
+                        log::warn!("Skipping malformed {} stream line: {}", DISPLAY_NAME, e);
+                        vec![]
+                    }
+                    Ok(chunk) => {
+                        let mut events = Vec::new();
+                        if let Some(error) = chunk.error.as_ref() {
+                            events.push(Err(format!("{} error: {}", DISPLAY_NAME, error)));
+                        }
+                        if let Some(text) = chunk.message.as_ref().map(|m| &m.content).filter(|c| !c.is_empty()) {
+                            events.push(Ok(ChatStreamEvent::Content(text.clone())));
+                        }
+                        if let Some(usage) = chunk.usage() {
+                            events.push(Ok(ChatStreamEvent::Usage(usage)));
+                        }
+                        events
+                    }
+                },
+            };
+            futures::stream::iter(events)
+        })))
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let model = self
+            .embedding_model
+            .as_deref()
+            .ok_or_else(|| format!("{} has no embeddings model configured", DISPLAY_NAME))?;
+
+        let url = format!("{}/api/embed", self.api_url);
+        let request = self.client
+            .post(&url)
+            .headers(self.headers()?)
+            .json(&OllamaEmbedRequest { model, input: inputs });
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send embeddings request to {}", DISPLAY_NAME), e))?;
+        let response = check_status(response, DISPLAY_NAME).await?;
+
+        let parsed: OllamaEmbedResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} embeddings response", DISPLAY_NAME), e))?;
+
+        if parsed.embeddings.len() != inputs.len() {
+            return Err(format!(
+                "{} returned {} embeddings for {} inputs",
+                DISPLAY_NAME,
+                parsed.embeddings.len(),
+                inputs.len()
+            )
+            .into());
+        }
+
+        Ok(parsed.embeddings)
+    }
+}
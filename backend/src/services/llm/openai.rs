@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use super::{
+    check_status, sse_data_lines, ChatEventStream, ChatStreamEvent, HttpClient, HttpTimeouts,
+    CompletionRequest, LlmMessage, LlmProvider, LlmResponse, ProviderConfig, ProviderError, TokenUsage,
+    ToolCall, ToolDefinition,
+};
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<WireMessage<'a>>,
+    temperature: f32,
+    max_tokens: i32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<WireTool<'a>>,
+}
+
+/// A message in the chat completions format, including tool calls and results
+#[derive(Debug, Serialize)]
+struct WireMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<WireToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a LlmMessage> for WireMessage<'a> {
+    fn from(message: &'a LlmMessage) -> Self {
+        WireMessage {
+            role: &message.role,
+            content: &message.content,
+            tool_calls: message.tool_calls.iter().map(WireToolCall::from).collect(),
+            tool_call_id: message.tool_call_id.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct WireTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: &'a ToolDefinition,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WireToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    function: WireFunction,
+}
+
+impl From<&ToolCall> for WireToolCall {
+    fn from(call: &ToolCall) -> Self {
+        WireToolCall {
+            id: call.id.clone(),
+            kind: "function".to_string(),
+            function: WireFunction { name: call.name.clone(), arguments: call.arguments.clone() },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WireFunction {
+    name: String,
+    arguments: String,
+}
+
+/// Asks for a final chunk carrying token usage
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+/// Assistant message of a completion; `content` is null when the model only calls tools
+#[derive(Debug, Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    /// Only sent on the last chunk, and only by servers that report streaming usage
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// Piece of a streamed tool call. The id and name come first; the arguments
+/// arrive in fragments addressed by `index`.
+#[derive(Debug, Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+/// Any backend speaking the OpenAI chat completions API (OpenAI, LM Studio)
+pub struct OpenAiCompatibleProvider {
+    client: HttpClient,
+    name: String,
+    display_name: &'static str,
+    api_url: String,
+    model_name: String,
+    api_key: Option<String>,
+    embedding_model: Option<String>,
+    timeouts: HttpTimeouts,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(display_name: &'static str, config: &ProviderConfig) -> Self {
+        OpenAiCompatibleProvider {
+            client: HttpClient::new(config),
+            name: config.name.clone(),
+            display_name,
+            api_url: config.api_url.clone(),
+            model_name: config.model_name.clone(),
+            api_key: config.api_key.clone(),
+            embedding_model: config.embedding_model.clone(),
+            timeouts: config.timeouts,
+        }
+    }
+
+    fn headers(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        // Add authorization header for OpenAI or if API key is provided
+        if let Some(ref api_key) = self.api_key {
+            let auth_value = format!("Bearer {}", api_key);
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&auth_value)
+                    .map_err(|e| format!("Invalid API key format: {}", e))?
+            );
+        }
+
+        Ok(headers)
+    }
+
+    async fn post_completion(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}/v1/chat/completions", self.api_url);
+        let body = ChatCompletionRequest {
+            model: &self.model_name,
+            messages: request.messages.iter().map(WireMessage::from).collect(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            stream,
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+            tools: request
+                .tools
+                .iter()
+                .map(|function| WireTool { kind: "function", function })
+                .collect(),
+        };
+
+        let request = self.client
+            .post(&url)
+            .headers(self.headers()?)
+            .timeout(self.timeouts.for_call(stream))
+            .json(&body);
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send request to {}", self.display_name), e))?;
+
+        check_status(response, self.display_name).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn display_name(&self) -> &str {
+        self.display_name
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.embedding_model.as_deref()
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        let response = self.post_completion(&request, false).await?;
+
+        let ai_response: ChatCompletionResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} response", self.display_name), e))?;
+
+        let content = ai_response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.unwrap_or_default())
+            .ok_or_else(|| "No response from AI model".to_string())?;
+
+        Ok(LlmResponse {
+            content,
+            citations: vec![],
+            usage: ai_response.usage,
+            cached: false,
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        let response = self.post_completion(&request, true).await?;
+        let mut lines = sse_data_lines(Box::pin(response.bytes_stream()));
+
+        Ok(Box::pin(async_stream::stream! {
+            // Tool calls by index: id, name and the arguments received so far
+            let mut calls: BTreeMap<usize, (String, String, String)> = BTreeMap::new();
+
+            while let Some(line) = lines.next().await {
+                let data = match line {
+                    Ok((_, data)) => data,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                if data.trim() == "[DONE]" {
+                    break;
+                }
+                let chunk = match serde_json::from_str::<ChatCompletionChunk>(&data) {
+                    Ok(chunk) => chunk,
+                    Err(_) => continue,
+                };
+
+                if let Some(choice) = chunk.choices.into_iter().next() {
+                    for delta in choice.delta.tool_calls {
+                        let call = calls.entry(delta.index).or_default();
+                        if let Some(id) = delta.id {
+                            call.0 = id;
+                        }
+                        if let Some(function) = delta.function {
+                            if let Some(name) = function.name {
+                                call.1.push_str(&name);
+                            }
+                            if let Some(arguments) = function.arguments {
+                                call.2.push_str(&arguments);
+                            }
+                        }
+                    }
+                    if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                        yield Ok(ChatStreamEvent::Content(content));
+                    }
+                }
+                if let Some(usage) = chunk.usage {
+                    yield Ok(ChatStreamEvent::Usage(usage));
+                }
+            }
+
+            for (index, (id, name, arguments)) in calls {
+                let id = if id.is_empty() { format!("call_{}", index) } else { id };
+                yield Ok(ChatStreamEvent::ToolCall(ToolCall { id, name, arguments }));
+            }
+        }))
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let model = self
+            .embedding_model
+            .as_deref()
+            .ok_or_else(|| format!("{} has no embeddings model configured", self.display_name))?;
+
+        let url = format!("{}/v1/embeddings", self.api_url);
+        let request = self.client
+            .post(&url)
+            .headers(self.headers()?)
+            .json(&EmbeddingRequest { model, input: inputs });
+        let response = self.client
+            .send(request)
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to send embeddings request to {}", self.display_name), e))?;
+        let response = check_status(response, self.display_name).await?;
+
+        let mut parsed: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::http(format!("Failed to parse {} embeddings response", self.display_name), e))?;
+
+        if parsed.data.len() != inputs.len() {
+            return Err(format!(
+                "{} returned {} embeddings for {} inputs",
+                self.display_name,
+                parsed.data.len(),
+                inputs.len()
+            )
+            .into());
+        }
+
+        parsed.data.sort_by_key(|d| d.index);
+        Ok(parsed.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use crate::config::AIProvider;
+use super::anthropic::MessagesApiProvider;
+use super::custom_rag::CustomRagProvider;
+use super::mock::MockProvider;
+use super::ollama::OllamaProvider;
+use super::openai::OpenAiCompatibleProvider;
+use super::{HttpTimeouts, LlmProvider};
+
+/// Settings handed to a provider factory
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    /// Name the provider is registered under
+    pub name: String,
+    pub api_url: String,
+    pub model_name: String,
+    pub api_key: Option<String>,
+    pub embedding_model: Option<String>,
+    pub timeouts: HttpTimeouts,
+    /// Record HTTP exchanges as fixtures in this directory
+    pub record_dir: Option<PathBuf>,
+}
+
+pub type ProviderFactory = fn(&ProviderConfig) -> Arc<dyn LlmProvider>;
+
+/// Maps provider names to factories
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        ProviderRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Registry with the providers that ship with DencapsBI, keyed by
+    /// [`AIProvider::as_str`]
+    pub fn with_builtin_providers() -> Self {
+        let mut registry = Self::new();
+        registry.register(AIProvider::OpenAI.as_str(), |config| {
+            Arc::new(OpenAiCompatibleProvider::new("OpenAI", config))
+        });
+        registry.register(AIProvider::LMStudio.as_str(), |config| {
+            Arc::new(OpenAiCompatibleProvider::new("LM Studio", config))
+        });
+        registry.register(AIProvider::CustomRAG.as_str(), |config| {
+            Arc::new(CustomRagProvider::new(config))
+        });
+        registry.register(AIProvider::Ollama.as_str(), |config| {
+            Arc::new(OllamaProvider::new(config))
+        });
+        registry.register(AIProvider::Anthropic.as_str(), |config| {
+            Arc::new(MessagesApiProvider::new(config))
+        });
+        registry.register(AIProvider::Mock.as_str(), |config| {
+            Arc::new(MockProvider::new(config))
+        });
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, factory: ProviderFactory) {
+        self.factories.insert(name.to_string(), factory);
+    }
+
+    /// Registered names, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Build the provider named in `config`
+    pub fn build(&self, config: &ProviderConfig) -> Result<Arc<dyn LlmProvider>, String> {
+        let factory = self.factories.get(&config.name).ok_or_else(|| {
+            format!(
+                "Unknown AI provider '{}'. Available providers: {}",
+                config.name,
+                self.names().join(", ")
+            )
+        })?;
+
+        Ok(factory(config))
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
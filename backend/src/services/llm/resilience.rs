@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use super::{ChatEventStream, CompletionRequest, LlmProvider, LlmResponse, ProviderError};
+
+/// Error returned while a provider's circuit breaker is open
+pub const PROVIDER_UNAVAILABLE: &str = "AI provider is temporarily unavailable. Please try again shortly.";
+
+/// Backoff for retried calls
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Full jitter: a random delay up to the exponential backoff for `attempt`
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerSettings {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker rejects calls before letting a trial call through
+    pub open_duration: Duration,
+}
+
+/// Retry and circuit breaker settings applied to every provider
+#[derive(Debug, Clone, Copy)]
+pub struct ResilienceSettings {
+    pub retry: RetryPolicy,
+    pub breaker: BreakerSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Breaker state as reported to admins
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub provider: String,
+    pub api_url: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub opened_at: Option<String>,
+    /// When an open breaker lets the next trial call through
+    pub retry_at: Option<String>,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<(Instant, DateTime<Utc>)>,
+    /// Start of the trial call while half-open
+    trial_started: Option<Instant>,
+    last_error: Option<String>,
+}
+
+/// Circuit breaker for one provider endpoint
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    provider: String,
+    api_url: String,
+    settings: BreakerSettings,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    fn new(provider: &str, api_url: &str, settings: BreakerSettings) -> Self {
+        CircuitBreaker {
+            provider: provider.to_string(),
+            api_url: api_url.to_string(),
+            settings,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                trial_started: None,
+                last_error: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerInner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn open_period_over(&self, inner: &BreakerInner) -> bool {
+        inner
+            .opened_at
+            .map(|(at, _)| at.elapsed() >= self.settings.open_duration)
+            .unwrap_or(true)
+    }
+
+    /// Whether a call may go out now. Once the open period has passed one trial call
+    /// is let through; a trial that never reports back is replaced after another period.
+    fn try_acquire(&self) -> bool {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::Open if self.open_period_over(&inner) => {
+                inner.state = BreakerState::HalfOpen;
+                inner.trial_started = Some(Instant::now());
+                true
+            }
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                let stale = inner
+                    .trial_started
+                    .map(|at| at.elapsed() >= self.settings.open_duration)
+                    .unwrap_or(true);
+                if stale {
+                    inner.trial_started = Some(Instant::now());
+                }
+                stale
+            }
+        }
+    }
+
+    /// Whether routing to this endpoint makes sense: closed, or due a trial call
+    pub fn is_available(&self) -> bool {
+        let inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => self.open_period_over(&inner),
+            BreakerState::HalfOpen => false,
+        }
+    }
+
+    /// The call succeeded
+    fn record_success(&self) {
+        let mut inner = self.lock();
+        if inner.state != BreakerState::Closed {
+            log::info!("Circuit closed for {} ({})", self.provider, self.api_url);
+        }
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.trial_started = None;
+    }
+
+    fn record_failure(&self, error: &str) {
+        let mut inner = self.lock();
+        inner.consecutive_failures += 1;
+        inner.last_error = Some(error.to_string());
+
+        let trip = inner.state == BreakerState::HalfOpen
+            || inner.consecutive_failures >= self.settings.failure_threshold;
+        if trip && inner.state != BreakerState::Open {
+            log::warn!(
+                "Circuit opened for {} ({}) after {} consecutive failures",
+                self.provider,
+                self.api_url,
+                inner.consecutive_failures
+            );
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some((Instant::now(), Utc::now()));
+            inner.trial_started = None;
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.lock();
+        let opened_at = inner.opened_at.map(|(_, at)| at);
+        let retry_at = match inner.state {
+            BreakerState::Open => opened_at.and_then(|at| {
+                chrono::Duration::from_std(self.settings.open_duration).ok().map(|d| at + d)
+            }),
+            _ => None,
+        };
+
+        BreakerStatus {
+            provider: self.provider.clone(),
+            api_url: self.api_url.clone(),
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            last_error: inner.last_error.clone(),
+            opened_at: opened_at.map(|at| at.to_rfc3339()),
+            retry_at: retry_at.map(|at| at.to_rfc3339()),
+        }
+    }
+}
+
+/// Circuit breakers keyed by provider and URL, so every model served by an
+/// endpoint shares one breaker
+pub struct BreakerRegistry {
+    settings: BreakerSettings,
+    breakers: RwLock<HashMap<(String, String), Arc<CircuitBreaker>>>,
+}
+
+impl BreakerRegistry {
+    pub fn new(settings: BreakerSettings) -> Self {
+        BreakerRegistry {
+            settings,
+            breakers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn breaker(&self, provider: &str, api_url: &str) -> Arc<CircuitBreaker> {
+        let key = (provider.to_string(), api_url.to_string());
+        if let Some(breaker) = self.breakers.read().ok().and_then(|b| b.get(&key).cloned()) {
+            return breaker;
+        }
+
+        let mut breakers = self.breakers.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        breakers
+            .entry(key)
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(provider, api_url, self.settings)))
+            .clone()
+    }
+
+    /// Every breaker created so far, sorted by provider
+    pub fn statuses(&self) -> Vec<BreakerStatus> {
+        let breakers: Vec<Arc<CircuitBreaker>> = self
+            .breakers
+            .read()
+            .map(|b| b.values().cloned().collect())
+            .unwrap_or_default();
+
+        let mut statuses: Vec<BreakerStatus> = breakers.iter().map(|b| b.status()).collect();
+        statuses.sort_by(|a, b| (&a.provider, &a.api_url).cmp(&(&b.provider, &b.api_url)));
+        statuses
+    }
+}
+
+/// Wraps a provider with its endpoint's circuit breaker. Non-streaming calls are
+/// retried with jittered backoff; streams are not, since output may already have
+/// reached the client.
+pub struct ResilientProvider {
+    inner: Arc<dyn LlmProvider>,
+    breaker: Arc<CircuitBreaker>,
+    retry: RetryPolicy,
+}
+
+impl ResilientProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, breaker: Arc<CircuitBreaker>, retry: RetryPolicy) -> Self {
+        ResilientProvider { inner, breaker, retry }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.breaker.is_available()
+    }
+
+    /// Run `call`, retrying transient failures up to `retries` times. Transient
+    /// failures count against the breaker. Any other answer, including a rejected
+    /// request, shows the provider is reachable and closes it, so a half-open
+    /// trial always ends.
+    async fn call<T, F, Fut>(&self, retries: u32, call: F) -> Result<T, ProviderError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            if !self.breaker.try_acquire() {
+                return Err(ProviderError::Unavailable);
+            }
+
+            match call().await {
+                Ok(value) => {
+                    self.breaker.record_success();
+                    return Ok(value);
+                }
+                Err(e) if e.is_transient() => {
+                    self.breaker.record_failure(&e.to_string());
+                    if attempt >= retries {
+                        return Err(e);
+                    }
+                    let delay = self.retry.delay(attempt);
+                    attempt += 1;
+                    log::warn!(
+                        "{} request failed, retry {} of {} in {:?}: {}",
+                        self.inner.display_name(),
+                        attempt,
+                        retries,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(ProviderError::Unavailable) => {
+                    self.breaker.record_failure(PROVIDER_UNAVAILABLE);
+                    return Err(ProviderError::Unavailable);
+                }
+                Err(e) => {
+                    self.breaker.record_success();
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ResilientProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn display_name(&self) -> &str {
+        self.inner.display_name()
+    }
+
+    fn model(&self) -> Option<&str> {
+        self.inner.model()
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.inner.embedding_model()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse, ProviderError> {
+        self.call(self.retry.max_retries, || self.inner.complete(request.clone())).await
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<ChatEventStream, ProviderError> {
+        self.call(0, || self.inner.stream(request.clone())).await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.call(self.retry.max_retries, || self.inner.embed(inputs)).await
+    }
+}
@@ -0,0 +1,609 @@
+//! Provider tests against recorded HTTP exchanges in `tests/fixtures/ai`, and against
+//! the offline mock provider
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use futures::StreamExt;
+use super::fixtures::{Fixture, ReplayServer};
+use super::mock::MockProvider;
+use crate::models::{ChartType, RenderContent, StructuredResponse};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use super::resilience::{BreakerRegistry, BreakerSettings, BreakerState, ResilientProvider, RetryPolicy};
+use super::{
+    ChatStreamEvent, CompletionRequest, HttpTimeouts, LlmMessage, LlmProvider, LlmResponse,
+    ProviderConfig, ProviderError, ProviderRegistry, TokenUsage, ToolCall, ToolDefinition,
+};
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ai")
+}
+
+async fn replay(names: &[&str]) -> ReplayServer {
+    let fixtures = names
+        .iter()
+        .map(|name| Fixture::load(&fixture_dir().join(name)).expect("fixture should load"))
+        .collect();
+    ReplayServer::start(fixtures).await.expect("replay server should start")
+}
+
+fn config(provider: &str, api_url: &str, record_dir: Option<PathBuf>) -> ProviderConfig {
+    ProviderConfig {
+        name: provider.to_string(),
+        api_url: api_url.to_string(),
+        model_name: "test-model".to_string(),
+        api_key: Some("test-key".to_string()),
+        embedding_model: None,
+        timeouts: HttpTimeouts {
+            connect: Duration::from_secs(5),
+            request: Duration::from_secs(5),
+            stream: Duration::from_secs(5),
+        },
+        record_dir,
+    }
+}
+
+fn provider(name: &str, server: &ReplayServer) -> Arc<dyn LlmProvider> {
+    ProviderRegistry::with_builtin_providers()
+        .build(&config(name, &server.url(), None))
+        .expect("provider should be registered")
+}
+
+fn request() -> CompletionRequest {
+    CompletionRequest {
+        messages: vec![
+            LlmMessage::system("You are a test assistant."),
+            LlmMessage::user("Which product led Q4 revenue?"),
+        ],
+        temperature: 0.2,
+        max_tokens: 100,
+        top_k: None,
+        tools: vec![],
+    }
+}
+
+/// Streamed reply: concatenated text, all events, and the error that ended it, if any
+struct Streamed {
+    text: String,
+    events: Vec<ChatStreamEvent>,
+    error: Option<String>,
+}
+
+impl Streamed {
+    fn usage(&self) -> Option<TokenUsage> {
+        self.events.iter().rev().find_map(|e| match e {
+            ChatStreamEvent::Usage(u) => Some(*u),
+            _ => None,
+        })
+    }
+}
+
+async fn stream(provider: &Arc<dyn LlmProvider>) -> Streamed {
+    stream_request(provider, request()).await
+}
+
+async fn stream_request(provider: &Arc<dyn LlmProvider>, request: CompletionRequest) -> Streamed {
+    let mut stream = provider.stream(request).await.expect("stream should start");
+    let mut streamed = Streamed { text: String::new(), events: Vec::new(), error: None };
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(event) => {
+                if let ChatStreamEvent::Content(ref text) = event {
+                    streamed.text.push_str(text);
+                }
+                streamed.events.push(event);
+            }
+            Err(e) => {
+                streamed.error = Some(e);
+                break;
+            }
+        }
+    }
+    streamed
+}
+
+async fn complete(provider: &Arc<dyn LlmProvider>) -> Result<LlmResponse, String> {
+    provider.complete(request()).await.map_err(String::from)
+}
+
+fn mock() -> Arc<dyn LlmProvider> {
+    Arc::new(MockProvider::new(&config("mock", "", None)))
+}
+
+fn asking(question: &str) -> CompletionRequest {
+    CompletionRequest {
+        messages: vec![LlmMessage::system("You are a test assistant."), LlmMessage::user(question)],
+        ..request()
+    }
+}
+
+#[tokio::test]
+async fn openai_complete_parses_content_and_usage() {
+    let server = replay(&["openai-chat.json"]).await;
+    let response = complete(&provider("openai", &server)).await.unwrap();
+
+    assert_eq!(response.content, "Product A leads Q4 revenue.");
+    let usage = response.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (21, 7, 28));
+    assert!(!usage.estimated);
+
+    let sent = &server.received()[0];
+    assert_eq!(sent.path, "/v1/chat/completions");
+    let body = sent.body.as_ref().unwrap();
+    assert_eq!(body["model"], "test-model");
+    assert_eq!(body["messages"][1]["content"], "Which product led Q4 revenue?");
+    assert!(body.get("stream").is_none());
+}
+
+#[tokio::test]
+async fn openai_stream_joins_split_chunks_and_reports_usage() {
+    let server = replay(&["openai-chat-stream.json"]).await;
+    let streamed = stream(&provider("lmstudio", &server)).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "Product A leads Q4 revenue.");
+    assert_eq!(streamed.usage().unwrap().total_tokens, 28);
+
+    let body = server.received()[0].body.clone().unwrap();
+    assert_eq!(body["stream"], true);
+    assert_eq!(body["stream_options"]["include_usage"], true);
+}
+
+#[tokio::test]
+async fn openai_stream_assembles_tool_calls_and_sends_tools() {
+    let server = replay(&["openai-tool-call-stream.json"]).await;
+    let mut request = request();
+    request.tools = vec![ToolDefinition {
+        name: "run_sql".to_string(),
+        description: "Run a SELECT query".to_string(),
+        parameters: serde_json::json!({ "type": "object", "properties": { "sql": { "type": "string" } } }),
+    }];
+    let earlier = ToolCall {
+        id: "call_prev".to_string(),
+        name: "list_datasets".to_string(),
+        arguments: "{}".to_string(),
+    };
+    request.messages.push(LlmMessage::tool_request(String::new(), vec![earlier]));
+    request.messages.push(LlmMessage::tool_result("call_prev", "[\"sales\"]"));
+    let streamed = stream_request(&provider("openai", &server), request).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "");
+    let calls: Vec<&ToolCall> = streamed
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            ChatStreamEvent::ToolCall(call) => Some(call),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "call_abc123");
+    assert_eq!(calls[0].name, "run_sql");
+    assert_eq!(calls[0].arguments, r#"{"sql": "SELECT product, SUM(revenue) FROM sales GROUP BY product"}"#);
+    assert_eq!(streamed.usage().unwrap().total_tokens, 82);
+
+    let body = server.received()[0].body.clone().unwrap();
+    assert_eq!(body["tools"][0]["type"], "function");
+    assert_eq!(body["tools"][0]["function"]["name"], "run_sql");
+    assert_eq!(body["messages"][2]["tool_calls"][0]["function"]["name"], "list_datasets");
+    assert_eq!(body["messages"][3]["role"], "tool");
+    assert_eq!(body["messages"][3]["tool_call_id"], "call_prev");
+}
+
+#[tokio::test]
+async fn openai_error_includes_status_and_body() {
+    let server = replay(&["openai-rate-limit.json"]).await;
+    let error = complete(&provider("openai", &server)).await.unwrap_err();
+
+    assert!(error.starts_with("OpenAI API error (429 Too Many Requests)"), "{}", error);
+    assert!(error.contains("Rate limit reached"), "{}", error);
+}
+
+#[tokio::test]
+async fn custom_rag_complete_maps_sources_to_citations() {
+    let server = replay(&["custom-rag-chat.json"]).await;
+    let response = complete(&provider("custom_rag", &server)).await.unwrap();
+
+    assert_eq!(response.content, "Refunds are issued within 14 days [1].");
+    assert!(response.usage.is_none());
+    assert_eq!(response.citations.len(), 1);
+    let citation = &response.citations[0];
+    assert_eq!(citation.document_id.as_deref(), Some("doc-7"));
+    assert_eq!(citation.title.as_deref(), Some("refund-policy.pdf"));
+    assert_eq!(citation.score, Some(0.91));
+
+    // System messages are folded into the single query string
+    let body = server.received()[0].body.clone().unwrap();
+    let message = body["message"].as_str().unwrap();
+    assert!(message.starts_with("Context:\nYou are a test assistant."), "{}", message);
+    assert!(message.ends_with("Query: Which product led Q4 revenue?"), "{}", message);
+    assert_eq!(body["top_k"], 5);
+}
+
+#[tokio::test]
+async fn custom_rag_stream_parses_sources_tokens_and_raw_text() {
+    let server = replay(&["custom-rag-stream.json"]).await;
+    let streamed = stream(&provider("custom_rag", &server)).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "Refunds are issued within 14 days.");
+    match streamed.events.first() {
+        Some(ChatStreamEvent::Sources(sources)) => {
+            assert_eq!(sources.len(), 1);
+            assert_eq!(sources[0].document_id.as_deref(), Some("doc-7"));
+            assert_eq!(sources[0].title.as_deref(), Some("refund-policy.pdf"));
+        }
+        other => panic!("expected sources first, got {:?}", other),
+    }
+    assert_eq!(server.received()[0].path, "/api/v1/chat/stream");
+}
+
+#[tokio::test]
+async fn custom_rag_error_includes_status_and_body() {
+    let server = replay(&["custom-rag-error.json"]).await;
+    let error = complete(&provider("custom_rag", &server)).await.unwrap_err();
+
+    assert_eq!(
+        error,
+        "Custom RAG API API error (500 Internal Server Error): vector store unavailable"
+    );
+}
+
+#[tokio::test]
+async fn ollama_complete_reports_eval_counts() {
+    let server = replay(&["ollama-chat.json"]).await;
+    let response = complete(&provider("ollama", &server)).await.unwrap();
+
+    assert_eq!(response.content, "Churn fell 3% last quarter.");
+    let usage = response.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (18, 9));
+
+    let body = server.received()[0].body.clone().unwrap();
+    assert_eq!(body["stream"], false);
+    assert_eq!(body["options"]["num_predict"], 100);
+}
+
+#[tokio::test]
+async fn ollama_stream_reads_ndjson() {
+    let server = replay(&["ollama-chat-stream.json"]).await;
+    let streamed = stream(&provider("ollama", &server)).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "Churn fell 3% last quarter.");
+    assert_eq!(streamed.usage().unwrap().total_tokens, 27);
+}
+
+#[tokio::test]
+async fn ollama_error_field_is_an_error() {
+    let server = replay(&["ollama-error.json"]).await;
+    let error = complete(&provider("ollama", &server)).await.unwrap_err();
+
+    assert_eq!(error, "Ollama error: model \"llama9\" not found, try pulling it first");
+}
+
+#[tokio::test]
+async fn messages_api_complete_joins_text_blocks() {
+    let server = replay(&["messages-api.json"]).await;
+    let response = complete(&provider("anthropic", &server)).await.unwrap();
+
+    assert_eq!(response.content, "Margins improved in every region.");
+    let usage = response.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (30, 8));
+
+    // The system prompt moves to its own field
+    let body = server.received()[0].body.clone().unwrap();
+    assert_eq!(body["system"], "You are a test assistant.");
+    assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(body["messages"][0]["role"], "user");
+}
+
+#[tokio::test]
+async fn messages_api_stream_collects_deltas_and_usage() {
+    let server = replay(&["messages-api-stream.json"]).await;
+    let streamed = stream(&provider("anthropic", &server)).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "Margins improved in every region.");
+    let usage = streamed.usage().unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (30, 8));
+}
+
+#[tokio::test]
+async fn messages_api_stream_error_event_ends_stream() {
+    let server = replay(&["messages-api-stream-error.json"]).await;
+    let streamed = stream(&provider("anthropic", &server)).await;
+
+    assert_eq!(streamed.text, "Margins");
+    assert_eq!(streamed.error.as_deref(), Some("Messages API error: Overloaded"));
+}
+
+#[tokio::test]
+async fn messages_api_error_includes_status_and_body() {
+    let server = replay(&["messages-api-invalid.json"]).await;
+    let error = complete(&provider("anthropic", &server)).await.unwrap_err();
+
+    assert!(error.starts_with("Messages API API error (400 Bad Request)"), "{}", error);
+    assert!(error.contains("max_tokens: must be greater than 0"), "{}", error);
+}
+
+#[tokio::test]
+async fn only_rate_limits_and_server_errors_are_transient() {
+    let server = replay(&["openai-rate-limit.json"]).await;
+    let error = provider("openai", &server).complete(request()).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 429, .. }), "{:?}", error);
+    assert!(error.is_transient());
+
+    let server = replay(&["custom-rag-error.json"]).await;
+    let error = provider("custom_rag", &server).complete(request()).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 500, .. }), "{:?}", error);
+    assert!(error.is_transient());
+
+    let server = replay(&["messages-api-invalid.json"]).await;
+    let error = provider("anthropic", &server).complete(request()).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 400, .. }), "{:?}", error);
+    assert!(!error.is_transient());
+}
+
+#[tokio::test]
+async fn stalled_response_body_counts_as_a_breaker_failure() {
+    // Answers with headers and half a body, then hangs
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 4096];
+                let _ = socket.read(&mut buffer).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 100\r\n\r\n{\"choices\":")
+                    .await;
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            });
+        }
+    });
+
+    let mut config = config("openai", &url, None);
+    config.timeouts.request = Duration::from_millis(200);
+    let inner = ProviderRegistry::with_builtin_providers().build(&config).unwrap();
+    let error = inner.complete(request()).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Transport(_)), "{:?}", error);
+
+    let breakers = BreakerRegistry::new(BreakerSettings {
+        failure_threshold: 1,
+        open_duration: Duration::from_secs(60),
+    });
+    let breaker = breakers.breaker("openai", &url);
+    let retry = RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+    let resilient = ResilientProvider::new(inner, breaker.clone(), retry);
+
+    assert!(resilient.complete(request()).await.is_err());
+    assert_eq!(breaker.status().state, BreakerState::Open);
+    assert_eq!(resilient.complete(request()).await.unwrap_err(), ProviderError::Unavailable);
+}
+
+#[tokio::test]
+async fn half_open_trial_ends_on_any_answer() {
+    let breakers = BreakerRegistry::new(BreakerSettings {
+        failure_threshold: 1,
+        open_duration: Duration::from_millis(50),
+    });
+    let breaker = breakers.breaker("mock", "");
+    let retry = RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+    let resilient = ResilientProvider::new(mock(), breaker.clone(), retry);
+
+    assert!(resilient.complete(asking("Sales? [mock:error=503]")).await.is_err());
+    assert_eq!(breaker.status().state, BreakerState::Open);
+
+    // A rejected trial still shows the provider is answering
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let error = resilient.complete(asking("Sales? [mock:error=400]")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 400, .. }), "{:?}", error);
+    assert_eq!(breaker.status().state, BreakerState::Closed);
+    assert_eq!(breaker.status().consecutive_failures, 0);
+
+    // A trial that cannot reach the provider reopens the breaker
+    assert!(resilient.complete(asking("Sales? [mock:error=503]")).await.is_err());
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let error = resilient.complete(asking("Sales? [mock:error=timeout]")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Transport(_)), "{:?}", error);
+    assert_eq!(breaker.status().state, BreakerState::Open);
+    assert_eq!(resilient.complete(request()).await.unwrap_err(), ProviderError::Unavailable);
+}
+
+#[tokio::test]
+async fn replay_server_returns_404_without_a_fixture() {
+    let server = replay(&[]).await;
+    let error = complete(&provider("openai", &server)).await.unwrap_err();
+
+    assert!(error.starts_with("OpenAI API error (404 Not Found)"), "{}", error);
+    assert!(error.contains("No fixture for POST /v1/chat/completions"), "{}", error);
+}
+
+#[tokio::test]
+async fn recorded_stream_replays_identically() {
+    let record_dir = std::env::temp_dir().join(format!("ai-fixtures-{}", uuid::Uuid::new_v4()));
+    let upstream = replay(&["openai-chat-stream.json"]).await;
+    let recording = ProviderRegistry::with_builtin_providers()
+        .build(&config("openai", &upstream.url(), Some(record_dir.clone())))
+        .unwrap();
+    let original = stream(&recording).await;
+
+    // The fixture is written once the body has been forwarded
+    let mut recorded = Vec::new();
+    for _ in 0..50 {
+        recorded = Fixture::load_dir(&record_dir).unwrap_or_default();
+        if !recorded.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(recorded.len(), 1);
+
+    let fixture = &recorded[0];
+    assert_eq!(fixture.provider, "openai");
+    assert_eq!(fixture.request, upstream.received()[0]);
+    assert_eq!(fixture.response.status, 200);
+    assert_eq!(fixture.response.headers["content-type"], "text/event-stream");
+    assert!(fixture.response.chunks.len() > 1);
+
+    let replayed = ReplayServer::start(recorded).await.unwrap();
+    let again = stream(&provider("openai", &replayed)).await;
+    assert_eq!(again.text, original.text);
+    assert_eq!(again.usage().unwrap().total_tokens, original.usage().unwrap().total_tokens);
+
+    std::fs::remove_dir_all(&record_dir).ok();
+}
+
+#[tokio::test]
+async fn mock_script_rules_answer_matching_messages() {
+    let script = std::env::temp_dir().join(format!("mock-script-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &script,
+        r#"[
+            { "contains": "q4 revenue", "reply": "Widgets led Q4 revenue." },
+            { "contains": "outage", "error": 502 }
+        ]"#,
+    )
+    .unwrap();
+    let provider: Arc<dyn LlmProvider> =
+        Arc::new(MockProvider::new(&config("mock", "", None)).with_script(&script));
+    std::fs::remove_file(&script).ok();
+
+    let response = complete(&provider).await.unwrap();
+    assert_eq!(response.content, "Widgets led Q4 revenue.");
+    let usage = response.usage.unwrap();
+    assert_eq!(usage.completion_tokens, 4);
+    assert_eq!(usage.total_tokens, usage.prompt_tokens + 4);
+
+    let streamed = stream(&provider).await;
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, "Widgets led Q4 revenue.");
+
+    let error = provider.complete(asking("Is there an outage?")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 502, .. }), "{}", error);
+    assert!(error.is_transient());
+}
+
+#[tokio::test]
+async fn mock_chart_reply_is_deterministic_json() {
+    let provider = mock();
+    let question = "Show a bar chart of sales by region";
+    let first = provider.complete(asking(question)).await.unwrap().content;
+    let second = provider.complete(asking(question)).await.unwrap().content;
+    assert_eq!(first, second);
+
+    let json = first
+        .split("```json")
+        .nth(1)
+        .and_then(|rest| rest.split("```").next())
+        .expect("reply should contain a json block");
+    let chart: serde_json::Value = serde_json::from_str(json.trim()).unwrap();
+    assert_eq!(chart["type"], "bar");
+    assert_eq!(chart["labels"].as_array().unwrap().len(), 4);
+    assert_eq!(chart["data"].as_array().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn mock_structured_reply_parses_as_structured_response() {
+    let mut request = asking("Plot the revenue trend");
+    request.messages[0] = LlmMessage::system(r#"Answer as JSON with an "items" array."#);
+    let content = mock().complete(request).await.unwrap().content;
+
+    let response: StructuredResponse = serde_json::from_str(&content).unwrap();
+    assert_eq!(response.items.len(), 3);
+    assert!(matches!(response.items[0], RenderContent::Text { .. }));
+    match &response.items[1] {
+        RenderContent::Chart { data } => {
+            assert_eq!(data.chart_type, ChartType::Line);
+            assert_eq!(data.datasets[0].data.len(), data.labels.len());
+        }
+        other => panic!("expected a chart, got {:?}", other),
+    }
+    assert!(matches!(response.items[2], RenderContent::Table { .. }));
+}
+
+#[tokio::test]
+async fn mock_stream_sends_the_reply_in_chunks_then_usage() {
+    let provider = mock();
+    let request = asking("Summarise sales for March");
+    let full = provider.complete(request.clone()).await.unwrap();
+    let streamed = stream_request(&provider, request).await;
+
+    assert_eq!(streamed.error, None);
+    assert_eq!(streamed.text, full.content);
+    let chunks = streamed
+        .events
+        .iter()
+        .filter(|e| matches!(e, ChatStreamEvent::Content(_)))
+        .count();
+    assert!(chunks > 1, "expected several chunks, got {}", chunks);
+    assert!(matches!(streamed.events.last(), Some(ChatStreamEvent::Usage(_))));
+    assert_eq!(streamed.usage().unwrap().total_tokens, full.usage.unwrap().total_tokens);
+}
+
+#[tokio::test]
+async fn mock_error_directive_fails_the_call() {
+    let provider = mock();
+
+    let error = provider.complete(asking("Sales? [mock:error=429]")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 429, .. }), "{}", error);
+    assert!(error.is_transient());
+
+    let error = provider.complete(asking("Sales? [mock:error=400]")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Status { status: 400, .. }), "{}", error);
+    assert!(!error.is_transient());
+
+    let error = provider.complete(asking("Sales? [mock:error=timeout]")).await.unwrap_err();
+    assert!(matches!(error, ProviderError::Transport(_)), "{}", error);
+
+    assert!(provider.stream(asking("Sales? [mock:error=503]")).await.is_err());
+}
+
+#[tokio::test]
+async fn mock_stream_error_cuts_the_reply_off() {
+    let provider = mock();
+    let full = provider.complete(asking("Summarise sales for March")).await.unwrap().content;
+    let streamed = stream_request(&provider, asking("Summarise sales for March [mock:stream_error]")).await;
+
+    assert_eq!(streamed.error.as_deref(), Some("Mock stream interrupted: injected failure"));
+    assert!(!streamed.text.is_empty());
+    assert!(full.starts_with(&streamed.text));
+    assert!(streamed.text.len() < full.len());
+    assert_eq!(streamed.usage().map(|u| u.total_tokens), None);
+}
+
+#[tokio::test]
+async fn mock_tool_directive_calls_an_offered_tool() {
+    let provider = mock();
+    let mut request = asking("What data is there? [mock:tool=list_datasets]");
+
+    // Without tools on offer the directive is ignored
+    let streamed = stream_request(&provider, request.clone()).await;
+    assert!(streamed.events.iter().all(|e| !matches!(e, ChatStreamEvent::ToolCall(_))));
+    assert!(!streamed.text.contains("[mock:"));
+
+    request.tools = vec![ToolDefinition {
+        name: "list_datasets".to_string(),
+        description: "List the project's datasets".to_string(),
+        parameters: serde_json::json!({ "type": "object", "properties": {} }),
+    }];
+    let streamed = stream_request(&provider, request.clone()).await;
+    let call = streamed
+        .events
+        .iter()
+        .find_map(|e| match e {
+            ChatStreamEvent::ToolCall(call) => Some(call.clone()),
+            _ => None,
+        })
+        .expect("the tool should be called");
+    assert_eq!(call.name, "list_datasets");
+    assert_eq!(call.arguments, "{}");
+
+    request.messages.push(LlmMessage::tool_request(String::new(), vec![call.clone()]));
+    request.messages.push(LlmMessage::tool_result(&call.id, "[\"sales\", \"costs\"]"));
+    let answered = stream_request(&provider, request).await;
+    assert_eq!(answered.error, None);
+    assert_eq!(answered.text, "Tool results:\n- [\"sales\", \"costs\"]");
+}
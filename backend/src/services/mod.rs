@@ -1,13 +1,53 @@
 pub mod ai;
+pub mod ai_settings;
+pub mod llm;
 pub mod user;
 pub mod project;
 pub mod analytics;
 pub mod chat;
 pub mod rbac;
+pub mod search;
+pub mod knowledge;
+pub mod prompts;
+pub mod usage;
+pub mod response_cache;
+pub mod dataset;
+pub mod sql;
+pub mod tools;
+pub mod audit;
+pub mod redaction;
+pub mod guardrails;
+pub mod attachments;
+pub mod stats;
+pub mod insights;
+pub mod profiling;
+pub mod quality;
+pub mod forecast;
+pub mod anomaly;
+pub mod notifications;
+pub mod scheduled_reports;
 
 pub use ai::AIService;
+pub use ai_settings::AiSettingsService;
 pub use user::UserService;
 pub use project::ProjectService;
 pub use analytics::AnalyticsService;
 pub use chat::ChatService;
 pub use rbac::RbacService;
+pub use search::SearchService;
+pub use knowledge::KnowledgeService;
+pub use prompts::PromptService;
+pub use usage::UsageService;
+pub use response_cache::ResponseCache;
+pub use dataset::DatasetService;
+pub use tools::ToolRegistry;
+pub use audit::AuditService;
+pub use redaction::RedactionService;
+pub use guardrails::GuardrailService;
+pub use attachments::AttachmentService;
+pub use insights::InsightService;
+pub use quality::QualityService;
+pub use forecast::ForecastService;
+pub use anomaly::AnomalyService;
+pub use notifications::NotificationService;
+pub use scheduled_reports::ScheduledReportService;
@@ -0,0 +1,90 @@
+//! In-app notifications for users, such as new anomalies found by a
+//! scheduled report.
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{Notification, NotificationKind};
+
+const DEFAULT_LIST_LIMIT: i64 = 20;
+const MAX_LIST_LIMIT: i64 = 100;
+
+pub struct NotificationService {
+    db: DatabaseManager,
+}
+
+impl NotificationService {
+    pub fn new(db: DatabaseManager) -> Self {
+        NotificationService { db }
+    }
+
+    /// Send the same notification to each of `user_ids`
+    pub async fn notify(
+        &self,
+        user_ids: &[String],
+        project_id: &str,
+        kind: NotificationKind,
+        title: &str,
+        message: &str,
+        report_id: Option<&str>,
+    ) -> Result<(), String> {
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+        let now = DateTime::now();
+        let notifications: Vec<Notification> = user_ids
+            .iter()
+            .map(|user_id| Notification {
+                id: None,
+                notification_id: Uuid::new_v4().to_string(),
+                user_id: user_id.clone(),
+                project_id: project_id.to_string(),
+                kind,
+                title: title.to_string(),
+                message: message.to_string(),
+                report_id: report_id.map(|id| id.to_string()),
+                read: false,
+                created_at: now,
+            })
+            .collect();
+        self.db
+            .notifications_collection()
+            .insert_many(notifications)
+            .await
+            .map_err(|e| format!("Failed to store notifications: {}", e))?;
+        Ok(())
+    }
+
+    /// Notifications of a user, newest first
+    pub async fn list(&self, user_id: &str, unread_only: bool, limit: Option<i64>) -> Result<Vec<Notification>, String> {
+        let mut filter = doc! { "user_id": user_id };
+        if unread_only {
+            filter.insert("read", false);
+        }
+        self.db
+            .notifications_collection()
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT))
+            .await
+            .map_err(|e| format!("Failed to get notifications: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect notifications: {}", e))
+    }
+
+    /// Mark a notification of the user as read; false if the user has no such notification
+    pub async fn mark_read(&self, user_id: &str, notification_id: &str) -> Result<bool, String> {
+        let result = self
+            .db
+            .notifications_collection()
+            .update_one(
+                doc! { "notification_id": notification_id, "user_id": user_id },
+                doc! { "$set": { "read": true } },
+            )
+            .await
+            .map_err(|e| format!("Failed to update notification: {}", e))?;
+        Ok(result.matched_count == 1)
+    }
+}
@@ -0,0 +1,227 @@
+//! Column profiles of a dataset: null rate, distinct values, numeric
+//! statistics and quantiles, most common values and the semantic type
+//! (id, date, currency, category, ...) inferred from the name and values.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use regex::Regex;
+use serde_json::Value;
+use crate::models::{ColumnInfo, ColumnProfile, Quantiles, SemanticType, ValueCount};
+use crate::services::stats;
+
+const TOP_VALUES: usize = 5;
+/// Most distinct values a category column may have
+const CATEGORY_MAX_DISTINCT: usize = 50;
+/// Share of non-empty values that must look like currency amounts
+const CURRENCY_SHARE: f64 = 0.9;
+
+/// Column names that hold money when their values are plain numbers
+const CURRENCY_NAMES: &[&str] = &[
+    "price", "amount", "cost", "revenue", "salary", "fee", "total", "sales", "income", "spend", "budget",
+];
+
+fn currency_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)^[-+]?\s*(?:[$€£¥₹]\s*[-+]?\d[\d,]*(?:\.\d+)?|\d[\d,]*(?:\.\d+)?\s*(?:[$€£¥₹]|usd|eur|gbp|jpy|inr|cad|aud))$",
+        )
+        .expect("currency pattern should compile")
+    })
+}
+
+fn uuid_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$")
+            .expect("uuid pattern should compile")
+    })
+}
+
+/// Amount of a currency string such as `$1,250.00` or `99 EUR`
+pub fn parse_currency(value: &Value) -> Option<f64> {
+    let text = value.as_str()?.trim();
+    if !currency_pattern().is_match(text) {
+        return None;
+    }
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-').collect();
+    digits.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Number in a cell for statistics and range checks, including numbers and
+/// currency amounts in text columns
+pub fn numeric_value(value: &Value) -> Option<f64> {
+    stats::number(value)
+        .or_else(|| parse_currency(value))
+        .or_else(|| value.as_str()?.trim().parse::<f64>().ok().filter(|n| n.is_finite()))
+}
+
+/// Text of a cell as counted and shown
+pub fn cell_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn is_id_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "id" || name.ends_with("_id") || name.ends_with(" id") || name.ends_with("uuid") || name.ends_with("guid")
+}
+
+fn is_currency_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    CURRENCY_NAMES.iter().any(|hint| name.contains(hint))
+}
+
+/// Semantic type of a column from its name, storage type and non-empty values
+fn semantic_type(column: &ColumnInfo, cells: &[&Value], distinct: usize) -> SemanticType {
+    let all_unique = !cells.is_empty() && distinct == cells.len();
+    let numeric = matches!(column.data_type.as_str(), "integer" | "number");
+
+    if column.data_type == "boolean" {
+        return SemanticType::Boolean;
+    }
+    if all_unique && (is_id_name(&column.name) || cells.iter().all(|v| v.as_str().is_some_and(|s| uuid_pattern().is_match(s)))) {
+        return SemanticType::Id;
+    }
+    if numeric {
+        // Money has at most two decimals
+        let cents = cells
+            .iter()
+            .filter_map(|v| stats::number(v))
+            .all(|n| ((n * 100.0).round() - n * 100.0).abs() < 1e-6);
+        return if is_currency_name(&column.name) && cents {
+            SemanticType::Currency
+        } else {
+            SemanticType::Numeric
+        };
+    }
+    if stats::is_date_column(cells) {
+        return SemanticType::Date;
+    }
+    let currency = cells.iter().filter(|v| parse_currency(v).is_some()).count();
+    if !cells.is_empty() && currency as f64 >= cells.len() as f64 * CURRENCY_SHARE {
+        return SemanticType::Currency;
+    }
+    if !cells.is_empty() && distinct <= CATEGORY_MAX_DISTINCT && distinct * 2 <= cells.len() {
+        return SemanticType::Category;
+    }
+    SemanticType::Text
+}
+
+/// Profile every column of a dataset
+pub fn profile_columns(columns: &[ColumnInfo], rows: &[Vec<Value>]) -> Vec<ColumnProfile> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let cells: Vec<&Value> = rows.iter().map(|r| &r[i]).filter(|v| !v.is_null()).collect();
+            let nulls = rows.len() - cells.len();
+
+            let mut counts: HashMap<String, i64> = HashMap::new();
+            for cell in &cells {
+                *counts.entry(cell_text(cell)).or_default() += 1;
+            }
+            let distinct = counts.len();
+            let semantic_type = semantic_type(column, &cells, distinct);
+
+            let mut top_values: Vec<ValueCount> = counts
+                .into_iter()
+                .map(|(value, count)| ValueCount { value, count })
+                .collect();
+            top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+            top_values.truncate(TOP_VALUES);
+
+            let numbers: Vec<f64> = match semantic_type {
+                SemanticType::Numeric | SemanticType::Currency | SemanticType::Id => {
+                    cells.iter().filter_map(|v| numeric_value(v)).collect()
+                }
+                _ => vec![],
+            };
+            let sorted = stats::sorted(&numbers);
+            let quantile = |q| stats::quantile(&sorted, q).map(|v| stats::round(v, 4));
+            let quantiles = sorted.first().map(|_| Quantiles {
+                p5: quantile(0.05).unwrap_or_default(),
+                p25: quantile(0.25).unwrap_or_default(),
+                p50: quantile(0.5).unwrap_or_default(),
+                p75: quantile(0.75).unwrap_or_default(),
+                p95: quantile(0.95).unwrap_or_default(),
+            });
+
+            let (earliest, latest) = if semantic_type == SemanticType::Date {
+                let dates: Vec<_> = cells.iter().filter_map(|v| stats::parse_date(v)).collect();
+                (
+                    dates.iter().min().map(|d| d.format("%Y-%m-%d").to_string()),
+                    dates.iter().max().map(|d| d.format("%Y-%m-%d").to_string()),
+                )
+            } else {
+                (None, None)
+            };
+
+            ColumnProfile {
+                column: column.name.clone(),
+                data_type: column.data_type.clone(),
+                semantic_type,
+                nulls: nulls as i64,
+                null_rate: if rows.is_empty() { 0.0 } else { stats::round(nulls as f64 / rows.len() as f64, 4) },
+                distinct: distinct as i64,
+                min: sorted.first().copied(),
+                max: sorted.last().copied(),
+                mean: stats::mean(&numbers).map(|m| stats::round(m, 4)),
+                quantiles,
+                earliest,
+                latest,
+                top_values,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::DatasetService;
+
+    #[test]
+    fn infers_semantic_types() {
+        let csv = "order_id,ordered_on,region,price,list_price,notes,ok\n\
+            1,2024-01-05,North,$1.50,10.25,first order,true\n\
+            2,2024-01-06,North,\"$1,200.00\",12.5,,false\n\
+            3,2024-02-01,South,€30,8,called back,true\n\
+            4,2024-02-03,North,$5,9.99,left at door,true\n";
+        let (columns, rows) = DatasetService::parse_table("orders.csv", csv.as_bytes()).unwrap();
+        let types: Vec<SemanticType> = profile_columns(&columns, &rows).iter().map(|p| p.semantic_type).collect();
+
+        assert_eq!(
+            types,
+            vec![
+                SemanticType::Id,
+                SemanticType::Date,
+                SemanticType::Category,
+                SemanticType::Currency,
+                SemanticType::Currency,
+                SemanticType::Text,
+                SemanticType::Boolean,
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_nulls_quantiles_and_top_values() {
+        let csv = "price,region,ordered_on\n$10,North,2024-03-01\n$20,North,\n,South,2024-01-15\n$40,,2024-02-10\n$30,North,2024-02-11\n";
+        let (columns, rows) = DatasetService::parse_table("orders.csv", csv.as_bytes()).unwrap();
+        let profiles = profile_columns(&columns, &rows);
+
+        let price = &profiles[0];
+        assert_eq!((price.nulls, price.null_rate, price.distinct), (1, 0.2, 4));
+        assert_eq!((price.min, price.max, price.mean), (Some(10.0), Some(40.0), Some(25.0)));
+        let quantiles = price.quantiles.as_ref().unwrap();
+        assert_eq!((quantiles.p25, quantiles.p50, quantiles.p75), (17.5, 25.0, 32.5));
+
+        assert_eq!(profiles[1].top_values[0], ValueCount { value: "North".to_string(), count: 3 });
+        assert_eq!(profiles[2].earliest.as_deref(), Some("2024-01-15"));
+        assert_eq!(profiles[2].latest.as_deref(), Some("2024-03-01"));
+        assert!(profiles[1].quantiles.is_none());
+    }
+}
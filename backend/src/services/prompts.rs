@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{
+    CreatePromptVersionDto, PromptScope, PromptTemplate, PromptTemplateResponse, PromptVersionRef,
+};
+
+// Names of the templates `AIService` renders
+pub const CHAT_SYSTEM: &str = "chat.system";
+pub const CHART_INSTRUCTION: &str = "chat.chart_instruction";
+pub const KNOWLEDGE_CONTEXT: &str = "chat.knowledge";
+pub const ATTACHMENTS_CONTEXT: &str = "chat.attachments";
+pub const TOOLS_INSTRUCTION: &str = "chat.tools";
+pub const STRUCTURED_SYSTEM: &str = "chat.structured_system";
+pub const ANALYTICS_SYSTEM: &str = "analytics.system";
+pub const CONVERSATION_TITLE: &str = "conversation.title";
+pub const FOLLOW_UP_SUGGESTIONS: &str = "chat.follow_ups";
+
+struct BuiltinTemplate {
+    name: &'static str,
+    description: &'static str,
+    content: &'static str,
+}
+
+/// Defaults shipped with DencapsBI; stored versions override them
+const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        name: CHAT_SYSTEM,
+        description: "System prompt for chat messages",
+        content: "You are DencapsBI Chat Assistant, an advanced AI analytics assistant. \
+            You help users with data analysis, business intelligence questions, and provide insights. \
+            You can discuss data visualization, analytics strategies, SQL queries, and statistical methods. \
+            Provide clear, structured, and actionable responses. When providing structured data like lists, \
+            tables, or code snippets, use markdown formatting.",
+    },
+    BuiltinTemplate {
+        name: CHART_INSTRUCTION,
+        description: "Added when the user asks for a chart; describes the chart JSON format",
+        content: r#"
+IMPORTANT: When the user asks for a chart or visualization, you MUST output the data in a JSON code block.
+
+Supported chart types: pie, bar, line, doughnut
+
+Use this EXACT format inside a ```json code block:
+{
+  "type": "pie",
+  "title": "Chart Title",
+  "labels": ["Category A", "Category B", "Category C"],
+  "data": [100, 200, 300]
+}
+
+Examples:
+
+For a PIE CHART showing tax vs take-home:
+```json
+{"type": "pie", "title": "Income Distribution", "labels": ["Tax Payable", "Take-Home Salary"], "data": [351000, 1649000]}
+```
+
+For a BAR CHART showing tax by slab:
+```json
+{"type": "bar", "title": "Tax by Income Slab", "labels": ["0-2.5L", "2.5L-5L", "5L-10L", "10L-20L"], "data": [0, 12500, 100000, 300000]}
+```
+
+For a LINE CHART showing trends:
+```json
+{"type": "line", "title": "Monthly Trend", "labels": ["Jan", "Feb", "Mar"], "data": [100, 150, 200]}
+```
+
+For a DOUGHNUT CHART:
+```json
+{"type": "doughnut", "title": "Expense Breakdown", "labels": ["Rent", "Food", "Transport"], "data": [500, 200, 100]}
+```
+
+ALWAYS use the correct "type" field based on what the user asks for:
+- "bar chart" or "bar graph" → type: "bar"
+- "pie chart" or "pie graph" → type: "pie"
+- "line chart" or "line graph" or "trend" → type: "line"
+- "doughnut" or "donut" → type: "doughnut"
+- Default to "pie" if no specific type is mentioned
+"#,
+    },
+    BuiltinTemplate {
+        name: KNOWLEDGE_CONTEXT,
+        description: "Grounds the answer in retrieved project documents",
+        content: "Use the following excerpts from the project's documents when they are relevant. \
+            Refer to them by their number, e.g. [1]. If they do not answer the question, say so \
+            rather than guessing.\n\nDocuments:\n{{documents}}",
+    },
+    BuiltinTemplate {
+        name: ATTACHMENTS_CONTEXT,
+        description: "Summaries of files the user attached to the conversation",
+        content: "The user attached the following files to this conversation. Each is summarized \
+            below; tables show their columns, statistics and first rows rather than every row. \
+            Answer questions about the files from these summaries, and say when a question needs \
+            data the summary does not include.\n\nFiles:\n{{files}}",
+    },
+    BuiltinTemplate {
+        name: TOOLS_INSTRUCTION,
+        description: "Added when the assistant can call server-side tools",
+        content: "You can call tools to look at this project's datasets and saved queries. \
+            When a question depends on the project's data, list or describe the datasets and run \
+            SQL instead of guessing, then answer from the results. Use create_chart when a chart \
+            helps; it is shown to the user with your answer, so do not repeat its data as JSON.",
+    },
+    BuiltinTemplate {
+        name: STRUCTURED_SYSTEM,
+        description: "System prompt for structured (JSON) chat responses",
+        content: "You are DencapsBI Chat Assistant, an advanced AI analytics assistant. \
+            You respond with structured JSON that can include text, charts, equations, tables, and datasets. \
+            \
+            Response Format (JSON):\n\
+            {\n\
+              \"items\": [\n\
+                {\"type\": \"text\", \"content\": \"Your explanation here\"},\n\
+                {\"type\": \"chart\", \"data\": {\"chart_type\": \"bar|line|pie\", \"title\": \"Chart Title\", \"labels\": [\"A\", \"B\"], \"datasets\": [{\"label\": \"Series\", \"data\": [1, 2]}]}},\n\
+                {\"type\": \"equation\", \"latex\": \"E = mc^2\", \"display\": true},\n\
+                {\"type\": \"table\", \"data\": {\"headers\": [\"Col1\", \"Col2\"], \"rows\": [[\"val1\", \"val2\"]]}},\n\
+                {\"type\": \"dataset\", \"data\": {\"name\": \"Dataset Name\", \"description\": \"Optional\", \"columns\": [{\"name\": \"col\", \"data_type\": \"string\"}], \"rows\": [[\"value\"]]}}\n\
+              ]\n\
+            }\n\
+            \
+            Always respond with valid JSON. Use text type for explanations, chart for visualizations, \
+            equation for math (LaTeX), table for tabular data, and dataset for structured data with schema.",
+    },
+    BuiltinTemplate {
+        name: ANALYTICS_SYSTEM,
+        description: "System prompt for analytics queries",
+        content: "You are DencapsBI, an advanced AI analytics assistant. \
+            You help users analyze data, generate insights, and create visualizations. \
+            Provide clear, actionable, and data-driven responses. \
+            When appropriate, suggest SQL queries, statistical analyses, or visualization recommendations.",
+    },
+    BuiltinTemplate {
+        name: CONVERSATION_TITLE,
+        description: "Generates conversation titles",
+        content: "You write titles for chat conversations. \
+            Reply with a concise title of at most six words that captures the topic. \
+            Do not use quotes, punctuation at the end, or any other text.",
+    },
+    BuiltinTemplate {
+        name: FOLLOW_UP_SUGGESTIONS,
+        description: "Suggests follow-up questions after a chat reply",
+        content: "You suggest what a user exploring their data might ask next. \
+            Given the latest exchange and the project's datasets, reply with two to four short \
+            follow-up questions, one per line. Each must be answerable from the conversation or \
+            the listed datasets and must not repeat the last question. \
+            Do not number them or add any other text.",
+    },
+];
+
+fn builtin(name: &str) -> Option<&'static BuiltinTemplate> {
+    BUILTIN_TEMPLATES.iter().find(|t| t.name == name)
+}
+
+/// Variables referenced as `{{name}}`, in order of first use
+pub fn extract_variables(content: &str) -> Vec<String> {
+    let mut variables: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        let name = after[..end].trim();
+        if is_variable_name(name) && !variables.iter().any(|v| v == name) {
+            variables.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    variables
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace `{{name}}` placeholders. Unknown placeholders are left as they are and
+/// substituted values are not scanned again.
+pub fn render_template(content: &str, vars: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+        let name = after[..end].trim();
+        match vars.iter().find(|(k, _)| *k == name) {
+            Some((_, value)) => output.push_str(value),
+            None => output.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedPrompt {
+    content: String,
+    scope: PromptScope,
+    version: i32,
+}
+
+/// Templates in effect for one request. Records which versions were rendered so
+/// they can be stored with the generated message.
+#[derive(Debug, Clone, Default)]
+pub struct PromptSet {
+    templates: HashMap<String, ResolvedPrompt>,
+    used: Vec<PromptVersionRef>,
+}
+
+impl PromptSet {
+    /// Only the built-in templates
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    pub fn render(&mut self, name: &str, vars: &[(&str, &str)]) -> String {
+        let (content, scope, version) = match self.templates.get(name) {
+            Some(t) => (t.content.as_str(), t.scope, t.version),
+            None => match builtin(name) {
+                Some(t) => (t.content, PromptScope::Builtin, 0),
+                None => {
+                    log::warn!("Unknown prompt template '{}'", name);
+                    return String::new();
+                }
+            },
+        };
+
+        let rendered = render_template(content, vars);
+        if !self.used.iter().any(|u| u.name == name) {
+            self.used.push(PromptVersionRef { name: name.to_string(), scope, version });
+        }
+        rendered
+    }
+
+    /// Template versions rendered so far
+    pub fn used(&self) -> Vec<PromptVersionRef> {
+        self.used.clone()
+    }
+}
+
+/// Versioned prompt templates with global and per-tenant overrides
+pub struct PromptService {
+    db: DatabaseManager,
+}
+
+impl PromptService {
+    pub fn new(db: DatabaseManager) -> Self {
+        PromptService { db }
+    }
+
+    /// Filter for the versions of `name` in one scope; `None` is the global scope
+    fn scope_filter(name: &str, tenant_id: Option<&str>) -> Document {
+        doc! {
+            "name": name,
+            "tenant_id": tenant_id.map(|t| Bson::String(t.to_string())).unwrap_or(Bson::Null),
+        }
+    }
+
+    /// Active templates for a tenant: tenant versions, then global ones, then built-ins.
+    /// Lookup failures fall back to the built-in templates.
+    pub async fn resolve(&self, tenant_id: Option<&str>) -> PromptSet {
+        use futures::stream::TryStreamExt;
+
+        let mut scopes = vec![Bson::Null];
+        if let Some(t) = tenant_id {
+            scopes.push(Bson::String(t.to_string()));
+        }
+
+        let active: Result<Vec<PromptTemplate>, String> = async {
+            self.db
+                .prompt_templates_collection()
+                .find(doc! { "is_active": true, "tenant_id": { "$in": scopes } })
+                .await
+                .map_err(|e| format!("Database error: {}", e))?
+                .try_collect()
+                .await
+                .map_err(|e| format!("Failed to fetch prompt templates: {}", e))
+        }
+        .await;
+
+        let active = match active {
+            Ok(templates) => templates,
+            Err(e) => {
+                log::warn!("Using built-in prompts: {}", e);
+                return PromptSet::builtin();
+            }
+        };
+
+        let mut set = PromptSet::builtin();
+        // Global versions first so tenant versions replace them
+        for template in active.iter().filter(|t| t.tenant_id.is_none()).chain(active.iter().filter(|t| t.tenant_id.is_some())) {
+            set.templates.insert(template.name.clone(), ResolvedPrompt {
+                content: template.content.clone(),
+                scope: if template.tenant_id.is_some() { PromptScope::Tenant } else { PromptScope::Global },
+                version: template.version,
+            });
+        }
+        set
+    }
+
+    /// Templates for the tenant that owns a project
+    pub async fn resolve_for_project(&self, project_id: &str) -> PromptSet {
+        match self.db.projects_collection().find_one(doc! { "project_id": project_id }).await {
+            Ok(Some(project)) => self.resolve(Some(&project.tenant_id)).await,
+            Ok(None) => self.resolve(None).await,
+            Err(e) => {
+                log::warn!("Failed to look up tenant for project {}: {}", project_id, e);
+                self.resolve(None).await
+            }
+        }
+    }
+
+    /// Every template with the version in effect for the tenant
+    pub async fn list_templates(&self, tenant_id: &str) -> Vec<PromptTemplateResponse> {
+        let set = self.resolve(Some(tenant_id)).await;
+        BUILTIN_TEMPLATES
+            .iter()
+            .map(|builtin| match set.templates.get(builtin.name) {
+                Some(t) => PromptTemplateResponse {
+                    name: builtin.name.to_string(),
+                    scope: t.scope,
+                    version: t.version,
+                    variables: extract_variables(&t.content),
+                    content: t.content.clone(),
+                    description: Some(builtin.description.to_string()),
+                    is_active: true,
+                    created_by: None,
+                    created_at: None,
+                },
+                None => Self::builtin_response(builtin, true),
+            })
+            .collect()
+    }
+
+    fn builtin_response(builtin: &BuiltinTemplate, is_active: bool) -> PromptTemplateResponse {
+        PromptTemplateResponse {
+            name: builtin.name.to_string(),
+            scope: PromptScope::Builtin,
+            version: 0,
+            content: builtin.content.to_string(),
+            variables: extract_variables(builtin.content),
+            description: Some(builtin.description.to_string()),
+            is_active,
+            created_by: None,
+            created_at: None,
+        }
+    }
+
+    /// Version history of a template in one scope, newest first, ending with the built-in
+    pub async fn get_versions(
+        &self,
+        name: &str,
+        tenant_id: Option<&str>,
+    ) -> Result<Vec<PromptTemplateResponse>, String> {
+        use futures::stream::TryStreamExt;
+
+        let builtin = builtin(name).ok_or_else(|| Self::unknown_template(name))?;
+
+        let versions: Vec<PromptTemplate> = self.db
+            .prompt_templates_collection()
+            .find(Self::scope_filter(name, tenant_id))
+            .sort(doc! { "version": -1 })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to fetch prompt versions: {}", e))?;
+
+        let any_active = versions.iter().any(|v| v.is_active);
+        let mut history: Vec<PromptTemplateResponse> =
+            versions.into_iter().map(PromptTemplateResponse::from).collect();
+        history.push(Self::builtin_response(builtin, !any_active));
+        Ok(history)
+    }
+
+    /// Store a new version of a template and make it the active one in its scope
+    pub async fn create_version(
+        &self,
+        name: &str,
+        tenant_id: Option<&str>,
+        user_id: &str,
+        dto: CreatePromptVersionDto,
+    ) -> Result<PromptTemplate, String> {
+        let builtin = builtin(name).ok_or_else(|| Self::unknown_template(name))?;
+
+        // Rendering only supplies the built-in variables, so the new content must use
+        // the same set
+        let allowed = extract_variables(builtin.content);
+        let variables = extract_variables(&dto.content);
+        if let Some(unknown) = variables.iter().find(|v| !allowed.contains(v)) {
+            return Err(format!(
+                "Unknown variable '{{{{{}}}}}' in template '{}'. Available variables: {}",
+                unknown,
+                name,
+                if allowed.is_empty() { "none".to_string() } else { allowed.join(", ") }
+            ));
+        }
+        if let Some(missing) = allowed.iter().find(|v| !variables.contains(v)) {
+            return Err(format!("Template '{}' must use the variable '{{{{{}}}}}'", name, missing));
+        }
+
+        let collection = self.db.prompt_templates_collection();
+        let latest = collection
+            .find_one(Self::scope_filter(name, tenant_id))
+            .sort(doc! { "version": -1 })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let template = PromptTemplate {
+            id: None,
+            template_id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            tenant_id: tenant_id.map(str::to_string),
+            version: latest.map(|t| t.version + 1).unwrap_or(1),
+            content: dto.content,
+            variables,
+            description: dto.description,
+            is_active: true,
+            created_by: user_id.to_string(),
+            created_at: DateTime::now(),
+        };
+
+        collection
+            .update_many(Self::scope_filter(name, tenant_id), doc! { "$set": { "is_active": false } })
+            .await
+            .map_err(|e| format!("Failed to update prompt versions: {}", e))?;
+
+        collection
+            .insert_one(&template)
+            .await
+            .map_err(|e| format!("Failed to save prompt version: {}", e))?;
+
+        Ok(template)
+    }
+
+    /// Make an earlier version active again. Version 0 removes the override so the
+    /// next scope down (global, then built-in) applies.
+    pub async fn rollback(
+        &self,
+        name: &str,
+        tenant_id: Option<&str>,
+        version: i32,
+    ) -> Result<PromptTemplateResponse, String> {
+        let builtin = builtin(name).ok_or_else(|| Self::unknown_template(name))?;
+        let collection = self.db.prompt_templates_collection();
+
+        let target = if version == 0 {
+            None
+        } else {
+            let mut filter = Self::scope_filter(name, tenant_id);
+            filter.insert("version", version);
+            let target = collection
+                .find_one(filter)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?
+                .ok_or_else(|| format!("Version {} of template '{}' not found", version, name))?;
+            Some(target)
+        };
+
+        collection
+            .update_many(Self::scope_filter(name, tenant_id), doc! { "$set": { "is_active": false } })
+            .await
+            .map_err(|e| format!("Failed to update prompt versions: {}", e))?;
+
+        match target {
+            Some(mut target) => {
+                collection
+                    .update_one(doc! { "template_id": &target.template_id }, doc! { "$set": { "is_active": true } })
+                    .await
+                    .map_err(|e| format!("Failed to activate prompt version: {}", e))?;
+                target.is_active = true;
+                Ok(target.into())
+            }
+            None => Ok(Self::builtin_response(builtin, true)),
+        }
+    }
+
+    fn unknown_template(name: &str) -> String {
+        let names: Vec<&str> = BUILTIN_TEMPLATES.iter().map(|t| t.name).collect();
+        format!("Unknown prompt template '{}'. Available templates: {}", name, names.join(", "))
+    }
+}
@@ -0,0 +1,475 @@
+//! Data-quality rules on dataset columns and the history of their runs.
+//!
+//! Each run stores the column profiles and rule results of a dataset, and
+//! flags anything that got worse since the previous run of the same table.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use regex::Regex;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{
+    ColumnProfile, CreateQualityRuleDto, Dataset, QualityCheck, QualityRegression, QualityRule,
+    QualityRuleResult, QualityRun,
+};
+use crate::services::profiling;
+use crate::services::stats;
+
+/// Failing values kept per rule result
+const FAILURE_EXAMPLES: usize = 5;
+/// Rise in a column's null rate that counts as a regression
+const NULL_RATE_REGRESSION: f64 = 0.05;
+const DEFAULT_RUN_LIMIT: i64 = 20;
+const MAX_RUN_LIMIT: i64 = 100;
+
+/// Stores quality rules per table and runs them against its datasets
+pub struct QualityService {
+    db: DatabaseManager,
+}
+
+impl QualityService {
+    pub fn new(db: DatabaseManager) -> Self {
+        QualityService { db }
+    }
+
+    /// Add a rule on a column of `dataset`'s table
+    pub async fn create_rule(
+        &self,
+        dataset: &Dataset,
+        user_id: &str,
+        dto: CreateQualityRuleDto,
+    ) -> Result<QualityRule, String> {
+        if !dataset.columns.iter().any(|c| c.name == dto.column) {
+            return Err(format!("Dataset '{}' has no column '{}'", dataset.name, dto.column));
+        }
+        match dto.check {
+            QualityCheck::Range { min: None, max: None } => {
+                return Err("A range check needs a min, a max or both".to_string());
+            }
+            QualityCheck::Range { min: Some(min), max: Some(max) } if min > max => {
+                return Err("Range min must not be greater than max".to_string());
+            }
+            QualityCheck::Regex { ref pattern } => {
+                full_match(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+            }
+            _ => {}
+        }
+
+        let rule = QualityRule {
+            id: None,
+            rule_id: Uuid::new_v4().to_string(),
+            project_id: dataset.project_id.clone(),
+            dataset_name: dataset.name.clone(),
+            column: dto.column,
+            check: dto.check,
+            max_failure_rate: dto.max_failure_rate.unwrap_or(0.0),
+            created_by: user_id.to_string(),
+            created_at: DateTime::now(),
+        };
+        self.db
+            .quality_rules_collection()
+            .insert_one(&rule)
+            .await
+            .map_err(|e| format!("Failed to create quality rule: {}", e))?;
+        Ok(rule)
+    }
+
+    /// Rules of a table, oldest first
+    pub async fn list_rules(&self, project_id: &str, dataset_name: &str) -> Result<Vec<QualityRule>, String> {
+        self.db
+            .quality_rules_collection()
+            .find(doc! { "project_id": project_id, "dataset_name": dataset_name })
+            .sort(doc! { "created_at": 1 })
+            .await
+            .map_err(|e| format!("Failed to get quality rules: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect quality rules: {}", e))
+    }
+
+    pub async fn get_rule(&self, rule_id: &str) -> Result<Option<QualityRule>, String> {
+        self.db
+            .quality_rules_collection()
+            .find_one(doc! { "rule_id": rule_id })
+            .await
+            .map_err(|e| format!("Failed to get quality rule: {}", e))
+    }
+
+    pub async fn delete_rule(&self, rule_id: &str) -> Result<(), String> {
+        self.db
+            .quality_rules_collection()
+            .delete_one(doc! { "rule_id": rule_id })
+            .await
+            .map_err(|e| format!("Failed to delete quality rule: {}", e))?;
+        Ok(())
+    }
+
+    /// Profile `rows`, evaluate the table's rules and store the run with any
+    /// regressions against the previous run
+    pub async fn run(
+        &self,
+        dataset: &Dataset,
+        user_id: &str,
+        rows: Arc<Vec<Vec<Value>>>,
+    ) -> Result<QualityRun, String> {
+        let rules = self.list_rules(&dataset.project_id, &dataset.name).await?;
+        let previous = self.latest_run(&dataset.project_id, &dataset.name).await?;
+
+        let columns = dataset.columns.clone();
+        let (profiles, mut results) = tokio::task::spawn_blocking(move || {
+            let profiles = profiling::profile_columns(&columns, &rows);
+            let results: Vec<QualityRuleResult> = rules
+                .iter()
+                .map(|rule| {
+                    let cells: Option<Vec<&Value>> = columns
+                        .iter()
+                        .position(|c| c.name == rule.column)
+                        .map(|i| rows.iter().map(|r| &r[i]).collect());
+                    evaluate(rule, cells.as_deref())
+                })
+                .collect();
+            (profiles, results)
+        })
+        .await
+        .map_err(|_| "Failed to check dataset quality".to_string())?;
+
+        let regressions = regressions(previous.as_ref(), &profiles, &mut results);
+        let run = QualityRun {
+            id: None,
+            run_id: Uuid::new_v4().to_string(),
+            project_id: dataset.project_id.clone(),
+            dataset_id: dataset.dataset_id.clone(),
+            dataset_name: dataset.name.clone(),
+            row_count: dataset.row_count,
+            passed: results.iter().all(|r| r.passed),
+            profiles,
+            results,
+            regressions,
+            triggered_by: user_id.to_string(),
+            created_at: DateTime::now(),
+        };
+        self.db
+            .quality_runs_collection()
+            .insert_one(&run)
+            .await
+            .map_err(|e| format!("Failed to store quality run: {}", e))?;
+
+        if !run.regressions.is_empty() {
+            log::warn!(
+                "Quality run {} of dataset {} flagged {} regression(s)",
+                run.run_id,
+                run.dataset_id,
+                run.regressions.len()
+            );
+        }
+        Ok(run)
+    }
+
+    /// Run the checks in the background, as done for each upload
+    pub fn spawn_run(self: &Arc<Self>, dataset: Dataset, user_id: &str, rows: Arc<Vec<Vec<Value>>>) {
+        let service = Arc::clone(self);
+        let user_id = user_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = service.run(&dataset, &user_id, rows).await {
+                log::warn!("Failed to check quality of dataset {}: {}", dataset.dataset_id, e);
+            }
+        });
+    }
+
+    /// Runs of a table, newest first
+    pub async fn list_runs(
+        &self,
+        project_id: &str,
+        dataset_name: &str,
+        limit: Option<i64>,
+        regressed_only: bool,
+    ) -> Result<Vec<QualityRun>, String> {
+        let mut filter = doc! { "project_id": project_id, "dataset_name": dataset_name };
+        if regressed_only {
+            filter.insert("regressions.0", doc! { "$exists": true });
+        }
+        self.db
+            .quality_runs_collection()
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(limit.unwrap_or(DEFAULT_RUN_LIMIT).clamp(1, MAX_RUN_LIMIT))
+            .await
+            .map_err(|e| format!("Failed to get quality runs: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect quality runs: {}", e))
+    }
+
+    async fn latest_run(&self, project_id: &str, dataset_name: &str) -> Result<Option<QualityRun>, String> {
+        self.db
+            .quality_runs_collection()
+            .find_one(doc! { "project_id": project_id, "dataset_name": dataset_name })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(|e| format!("Failed to get quality run: {}", e))
+    }
+}
+
+/// `pattern` anchored to match a whole value
+fn full_match(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+fn describe_check(check: &QualityCheck) -> String {
+    match check {
+        QualityCheck::NotNull => "not null".to_string(),
+        QualityCheck::Unique => "unique".to_string(),
+        QualityCheck::Range { min, max } => format!(
+            "range {} to {}",
+            min.map(|v| v.to_string()).unwrap_or_else(|| "-inf".to_string()),
+            max.map(|v| v.to_string()).unwrap_or_else(|| "inf".to_string())
+        ),
+        QualityCheck::Regex { pattern } => format!("pattern /{}/", pattern),
+    }
+}
+
+fn percent(rate: f64) -> String {
+    format!("{:.1}%", rate * 100.0)
+}
+
+/// Evaluate `rule` on the cells of its column, `None` when the column is gone
+pub fn evaluate(rule: &QualityRule, cells: Option<&[&Value]>) -> QualityRuleResult {
+    let mut result = QualityRuleResult {
+        rule_id: rule.rule_id.clone(),
+        column: rule.column.clone(),
+        check: rule.check.clone(),
+        checked: 0,
+        failed: 0,
+        failure_rate: 0.0,
+        passed: false,
+        examples: vec![],
+        regressed: false,
+    };
+    let Some(cells) = cells else {
+        result.failure_rate = 1.0;
+        result.examples.push(format!("Column '{}' is missing", rule.column));
+        return result;
+    };
+
+    let values: Vec<&Value> = match rule.check {
+        // Empty cells are what not-null checks; the other checks skip them
+        QualityCheck::NotNull => cells.to_vec(),
+        _ => cells.iter().copied().filter(|v| !is_empty(v)).collect(),
+    };
+    let pattern = match rule.check {
+        QualityCheck::Regex { ref pattern } => full_match(pattern).ok(),
+        _ => None,
+    };
+
+    let mut seen = HashSet::new();
+    let mut failures = Vec::new();
+    for value in &values {
+        let failed = match rule.check {
+            QualityCheck::NotNull => is_empty(value),
+            QualityCheck::Unique => !seen.insert(profiling::cell_text(value)),
+            QualityCheck::Range { min, max } => match profiling::numeric_value(value) {
+                Some(n) => min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max),
+                None => true,
+            },
+            QualityCheck::Regex { .. } => !pattern
+                .as_ref()
+                .is_some_and(|p| p.is_match(&profiling::cell_text(value))),
+        };
+        if failed {
+            failures.push(*value);
+        }
+    }
+
+    result.checked = values.len() as i64;
+    result.failed = failures.len() as i64;
+    if !values.is_empty() {
+        result.failure_rate = stats::round(failures.len() as f64 / values.len() as f64, 4);
+    }
+    result.passed = result.failure_rate <= rule.max_failure_rate;
+    result.examples = failures
+        .iter()
+        .map(|v| if v.is_null() { "null".to_string() } else { profiling::cell_text(v) })
+        .collect::<Vec<_>>();
+    result.examples.dedup();
+    result.examples.truncate(FAILURE_EXAMPLES);
+    result
+}
+
+/// What got worse since `previous`: rules that started failing or fail more,
+/// columns with more empty values, a different semantic type, or gone.
+/// Regressed rule results are marked as such.
+pub fn regressions(
+    previous: Option<&QualityRun>,
+    profiles: &[ColumnProfile],
+    results: &mut [QualityRuleResult],
+) -> Vec<QualityRegression> {
+    let Some(previous) = previous else {
+        return vec![];
+    };
+    let mut regressions = Vec::new();
+
+    for result in results.iter_mut() {
+        let Some(before) = previous.results.iter().find(|r| r.rule_id == result.rule_id) else {
+            continue;
+        };
+        let message = if before.passed && !result.passed {
+            format!(
+                "Check '{}' on '{}' now fails for {} of values (was {})",
+                describe_check(&result.check),
+                result.column,
+                percent(result.failure_rate),
+                percent(before.failure_rate)
+            )
+        } else if !result.passed && result.failure_rate > before.failure_rate {
+            format!(
+                "Check '{}' on '{}' fails more often: {} of values (was {})",
+                describe_check(&result.check),
+                result.column,
+                percent(result.failure_rate),
+                percent(before.failure_rate)
+            )
+        } else {
+            continue;
+        };
+        result.regressed = true;
+        regressions.push(QualityRegression {
+            column: result.column.clone(),
+            rule_id: Some(result.rule_id.clone()),
+            message,
+        });
+    }
+
+    for before in &previous.profiles {
+        let Some(profile) = profiles.iter().find(|p| p.column == before.column) else {
+            regressions.push(QualityRegression {
+                column: before.column.clone(),
+                rule_id: None,
+                message: format!("Column '{}' is no longer present", before.column),
+            });
+            continue;
+        };
+        if profile.null_rate - before.null_rate >= NULL_RATE_REGRESSION {
+            regressions.push(QualityRegression {
+                column: profile.column.clone(),
+                rule_id: None,
+                message: format!(
+                    "Empty values in '{}' rose to {} (was {})",
+                    profile.column,
+                    percent(profile.null_rate),
+                    percent(before.null_rate)
+                ),
+            });
+        }
+        if profile.semantic_type != before.semantic_type {
+            regressions.push(QualityRegression {
+                column: profile.column.clone(),
+                rule_id: None,
+                message: format!(
+                    "'{}' looks like {} now (was {})",
+                    profile.column,
+                    profile.semantic_type.as_str(),
+                    before.semantic_type.as_str()
+                ),
+            });
+        }
+    }
+    regressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::services::DatasetService;
+
+    fn rule(column: &str, check: QualityCheck, max_failure_rate: f64) -> QualityRule {
+        QualityRule {
+            id: None,
+            rule_id: format!("{}-rule", column),
+            project_id: "p".to_string(),
+            dataset_name: "orders".to_string(),
+            column: column.to_string(),
+            check,
+            max_failure_rate,
+            created_by: "u".to_string(),
+            created_at: DateTime::now(),
+        }
+    }
+
+    #[test]
+    fn evaluates_rules() {
+        let values = [json!("A-1"), json!("A-2"), json!(null), json!("A-2"), json!("B3")];
+        let cells: Vec<&Value> = values.iter().collect();
+
+        let not_null = evaluate(&rule("code", QualityCheck::NotNull, 0.0), Some(&cells));
+        assert_eq!((not_null.checked, not_null.failed, not_null.passed), (5, 1, false));
+        assert_eq!(not_null.examples, vec!["null"]);
+
+        let unique = evaluate(&rule("code", QualityCheck::Unique, 0.25), Some(&cells));
+        assert_eq!((unique.checked, unique.failed, unique.failure_rate, unique.passed), (4, 1, 0.25, true));
+
+        let pattern = QualityCheck::Regex { pattern: "[A-Z]-\\d+".to_string() };
+        let regex = evaluate(&rule("code", pattern, 0.0), Some(&cells));
+        assert_eq!((regex.failed, regex.examples.clone()), (1, vec!["B3".to_string()]));
+
+        let amounts = [json!(5), json!("$12.50"), json!(-1), json!("n/a")];
+        let amounts: Vec<&Value> = amounts.iter().collect();
+        let range = evaluate(&rule("amount", QualityCheck::Range { min: Some(0.0), max: Some(100.0) }, 0.0), Some(&amounts));
+        assert_eq!((range.failed, range.examples.clone()), (2, vec!["-1".to_string(), "n/a".to_string()]));
+
+        let missing = evaluate(&rule("gone", QualityCheck::NotNull, 0.0), None);
+        assert!(!missing.passed);
+    }
+
+    #[test]
+    fn flags_regressions_against_previous_run() {
+        let (columns, rows) = DatasetService::parse_table("orders.csv", b"region,price\nNorth,$10\nSouth,$20\nNorth,$30\nWest,$40\n").unwrap();
+        let before = profiling::profile_columns(&columns, &rows);
+        let (columns, rows) = DatasetService::parse_table("orders.csv", b"price,qty\n$10,1\n,2\nabc,3\ndef,4\n").unwrap();
+        let after = profiling::profile_columns(&columns, &rows);
+
+        let price_rule = rule("price", QualityCheck::NotNull, 0.0);
+        let cells: Vec<&Value> = rows.iter().map(|r| &r[0]).collect();
+        let previous = QualityRun {
+            id: None,
+            run_id: "r1".to_string(),
+            project_id: "p".to_string(),
+            dataset_id: "d1".to_string(),
+            dataset_name: "orders".to_string(),
+            row_count: 4,
+            profiles: before,
+            results: vec![QualityRuleResult { passed: true, failed: 0, failure_rate: 0.0, ..evaluate(&price_rule, Some(&cells)) }],
+            regressions: vec![],
+            passed: true,
+            triggered_by: "u".to_string(),
+            created_at: DateTime::now(),
+        };
+
+        let mut results = vec![evaluate(&price_rule, Some(&cells))];
+        let found = regressions(Some(&previous), &after, &mut results);
+        let messages: Vec<&str> = found.iter().map(|r| r.message.as_str()).collect();
+
+        assert!(results[0].regressed);
+        assert_eq!(
+            messages,
+            vec![
+                "Check 'not null' on 'price' now fails for 25.0% of values (was 0.0%)",
+                "Column 'region' is no longer present",
+                "Empty values in 'price' rose to 25.0% (was 0.0%)",
+                "'price' looks like text now (was currency)",
+            ]
+        );
+        assert!(regressions(None, &after, &mut results).is_empty());
+    }
+}
@@ -584,6 +584,94 @@ impl RbacService {
             .map_err(|e| format!("Failed to collect memberships: {}", e))
     }
 
+    /// List the projects in a tenant on which the user holds the given permission.
+    /// Candidates are every tenant project for admins, otherwise the user's memberships
+    /// and owned projects; each is then checked through `resolve_permissions`.
+    pub async fn accessible_project_ids(
+        &self,
+        user_id: &str,
+        tenant_id: &str,
+        permission: Permission,
+    ) -> Result<Vec<String>, String> {
+        use futures::TryStreamExt;
+
+        let user = self.get_user(user_id).await?;
+
+        let filter = if user.role == "admin" {
+            doc! { "tenant_id": tenant_id }
+        } else {
+            let member_of: Vec<String> = self
+                .get_user_memberships(user_id)
+                .await?
+                .into_iter()
+                .map(|m| m.project_id)
+                .collect();
+            doc! {
+                "tenant_id": tenant_id,
+                "$or": [
+                    { "owner_id": user_id },
+                    { "member_ids": user_id },
+                    { "project_id": { "$in": member_of } },
+                ]
+            }
+        };
+
+        let projects: Vec<crate::models::Project> = self.db
+            .projects_collection()
+            .find(filter)
+            .await
+            .map_err(|e| format!("Failed to get projects: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect projects: {}", e))?;
+
+        let mut project_ids = Vec::new();
+        for project in projects {
+            let resolved = self.resolve_permissions(user_id, Some(&project.project_id)).await?;
+            if resolved.has_permission(permission) {
+                project_ids.push(project.project_id);
+            }
+        }
+
+        Ok(project_ids)
+    }
+
+    /// List the users of a project who hold the given permission on it: the owner,
+    /// listed members and role members, each checked through `resolve_permissions`.
+    pub async fn project_users_with(
+        &self,
+        project_id: &str,
+        permission: Permission,
+    ) -> Result<Vec<String>, String> {
+        let mut candidates: Vec<String> = Vec::new();
+        if let Some(project) = self.get_project(project_id).await? {
+            candidates.push(project.owner_id);
+            candidates.extend(project.member_ids);
+        }
+        candidates.extend(
+            self.get_project_memberships(project_id)
+                .await?
+                .into_iter()
+                .map(|m| m.user_id),
+        );
+
+        let mut seen = HashSet::new();
+        let mut user_ids = Vec::new();
+        for user_id in candidates {
+            if !seen.insert(user_id.clone()) {
+                continue;
+            }
+            match self.resolve_permissions(&user_id, Some(project_id)).await {
+                Ok(resolved) if resolved.has_permission(permission) => user_ids.push(user_id),
+                Ok(_) => {}
+                // Deleted users stay listed on projects
+                Err(e) => log::debug!("Skipping user {} of project {}: {}", user_id, project_id, e),
+            }
+        }
+
+        Ok(user_ids)
+    }
+
     // ========================================================================
     // System Role Initialization
     // ========================================================================
@@ -0,0 +1,592 @@
+//! Removal of personal data from prompts before they reach an AI provider.
+//!
+//! A [`Redactor`] replaces emails, phone numbers, card numbers, national IDs and
+//! tenant-defined patterns with numbered placeholders such as `[EMAIL_1]`, and
+//! puts the original values back into the reply. A value keeps its placeholder
+//! for the whole request so the model can still refer to it. Values are never
+//! stored or logged; the audit trail records how many of each kind were replaced.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use futures::StreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::ReplaceOptions;
+use regex::{Regex, RegexBuilder};
+use serde_json::json;
+use crate::db::DatabaseManager;
+use crate::models::{
+    CustomRedactionPattern, PiiCategory, RedactionSettings, RedactionSettingsResponse,
+    UpdateRedactionSettingsDto,
+};
+use crate::services::audit::{AuditEvent, AuditService};
+use crate::services::llm::{ChatEventStream, ChatStreamEvent};
+
+/// Audit action recorded when a prompt had values replaced
+pub const PII_REDACTED: &str = "pii.redacted";
+
+/// Audit action recorded when a tenant's settings change
+pub const REDACTION_SETTINGS_UPDATED: &str = "redaction.settings_updated";
+
+/// Longest placeholder; streamed text ending in a `[` closer than this is held back
+const MAX_PLACEHOLDER_LEN: usize = 48;
+
+/// Compiled size limit for tenant patterns, so one pattern can't exhaust memory
+const CUSTOM_PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Clone)]
+struct Rule {
+    label: String,
+    regex: Regex,
+    /// Further check on a match, e.g. the card number checksum
+    accept: fn(&str) -> bool,
+}
+
+fn any_match(_: &str) -> bool {
+    true
+}
+
+fn digit_count(text: &str) -> usize {
+    text.chars().filter(char::is_ascii_digit).count()
+}
+
+fn phone_digits(text: &str) -> bool {
+    (9..=15).contains(&digit_count(text))
+}
+
+/// Card numbers have 13 to 19 digits and pass the Luhn check
+fn luhn_valid(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn builtin_rule(category: PiiCategory) -> Rule {
+    static RULES: OnceLock<Vec<(PiiCategory, Rule)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        let rule = |category: PiiCategory, pattern: &str, accept: fn(&str) -> bool| {
+            (
+                category,
+                Rule {
+                    label: category.label().to_string(),
+                    regex: Regex::new(pattern).expect("built-in redaction pattern should compile"),
+                    accept,
+                },
+            )
+        };
+        vec![
+            rule(
+                PiiCategory::Email,
+                r"(?i)\b[A-Z0-9._%+-]+@[A-Z0-9-]+(?:\.[A-Z0-9-]+)*\.[A-Z]{2,}\b",
+                any_match,
+            ),
+            rule(PiiCategory::CardNumber, r"\b(?:\d[ -]?){12,18}\d\b", luhn_valid),
+            // US Social Security and UK National Insurance numbers
+            rule(
+                PiiCategory::NationalId,
+                r"\b\d{3}-\d{2}-\d{4}\b|\b[A-CEGHJ-PR-TW-Z]{2} ?\d{2} ?\d{2} ?\d{2} ?[A-D]\b",
+                any_match,
+            ),
+            // International with a country code, North American, or national with a trunk 0
+            rule(
+                PiiCategory::Phone,
+                r"\+\d{1,3}[\s.-]?(?:\(\d{1,4}\)[\s.-]?)?\d{1,4}(?:[\s.-]?\d{2,4}){1,4}\b|\(\d{3}\)\s?\d{3}[\s.-]\d{4}\b|\b\d{3}[\s.-]\d{3}[\s.-]\d{4}\b|\b0\d{2,4}[\s.-]?\d{3,4}[\s.-]?\d{3,4}\b",
+                phone_digits,
+            ),
+        ]
+    });
+    rules
+        .iter()
+        .find(|(c, _)| *c == category)
+        .map(|(_, rule)| rule.clone())
+        .expect("every category has a built-in rule")
+}
+
+/// Placeholder label for a tenant pattern name: `employee id` becomes `EMPLOYEE_ID`
+fn custom_label(name: &str) -> Option<String> {
+    let label: String = name
+        .trim()
+        .to_uppercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let label = label.trim_matches('_').to_string();
+    label.starts_with(|c: char| c.is_ascii_alphabetic()).then_some(label)
+}
+
+fn compile_custom(pattern: &CustomRedactionPattern) -> Result<Rule, String> {
+    let label = custom_label(&pattern.name)
+        .ok_or_else(|| format!("Pattern name '{}' must start with a letter", pattern.name))?;
+    let regex = RegexBuilder::new(&pattern.pattern)
+        .size_limit(CUSTOM_PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern '{}': {}", pattern.name, e))?;
+    Ok(Rule { label, regex, accept: any_match })
+}
+
+/// Built-in categories from most to least specific, so a card number is never taken for a phone number
+const PRIORITY: [PiiCategory; 4] = [
+    PiiCategory::Email,
+    PiiCategory::CardNumber,
+    PiiCategory::NationalId,
+    PiiCategory::Phone,
+];
+
+/// Rules in priority order: built-in categories first, then tenant patterns
+fn rules(categories: &[PiiCategory], custom_patterns: &[CustomRedactionPattern]) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = PRIORITY
+        .into_iter()
+        .filter(|c| categories.contains(c))
+        .map(builtin_rule)
+        .collect();
+    for pattern in custom_patterns {
+        match compile_custom(pattern) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => log::warn!("Skipping redaction pattern: {}", e),
+        }
+    }
+    rules
+}
+
+fn placeholder_regex() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\[[A-Z][A-Z0-9_]*_\d+\]").expect("placeholder pattern should compile"))
+}
+
+/// Who a redactor works for, for the audit trail
+#[derive(Debug, Clone)]
+struct RedactionScope {
+    tenant_id: String,
+    project_id: String,
+    user_id: String,
+    operation: &'static str,
+}
+
+/// Values replaced so far in one request
+#[derive(Default)]
+struct Vault {
+    placeholders: HashMap<String, String>,
+    values: HashMap<String, String>,
+    last_number: HashMap<String, usize>,
+    /// Replacements per label since the last audit
+    unaudited: BTreeMap<String, usize>,
+}
+
+impl Vault {
+    fn placeholder(&mut self, label: &str, value: &str) -> String {
+        *self.unaudited.entry(label.to_string()).or_default() += 1;
+        if let Some(placeholder) = self.placeholders.get(value) {
+            return placeholder.clone();
+        }
+        let number = self.last_number.entry(label.to_string()).or_default();
+        *number += 1;
+        let placeholder = format!("[{}_{}]", label, number);
+        self.placeholders.insert(value.to_string(), placeholder.clone());
+        self.values.insert(placeholder.clone(), value.to_string());
+        placeholder
+    }
+}
+
+/// Redaction for one request. Clones share the same placeholders.
+#[derive(Clone)]
+pub struct Redactor {
+    rules: Arc<Vec<Rule>>,
+    vault: Arc<Mutex<Vault>>,
+    scope: Option<RedactionScope>,
+}
+
+impl Redactor {
+    fn new(rules: Vec<Rule>, scope: Option<RedactionScope>) -> Self {
+        Redactor {
+            rules: Arc::new(rules),
+            vault: Arc::new(Mutex::new(Vault::default())),
+            scope,
+        }
+    }
+
+    /// Redactor that leaves text unchanged
+    pub fn disabled() -> Self {
+        Self::new(vec![], None)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    fn vault(&self) -> MutexGuard<'_, Vault> {
+        self.vault.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace every match with its placeholder. Where matches overlap, the
+    /// higher-priority rule wins.
+    pub fn redact(&self, text: &str) -> String {
+        if !self.is_enabled() {
+            return text.to_string();
+        }
+
+        let mut accepted: Vec<(usize, usize, usize)> = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            for m in rule.regex.find_iter(text) {
+                let overlaps = accepted.iter().any(|&(start, end, _)| m.start() < end && start < m.end());
+                if !m.is_empty() && !overlaps && (rule.accept)(m.as_str()) {
+                    accepted.push((m.start(), m.end(), index));
+                }
+            }
+        }
+        if accepted.is_empty() {
+            return text.to_string();
+        }
+        accepted.sort_unstable();
+
+        let mut vault = self.vault();
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end, index) in accepted {
+            redacted.push_str(&text[last..start]);
+            redacted.push_str(&vault.placeholder(&self.rules[index].label, &text[start..end]));
+            last = end;
+        }
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+
+    /// Put the original values back in place of known placeholders
+    pub fn restore(&self, text: &str) -> String {
+        let vault = self.vault();
+        if vault.values.is_empty() || !text.contains('[') {
+            return text.to_string();
+        }
+        placeholder_regex()
+            .replace_all(text, |caps: &regex::Captures| {
+                vault.values.get(&caps[0]).cloned().unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Restore placeholders in streamed text and tool call arguments. Text that may
+    /// end in part of a placeholder is held back until the next chunk completes it.
+    pub fn restore_stream(&self, stream: ChatEventStream) -> ChatEventStream {
+        if !self.is_enabled() {
+            return stream;
+        }
+
+        let redactor = self.clone();
+        let mut stream = stream;
+        Box::pin(async_stream::stream! {
+            let mut pending = String::new();
+
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(ChatStreamEvent::Content(text)) => {
+                        pending.push_str(&text);
+                        let ready = Self::complete_prefix(&pending);
+                        if ready > 0 {
+                            let rest = pending.split_off(ready);
+                            let done = std::mem::replace(&mut pending, rest);
+                            yield Ok(ChatStreamEvent::Content(redactor.restore(&done)));
+                        }
+                    }
+                    other => {
+                        if !pending.is_empty() {
+                            let done = std::mem::take(&mut pending);
+                            yield Ok(ChatStreamEvent::Content(redactor.restore(&done)));
+                        }
+                        yield match other {
+                            Ok(ChatStreamEvent::ToolCall(mut call)) => {
+                                call.arguments = redactor.restore(&call.arguments);
+                                Ok(ChatStreamEvent::ToolCall(call))
+                            }
+                            other => other,
+                        };
+                    }
+                }
+            }
+
+            if !pending.is_empty() {
+                yield Ok(ChatStreamEvent::Content(redactor.restore(&pending)));
+            }
+        })
+    }
+
+    /// Length of the prefix of `text` that cannot be the start of a split placeholder
+    fn complete_prefix(text: &str) -> usize {
+        match text.rfind('[') {
+            Some(open) if !text[open..].contains(']') && text.len() - open < MAX_PLACEHOLDER_LEN => open,
+            _ => text.len(),
+        }
+    }
+
+    /// Replacements per label since the last call
+    fn take_counts(&self) -> BTreeMap<String, usize> {
+        std::mem::take(&mut self.vault().unaudited)
+    }
+}
+
+/// Per-tenant redaction settings and the redactors built from them
+pub struct RedactionService {
+    db: DatabaseManager,
+    audit_service: Arc<AuditService>,
+    /// Whether tenants without their own settings redact every built-in category
+    default_enabled: bool,
+    /// Tenant of each project seen so far; projects never move between tenants
+    tenants: RwLock<HashMap<String, String>>,
+}
+
+impl RedactionService {
+    pub fn new(db: DatabaseManager, audit_service: Arc<AuditService>, default_enabled: bool) -> Self {
+        RedactionService {
+            db,
+            audit_service,
+            default_enabled,
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    async fn tenant_for_project(&self, project_id: &str) -> Result<String, String> {
+        if let Some(tenant) = self.tenants.read().ok().and_then(|t| t.get(project_id).cloned()) {
+            return Ok(tenant);
+        }
+
+        let project = self.db
+            .projects_collection()
+            .find_one(doc! { "project_id": project_id })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| "Project not found".to_string())?;
+
+        if let Ok(mut tenants) = self.tenants.write() {
+            tenants.insert(project_id.to_string(), project.tenant_id.clone());
+        }
+        Ok(project.tenant_id)
+    }
+
+    async fn stored_settings(&self, tenant_id: &str) -> Result<Option<RedactionSettings>, String> {
+        self.db
+            .redaction_settings_collection()
+            .find_one(doc! { "tenant_id": tenant_id })
+            .await
+            .map_err(|e| format!("Failed to load redaction settings: {}", e))
+    }
+
+    /// Redactor for one AI request made by `user_id` in `project_id`. If the
+    /// tenant's settings can't be loaded the configured default applies.
+    pub async fn redactor(&self, project_id: &str, user_id: &str, operation: &'static str) -> Redactor {
+        let default_rules = || {
+            if self.default_enabled {
+                rules(&PiiCategory::all(), &[])
+            } else {
+                vec![]
+            }
+        };
+
+        let tenant_id = match self.tenant_for_project(project_id).await {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("Redaction for project {} uses the default settings: {}", project_id, e);
+                return Redactor::new(default_rules(), None);
+            }
+        };
+
+        let rules = match self.stored_settings(&tenant_id).await {
+            Ok(Some(settings)) if settings.enabled => rules(&settings.categories, &settings.custom_patterns),
+            Ok(Some(_)) => vec![],
+            Ok(None) => default_rules(),
+            Err(e) => {
+                log::warn!("Redaction for tenant {} uses the default settings: {}", tenant_id, e);
+                default_rules()
+            }
+        };
+
+        Redactor::new(
+            rules,
+            Some(RedactionScope {
+                tenant_id,
+                project_id: project_id.to_string(),
+                user_id: user_id.to_string(),
+                operation,
+            }),
+        )
+    }
+
+    /// Record what `redactor` has replaced since it was last audited
+    pub async fn audit(&self, redactor: &Redactor) {
+        let counts = redactor.take_counts();
+        if counts.is_empty() {
+            return;
+        }
+        let Some(scope) = redactor.scope.clone() else {
+            log::warn!("Redacted {:?} without a tenant to audit against", counts);
+            return;
+        };
+
+        self.audit_service
+            .record(AuditEvent {
+                tenant_id: scope.tenant_id,
+                project_id: Some(scope.project_id),
+                user_id: scope.user_id,
+                action: PII_REDACTED,
+                details: json!({ "operation": scope.operation, "counts": counts }),
+            })
+            .await;
+    }
+
+    pub async fn get_settings(&self, tenant_id: &str) -> Result<RedactionSettingsResponse, String> {
+        Ok(match self.stored_settings(tenant_id).await? {
+            Some(settings) => Self::response(settings),
+            None => RedactionSettingsResponse {
+                tenant_id: tenant_id.to_string(),
+                enabled: self.default_enabled,
+                categories: PiiCategory::all(),
+                custom_patterns: vec![],
+                source: "default".to_string(),
+                updated_by: None,
+                updated_at: None,
+            },
+        })
+    }
+
+    /// Replace a tenant's settings. Custom patterns must compile.
+    pub async fn update_settings(
+        &self,
+        tenant_id: &str,
+        updated_by: &str,
+        dto: UpdateRedactionSettingsDto,
+    ) -> Result<RedactionSettingsResponse, String> {
+        for pattern in &dto.custom_patterns {
+            compile_custom(pattern)?;
+        }
+
+        let mut categories = dto.categories.unwrap_or_else(PiiCategory::all);
+        categories.dedup();
+        let settings = RedactionSettings {
+            id: None,
+            tenant_id: tenant_id.to_string(),
+            enabled: dto.enabled,
+            categories,
+            custom_patterns: dto.custom_patterns,
+            updated_by: updated_by.to_string(),
+            updated_at: DateTime::now(),
+        };
+
+        self.db
+            .redaction_settings_collection()
+            .replace_one(doc! { "tenant_id": tenant_id }, &settings)
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await
+            .map_err(|e| format!("Failed to save redaction settings: {}", e))?;
+
+        self.audit_service
+            .record(AuditEvent {
+                tenant_id: tenant_id.to_string(),
+                project_id: None,
+                user_id: updated_by.to_string(),
+                action: REDACTION_SETTINGS_UPDATED,
+                details: json!({
+                    "enabled": settings.enabled,
+                    "categories": &settings.categories,
+                    "custom_patterns": settings.custom_patterns.iter().map(|p| &p.name).collect::<Vec<_>>(),
+                }),
+            })
+            .await;
+
+        Ok(Self::response(settings))
+    }
+
+    fn response(settings: RedactionSettings) -> RedactionSettingsResponse {
+        RedactionSettingsResponse {
+            tenant_id: settings.tenant_id,
+            enabled: settings.enabled,
+            categories: settings.categories,
+            custom_patterns: settings.custom_patterns,
+            source: "override".to_string(),
+            updated_by: Some(settings.updated_by),
+            updated_at: Some(settings.updated_at.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(custom: &[(&str, &str)]) -> Redactor {
+        let custom: Vec<CustomRedactionPattern> = custom
+            .iter()
+            .map(|(name, pattern)| CustomRedactionPattern {
+                name: name.to_string(),
+                pattern: pattern.to_string(),
+            })
+            .collect();
+        Redactor::new(rules(&PiiCategory::all(), &custom), None)
+    }
+
+    #[test]
+    fn replaces_builtin_categories_and_restores_them() {
+        let r = redactor(&[]);
+        let text = "Mail jane.doe@example.com or call +44 20 7946 0958 / (555) 123-4567. \
+                    Card 4111 1111 1111 1111, SSN 123-45-6789.";
+        let redacted = r.redact(text);
+
+        assert_eq!(
+            redacted,
+            "Mail [EMAIL_1] or call [PHONE_1] / [PHONE_2]. Card [CARD_1], SSN [NATIONAL_ID_1]."
+        );
+        assert_eq!(r.restore(&redacted), text);
+    }
+
+    #[test]
+    fn repeated_values_share_a_placeholder() {
+        let r = redactor(&[]);
+        assert_eq!(r.redact("a@x.io, b@x.io"), "[EMAIL_1], [EMAIL_2]");
+        assert_eq!(r.redact("again a@x.io"), "again [EMAIL_1]");
+        assert_eq!(r.take_counts().get("EMAIL"), Some(&3));
+        assert!(r.take_counts().is_empty());
+    }
+
+    #[test]
+    fn ignores_numbers_that_are_not_pii() {
+        let r = redactor(&[]);
+        let text = "Revenue was 1234567 in 2023; order 4111 1111 1111 1112 shipped.";
+        assert_eq!(r.redact(text), text);
+    }
+
+    #[test]
+    fn custom_patterns_use_their_name_and_lose_to_builtins() {
+        let r = redactor(&[("employee id", r"EMP-\d{5}"), ("domain", r"example\.com")]);
+        assert_eq!(
+            r.redact("EMP-00042 wrote to ops@example.com"),
+            "[EMPLOYEE_ID_1] wrote to [EMAIL_1]"
+        );
+        assert!(compile_custom(&CustomRedactionPattern { name: "42".into(), pattern: "x".into() }).is_err());
+        assert!(compile_custom(&CustomRedactionPattern { name: "bad".into(), pattern: "(".into() }).is_err());
+    }
+
+    #[tokio::test]
+    async fn restores_placeholders_split_across_chunks() {
+        let r = redactor(&[]);
+        r.redact("jane@example.com");
+        let chunks = ["Write to [EMA", "IL_1] today", " [see notes]"];
+        let stream: ChatEventStream = Box::pin(futures::stream::iter(
+            chunks.map(|c| Ok(ChatStreamEvent::Content(c.to_string()))),
+        ));
+
+        let mut restored = r.restore_stream(stream);
+        let mut text = String::new();
+        while let Some(Ok(ChatStreamEvent::Content(chunk))) = restored.next().await {
+            assert!(!chunk.contains("[EMA"), "{}", chunk);
+            text.push_str(&chunk);
+        }
+        assert_eq!(text, "Write to jane@example.com today [see notes]");
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::db::DatabaseManager;
+use crate::models::MessageCitation;
+use crate::services::llm::{LlmMessage, LlmResponse};
+use crate::services::KnowledgeService;
+
+/// Questions kept per prompt fingerprint for similarity matching
+const MAX_INDEXED_QUESTIONS: usize = 500;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheSettings {
+    pub ttl_secs: u64,
+    /// Minimum cosine similarity for a differently worded question to reuse an
+    /// answer. Exact matches only when unset.
+    pub similarity_threshold: Option<f64>,
+}
+
+/// Identifies a cacheable request. Everything but the final user message goes into
+/// the fingerprint; the question is compared exactly or by embedding within it.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    project_id: String,
+    fingerprint: String,
+    question: String,
+    exact: String,
+}
+
+impl CacheKey {
+    pub fn new(
+        project_id: &str,
+        provider: &str,
+        model: Option<&str>,
+        temperature: f32,
+        max_tokens: i32,
+        top_k: Option<i32>,
+        messages: &[LlmMessage],
+    ) -> Self {
+        let (question, context) = match messages.split_last() {
+            Some((last, rest)) if last.role == "user" => (Self::normalize_question(&last.content), rest),
+            _ => (String::new(), messages),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}\n{}\n{}\n{}\n{}\n{:?}\n",
+            project_id,
+            provider,
+            model.unwrap_or_default(),
+            temperature,
+            max_tokens,
+            top_k
+        ));
+        for message in context {
+            hasher.update(format!("{}:{}\n", message.role, Self::collapse_whitespace(&message.content)));
+        }
+        let fingerprint = format!("{:x}", hasher.finalize());
+        let exact = format!("{:x}", Sha256::digest(format!("{}\n{}", fingerprint, question)));
+
+        CacheKey {
+            project_id: project_id.to_string(),
+            fingerprint,
+            question,
+            exact,
+        }
+    }
+
+    /// The user's question as compared for cache hits
+    pub fn question(&self) -> &str {
+        &self.question
+    }
+
+    fn collapse_whitespace(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Case, spacing and trailing punctuation don't change the question
+    fn normalize_question(text: &str) -> String {
+        Self::collapse_whitespace(&text.to_lowercase())
+            .trim_end_matches(['?', '.', '!'])
+            .trim_end()
+            .to_string()
+    }
+}
+
+/// Question embedding used for similarity matches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionEmbedding {
+    pub model: String,
+    pub vector: Vec<f32>,
+}
+
+impl QuestionEmbedding {
+    /// Embedding computed without the provider
+    pub fn local(question: &str) -> Self {
+        QuestionEmbedding {
+            model: crate::services::knowledge::LOCAL_EMBEDDING_MODEL.to_string(),
+            vector: KnowledgeService::local_embedding(question),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    content: String,
+    #[serde(default)]
+    citations: Vec<MessageCitation>,
+}
+
+/// Redis-backed cache of AI answers, scoped per project. Cache errors are logged
+/// and treated as misses so the provider still answers.
+pub struct ResponseCache {
+    db: DatabaseManager,
+    settings: CacheSettings,
+}
+
+impl ResponseCache {
+    pub fn new(db: DatabaseManager, settings: CacheSettings) -> Self {
+        ResponseCache { db, settings }
+    }
+
+    /// Whether lookups compare question embeddings
+    pub fn similarity_enabled(&self) -> bool {
+        self.settings.similarity_threshold.is_some()
+    }
+
+    fn entry_key(exact: &str) -> String {
+        format!("ai_cache:entry:{}", exact)
+    }
+
+    fn index_key(fingerprint: &str) -> String {
+        format!("ai_cache:index:{}", fingerprint)
+    }
+
+    fn project_key(project_id: &str) -> String {
+        format!("ai_cache:project:{}", project_id)
+    }
+
+    /// Cached answer for the exact question, or failing that for the most similar
+    /// indexed question above the threshold
+    pub async fn lookup(&self, key: &CacheKey, embedding: Option<&QuestionEmbedding>) -> Option<LlmResponse> {
+        match self.try_lookup(key, embedding).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::warn!("Response cache lookup failed: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn try_lookup(&self, key: &CacheKey, embedding: Option<&QuestionEmbedding>) -> Result<Option<LlmResponse>, String> {
+        if let Some(response) = self.get_entry(&key.exact).await? {
+            return Ok(Some(response));
+        }
+
+        let (threshold, embedding) = match (self.settings.similarity_threshold, embedding) {
+            (Some(t), Some(e)) => (t, e),
+            _ => return Ok(None),
+        };
+
+        let mut redis = self.db.redis.as_ref().clone();
+        let index: HashMap<String, String> = redis
+            .hgetall(Self::index_key(&key.fingerprint))
+            .await
+            .map_err(|e| format!("Failed to read cache index: {}", e))?;
+
+        let best = index
+            .iter()
+            .filter_map(|(exact, value)| {
+                let indexed: QuestionEmbedding = serde_json::from_str(value).ok()?;
+                if indexed.model != embedding.model {
+                    return None;
+                }
+                let score = KnowledgeService::cosine_similarity(&indexed.vector, &embedding.vector);
+                (score >= threshold).then_some((exact, score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let Some((exact, score)) = best else {
+            return Ok(None);
+        };
+
+        match self.get_entry(exact).await? {
+            Some(response) => {
+                log::debug!("Response cache similarity hit ({:.3})", score);
+                Ok(Some(response))
+            }
+            None => {
+                // The answer expired before its index entry
+                let _: () = redis
+                    .hdel(Self::index_key(&key.fingerprint), exact)
+                    .await
+                    .map_err(|e| format!("Failed to prune cache index: {}", e))?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_entry(&self, exact: &str) -> Result<Option<LlmResponse>, String> {
+        let mut redis = self.db.redis.as_ref().clone();
+        let value: Option<String> = redis
+            .get(Self::entry_key(exact))
+            .await
+            .map_err(|e| format!("Failed to read cached response: {}", e))?;
+
+        Ok(value
+            .and_then(|v| serde_json::from_str::<CachedResponse>(&v).ok())
+            .map(|cached| LlmResponse {
+                content: cached.content,
+                citations: cached.citations,
+                usage: None,
+                cached: true,
+            }))
+    }
+
+    /// Store an answer, indexing its question embedding when given
+    pub async fn store(&self, key: &CacheKey, response: &LlmResponse, embedding: Option<&QuestionEmbedding>) {
+        if let Err(e) = self.try_store(key, response, embedding).await {
+            log::warn!("Failed to cache AI response: {}", e);
+        }
+    }
+
+    async fn try_store(&self, key: &CacheKey, response: &LlmResponse, embedding: Option<&QuestionEmbedding>) -> Result<(), String> {
+        let mut redis = self.db.redis.as_ref().clone();
+        let ttl = self.settings.ttl_secs;
+        let entry_key = Self::entry_key(&key.exact);
+        let project_key = Self::project_key(&key.project_id);
+
+        let value = serde_json::to_string(&CachedResponse {
+            content: response.content.clone(),
+            citations: response.citations.clone(),
+        })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+        let _: () = redis
+            .set_ex(&entry_key, value, ttl)
+            .await
+            .map_err(|e| format!("Failed to store response: {}", e))?;
+        let _: () = redis
+            .sadd(&project_key, &entry_key)
+            .await
+            .map_err(|e| format!("Failed to track cached response: {}", e))?;
+
+        if let (Some(embedding), true) = (embedding, self.similarity_enabled()) {
+            let index_key = Self::index_key(&key.fingerprint);
+            let size: usize = redis
+                .hlen(&index_key)
+                .await
+                .map_err(|e| format!("Failed to read cache index: {}", e))?;
+            if size < MAX_INDEXED_QUESTIONS {
+                let value = serde_json::to_string(embedding)
+                    .map_err(|e| format!("Failed to serialize embedding: {}", e))?;
+                let _: () = redis
+                    .hset(&index_key, &key.exact, value)
+                    .await
+                    .map_err(|e| format!("Failed to index cached response: {}", e))?;
+                let _: () = redis
+                    .expire(&index_key, ttl as i64)
+                    .await
+                    .map_err(|e| format!("Failed to set cache index expiry: {}", e))?;
+                let _: () = redis
+                    .sadd(&project_key, &index_key)
+                    .await
+                    .map_err(|e| format!("Failed to track cache index: {}", e))?;
+            }
+        }
+
+        let _: () = redis
+            .expire(&project_key, ttl as i64)
+            .await
+            .map_err(|e| format!("Failed to set cache expiry: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Drop every cached answer for a project, returning how many keys were removed
+    pub async fn invalidate_project(&self, project_id: &str) -> Result<u64, String> {
+        let mut redis = self.db.redis.as_ref().clone();
+        let project_key = Self::project_key(project_id);
+
+        let mut keys: Vec<String> = redis
+            .smembers(&project_key)
+            .await
+            .map_err(|e| format!("Failed to read cached responses: {}", e))?;
+        keys.push(project_key);
+
+        let removed: u64 = redis
+            .del(&keys)
+            .await
+            .map_err(|e| format!("Failed to invalidate cached responses: {}", e))?;
+
+        // The tracking set itself is not an answer
+        Ok(removed.saturating_sub(1))
+    }
+}
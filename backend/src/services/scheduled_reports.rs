@@ -0,0 +1,338 @@
+//! Anomaly checks that run on a schedule.
+//!
+//! A background loop picks up reports that are due, claims each one by moving
+//! its next run forward (so only one server instance runs it), checks its
+//! dataset table, query or saved query, and notifies the project's `report:read` users
+//! about anomalies that were not reported before.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, DateTime};
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{
+    AnomalyDirection, AnomalyPoint, CreateScheduledReportDto, NotificationKind, Permission, ScheduledReport,
+};
+use crate::services::anomaly::AnomalySource;
+use crate::services::{AnomalyService, NotificationService, RbacService};
+
+/// Anomalies remembered per report as already notified
+const MAX_NOTIFIED: usize = 1000;
+/// Anomalies listed in a notification
+const NOTIFIED_EXAMPLES: usize = 5;
+
+/// Key of an anomaly that stays the same across runs
+fn anomaly_key(point: &AnomalyPoint) -> String {
+    let direction = match point.direction {
+        AnomalyDirection::Above => "above",
+        AnomalyDirection::Below => "below",
+    };
+    format!("{}|{}", point.label, direction)
+}
+
+/// Notification text for the new anomalies of a report
+fn notification_text(report: &ScheduledReport, anomalies: &[&AnomalyPoint]) -> (String, String) {
+    let title = match anomalies.len() {
+        1 => format!("1 new anomaly in {}", report.name),
+        n => format!("{} new anomalies in {}", n, report.name),
+    };
+    let mut lines: Vec<String> = anomalies
+        .iter()
+        .take(NOTIFIED_EXAMPLES)
+        .map(|a| {
+            format!(
+                "{} {}: {} is {} the expected {} ({:?} severity)",
+                report.detection.column,
+                a.label,
+                a.value,
+                match a.direction {
+                    AnomalyDirection::Above => "above",
+                    AnomalyDirection::Below => "below",
+                },
+                a.expected,
+                a.severity,
+            )
+        })
+        .collect();
+    if anomalies.len() > NOTIFIED_EXAMPLES {
+        lines.push(format!("and {} more", anomalies.len() - NOTIFIED_EXAMPLES));
+    }
+    (title, lines.join("\n"))
+}
+
+fn minutes_from_now(minutes: i64) -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() + minutes * 60_000)
+}
+
+pub struct ScheduledReportService {
+    db: DatabaseManager,
+    anomaly_service: Arc<AnomalyService>,
+    notification_service: Arc<NotificationService>,
+    rbac_service: Arc<RbacService>,
+}
+
+impl ScheduledReportService {
+    pub fn new(
+        db: DatabaseManager,
+        anomaly_service: Arc<AnomalyService>,
+        notification_service: Arc<NotificationService>,
+        rbac_service: Arc<RbacService>,
+    ) -> Self {
+        ScheduledReportService { db, anomaly_service, notification_service, rbac_service }
+    }
+
+    /// Add a report; its first run is on the next tick of the scheduler
+    pub async fn create(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        dto: CreateScheduledReportDto,
+    ) -> Result<ScheduledReport, String> {
+        let sources = [dto.dataset_name.is_some(), dto.sql.is_some(), dto.query_id.is_some()];
+        if sources.iter().filter(|&&given| given).count() != 1 {
+            return Err("Give one of dataset_name, sql or query_id".to_string());
+        }
+
+        let now = DateTime::now();
+        let report = ScheduledReport {
+            id: None,
+            report_id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            name: dto.name,
+            dataset_name: dto.dataset_name,
+            sql: dto.sql,
+            query_id: dto.query_id,
+            detection: dto.detection,
+            every_minutes: dto.every_minutes,
+            enabled: true,
+            next_run_at: now,
+            last_run_at: None,
+            last_error: None,
+            last_anomalies: vec![],
+            notified: vec![],
+            created_by: user_id.to_string(),
+            created_at: now,
+        };
+        self.db
+            .scheduled_reports_collection()
+            .insert_one(&report)
+            .await
+            .map_err(|e| format!("Failed to create scheduled report: {}", e))?;
+        Ok(report)
+    }
+
+    /// Reports of a project, newest first
+    pub async fn list(&self, project_id: &str) -> Result<Vec<ScheduledReport>, String> {
+        self.db
+            .scheduled_reports_collection()
+            .find(doc! { "project_id": project_id })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(|e| format!("Failed to get scheduled reports: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect scheduled reports: {}", e))
+    }
+
+    pub async fn get(&self, report_id: &str) -> Result<Option<ScheduledReport>, String> {
+        self.db
+            .scheduled_reports_collection()
+            .find_one(doc! { "report_id": report_id })
+            .await
+            .map_err(|e| format!("Failed to get scheduled report: {}", e))
+    }
+
+    pub async fn delete(&self, report_id: &str) -> Result<(), String> {
+        self.db
+            .scheduled_reports_collection()
+            .delete_one(doc! { "report_id": report_id })
+            .await
+            .map_err(|e| format!("Failed to delete scheduled report: {}", e))?;
+        Ok(())
+    }
+
+    /// Check the report's source for anomalies and notify about the new ones.
+    /// A failed check is recorded on the report rather than returned. The new
+    /// anomalies are recorded as notified before notifications go out, so a
+    /// concurrent run or a crash can't report them twice.
+    pub async fn run(&self, mut report: ScheduledReport) -> Result<ScheduledReport, String> {
+        let source = match (&report.dataset_name, &report.sql, &report.query_id) {
+            (Some(name), _, _) => AnomalySource::Table(name),
+            (None, Some(sql), _) => AnomalySource::Sql(sql),
+            (None, None, Some(query_id)) => AnomalySource::SavedQuery(query_id),
+            (None, None, None) => return Err("Scheduled report has no dataset or query".to_string()),
+        };
+        report.last_run_at = Some(DateTime::now());
+        let previously_notified = report.notified.clone();
+
+        let mut fresh: Vec<AnomalyPoint> = vec![];
+        match self.anomaly_service.detect(&report.project_id, source, &report.detection).await {
+            Ok(response) => {
+                report.last_error = None;
+                report.last_anomalies = response.anomalies;
+
+                let notified: HashSet<&String> = report.notified.iter().collect();
+                fresh = report
+                    .last_anomalies
+                    .iter()
+                    .filter(|a| !notified.contains(&anomaly_key(a)))
+                    .cloned()
+                    .collect();
+                report.notified.extend(fresh.iter().map(anomaly_key));
+                let excess = report.notified.len().saturating_sub(MAX_NOTIFIED);
+                report.notified.drain(..excess);
+            }
+            Err(e) => {
+                log::warn!("Scheduled report {} failed: {}", report.report_id, e);
+                report.last_error = Some(e);
+            }
+        }
+
+        let last_anomalies = bson::to_bson(&report.last_anomalies)
+            .map_err(|e| format!("Failed to encode anomalies: {}", e))?;
+        let saved = self
+            .db
+            .scheduled_reports_collection()
+            .update_one(
+                doc! { "report_id": &report.report_id, "notified": &previously_notified },
+                doc! { "$set": {
+                    "last_run_at": report.last_run_at,
+                    "last_error": &report.last_error,
+                    "last_anomalies": last_anomalies,
+                    "notified": &report.notified,
+                } },
+            )
+            .await
+            .map_err(|e| format!("Failed to update scheduled report: {}", e))?;
+        if saved.matched_count == 0 {
+            log::info!(
+                "Scheduled report {} was deleted or run elsewhere meanwhile; not notifying",
+                report.report_id
+            );
+            return Ok(report);
+        }
+
+        if !fresh.is_empty() {
+            let users = self
+                .rbac_service
+                .project_users_with(&report.project_id, Permission::ReportRead)
+                .await?;
+            let (title, message) = notification_text(&report, &fresh.iter().collect::<Vec<_>>());
+            self.notification_service
+                .notify(
+                    &users,
+                    &report.project_id,
+                    NotificationKind::Anomaly,
+                    &title,
+                    &message,
+                    Some(&report.report_id),
+                )
+                .await?;
+        }
+        Ok(report)
+    }
+
+    /// Run every enabled report that is due, claiming each one first so that
+    /// other server instances skip it
+    async fn run_due(&self) -> Result<(), String> {
+        let due: Vec<ScheduledReport> = self
+            .db
+            .scheduled_reports_collection()
+            .find(doc! { "enabled": true, "next_run_at": { "$lte": DateTime::now() } })
+            .await
+            .map_err(|e| format!("Failed to get due scheduled reports: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect due scheduled reports: {}", e))?;
+
+        for report in due {
+            let claimed = self
+                .db
+                .scheduled_reports_collection()
+                .update_one(
+                    doc! { "report_id": &report.report_id, "next_run_at": report.next_run_at },
+                    doc! { "$set": { "next_run_at": minutes_from_now(report.every_minutes) } },
+                )
+                .await
+                .map_err(|e| format!("Failed to claim scheduled report: {}", e))?;
+            if claimed.modified_count != 1 {
+                continue;
+            }
+            let report_id = report.report_id.clone();
+            if let Err(e) = self.run(report).await {
+                log::warn!("Failed to run scheduled report {}: {}", report_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check for due reports every `tick` in the background
+    pub fn start(self: Arc<Self>, tick: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.run_due().await {
+                    log::warn!("Failed to run scheduled reports: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AnomalyOptions, AnomalySeverity};
+
+    fn point(label: &str, direction: AnomalyDirection) -> AnomalyPoint {
+        AnomalyPoint {
+            label: label.to_string(),
+            index: 0,
+            value: 250.0,
+            expected: 100.0,
+            score: 7.5,
+            direction,
+            severity: AnomalySeverity::High,
+        }
+    }
+
+    #[test]
+    fn describes_new_anomalies_once_per_period_and_direction() {
+        let above = point("2024-01-17", AnomalyDirection::Above);
+        let below = point("2024-01-17", AnomalyDirection::Below);
+        assert_ne!(anomaly_key(&above), anomaly_key(&below));
+        assert_eq!(anomaly_key(&above), anomaly_key(&point("2024-01-17", AnomalyDirection::Above)));
+
+        let report = ScheduledReport {
+            id: None,
+            report_id: "r1".to_string(),
+            project_id: "p1".to_string(),
+            name: "Daily orders".to_string(),
+            dataset_name: Some("orders".to_string()),
+            sql: None,
+            query_id: None,
+            detection: serde_json::from_value::<AnomalyOptions>(serde_json::json!({ "column": "orders" })).unwrap(),
+            every_minutes: 60,
+            enabled: true,
+            next_run_at: DateTime::now(),
+            last_run_at: None,
+            last_error: None,
+            last_anomalies: vec![],
+            notified: vec![],
+            created_by: "u1".to_string(),
+            created_at: DateTime::now(),
+        };
+        let points: Vec<AnomalyPoint> = (1..=7).map(|d| point(&format!("2024-01-0{}", d), AnomalyDirection::Above)).collect();
+        let (title, message) = notification_text(&report, &points.iter().collect::<Vec<_>>());
+
+        assert_eq!(title, "7 new anomalies in Daily orders");
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "orders 2024-01-01: 250 is above the expected 100 (High severity)");
+        assert_eq!(lines[5], "and 2 more");
+    }
+}
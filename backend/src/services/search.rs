@@ -0,0 +1,245 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use crate::db::DatabaseManager;
+use crate::models::{
+    AnalyticsQuery, Conversation, ConversationVisibility, HighlightRange, SearchHit, SearchHitKind,
+};
+
+/// Characters of context kept on each side of the first match in a snippet
+const SNIPPET_CONTEXT_CHARS: usize = 80;
+
+/// Full-text search over conversations and analytics queries
+pub struct SearchService {
+    db: DatabaseManager,
+}
+
+impl SearchService {
+    pub fn new(db: DatabaseManager) -> Self {
+        SearchService { db }
+    }
+
+    /// Search conversations in `project_ids` that are visible to the user
+    pub async fn search_conversations(
+        &self,
+        query: &str,
+        user_id: &str,
+        project_ids: &[String],
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, String> {
+        if project_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let filter = doc! {
+            "$text": { "$search": query },
+            "project_id": { "$in": project_ids },
+            "$or": [
+                { "user_id": user_id },
+                { "visibility": ConversationVisibility::Project.as_str() },
+                {
+                    "visibility": ConversationVisibility::Users.as_str(),
+                    "shared_with.user_id": user_id,
+                },
+            ]
+        };
+
+        let docs = self
+            .find_ranked(self.db.conversations_collection().clone_with_type(), filter, limit)
+            .await?;
+
+        let terms = Self::query_terms(query);
+        let mut hits = Vec::with_capacity(docs.len());
+        for (document, score) in docs {
+            let conversation: Conversation = match mongodb::bson::from_document(document) {
+                Ok(c) => c,
+                Err(e) => {
+                    log::warn!("Failed to parse conversation search hit: {}", e);
+                    continue;
+                }
+            };
+
+            // Prefer a matching message for the snippet, fall back to the title
+            let matching_message = conversation
+                .messages
+                .iter()
+                .position(|m| Self::contains_any(&m.content, &terms));
+            let (snippet, highlights) = match matching_message {
+                Some(idx) => Self::snippet(&conversation.messages[idx].content, &terms),
+                None => Self::snippet(&conversation.title, &terms),
+            };
+
+            hits.push(SearchHit {
+                kind: SearchHitKind::Conversation,
+                id: conversation.conversation_id.to_string(),
+                project_id: conversation.project_id.to_string(),
+                title: conversation.title,
+                snippet,
+                highlights,
+                message_index: matching_message,
+                score,
+                updated_at: conversation.updated_at.to_string(),
+            });
+        }
+
+        Ok(hits)
+    }
+
+    /// Search analytics queries in `project_ids`
+    pub async fn search_queries(
+        &self,
+        query: &str,
+        project_ids: &[String],
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, String> {
+        if project_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let filter = doc! {
+            "$text": { "$search": query },
+            "project_id": { "$in": project_ids },
+        };
+
+        let docs = self
+            .find_ranked(self.db.queries_collection().clone_with_type(), filter, limit)
+            .await?;
+
+        let terms = Self::query_terms(query);
+        let mut hits = Vec::with_capacity(docs.len());
+        for (document, score) in docs {
+            let analytics_query: AnalyticsQuery = match mongodb::bson::from_document(document) {
+                Ok(q) => q,
+                Err(e) => {
+                    log::warn!("Failed to parse analytics query search hit: {}", e);
+                    continue;
+                }
+            };
+
+            let response_text = analytics_query.response_text.as_deref().unwrap_or_default();
+            let (snippet, highlights) = if !Self::contains_any(&analytics_query.query_text, &terms)
+                && Self::contains_any(response_text, &terms)
+            {
+                Self::snippet(response_text, &terms)
+            } else {
+                Self::snippet(&analytics_query.query_text, &terms)
+            };
+
+            hits.push(SearchHit {
+                kind: SearchHitKind::AnalyticsQuery,
+                id: analytics_query.query_id,
+                project_id: analytics_query.project_id,
+                title: analytics_query.query_text.chars().take(100).collect(),
+                snippet,
+                highlights,
+                message_index: None,
+                score,
+                updated_at: analytics_query
+                    .completed_at
+                    .unwrap_or(analytics_query.created_at)
+                    .to_string(),
+            });
+        }
+
+        Ok(hits)
+    }
+
+    /// Run a `$text` query sorted by relevance, returning each document with its score
+    async fn find_ranked(
+        &self,
+        collection: mongodb::Collection<Document>,
+        filter: Document,
+        limit: i64,
+    ) -> Result<Vec<(Document, f64)>, String> {
+        let documents: Vec<Document> = collection
+            .find(filter)
+            .projection(doc! { "score": { "$meta": "textScore" } })
+            .sort(doc! { "score": { "$meta": "textScore" } })
+            .limit(limit)
+            .await
+            .map_err(|e| format!("Search failed: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect search results: {}", e))?;
+
+        Ok(documents
+            .into_iter()
+            .map(|mut d| {
+                let score = d.get_f64("score").unwrap_or(0.0);
+                d.remove("score");
+                (d, score)
+            })
+            .collect())
+    }
+
+    /// Split a search string into lowercase terms, ignoring negations and quotes
+    fn query_terms(query: &str) -> Vec<Vec<char>> {
+        query
+            .split_whitespace()
+            .filter(|t| !t.starts_with('-'))
+            .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|t| !t.is_empty())
+            .map(|t| t.chars().map(Self::fold).collect())
+            .collect()
+    }
+
+    fn fold(c: char) -> char {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+
+    fn contains_any(text: &str, terms: &[Vec<char>]) -> bool {
+        let folded: Vec<char> = text.chars().map(Self::fold).collect();
+        terms.iter().any(|t| !Self::find_all(&folded, t).is_empty())
+    }
+
+    fn find_all(haystack: &[char], needle: &[char]) -> Vec<usize> {
+        if needle.is_empty() || needle.len() > haystack.len() {
+            return vec![];
+        }
+        (0..=haystack.len() - needle.len())
+            .filter(|&i| haystack[i..i + needle.len()] == *needle)
+            .collect()
+    }
+
+    /// Cut a window around the first matched term and report term positions inside it.
+    /// Offsets are in characters so they are safe for multi-byte text.
+    fn snippet(text: &str, terms: &[Vec<char>]) -> (String, Vec<HighlightRange>) {
+        let chars: Vec<char> = text.chars().collect();
+        let folded: Vec<char> = chars.iter().copied().map(Self::fold).collect();
+
+        let first_match = terms
+            .iter()
+            .filter_map(|t| Self::find_all(&folded, t).first().copied())
+            .min()
+            .unwrap_or(0);
+
+        let start = first_match.saturating_sub(SNIPPET_CONTEXT_CHARS);
+        let end = (first_match + SNIPPET_CONTEXT_CHARS * 2).min(chars.len());
+
+        let mut highlights: Vec<HighlightRange> = terms
+            .iter()
+            .flat_map(|t| {
+                Self::find_all(&folded[start..end], t)
+                    .into_iter()
+                    .map(move |pos| (pos, pos + t.len()))
+            })
+            .map(|(s, e)| HighlightRange { start: s, end: e })
+            .collect();
+        highlights.sort_by_key(|h| h.start);
+
+        let mut snippet: String = chars[start..end].iter().collect();
+        let mut offset = 0;
+        if start > 0 {
+            snippet.insert(0, '…');
+            offset = 1;
+        }
+        if end < chars.len() {
+            snippet.push('…');
+        }
+        for h in highlights.iter_mut() {
+            h.start += offset;
+            h.end += offset;
+        }
+
+        (snippet, highlights)
+    }
+}
@@ -0,0 +1,1004 @@
+//! Read-only SQL over project datasets.
+//!
+//! A single `SELECT` over one table is supported:
+//!
+//! ```text
+//! SELECT [DISTINCT] items FROM table [WHERE condition]
+//!     [GROUP BY columns] [ORDER BY keys [ASC|DESC]] [LIMIT n] [OFFSET n]
+//! ```
+//!
+//! Items are `*`, columns, literals and `COUNT`, `SUM`, `AVG`, `MIN`, `MAX`,
+//! each with an optional `AS` alias. Conditions combine comparisons, `LIKE`
+//! (case-insensitive), `IN`, `BETWEEN`, `IS [NOT] NULL`, `AND`, `OR`, `NOT` and
+//! parentheses. Everything else, including any statement that writes, is rejected.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use serde_json::Value;
+use crate::models::{ColumnInfo, SqlQueryResult};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// `"name"` or `` `name` ``, never a keyword
+    QuotedIdent(String),
+    Str(String),
+    Number(Value),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["<=", ">=", "<>", "!=", "=", "<", ">", ",", "(", ")", "*", ";", "-"];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number(&text).ok_or_else(|| format!("Invalid number '{}'", text))?));
+        } else if c == '\'' || c == '"' || c == '`' {
+            // Quotes inside are escaped by doubling them
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("Unterminated quoted text".to_string()),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '\'' { Token::Str(text) } else { Token::QuotedIdent(text) });
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| format!("Unexpected character '{}'", c))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Integer when the text has no fraction, otherwise a float
+fn number(text: &str) -> Option<Value> {
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(Value::from(n));
+    }
+    text.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(Aggregate::Count),
+            "sum" => Some(Aggregate::Sum),
+            "avg" => Some(Aggregate::Avg),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Column(String),
+    Literal(Value),
+    /// `None` column means `COUNT(*)`
+    Aggregate(Aggregate, Option<String>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    IsNull(Box<Expr>, bool),
+    Like(Box<Expr>, Box<Expr>, bool),
+    In(Box<Expr>, Vec<Expr>, bool),
+}
+
+impl Expr {
+    fn has_aggregate(&self) -> bool {
+        match self {
+            Expr::Aggregate(..) => true,
+            Expr::Column(_) | Expr::Literal(_) => false,
+            Expr::Compare(a, _, b) | Expr::And(a, b) | Expr::Or(a, b) | Expr::Like(a, b, _) => {
+                a.has_aggregate() || b.has_aggregate()
+            }
+            Expr::Not(a) | Expr::IsNull(a, _) => a.has_aggregate(),
+            Expr::In(a, list, _) => a.has_aggregate() || list.iter().any(Expr::has_aggregate),
+        }
+    }
+
+    /// Output column name when the item has no alias
+    fn label(&self) -> String {
+        match self {
+            Expr::Column(name) => name.clone(),
+            Expr::Aggregate(agg, column) => {
+                format!("{}({})", agg.name(), column.as_deref().unwrap_or("*"))
+            }
+            Expr::Literal(value) => value.to_string(),
+            _ => "expr".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SelectItem {
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// A parsed read-only query
+#[derive(Debug, Clone)]
+pub struct Query {
+    distinct: bool,
+    items: Vec<SelectItem>,
+    table: String,
+    filter: Option<Expr>,
+    group_by: Vec<String>,
+    /// Keys with `true` for descending
+    order_by: Vec<(Expr, bool)>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl Query {
+    /// Table named in `FROM`
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+}
+
+/// Keywords that end an unaliased select item or table name
+const RESERVED: &[&str] = &[
+    "select", "distinct", "from", "where", "group", "by", "order", "limit", "offset", "as",
+    "and", "or", "not", "is", "null", "like", "in", "between", "asc", "desc", "having", "join",
+    "union", "true", "false",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("Expected {} {}", keyword.to_uppercase(), self.found()))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' {}", symbol, self.found()))
+        }
+    }
+
+    /// Description of the current token for error messages
+    fn found(&self) -> String {
+        match self.peek() {
+            None => "at end of query".to_string(),
+            Some(Token::Ident(s)) | Some(Token::QuotedIdent(s)) => format!("near '{}'", s),
+            Some(Token::Str(s)) => format!("near '{}'", s),
+            Some(Token::Number(n)) => format!("near {}", n),
+            Some(Token::Symbol(s)) => format!("near '{}'", s),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(Token::QuotedIdent(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            Some(Token::Ident(name)) if !RESERVED.contains(&name.to_ascii_lowercase().as_str()) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(format!("Expected a name {}", self.found())),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, String> {
+        if !self.keyword("select") {
+            return Err("Only SELECT statements are allowed".to_string());
+        }
+        let distinct = self.keyword("distinct");
+
+        let mut items = Vec::new();
+        loop {
+            items.push(self.select_item()?);
+            if !self.symbol(",") {
+                break;
+            }
+        }
+
+        self.expect_keyword("from")?;
+        let table = self.identifier()?;
+
+        let filter = if self.keyword("where") { Some(self.expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if self.keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                group_by.push(self.identifier()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let key = self.primary()?;
+                let descending = if self.keyword("desc") {
+                    true
+                } else {
+                    self.keyword("asc");
+                    false
+                };
+                order_by.push((key, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let limit = if self.keyword("limit") { Some(self.count()?) } else { None };
+        let offset = if self.keyword("offset") { self.count()? } else { 0 };
+
+        self.symbol(";");
+        if self.peek().is_some() {
+            return Err(format!("Unexpected input {}", self.found()));
+        }
+
+        Ok(Query { distinct, items, table, filter, group_by, order_by, limit, offset })
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, String> {
+        if self.symbol("*") {
+            return Ok(SelectItem::Wildcard);
+        }
+        let expr = self.expr()?;
+        let alias = if self.keyword("as") {
+            Some(self.identifier()?)
+        } else {
+            match self.peek() {
+                Some(Token::Ident(_)) | Some(Token::QuotedIdent(_)) => self.identifier().ok(),
+                _ => None,
+            }
+        };
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Number(Value::Number(n))) if n.as_u64().is_some() => Ok(n.as_u64().unwrap_or(0) as usize),
+            _ => Err("LIMIT and OFFSET take a whole number".to_string()),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.and_expr()?;
+        while self.keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.not_expr()?;
+        while self.keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.not_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr, String> {
+        let left = self.primary()?;
+
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Expr::IsNull(Box::new(left), negated));
+        }
+
+        let negated = self.keyword("not");
+        if self.keyword("like") {
+            return Ok(Expr::Like(Box::new(left), Box::new(self.primary()?), negated));
+        }
+        if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut list = Vec::new();
+            loop {
+                list.push(self.primary()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(Expr::In(Box::new(left), list, negated));
+        }
+        if self.keyword("between") {
+            let low = self.primary()?;
+            self.expect_keyword("and")?;
+            let high = self.primary()?;
+            let between = Expr::And(
+                Box::new(Expr::Compare(Box::new(left.clone()), CompareOp::GtEq, Box::new(low))),
+                Box::new(Expr::Compare(Box::new(left), CompareOp::LtEq, Box::new(high))),
+            );
+            return Ok(if negated { Expr::Not(Box::new(between)) } else { between });
+        }
+        if negated {
+            return Err(format!("Expected LIKE, IN or BETWEEN after NOT {}", self.found()));
+        }
+
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => CompareOp::Eq,
+            Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => CompareOp::NotEq,
+            Some(Token::Symbol("<")) => CompareOp::Lt,
+            Some(Token::Symbol("<=")) => CompareOp::LtEq,
+            Some(Token::Symbol(">")) => CompareOp::Gt,
+            Some(Token::Symbol(">=")) => CompareOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(Box::new(left), op, Box::new(self.primary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.symbol("(") {
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        if self.symbol("-") {
+            return match self.next() {
+                Some(Token::Number(n)) => {
+                    let negated = n.as_i64().map(|i| Value::from(-i)).or_else(|| {
+                        n.as_f64().and_then(|f| serde_json::Number::from_f64(-f)).map(Value::Number)
+                    });
+                    negated.map(Expr::Literal).ok_or_else(|| "Invalid number".to_string())
+                }
+                _ => Err("Expected a number after '-'".to_string()),
+            };
+        }
+
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(n))
+            }
+            Some(Token::Str(s)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::String(s)))
+            }
+            Some(Token::QuotedIdent(name)) => {
+                self.pos += 1;
+                Ok(Expr::Column(name))
+            }
+            Some(Token::Ident(word)) => {
+                let lower = word.to_ascii_lowercase();
+                match lower.as_str() {
+                    "null" => {
+                        self.pos += 1;
+                        return Ok(Expr::Literal(Value::Null));
+                    }
+                    "true" | "false" => {
+                        self.pos += 1;
+                        return Ok(Expr::Literal(Value::Bool(lower == "true")));
+                    }
+                    _ => {}
+                }
+
+                if matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol("("))) {
+                    let aggregate = Aggregate::from_name(&word)
+                        .ok_or_else(|| format!("Unsupported function '{}'", word))?;
+                    self.pos += 2;
+                    let column = if self.symbol("*") {
+                        if aggregate != Aggregate::Count {
+                            return Err(format!("{}(*) is not supported", word.to_uppercase()));
+                        }
+                        None
+                    } else {
+                        Some(self.identifier()?)
+                    };
+                    self.expect_symbol(")")?;
+                    return Ok(Expr::Aggregate(aggregate, column));
+                }
+
+                Ok(Expr::Column(self.identifier()?))
+            }
+            _ => Err(format!("Expected a value {}", self.found())),
+        }
+    }
+}
+
+/// Parse one read-only statement
+pub fn parse(sql: &str) -> Result<Query, String> {
+    let tokens = tokenize(sql)?;
+    if tokens.is_empty() {
+        return Err("Query is empty".to_string());
+    }
+    Parser { tokens, pos: 0 }.query()
+}
+
+/// Column positions by name, ignoring case
+struct Columns<'a> {
+    columns: &'a [ColumnInfo],
+}
+
+impl Columns<'_> {
+    fn index(&self, name: &str) -> Result<usize, String> {
+        self.columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown column '{}'", name))
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// SQL ordering of two values; `None` when either is null
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Number(_), _) | (_, Value::Number(_)) => match (as_number(a), as_number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => Some(text(a).cmp(&text(b))),
+        },
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        _ => Some(text(a).cmp(&text(b))),
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Case-insensitive `LIKE` with `%` and `_` wildcards
+fn like(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let (mut v, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '_' || pattern[p] == value[v]) {
+            v += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((bp, bv)) = backtrack {
+            p = bp + 1;
+            v = bv + 1;
+            backtrack = Some((bp, bv + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+fn truthy(value: &Value) -> bool {
+    matches!(value, Value::Bool(true))
+}
+
+/// Evaluate a row expression; comparisons with null give null
+fn eval(expr: &Expr, row: &[Value], columns: &Columns) -> Result<Value, String> {
+    Ok(match expr {
+        Expr::Column(name) => row.get(columns.index(name)?).cloned().unwrap_or(Value::Null),
+        Expr::Literal(value) => value.clone(),
+        Expr::Aggregate(agg, _) => {
+            return Err(format!("{} is only allowed in the select list", agg.name().to_uppercase()));
+        }
+        Expr::Compare(a, op, b) => {
+            let ordering = compare(&eval(a, row, columns)?, &eval(b, row, columns)?);
+            match ordering {
+                None => Value::Null,
+                Some(o) => Value::Bool(match op {
+                    CompareOp::Eq => o == Ordering::Equal,
+                    CompareOp::NotEq => o != Ordering::Equal,
+                    CompareOp::Lt => o == Ordering::Less,
+                    CompareOp::LtEq => o != Ordering::Greater,
+                    CompareOp::Gt => o == Ordering::Greater,
+                    CompareOp::GtEq => o != Ordering::Less,
+                }),
+            }
+        }
+        Expr::And(a, b) => Value::Bool(truthy(&eval(a, row, columns)?) && truthy(&eval(b, row, columns)?)),
+        Expr::Or(a, b) => Value::Bool(truthy(&eval(a, row, columns)?) || truthy(&eval(b, row, columns)?)),
+        Expr::Not(a) => match eval(a, row, columns)? {
+            Value::Null => Value::Null,
+            value => Value::Bool(!truthy(&value)),
+        },
+        Expr::IsNull(a, negated) => Value::Bool(eval(a, row, columns)?.is_null() != *negated),
+        Expr::Like(a, pattern, negated) => match (eval(a, row, columns)?, eval(pattern, row, columns)?) {
+            (Value::Null, _) | (_, Value::Null) => Value::Null,
+            (value, pattern) => Value::Bool(like(&text(&value), &text(&pattern)) != *negated),
+        },
+        Expr::In(a, list, negated) => {
+            let value = eval(a, row, columns)?;
+            if value.is_null() {
+                Value::Null
+            } else {
+                let mut found = false;
+                for item in list {
+                    if compare(&value, &eval(item, row, columns)?) == Some(Ordering::Equal) {
+                        found = true;
+                        break;
+                    }
+                }
+                Value::Bool(found != *negated)
+            }
+        }
+    })
+}
+
+/// Aggregate over the rows of one group
+fn aggregate(agg: Aggregate, column: Option<&str>, rows: &[&Vec<Value>], columns: &Columns) -> Result<Value, String> {
+    let index = match column {
+        Some(name) => Some(columns.index(name)?),
+        None => None,
+    };
+    let values: Vec<&Value> = match index {
+        Some(i) => rows.iter().filter_map(|r| r.get(i)).filter(|v| !v.is_null()).collect(),
+        None => return Ok(Value::from(rows.len() as i64)),
+    };
+
+    match agg {
+        Aggregate::Count => Ok(Value::from(values.len() as i64)),
+        Aggregate::Sum | Aggregate::Avg => {
+            if values.is_empty() {
+                return Ok(Value::Null);
+            }
+            let numbers = values
+                .iter()
+                .map(|v| as_number(v))
+                .collect::<Option<Vec<f64>>>()
+                .ok_or_else(|| format!("{} needs a numeric column", agg.name().to_uppercase()))?;
+            let sum: f64 = numbers.iter().sum();
+            if agg == Aggregate::Avg {
+                return Ok(serde_json::Number::from_f64(sum / numbers.len() as f64)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null));
+            }
+            if values.iter().all(|v| v.is_i64()) {
+                let total = values.iter().filter_map(|v| v.as_i64()).try_fold(0i64, |acc, n| acc.checked_add(n));
+                if let Some(total) = total {
+                    return Ok(Value::from(total));
+                }
+            }
+            Ok(serde_json::Number::from_f64(sum).map(Value::Number).unwrap_or(Value::Null))
+        }
+        Aggregate::Min | Aggregate::Max => {
+            let wanted = if agg == Aggregate::Min { Ordering::Less } else { Ordering::Greater };
+            let mut best: Option<&Value> = None;
+            for value in values {
+                if best.is_none_or(|b| compare(value, b) == Some(wanted)) {
+                    best = Some(value);
+                }
+            }
+            Ok(best.cloned().unwrap_or(Value::Null))
+        }
+    }
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        _ => "string",
+    }
+}
+
+fn output_type(expr: &Expr, columns: &[ColumnInfo], lookup: &Columns) -> String {
+    let source = |name: &str| {
+        lookup
+            .index(name)
+            .map(|i| columns[i].data_type.clone())
+            .unwrap_or_else(|_| "string".to_string())
+    };
+    match expr {
+        Expr::Column(name) => source(name),
+        Expr::Literal(value) => value_type(value).to_string(),
+        Expr::Aggregate(Aggregate::Count, _) => "integer".to_string(),
+        Expr::Aggregate(Aggregate::Avg, _) => "number".to_string(),
+        Expr::Aggregate(Aggregate::Sum, Some(name)) => {
+            if source(name) == "integer" { "integer".to_string() } else { "number".to_string() }
+        }
+        Expr::Aggregate(_, Some(name)) => source(name),
+        _ => "boolean".to_string(),
+    }
+}
+
+/// Run `query` over `rows` of a table with `columns`, returning at most `max_rows` rows
+pub fn execute(
+    query: &Query,
+    columns: &[ColumnInfo],
+    rows: Vec<Vec<Value>>,
+    max_rows: usize,
+) -> Result<SqlQueryResult, String> {
+    let lookup = Columns { columns };
+
+    // Expand the select list into expressions with output names
+    let mut outputs: Vec<(Expr, String)> = Vec::new();
+    for item in &query.items {
+        match item {
+            SelectItem::Wildcard => {
+                outputs.extend(columns.iter().map(|c| (Expr::Column(c.name.clone()), c.name.clone())));
+            }
+            SelectItem::Expr { expr, alias } => {
+                let name = alias.clone().unwrap_or_else(|| expr.label());
+                outputs.push((expr.clone(), name));
+            }
+        }
+    }
+    for (expr, _) in &outputs {
+        if let Expr::Column(name) = expr {
+            lookup.index(name)?;
+        }
+    }
+
+    let mut matched = Vec::new();
+    for row in rows {
+        let keep = match query.filter {
+            Some(ref filter) => truthy(&eval(filter, &row, &lookup)?),
+            None => true,
+        };
+        if keep {
+            matched.push(row);
+        }
+    }
+
+    let grouped = !query.group_by.is_empty() || outputs.iter().any(|(e, _)| e.has_aggregate());
+
+    // Each result row with the values its ORDER BY keys sort on
+    let mut result: Vec<(Vec<Value>, Vec<Value>)> = Vec::new();
+
+    if grouped {
+        if query.items.iter().any(|i| matches!(i, SelectItem::Wildcard)) {
+            return Err("SELECT * cannot be combined with GROUP BY or aggregates".to_string());
+        }
+        let group_indexes = query
+            .group_by
+            .iter()
+            .map(|name| lookup.index(name))
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        // Groups in first-seen order
+        let mut keys: Vec<String> = Vec::new();
+        let mut groups: Vec<Vec<&Vec<Value>>> = Vec::new();
+        for row in &matched {
+            let key = serde_json::to_string(
+                &group_indexes.iter().map(|i| row.get(*i).cloned().unwrap_or(Value::Null)).collect::<Vec<_>>(),
+            )
+            .unwrap_or_default();
+            match keys.iter().position(|k| *k == key) {
+                Some(i) => groups[i].push(row),
+                None => {
+                    keys.push(key);
+                    groups.push(vec![row]);
+                }
+            }
+        }
+        if groups.is_empty() && query.group_by.is_empty() {
+            groups.push(Vec::new());
+        }
+
+        let evaluate = |expr: &Expr, group: &[&Vec<Value>]| -> Result<Value, String> {
+            match expr {
+                Expr::Aggregate(agg, column) => aggregate(*agg, column.as_deref(), group, &lookup),
+                Expr::Column(name) => {
+                    if !query.group_by.iter().any(|g| g.eq_ignore_ascii_case(name)) {
+                        return Err(format!("Column '{}' must appear in GROUP BY or be aggregated", name));
+                    }
+                    Ok(group.first().and_then(|r| r.get(lookup.index(name).ok()?)).cloned().unwrap_or(Value::Null))
+                }
+                Expr::Literal(value) => Ok(value.clone()),
+                _ => Err("Only columns, literals and aggregates can be selected with GROUP BY".to_string()),
+            }
+        };
+
+        for group in &groups {
+            let row = outputs
+                .iter()
+                .map(|(expr, _)| evaluate(expr, group))
+                .collect::<Result<Vec<_>, String>>()?;
+            let mut sort = Vec::new();
+            for (key, _) in &query.order_by {
+                sort.push(match order_position(key, &outputs)? {
+                    Some(i) => row[i].clone(),
+                    None => evaluate(key, group)?,
+                });
+            }
+            result.push((row, sort));
+        }
+    } else {
+        for source in &matched {
+            let row = outputs
+                .iter()
+                .map(|(expr, _)| eval(expr, source, &lookup))
+                .collect::<Result<Vec<_>, String>>()?;
+            let mut sort = Vec::new();
+            for (key, _) in &query.order_by {
+                sort.push(match order_position(key, &outputs)? {
+                    Some(i) => row[i].clone(),
+                    None => eval(key, source, &lookup)?,
+                });
+            }
+            result.push((row, sort));
+        }
+    }
+
+    if query.distinct {
+        let mut seen = HashSet::new();
+        result.retain(|(row, _)| seen.insert(serde_json::to_string(row).unwrap_or_default()));
+    }
+
+    if !query.order_by.is_empty() {
+        result.sort_by(|(_, a), (_, b)| {
+            for (i, (_, descending)) in query.order_by.iter().enumerate() {
+                // Nulls sort last in either direction
+                let ordering = match (a[i].is_null(), b[i].is_null()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    _ => {
+                        let o = compare(&a[i], &b[i]).unwrap_or(Ordering::Equal);
+                        if *descending { o.reverse() } else { o }
+                    }
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+
+    let available = result.len().saturating_sub(query.offset);
+    let wanted = query.limit.unwrap_or(available).min(available);
+    let take = wanted.min(max_rows);
+
+    Ok(SqlQueryResult {
+        columns: outputs
+            .iter()
+            .map(|(expr, name)| ColumnInfo { name: name.clone(), data_type: output_type(expr, columns, &lookup) })
+            .collect(),
+        rows: result.into_iter().skip(query.offset).take(take).map(|(row, _)| row).collect(),
+        truncated: take < wanted,
+    })
+}
+
+/// Output column an ORDER BY key refers to: a 1-based position, an alias or a selected expression
+fn order_position(key: &Expr, outputs: &[(Expr, String)]) -> Result<Option<usize>, String> {
+    match key {
+        Expr::Literal(Value::Number(n)) => {
+            let position = n.as_u64().unwrap_or(0) as usize;
+            if position == 0 || position > outputs.len() {
+                return Err(format!("ORDER BY position {} is out of range", n));
+            }
+            Ok(Some(position - 1))
+        }
+        Expr::Column(name) => Ok(outputs
+            .iter()
+            .position(|(_, output)| output.eq_ignore_ascii_case(name))
+            .or_else(|| outputs.iter().position(|(expr, _)| expr == key))),
+        _ => Ok(outputs.iter().position(|(expr, _)| expr == key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table() -> (Vec<ColumnInfo>, Vec<Vec<Value>>) {
+        let columns = vec![
+            ColumnInfo { name: "region".to_string(), data_type: "string".to_string() },
+            ColumnInfo { name: "product".to_string(), data_type: "string".to_string() },
+            ColumnInfo { name: "revenue".to_string(), data_type: "integer".to_string() },
+        ];
+        let rows = vec![
+            vec![json!("North"), json!("Widget"), json!(120)],
+            vec![json!("South"), json!("Widget"), json!(80)],
+            vec![json!("North"), json!("Gadget"), json!(200)],
+            vec![json!("East"), json!("Gadget"), Value::Null],
+        ];
+        (columns, rows)
+    }
+
+    fn run(sql: &str) -> Result<SqlQueryResult, String> {
+        let (columns, rows) = table();
+        execute(&parse(sql)?, &columns, rows, 100)
+    }
+
+    #[test]
+    fn filters_projects_and_orders() {
+        let result = run("SELECT product, revenue FROM sales WHERE region = 'North' ORDER BY revenue DESC").unwrap();
+        assert_eq!(result.columns[1].name, "revenue");
+        assert_eq!(result.rows, vec![vec![json!("Gadget"), json!(200)], vec![json!("Widget"), json!(120)]]);
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn groups_with_aggregates_and_aliases() {
+        let result = run(
+            "select region, count(*) as orders, sum(revenue) total from sales \
+             group by region order by total desc",
+        )
+        .unwrap();
+        let names: Vec<&str> = result.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["region", "orders", "total"]);
+        assert_eq!(result.columns[2].data_type, "integer");
+        // The null total sorts last
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![json!("North"), json!(2), json!(320)],
+                vec![json!("South"), json!(1), json!(80)],
+                vec![json!("East"), json!(1), Value::Null],
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregates_without_group_by_return_one_row() {
+        let result = run("SELECT COUNT(revenue), AVG(revenue), MAX(revenue) FROM sales WHERE revenue > 1000").unwrap();
+        assert_eq!(result.rows, vec![vec![json!(0), Value::Null, Value::Null]]);
+    }
+
+    #[test]
+    fn supports_like_in_between_and_null_checks() {
+        let result = run(
+            "SELECT region FROM sales WHERE product LIKE 'wid%' AND region IN ('North', 'South') \
+             AND revenue BETWEEN 100 AND 150",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![json!("North")]]);
+
+        let result = run("SELECT region FROM sales WHERE revenue IS NULL").unwrap();
+        assert_eq!(result.rows, vec![vec![json!("East")]]);
+    }
+
+    #[test]
+    fn limits_rows_and_reports_truncation() {
+        let (columns, rows) = table();
+        let result = execute(&parse("SELECT * FROM sales").unwrap(), &columns, rows, 2).unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert!(result.truncated);
+
+        let result = run("SELECT DISTINCT product FROM sales LIMIT 5 OFFSET 1").unwrap();
+        assert_eq!(result.rows, vec![vec![json!("Gadget")]]);
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn rejects_writes_and_unknown_columns() {
+        assert_eq!(parse("DELETE FROM sales").unwrap_err(), "Only SELECT statements are allowed");
+        assert!(parse("SELECT * FROM sales; DROP TABLE sales").is_err());
+        assert_eq!(run("SELECT price FROM sales").unwrap_err(), "Unknown column 'price'");
+        assert_eq!(
+            run("SELECT product, COUNT(*) FROM sales").unwrap_err(),
+            "Column 'product' must appear in GROUP BY or be aggregated"
+        );
+    }
+}
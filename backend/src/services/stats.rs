@@ -0,0 +1,165 @@
+//! Descriptive statistics over dataset columns.
+//!
+//! Numbers come from JSON cells: integers and floats count, everything else
+//! (null, strings, booleans) is skipped.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::Value;
+
+/// Numeric value of a cell
+pub fn number(value: &Value) -> Option<f64> {
+    value.as_f64().filter(|n| n.is_finite())
+}
+
+pub fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Sample standard deviation
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values)?;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+/// `values` sorted ascending
+pub fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+/// Quantile `q` (0..=1) of ascending `sorted` values, interpolating between neighbours
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f64;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * fraction)
+}
+
+/// Pearson correlation of paired values, `None` when either side is constant
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() != ys.len() || xs.len() < 3 {
+        return None;
+    }
+    let (mx, my) = (mean(xs)?, mean(ys)?);
+    let mut covariance = 0.0;
+    let mut vx = 0.0;
+    let mut vy = 0.0;
+    for (x, y) in xs.iter().zip(ys) {
+        covariance += (x - mx) * (y - my);
+        vx += (x - mx).powi(2);
+        vy += (y - my).powi(2);
+    }
+    if vx == 0.0 || vy == 0.0 {
+        return None;
+    }
+    Some(covariance / (vx.sqrt() * vy.sqrt()))
+}
+
+/// Least-squares slope and intercept of `values` against their index
+pub fn linear_fit(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = mean(values)?;
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for (i, y) in values.iter().enumerate() {
+        let dx = i as f64 - mean_x;
+        numerator += dx * (y - mean_y);
+        denominator += dx * dx;
+    }
+    let slope = numerator / denominator;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+/// Date in a cell: ISO dates and date-times, or `YYYY/MM/DD`. Forms like
+/// `03/09/2024` are ambiguous between day and month first and are not parsed.
+pub fn parse_date(value: &Value) -> Option<NaiveDate> {
+    let text = value.as_str()?.trim();
+    if text.len() < 8 {
+        return None;
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d").or_else(|_| NaiveDate::parse_from_str(text, "%Y/%m/%d")) {
+        return Some(date);
+    }
+    // Date-times, ignoring fractional seconds and offsets
+    let prefix = text.get(..19).unwrap_or(text);
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(prefix, format).ok())
+        .map(|dt| dt.date())
+}
+
+/// Share of a column's values that must parse as dates for it to be a date column
+const DATE_COLUMN_SHARE: f64 = 0.9;
+
+/// Whether non-null `cells` are nearly all dates
+pub fn is_date_column(cells: &[&Value]) -> bool {
+    let dates = cells.iter().filter(|v| parse_date(v).is_some()).count();
+    !cells.is_empty() && dates as f64 >= cells.len() as f64 * DATE_COLUMN_SHARE
+}
+
+/// Standard normal quantile of probability `p` (0 < p < 1), accurate to about 4.5e-4
+/// (Abramowitz and Stegun 26.2.23)
+pub fn normal_quantile(p: f64) -> f64 {
+    let tail = p.min(1.0 - p).clamp(f64::MIN_POSITIVE, 0.5);
+    let t = (-2.0 * tail.ln()).sqrt();
+    let z = t - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+        / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t);
+    if p < 0.5 { -z } else { z }
+}
+
+/// `value` rounded to `decimals` places for display and storage
+pub fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn computes_descriptive_statistics() {
+        let values = [4.0, 1.0, 3.0, 2.0];
+        assert_eq!(mean(&values), Some(2.5));
+        assert_eq!(round(std_dev(&values).unwrap(), 4), 1.291);
+
+        let sorted = sorted(&values);
+        assert_eq!(quantile(&sorted, 0.5), Some(2.5));
+        assert_eq!(quantile(&sorted, 0.25), Some(1.75));
+        assert_eq!(quantile(&[], 0.5), None);
+
+        assert_eq!(round(pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.5]).unwrap(), 3), 0.998);
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[5.0, 5.0, 5.0]), None);
+        assert_eq!(linear_fit(&[1.0, 3.0, 5.0]), Some((2.0, 1.0)));
+        assert_eq!(round(normal_quantile(0.975), 2), 1.96);
+        assert_eq!(round(normal_quantile(0.1), 2), -1.28);
+    }
+
+    #[test]
+    fn parses_common_date_formats() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        assert_eq!(parse_date(&json!("2024-03-09")), Some(day));
+        assert_eq!(parse_date(&json!("2024/03/09")), Some(day));
+        assert_eq!(parse_date(&json!("2024-03-09T14:30:00Z")), Some(day));
+        assert_eq!(parse_date(&json!("2024-03-09 14:30:00")), Some(day));
+        assert_eq!(parse_date(&json!("03/09/2024")), None);
+        assert_eq!(parse_date(&json!(20240309)), None);
+    }
+}
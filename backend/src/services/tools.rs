@@ -0,0 +1,593 @@
+//! Server-side tools the assistant can call while answering.
+//!
+//! Each tool implements [`Tool`] and declares the permission it needs. The
+//! [`ToolRegistry`] only offers a caller the tools their resolved permissions
+//! allow, and checks again before running a call, so a reply can never read
+//! more than the caller could through the API.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::db::DatabaseManager;
+use validator::Validate;
+use crate::models::{
+    ChartData, ForecastAggregate, ForecastDto, ForecastInterval, Permission, ResolvedPermissions,
+    SqlQueryResult, ToolInvocation,
+};
+use crate::services::{DatasetService, ForecastService};
+use crate::services::llm::{ToolCall, ToolDefinition};
+
+/// Rows a query tool returns to the model
+const TOOL_MAX_ROWS: usize = 100;
+
+/// Sample rows included when describing a dataset
+const DESCRIBE_SAMPLE_ROWS: i64 = 5;
+
+/// Longest tool output sent back to the model, in characters
+const MAX_OUTPUT_CHARS: usize = 16_000;
+
+/// Who a tool call runs for
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    pub project_id: String,
+    pub user_id: String,
+    pub permissions: ResolvedPermissions,
+}
+
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// What the tool does, for the model
+    fn description(&self) -> &'static str;
+
+    /// JSON Schema of the arguments object
+    fn parameters(&self) -> Value;
+
+    /// Permission the caller needs in the project
+    fn permission(&self) -> Permission;
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String>;
+}
+
+/// Tools available to chat replies
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        ToolRegistry { tools: Vec::new() }
+    }
+
+    /// Registry with the dataset, saved query, chart and forecast tools
+    pub fn with_builtin_tools(db: DatabaseManager, datasets: Arc<DatasetService>) -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(ListDatasets { datasets: datasets.clone() }));
+        registry.register(Arc::new(DescribeDataset { datasets: datasets.clone() }));
+        registry.register(Arc::new(RunSql { datasets: datasets.clone() }));
+        registry.register(Arc::new(GetSavedQuery { db }));
+        registry.register(Arc::new(CreateChart { datasets: datasets.clone() }));
+        registry.register(Arc::new(Forecast { forecasts: ForecastService::new(datasets.clone()), datasets }));
+        registry
+    }
+
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(tool);
+    }
+
+    /// Definitions of the tools `permissions` allow
+    pub fn definitions(&self, permissions: &ResolvedPermissions) -> Vec<ToolDefinition> {
+        self.tools
+            .iter()
+            .filter(|t| permissions.has_permission(t.permission()))
+            .map(|t| ToolDefinition {
+                name: t.name().to_string(),
+                description: t.description().to_string(),
+                parameters: t.parameters(),
+            })
+            .collect()
+    }
+
+    /// Run a call for `context`. Failures are reported in the invocation so the
+    /// model can see them and recover.
+    pub async fn invoke(&self, context: &ToolContext, call: &ToolCall) -> ToolInvocation {
+        let parsed = if call.arguments.trim().is_empty() {
+            Ok(json!({}))
+        } else {
+            serde_json::from_str::<Value>(&call.arguments)
+        };
+        let mut invocation = ToolInvocation {
+            call_id: call.id.clone(),
+            name: call.name.clone(),
+            arguments: parsed.as_ref().cloned().unwrap_or_else(|_| Value::String(call.arguments.clone())),
+            output: None,
+            error: None,
+        };
+
+        let result = match (self.tools.iter().find(|t| t.name() == call.name), parsed) {
+            (None, _) => Err(format!("Unknown tool '{}'", call.name)),
+            (Some(_), Err(e)) => Err(format!("Arguments are not valid JSON: {}", e)),
+            (Some(tool), Ok(_)) if !context.permissions.has_permission(tool.permission()) => {
+                Err(format!("Permission denied: requires {}", tool.permission().as_str()))
+            }
+            (Some(tool), Ok(arguments)) => tool.call(context, arguments).await,
+        };
+
+        match result {
+            Ok(output) => invocation.output = Some(output),
+            Err(e) => {
+                log::info!(
+                    "Tool {} failed for user {} in project {}: {}",
+                    call.name, context.user_id, context.project_id, e
+                );
+                invocation.error = Some(e);
+            }
+        }
+        invocation
+    }
+
+    /// Text of an invocation's outcome as sent back to the model
+    pub fn result_text(invocation: &ToolInvocation) -> String {
+        let text = match (&invocation.output, &invocation.error) {
+            (_, Some(error)) => json!({ "error": error }).to_string(),
+            (Some(output), None) => output.to_string(),
+            (None, None) => "null".to_string(),
+        };
+        if text.chars().count() <= MAX_OUTPUT_CHARS {
+            return text;
+        }
+        let mut truncated: String = text.chars().take(MAX_OUTPUT_CHARS).collect();
+        truncated.push_str("... [truncated]");
+        truncated
+    }
+
+    /// Chart JSON block produced by a `create_chart` or `forecast` invocation
+    pub fn chart_block(invocation: &ToolInvocation) -> Option<String> {
+        if ![CreateChart::NAME, Forecast::NAME].contains(&invocation.name.as_str()) || invocation.error.is_some() {
+            return None;
+        }
+        let chart = invocation.output.as_ref()?.get("chart")?;
+        Some(format!("```json\n{}\n```", chart))
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn arguments<T: for<'de> Deserialize<'de>>(tool: &str, value: Value) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|e| format!("Invalid arguments for {}: {}", tool, e))
+}
+
+fn query_output(result: SqlQueryResult) -> Value {
+    json!({
+        "columns": result.columns,
+        "rows": result.rows,
+        "row_count": result.rows.len(),
+        "truncated": result.truncated,
+    })
+}
+
+struct ListDatasets {
+    datasets: Arc<DatasetService>,
+}
+
+#[async_trait]
+impl Tool for ListDatasets {
+    fn name(&self) -> &'static str {
+        "list_datasets"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the datasets (SQL tables) in the current project with their columns and row counts."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ProjectRead
+    }
+
+    async fn call(&self, context: &ToolContext, _arguments: Value) -> Result<Value, String> {
+        let datasets = self.datasets.get_project_datasets(&context.project_id).await?;
+        Ok(json!({
+            "datasets": datasets
+                .into_iter()
+                .map(|d| json!({
+                    "name": d.name,
+                    "description": d.description,
+                    "row_count": d.row_count,
+                    "columns": d.columns.into_iter().map(|c| c.name).collect::<Vec<_>>(),
+                }))
+                .collect::<Vec<_>>()
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct DatasetArgs {
+    name: String,
+}
+
+struct DescribeDataset {
+    datasets: Arc<DatasetService>,
+}
+
+#[async_trait]
+impl Tool for DescribeDataset {
+    fn name(&self) -> &'static str {
+        "describe_dataset"
+    }
+
+    fn description(&self) -> &'static str {
+        "Describe a dataset's schema: column names and types, row count and a few sample rows."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "name": { "type": "string", "description": "Dataset (table) name" } },
+            "required": ["name"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ProjectRead
+    }
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String> {
+        let args: DatasetArgs = self::arguments(self.name(), arguments)?;
+        let dataset = self
+            .datasets
+            .find_by_name(&context.project_id, &args.name)
+            .await?
+            .ok_or_else(|| format!("No dataset named '{}'", args.name))?;
+        let sample = self.datasets.preview(&dataset, DESCRIBE_SAMPLE_ROWS).await?;
+        Ok(json!({
+            "name": dataset.name,
+            "description": dataset.description,
+            "row_count": dataset.row_count,
+            "columns": dataset.columns,
+            "sample_rows": sample,
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct SqlArgs {
+    sql: String,
+}
+
+struct RunSql {
+    datasets: Arc<DatasetService>,
+}
+
+#[async_trait]
+impl Tool for RunSql {
+    fn name(&self) -> &'static str {
+        "run_sql"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a read-only SQL SELECT against one dataset. Supports WHERE, GROUP BY, ORDER BY, LIMIT, \
+         COUNT/SUM/AVG/MIN/MAX, LIKE, IN and BETWEEN; no joins or subqueries. Quote column names \
+         containing spaces with double quotes. At most 100 rows are returned."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "sql": { "type": "string", "description": "A single SELECT statement" } },
+            "required": ["sql"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ProjectRead
+    }
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String> {
+        let args: SqlArgs = self::arguments(self.name(), arguments)?;
+        let result = self.datasets.query(&context.project_id, &args.sql, TOOL_MAX_ROWS).await?;
+        Ok(query_output(result))
+    }
+}
+
+#[derive(Deserialize)]
+struct SavedQueryArgs {
+    query_id: String,
+}
+
+struct GetSavedQuery {
+    db: DatabaseManager,
+}
+
+#[async_trait]
+impl Tool for GetSavedQuery {
+    fn name(&self) -> &'static str {
+        "get_saved_query"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fetch a saved analytics query of the current project and its result by query_id."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "query_id": { "type": "string" } },
+            "required": ["query_id"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ReportRead
+    }
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String> {
+        let args: SavedQueryArgs = self::arguments(self.name(), arguments)?;
+        let query = self
+            .db
+            .queries_collection()
+            .find_one(doc! { "query_id": &args.query_id, "project_id": &context.project_id })
+            .await
+            .map_err(|e| format!("Failed to get query: {}", e))?
+            .ok_or_else(|| format!("No saved query '{}' in this project", args.query_id))?;
+        Ok(json!({
+            "query_id": query.query_id,
+            "query_text": query.query_text,
+            "status": query.status,
+            "result": query.response_text,
+            "created_at": query.created_at.to_string(),
+            "completed_at": query.completed_at.map(|d| d.to_string()),
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct ChartArgs {
+    chart_type: String,
+    #[serde(default)]
+    title: Option<String>,
+    /// Query whose first column gives the labels
+    #[serde(default)]
+    sql: Option<String>,
+    /// Numeric column to plot from the query; defaults to the first numeric one
+    #[serde(default)]
+    value_column: Option<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    data: Vec<f64>,
+}
+
+struct CreateChart {
+    datasets: Arc<DatasetService>,
+}
+
+impl CreateChart {
+    const NAME: &'static str = "create_chart";
+
+    /// Labels and values from a query result
+    fn series(result: &SqlQueryResult, value_column: Option<&str>) -> Result<(Vec<String>, Vec<f64>), String> {
+        if result.columns.len() < 2 {
+            return Err("The chart query must return a label column and a value column".to_string());
+        }
+        let index = match value_column {
+            Some(name) => result
+                .columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("The query returned no column '{}'", name))?,
+            None => result
+                .columns
+                .iter()
+                .skip(1)
+                .position(|c| c.data_type == "integer" || c.data_type == "number")
+                .map(|i| i + 1)
+                .ok_or_else(|| "The chart query returned no numeric column".to_string())?,
+        };
+
+        let labels = result
+            .rows
+            .iter()
+            .map(|row| match row.first() {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => "(empty)".to_string(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+        let data = result
+            .rows
+            .iter()
+            .map(|row| row.get(index).and_then(Value::as_f64).unwrap_or(0.0))
+            .collect();
+        Ok((labels, data))
+    }
+}
+
+#[async_trait]
+impl Tool for CreateChart {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn description(&self) -> &'static str {
+        "Create a chart that is shown to the user with the answer. Either give `sql` (first column \
+         is the label, `value_column` or the first numeric column is plotted) or explicit `labels` \
+         and `data` of equal length."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "chart_type": { "type": "string", "enum": ["bar", "line", "pie", "doughnut"] },
+                "title": { "type": "string" },
+                "sql": { "type": "string" },
+                "value_column": { "type": "string" },
+                "labels": { "type": "array", "items": { "type": "string" } },
+                "data": { "type": "array", "items": { "type": "number" } }
+            },
+            "required": ["chart_type"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ReportCreate
+    }
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String> {
+        let args: ChartArgs = self::arguments(self.name(), arguments)?;
+        let chart_type = args.chart_type.to_lowercase();
+        if !["bar", "line", "pie", "doughnut"].contains(&chart_type.as_str()) {
+            return Err(format!("Unsupported chart type '{}'", args.chart_type));
+        }
+
+        let (labels, data) = match args.sql {
+            Some(ref sql) => {
+                let result = self.datasets.query(&context.project_id, sql, TOOL_MAX_ROWS).await?;
+                Self::series(&result, args.value_column.as_deref())?
+            }
+            None => (args.labels, args.data),
+        };
+        if labels.is_empty() {
+            return Err("A chart needs at least one label".to_string());
+        }
+        if labels.len() != data.len() {
+            return Err("labels and data must have the same length".to_string());
+        }
+
+        Ok(json!({
+            "chart": {
+                "type": chart_type,
+                "title": args.title.unwrap_or_default(),
+                "labels": labels,
+                "data": data,
+            }
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct ForecastArgs {
+    /// Dataset (table) name; give this or `sql`
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    sql: Option<String>,
+    date_column: String,
+    measure: String,
+    #[serde(default)]
+    interval: Option<ForecastInterval>,
+    #[serde(default)]
+    aggregate: ForecastAggregate,
+    #[serde(default)]
+    horizon: Option<usize>,
+    #[serde(default)]
+    confidence: Option<f64>,
+}
+
+struct Forecast {
+    forecasts: ForecastService,
+    datasets: Arc<DatasetService>,
+}
+
+impl Forecast {
+    const NAME: &'static str = "forecast";
+
+    /// `chart` in the form chat replies render from JSON blocks
+    fn chart_value(chart: &ChartData) -> Value {
+        json!({
+            "type": "line",
+            "title": chart.title,
+            "labels": chart.labels,
+            "datasets": chart
+                .datasets
+                .iter()
+                .map(|d| json!({ "label": d.label, "data": d.data }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for Forecast {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn description(&self) -> &'static str {
+        "Forecast a numeric measure over a date column with Holt-Winters exponential smoothing. \
+         Give a dataset `name` or a `sql` query. Values are summed (or averaged) per interval. \
+         Returns forecasts with prediction intervals and shows a line chart to the user. Use this \
+         instead of estimating future values yourself."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Dataset (table) name" },
+                "sql": { "type": "string", "description": "A single SELECT statement, instead of name" },
+                "date_column": { "type": "string" },
+                "measure": { "type": "string", "description": "Numeric column to forecast" },
+                "interval": { "type": "string", "enum": ["day", "week", "month", "quarter", "year"] },
+                "aggregate": { "type": "string", "enum": ["sum", "avg"] },
+                "horizon": { "type": "integer", "description": "Periods to forecast, 1-365" },
+                "confidence": { "type": "number", "description": "Prediction interval coverage, 0.5-0.99 (default 0.95)" }
+            },
+            "required": ["date_column", "measure"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::ProjectRead
+    }
+
+    async fn call(&self, context: &ToolContext, arguments: Value) -> Result<Value, String> {
+        let args: ForecastArgs = self::arguments(self.name(), arguments)?;
+        let dataset_id = match args.name {
+            Some(ref name) => Some(
+                self.datasets
+                    .find_by_name(&context.project_id, name)
+                    .await?
+                    .ok_or_else(|| format!("No dataset named '{}'", name))?
+                    .dataset_id,
+            ),
+            None => None,
+        };
+        let dto = ForecastDto {
+            dataset_id,
+            sql: args.sql,
+            date_column: args.date_column,
+            measure: args.measure,
+            interval: args.interval,
+            aggregate: args.aggregate,
+            horizon: args.horizon,
+            confidence: args.confidence,
+        };
+        dto.validate().map_err(|e| format!("Invalid arguments for {}: {}", self.name(), e))?;
+
+        let response = self.forecasts.forecast(&context.project_id, &dto).await?;
+        Ok(json!({
+            "method": response.method,
+            "interval": response.interval,
+            "season_length": response.season_length,
+            "history_periods": response.history.len(),
+            "last_actual": response.history.last(),
+            "rmse": response.rmse,
+            "confidence": response.confidence,
+            "forecast": response.forecast,
+            "chart": Self::chart_value(&response.chart),
+        }))
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::{Datelike, NaiveDate, Utc};
+use futures::{StreamExt, TryStreamExt};
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::ReplaceOptions;
+use uuid::Uuid;
+use crate::db::DatabaseManager;
+use crate::models::{
+    SetTokenQuotaDto, TokenQuota, TokenQuotaResponse, TokenQuotasResponse, UsageAggregate,
+    UsageRecord, UsageReportQuery, UsageReportResponse,
+};
+use crate::services::llm::{ChatEventStream, ChatStreamEvent, TokenUsage};
+
+/// Error returned when a tenant or user has used its monthly tokens
+pub const TOKEN_QUOTA_EXCEEDED: &str = "Monthly token quota exceeded";
+
+/// Configured monthly token quotas, 0 for unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaDefaults {
+    pub tenant_monthly_tokens: i64,
+    pub user_monthly_tokens: i64,
+}
+
+/// Who made an AI request and which model served it
+#[derive(Debug, Clone)]
+pub struct UsageEvent {
+    pub project_id: String,
+    pub user_id: String,
+    /// What the request was for, e.g. `chat` or `analytics`
+    pub operation: &'static str,
+    pub provider: String,
+    pub model: Option<String>,
+}
+
+/// Per-request token accounting and monthly quotas per tenant and user
+pub struct UsageService {
+    db: DatabaseManager,
+    defaults: QuotaDefaults,
+    /// Tenant of each project seen so far; projects never move between tenants
+    tenants: RwLock<HashMap<String, String>>,
+}
+
+impl UsageService {
+    pub fn new(db: DatabaseManager, defaults: QuotaDefaults) -> Self {
+        UsageService {
+            db,
+            defaults,
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    async fn tenant_for_project(&self, project_id: &str) -> Result<String, String> {
+        if let Some(tenant) = self.tenants.read().ok().and_then(|t| t.get(project_id).cloned()) {
+            return Ok(tenant);
+        }
+
+        let project = self.db
+            .projects_collection()
+            .find_one(doc! { "project_id": project_id })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| "Project not found".to_string())?;
+
+        if let Ok(mut tenants) = self.tenants.write() {
+            tenants.insert(project_id.to_string(), project.tenant_id.clone());
+        }
+        Ok(project.tenant_id)
+    }
+
+    /// Label and bounds of a `YYYY-MM` month, the current one when `None`
+    fn month_range(month: Option<&str>) -> Result<(String, DateTime, DateTime), String> {
+        let start = match month {
+            Some(m) => NaiveDate::parse_from_str(&format!("{}-01", m), "%Y-%m-%d")
+                .map_err(|_| "Invalid month, expected YYYY-MM".to_string())?,
+            None => Utc::now().date_naive().with_day(1).unwrap_or_default(),
+        };
+        let end = if start.month() == 12 {
+            NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)
+        }
+        .ok_or_else(|| "Invalid month".to_string())?;
+
+        let to_bson = |date: NaiveDate| {
+            let millis = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp_millis();
+            DateTime::from_millis(millis)
+        };
+        Ok((start.format("%Y-%m").to_string(), to_bson(start), to_bson(end)))
+    }
+
+    fn as_i64(value: Option<&Bson>) -> i64 {
+        match value {
+            Some(Bson::Int32(n)) => *n as i64,
+            Some(Bson::Int64(n)) => *n,
+            Some(Bson::Double(n)) => *n as i64,
+            _ => 0,
+        }
+    }
+
+    /// Total tokens of the records matching `filter`
+    async fn tokens_used(&self, filter: Document) -> Result<i64, String> {
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$group": { "_id": Bson::Null, "total": { "$sum": "$total_tokens" } } },
+        ];
+
+        let rows: Vec<Document> = self.db
+            .usage_records_collection()
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| format!("Failed to aggregate usage: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect usage: {}", e))?;
+
+        Ok(rows.first().map(|row| Self::as_i64(row.get("total"))).unwrap_or(0))
+    }
+
+    fn quota_filter(tenant_id: &str, user_id: Option<&str>) -> Document {
+        doc! {
+            "tenant_id": tenant_id,
+            "user_id": user_id.map(|u| Bson::String(u.to_string())).unwrap_or(Bson::Null),
+        }
+    }
+
+    /// Quota for the tenant, or for one user, and whether it was set explicitly
+    async fn effective_quota(&self, tenant_id: &str, user_id: Option<&str>) -> Result<(i64, bool), String> {
+        let stored = self.db
+            .token_quotas_collection()
+            .find_one(Self::quota_filter(tenant_id, user_id))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(match stored {
+            Some(quota) => (quota.monthly_tokens, true),
+            None if user_id.is_some() => (self.defaults.user_monthly_tokens, false),
+            None => (self.defaults.tenant_monthly_tokens, false),
+        })
+    }
+
+    async fn quota_exceeded(&self, project_id: &str, user_id: &str) -> Result<bool, String> {
+        let tenant_id = self.tenant_for_project(project_id).await?;
+        let (_, start, end) = Self::month_range(None)?;
+        let period = doc! { "$gte": start, "$lt": end };
+
+        let (tenant_limit, _) = self.effective_quota(&tenant_id, None).await?;
+        if tenant_limit > 0 {
+            let used = self
+                .tokens_used(doc! { "tenant_id": &tenant_id, "created_at": period.clone() })
+                .await?;
+            if used >= tenant_limit {
+                return Ok(true);
+            }
+        }
+
+        let (user_limit, _) = self.effective_quota(&tenant_id, Some(user_id)).await?;
+        if user_limit > 0 {
+            let used = self
+                .tokens_used(doc! { "tenant_id": &tenant_id, "user_id": user_id, "created_at": period })
+                .await?;
+            if used >= user_limit {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Reject the request with [`TOKEN_QUOTA_EXCEEDED`] when the project's tenant or the
+    /// user has used this month's tokens. Lookup failures are logged and the request is
+    /// allowed, so an accounting problem never blocks chat.
+    pub async fn check_quota(&self, project_id: &str, user_id: &str) -> Result<(), String> {
+        match self.quota_exceeded(project_id, user_id).await {
+            Ok(false) => Ok(()),
+            Ok(true) => Err(TOKEN_QUOTA_EXCEEDED.to_string()),
+            Err(e) => {
+                log::warn!("Token quota check failed for project {}: {}", project_id, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Store the tokens used by one request. Failures are logged, not returned.
+    pub async fn record(&self, event: UsageEvent, usage: TokenUsage) {
+        if usage.total_tokens == 0 {
+            return;
+        }
+
+        let tenant_id = match self.tenant_for_project(&event.project_id).await {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("Failed to record token usage for project {}: {}", event.project_id, e);
+                return;
+            }
+        };
+
+        let record = UsageRecord {
+            id: None,
+            usage_id: Uuid::new_v4().to_string(),
+            tenant_id,
+            project_id: event.project_id,
+            user_id: event.user_id,
+            operation: event.operation.to_string(),
+            provider: event.provider,
+            model: event.model,
+            prompt_tokens: usage.prompt_tokens as i64,
+            completion_tokens: usage.completion_tokens as i64,
+            total_tokens: usage.total_tokens as i64,
+            estimated: usage.estimated,
+            created_at: DateTime::now(),
+        };
+
+        if let Err(e) = self.db.usage_records_collection().insert_one(&record).await {
+            log::warn!("Failed to record token usage: {}", e);
+        }
+    }
+
+    /// Record the usage reported on a streamed reply once the stream ends,
+    /// including when the client disconnects part way through
+    pub fn meter(self: &Arc<Self>, stream: ChatEventStream, event: UsageEvent) -> ChatEventStream {
+        let mut meter = StreamMeter {
+            service: self.clone(),
+            event: Some(event),
+            usage: None,
+            completion: String::new(),
+        };
+        let mut stream = stream;
+
+        Box::pin(async_stream::stream! {
+            while let Some(item) = stream.next().await {
+                match &item {
+                    Ok(ChatStreamEvent::Content(text)) => meter.completion.push_str(text),
+                    Ok(ChatStreamEvent::Usage(usage)) => meter.usage = Some(*usage),
+                    _ => {}
+                }
+                yield item;
+            }
+        })
+    }
+
+    /// Tokens used in a tenant for a month, grouped by project, user, provider and model
+    pub async fn usage_report(
+        &self,
+        tenant_id: &str,
+        query: &UsageReportQuery,
+    ) -> Result<UsageReportResponse, String> {
+        let (month, start, end) = Self::month_range(query.month.as_deref())?;
+
+        let mut filter = doc! {
+            "tenant_id": tenant_id,
+            "created_at": { "$gte": start, "$lt": end },
+        };
+        if let Some(ref project_id) = query.project_id {
+            filter.insert("project_id", project_id);
+        }
+        if let Some(ref user_id) = query.user_id {
+            filter.insert("user_id", user_id);
+        }
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$group": {
+                "_id": {
+                    "project_id": "$project_id",
+                    "user_id": "$user_id",
+                    "provider": "$provider",
+                    "model": "$model",
+                },
+                "requests": { "$sum": 1 },
+                "prompt_tokens": { "$sum": "$prompt_tokens" },
+                "completion_tokens": { "$sum": "$completion_tokens" },
+                "total_tokens": { "$sum": "$total_tokens" },
+            }},
+            doc! { "$sort": { "total_tokens": -1 } },
+        ];
+
+        let rows: Vec<Document> = self.db
+            .usage_records_collection()
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| format!("Failed to aggregate usage: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to collect usage: {}", e))?;
+
+        let breakdown: Vec<UsageAggregate> = rows
+            .iter()
+            .filter_map(|row| {
+                let key = row.get_document("_id").ok()?;
+                Some(UsageAggregate {
+                    project_id: key.get_str("project_id").unwrap_or_default().to_string(),
+                    user_id: key.get_str("user_id").unwrap_or_default().to_string(),
+                    provider: key.get_str("provider").unwrap_or_default().to_string(),
+                    model: key.get_str("model").ok().map(str::to_string),
+                    requests: Self::as_i64(row.get("requests")),
+                    prompt_tokens: Self::as_i64(row.get("prompt_tokens")),
+                    completion_tokens: Self::as_i64(row.get("completion_tokens")),
+                    total_tokens: Self::as_i64(row.get("total_tokens")),
+                })
+            })
+            .collect();
+
+        let (tenant_quota, _) = self.effective_quota(tenant_id, None).await?;
+
+        Ok(UsageReportResponse {
+            tenant_id: tenant_id.to_string(),
+            month,
+            total_tokens: breakdown.iter().map(|b| b.total_tokens).sum(),
+            tenant_quota,
+            breakdown,
+        })
+    }
+
+    async fn quota_response(
+        &self,
+        tenant_id: &str,
+        user_id: Option<&str>,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<TokenQuotaResponse, String> {
+        let (monthly_tokens, is_override) = self.effective_quota(tenant_id, user_id).await?;
+        let mut filter = doc! { "tenant_id": tenant_id, "created_at": { "$gte": start, "$lt": end } };
+        if let Some(u) = user_id {
+            filter.insert("user_id", u);
+        }
+
+        Ok(TokenQuotaResponse {
+            user_id: user_id.map(str::to_string),
+            monthly_tokens,
+            used_tokens: self.tokens_used(filter).await?,
+            source: if is_override { "override" } else { "default" }.to_string(),
+        })
+    }
+
+    /// The tenant quota and every user override, with this month's usage
+    pub async fn get_quotas(&self, tenant_id: &str) -> Result<TokenQuotasResponse, String> {
+        let (month, start, end) = Self::month_range(None)?;
+
+        let overrides: Vec<TokenQuota> = self.db
+            .token_quotas_collection()
+            .find(doc! { "tenant_id": tenant_id, "user_id": { "$ne": Bson::Null } })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to fetch quotas: {}", e))?;
+
+        let tenant = self.quota_response(tenant_id, None, start, end).await?;
+        let mut users = Vec::with_capacity(overrides.len());
+        for quota in &overrides {
+            users.push(self.quota_response(tenant_id, quota.user_id.as_deref(), start, end).await?);
+        }
+
+        Ok(TokenQuotasResponse {
+            tenant_id: tenant_id.to_string(),
+            month,
+            tenant,
+            default_user_monthly_tokens: self.defaults.user_monthly_tokens,
+            users,
+        })
+    }
+
+    /// Set or clear the tenant quota, or a user's quota when `dto.user_id` is set
+    pub async fn set_quota(
+        &self,
+        tenant_id: &str,
+        updated_by: &str,
+        dto: SetTokenQuotaDto,
+    ) -> Result<TokenQuotaResponse, String> {
+        if let Some(ref user_id) = dto.user_id {
+            let member = self.db
+                .users_collection()
+                .find_one(doc! { "user_id": user_id, "tenant_id": tenant_id })
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            if member.is_none() {
+                return Err("User not found".to_string());
+            }
+        }
+
+        let filter = Self::quota_filter(tenant_id, dto.user_id.as_deref());
+        match dto.monthly_tokens {
+            Some(monthly_tokens) => {
+                let quota = TokenQuota {
+                    id: None,
+                    tenant_id: tenant_id.to_string(),
+                    user_id: dto.user_id.clone(),
+                    monthly_tokens,
+                    updated_by: updated_by.to_string(),
+                    updated_at: DateTime::now(),
+                };
+                self.db
+                    .token_quotas_collection()
+                    .replace_one(filter, &quota)
+                    .with_options(ReplaceOptions::builder().upsert(true).build())
+                    .await
+                    .map_err(|e| format!("Failed to save quota: {}", e))?;
+            }
+            None => {
+                self.db
+                    .token_quotas_collection()
+                    .delete_one(filter)
+                    .await
+                    .map_err(|e| format!("Failed to remove quota: {}", e))?;
+            }
+        }
+
+        let (_, start, end) = Self::month_range(None)?;
+        self.quota_response(tenant_id, dto.user_id.as_deref(), start, end).await
+    }
+}
+
+/// Records a streamed reply's usage when the stream is dropped
+struct StreamMeter {
+    service: Arc<UsageService>,
+    event: Option<UsageEvent>,
+    usage: Option<TokenUsage>,
+    /// Text received so far, for estimating streams cut short
+    completion: String,
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        let Some(event) = self.event.take() else { return };
+        let usage = self
+            .usage
+            .unwrap_or_else(|| TokenUsage::estimate("", &self.completion));
+        let service = self.service.clone();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move { service.record(event, usage).await });
+        }
+    }
+}